@@ -0,0 +1,5 @@
+-- Accounts are created from email and password alone
+ALTER TABLE users ALTER COLUMN username DROP NOT NULL;
+
+-- Track profile changes (password updates etc.)
+ALTER TABLE users ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
@@ -0,0 +1,17 @@
+-- Append-only log of security-relevant events
+CREATE TABLE audit_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    event_type TEXT NOT NULL,
+    -- Subject of the event; kept after the account is deleted
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    -- Who performed the action, e.g. the admin changing a role
+    actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    ip TEXT,
+    user_agent TEXT,
+    metadata JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX audit_events_user_id_created_at_idx ON audit_events (user_id, created_at DESC);
+CREATE INDEX audit_events_event_type_created_at_idx ON audit_events (event_type, created_at DESC);
+CREATE INDEX audit_events_created_at_idx ON audit_events (created_at DESC);
@@ -0,0 +1,8 @@
+-- Consecutive failed logins per email address. Keyed by the address as
+-- typed (lowercased) rather than user id, so unknown emails are throttled
+-- exactly like real ones.
+CREATE TABLE login_attempts (
+    email TEXT PRIMARY KEY,
+    failed_count INTEGER NOT NULL DEFAULT 0,
+    last_failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS user_sessions_family_idx;
+ALTER TABLE user_sessions DROP COLUMN IF EXISTS family_id;
+DROP TABLE IF EXISTS refresh_tokens;
@@ -0,0 +1,19 @@
+-- Long-lived, single-use refresh tokens. Each login starts a family; every
+-- rotation adds a token to it, and reusing a spent token revokes the family.
+CREATE TABLE refresh_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    family_id UUID NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX refresh_tokens_family_idx ON refresh_tokens (family_id);
+CREATE INDEX refresh_tokens_user_idx ON refresh_tokens (user_id);
+
+-- Access tokens remember their family so revoking it ends them too
+ALTER TABLE user_sessions ADD COLUMN family_id UUID;
+CREATE INDEX user_sessions_family_idx ON user_sessions (family_id);
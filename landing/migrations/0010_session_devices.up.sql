@@ -0,0 +1,9 @@
+-- Where each session was signed in from, for the active sessions page.
+-- A session keeps its row across refreshes, so these describe the login.
+ALTER TABLE user_sessions
+    ADD COLUMN device_label TEXT,
+    ADD COLUMN user_agent TEXT,
+    ADD COLUMN ip TEXT,
+    ADD COLUMN last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
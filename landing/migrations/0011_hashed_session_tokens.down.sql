@@ -0,0 +1,5 @@
+-- Hashes can't be turned back into tokens; everyone signs in again
+DELETE FROM user_sessions;
+
+ALTER INDEX IF EXISTS user_sessions_token_hash_key RENAME TO user_sessions_token_key;
+ALTER TABLE user_sessions RENAME COLUMN token_hash TO token;
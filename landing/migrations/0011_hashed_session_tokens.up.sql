@@ -0,0 +1,8 @@
+-- Access tokens are stored as an HMAC keyed with SESSION_TOKEN_KEY. The key
+-- isn't available here, so existing plaintext tokens can't be converted and
+-- are dropped. Refresh tokens are untouched: clients renew with them and get
+-- a new, hashed session row.
+DELETE FROM user_sessions;
+
+ALTER TABLE user_sessions RENAME COLUMN token TO token_hash;
+ALTER INDEX IF EXISTS user_sessions_token_key RENAME TO user_sessions_token_hash_key;
@@ -0,0 +1,24 @@
+-- WebAuthn credentials; public_key is the COSE_Key from registration
+CREATE TABLE passkeys (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    credential_id BYTEA NOT NULL UNIQUE,
+    public_key BYTEA NOT NULL,
+    -- Last signature counter, to spot cloned authenticators
+    sign_count BIGINT NOT NULL DEFAULT 0,
+    name TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX passkeys_user_id_idx ON passkeys (user_id);
+
+-- Outstanding ceremonies; user_id is set when a signed-in user registers
+CREATE TABLE passkey_challenges (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    challenge_hash TEXT NOT NULL UNIQUE,
+    ceremony TEXT NOT NULL CHECK (ceremony IN ('register', 'authenticate')),
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,14 @@
+-- Emailed sign-in links. Only digests are stored: of the token in the link,
+-- and of the secret kept in a cookie by the browser that asked for it.
+-- email rather than user_id, since the account may not exist yet.
+CREATE TABLE magic_links (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    email TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    browser_hash TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX magic_links_email_idx ON magic_links (email);
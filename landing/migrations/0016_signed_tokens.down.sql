@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS revoked_token_sessions;
+DROP TABLE IF EXISTS signing_keys;
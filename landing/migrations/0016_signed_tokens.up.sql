@@ -0,0 +1,19 @@
+-- Keys signing stateless access tokens. The HMAC secret is derived from
+-- key_seed and SESSION_TOKEN_KEY, so the table alone can't forge tokens.
+-- The newest key without retire_at signs; every key verifies until its
+-- retire_at has passed.
+CREATE TABLE signing_keys (
+    kid TEXT PRIMARY KEY,
+    key_seed TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    retire_at TIMESTAMPTZ
+);
+
+-- Sessions signed out before their signed tokens expire; kept until the
+-- last token that could name them has expired
+CREATE TABLE revoked_token_sessions (
+    session_id UUID PRIMARY KEY,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX revoked_token_sessions_expires_at_idx ON revoked_token_sessions (expires_at);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS audit_events_impersonator_id_created_at_idx;
+ALTER TABLE audit_events DROP COLUMN IF EXISTS impersonator_id;
+ALTER TABLE user_sessions DROP COLUMN IF EXISTS impersonator_id;
@@ -0,0 +1,12 @@
+-- Sessions an admin opened to act as another user. They carry no refresh
+-- token family and end with the impersonator's account.
+ALTER TABLE user_sessions
+    ADD COLUMN impersonator_id UUID REFERENCES users(id) ON DELETE CASCADE;
+
+-- Admin acting as the user when the event happened
+ALTER TABLE audit_events
+    ADD COLUMN impersonator_id UUID REFERENCES users(id) ON DELETE SET NULL;
+
+CREATE INDEX audit_events_impersonator_id_created_at_idx
+    ON audit_events (impersonator_id, created_at DESC)
+    WHERE impersonator_id IS NOT NULL;
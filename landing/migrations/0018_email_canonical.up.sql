@@ -0,0 +1,10 @@
+-- Form of the address accounts are unique under, see
+-- `EmailPolicy::canonicalize`. Existing accounts get the lowercased
+-- address; run `admin canonicalize-emails` afterwards to apply IDN and
+-- plus-tag rules. Fails if two accounts differ only in case, which have
+-- to be merged first.
+ALTER TABLE users ADD COLUMN email_canonical TEXT;
+UPDATE users SET email_canonical = LOWER(TRIM(email));
+ALTER TABLE users ALTER COLUMN email_canonical SET NOT NULL;
+
+CREATE UNIQUE INDEX users_email_canonical_key ON users (email_canonical);
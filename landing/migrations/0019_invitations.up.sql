@@ -0,0 +1,18 @@
+-- Codes that let people register while `invite_only` is on; only a
+-- SHA-256 digest of the code is stored. email, when set, is the canonical
+-- address the code is bound to; max_uses is NULL for codes without a limit.
+CREATE TABLE invitations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    code_hash TEXT NOT NULL UNIQUE,
+    email TEXT,
+    max_uses INTEGER CHECK (max_uses > 0),
+    use_count INTEGER NOT NULL DEFAULT 0,
+    invited_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    expires_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Invitation the account was registered with, and through it who invited them
+ALTER TABLE users
+    ADD COLUMN invitation_id UUID REFERENCES invitations(id) ON DELETE SET NULL;
@@ -0,0 +1,6 @@
+-- Digests can't be turned back into links; owners resend them
+DELETE FROM email_verification_tokens;
+
+ALTER INDEX IF EXISTS email_verification_tokens_token_hash_key
+    RENAME TO email_verification_tokens_token_key;
+ALTER TABLE email_verification_tokens RENAME COLUMN token_hash TO token;
@@ -0,0 +1,7 @@
+-- Verification tokens are stored as a SHA-256 digest, like reset tokens.
+-- Outstanding plaintext tokens are dropped; their owners can resend the link.
+DELETE FROM email_verification_tokens;
+
+ALTER TABLE email_verification_tokens RENAME COLUMN token TO token_hash;
+ALTER INDEX IF EXISTS email_verification_tokens_token_key
+    RENAME TO email_verification_tokens_token_hash_key;
@@ -1,5 +1,16 @@
 // src/bin/admin.rs
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
 use clap::Parser;
+use uuid::Uuid;
+
+use landing::db::{self, queries, DbError};
+use landing::server::auth::signed_tokens::DEFAULT_RELOAD_INTERVAL_SECS;
+use landing::server::auth::{EmailPolicy, PgSigningKeyStore, SigningKeyStore};
+use landing::server::{
+    AuditEvent, AuditEventType, AuditFilter, AuditRecorder, AuthConfig, AuthProvider,
+    PgAuditRecorder, PgAuthProvider,
+};
 
 #[derive(Parser)]
 struct Cli {
@@ -11,12 +22,245 @@ struct Cli {
 enum Command {
     AddUser { username: String },
     ListUsers,
+    /// Lift the failed-login lockout on an email address
+    Unlock { email: String },
+    /// Show security audit events, newest first
+    Audit {
+        /// Only events about this user ID
+        #[clap(long)]
+        user: Option<Uuid>,
+        /// Only events of this type, e.g. `login_failure`
+        #[clap(long)]
+        event: Option<AuditEventType>,
+        /// Only events at or after this RFC 3339 time
+        #[clap(long)]
+        since: Option<DateTime<Utc>>,
+        /// Only events before this RFC 3339 time
+        #[clap(long)]
+        until: Option<DateTime<Utc>>,
+        #[clap(long, default_value_t = 100)]
+        limit: i64,
+    },
+    /// Start signing access tokens with a new key
+    RotateSigningKey {
+        /// How long tokens signed with the old keys keep verifying; defaults
+        /// to the access token lifetime plus time for servers to reload
+        #[clap(long)]
+        grace_minutes: Option<i64>,
+    },
+    /// List the keys that still verify access tokens
+    SigningKeys,
+    /// Recompute every account's canonical email under the current
+    /// `EMAIL_FOLD_PLUS_TAGS` setting
+    CanonicalizeEmails,
+    /// Create a registration invitation and print its code, which isn't
+    /// shown again
+    CreateInvite {
+        /// Only this address may register with the code
+        #[clap(long)]
+        email: Option<String>,
+        /// Accounts the code can create
+        #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        max_uses: u32,
+        /// Let the code create any number of accounts
+        #[clap(long, conflicts_with = "max_uses")]
+        unlimited: bool,
+        /// Days until the code stops working; never if not given
+        #[clap(long)]
+        expires_days: Option<i64>,
+        /// Email of the account the invitation is attributed to
+        #[clap(long)]
+        invited_by: Option<String>,
+    },
+    /// List registration invitations, newest first
+    Invites,
+    /// Stop an invitation code from being used
+    RevokeInvite { id: Uuid },
 }
 
-fn main() {
+#[tokio::main]
+async fn main() -> Result<()> {
     let args = Cli::parse();
     match args.command {
         Command::AddUser { username } => println!("Adding user: {}", username),
         Command::ListUsers => println!("Listing users..."),
+        Command::Unlock { email } => {
+            let pool = connect().await?;
+            let unlocked = PgAuthProvider::new(pool.clone())
+                .with_config(AuthConfig::from_env())
+                .unlock_account(&email)
+                .await?;
+            if unlocked {
+                PgAuditRecorder::new(pool)
+                    .record(
+                        AuditEvent::new(AuditEventType::AccountUnlocked, None)
+                            .metadata(serde_json::json!({ "email": email, "via": "admin_cli" })),
+                    )
+                    .await?;
+                println!("Unlocked {email}");
+            } else {
+                println!("{email} had no failed logins on record");
+            }
+        }
+        Command::Audit { user, event, since, until, limit } => {
+            let pool = connect().await?;
+            let filter = AuditFilter { user_id: user, event_type: event, since, until, limit };
+
+            for record in PgAuditRecorder::new(pool).query(&filter).await? {
+                println!(
+                    "{} {:<24} user={} actor={} impersonator={} ip={} ua={} {}",
+                    record.created_at.to_rfc3339(),
+                    record.event_type,
+                    fmt_opt(record.user_id),
+                    fmt_opt(record.actor_id),
+                    fmt_opt(record.impersonator_id),
+                    fmt_opt(record.ip),
+                    fmt_opt(record.user_agent),
+                    record.metadata,
+                );
+            }
+        }
+        Command::RotateSigningKey { grace_minutes } => {
+            let pool = connect().await?;
+            let grace = match grace_minutes {
+                Some(minutes) => Duration::minutes(minutes),
+                None => {
+                    AuthConfig::from_env().access_token_ttl
+                        + Duration::seconds(2 * DEFAULT_RELOAD_INTERVAL_SECS)
+                }
+            };
+            let store = PgSigningKeyStore::new(pool.clone());
+            let pruned = store.prune().await?;
+            let key = store.rotate(grace).await?;
+            PgAuditRecorder::new(pool)
+                .record(
+                    AuditEvent::new(AuditEventType::SigningKeyRotated, None).metadata(
+                        serde_json::json!({ "kid": key.kid, "grace_minutes": grace.num_minutes(), "via": "admin_cli" }),
+                    ),
+                )
+                .await?;
+            println!("Signing with {}; older keys retire at {}", key.kid, (Utc::now() + grace).to_rfc3339());
+            if pruned > 0 {
+                println!("Pruned {pruned} expired keys and revocations");
+            }
+        }
+        Command::SigningKeys => {
+            let pool = connect().await?;
+            for key in PgSigningKeyStore::new(pool).keys().await? {
+                println!(
+                    "{} created={} retires={}",
+                    key.kid,
+                    key.created_at.to_rfc3339(),
+                    fmt_opt(key.retire_at.map(|at| at.to_rfc3339())),
+                );
+            }
+        }
+        Command::CanonicalizeEmails => {
+            let pool = connect().await?;
+            let policy = EmailPolicy::from_env();
+            let mut updated = 0;
+            for user in queries::users::list_users(&pool).await? {
+                let canonical = policy.canonicalize(&user.email);
+                if canonical == user.email_canonical {
+                    continue;
+                }
+                match queries::users::update_email_canonical(&pool, user.id, &canonical).await {
+                    Ok(()) => updated += 1,
+                    // Left as is; the accounts have to be merged by hand
+                    Err(DbError::ConstraintViolation(_)) => {
+                        println!("{} ({}) clashes with another account as {canonical}", user.email, user.id);
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            println!("Updated {updated} accounts");
+        }
+        Command::CreateInvite { email, max_uses, unlimited, expires_days, invited_by } => {
+            let pool = connect().await?;
+            let config = AuthConfig::from_env();
+            let invited_by = match invited_by {
+                Some(inviter) => {
+                    let canonical = config.email_policy.canonicalize(&inviter);
+                    match queries::users::get_user_by_email(&pool, &canonical).await {
+                        Ok(user) => Some(user.id),
+                        Err(DbError::NotFound) => anyhow::bail!("No account with email {inviter}"),
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                None => None,
+            };
+            let max_uses = (!unlimited).then_some(max_uses);
+            let expires_at = expires_days.map(|days| Utc::now() + Duration::days(days));
+
+            let created = PgAuthProvider::new(pool.clone())
+                .with_config(config)
+                .create_invitation(email.as_deref(), max_uses, expires_at, invited_by)
+                .await?;
+            let invitation = &created.invitation;
+            PgAuditRecorder::new(pool)
+                .record(
+                    AuditEvent::new(AuditEventType::InvitationCreated, None).actor(invited_by).metadata(
+                        serde_json::json!({
+                            "invitation_id": invitation.id,
+                            "email": invitation.email,
+                            "max_uses": invitation.max_uses,
+                            "expires_at": invitation.expires_at,
+                            "via": "admin_cli",
+                        }),
+                    ),
+                )
+                .await?;
+            println!("Invitation {} created with code {}", invitation.id, created.code);
+        }
+        Command::Invites => {
+            let pool = connect().await?;
+            let now = Utc::now();
+            for invitation in PgAuthProvider::new(pool).list_invitations().await? {
+                let status = if invitation.revoked_at.is_some() {
+                    "revoked"
+                } else if invitation.is_usable(now) {
+                    "active"
+                } else if invitation.expires_at.is_some_and(|at| at <= now) {
+                    "expired"
+                } else {
+                    "used"
+                };
+                println!(
+                    "{} {:<7} uses={}/{} email={} invited_by={} expires={} created={}",
+                    invitation.id,
+                    status,
+                    invitation.use_count,
+                    invitation.max_uses.map_or_else(|| "unlimited".into(), |max| max.to_string()),
+                    fmt_opt(invitation.email),
+                    fmt_opt(invitation.invited_by),
+                    fmt_opt(invitation.expires_at.map(|at| at.to_rfc3339())),
+                    invitation.created_at.to_rfc3339(),
+                );
+            }
+        }
+        Command::RevokeInvite { id } => {
+            let pool = connect().await?;
+            if PgAuthProvider::new(pool.clone()).revoke_invitation(id).await? {
+                PgAuditRecorder::new(pool)
+                    .record(
+                        AuditEvent::new(AuditEventType::InvitationRevoked, None)
+                            .metadata(serde_json::json!({ "invitation_id": id, "via": "admin_cli" })),
+                    )
+                    .await?;
+                println!("Revoked invitation {id}");
+            } else {
+                println!("No active invitation {id}");
+            }
+        }
     }
+    Ok(())
+}
+
+async fn connect() -> Result<db::DbPool> {
+    dotenv::dotenv().ok();
+    Ok(db::create_pool(&std::env::var("DATABASE_URL")?).await?)
+}
+
+fn fmt_opt<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_else(|| "-".into())
 }
@@ -1,11 +1,20 @@
 use anyhow::Result;
-use axum::{Extension, Json, Router, routing::post, http::StatusCode};
+use axum::{Extension, Json, Router, routing::post, http::StatusCode, middleware};
 use sqlx::{PgPool, migrate::Migrator};
+use std::net::SocketAddr;
 use std::path::Path;
+use std::sync::Arc;
 use tower_http::cors::CorsLayer;
 
-mod db;
-mod server;
+use landing::db;
+use landing::server::auth::{
+    auth_middleware, csrf_protect, rate_limit, require_permission, OidcClient, PgSigningKeyStore,
+    RateLimiter, TokenSigner,
+};
+use landing::server::{
+    api, audit, AuthConfig, AuthContext, OidcConfig, PgAuditRecorder, PgAuthProvider,
+    RateLimitConfig, SessionConfig,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -18,16 +27,50 @@ async fn main() -> Result<()> {
     let migrator = Migrator::new(Path::new("./migrations")).await?;
     migrator.run(&pool).await?;
 
-    // 3. Configure routes
-    let app = Router::new()
+    // 3. Set up authentication
+    let config = AuthConfig::from_env();
+    let session_config = SessionConfig::from_env();
+    let provider = PgAuthProvider::new(pool.clone()).with_config(config.clone());
+    let limiter = Arc::new(
+        RateLimiter::new(RateLimitConfig::from_env()).with_email_policy(config.email_policy.clone()),
+    );
+    let mut auth = AuthContext::new(Arc::new(provider))
+        .with_audit(Arc::new(PgAuditRecorder::new(pool.clone())))
+        .with_rate_limiter(limiter.clone());
+    if session_config.signed_tokens {
+        let store = Arc::new(PgSigningKeyStore::new(pool.clone()));
+        let signer = TokenSigner::new(store, config.session_token_key.clone())
+            .with_token_ttl(config.access_token_ttl);
+        auth = auth.with_signed_tokens(Arc::new(signer));
+    }
+    auth = auth.with_session_config(session_config);
+    if let Some(oidc_config) = OidcConfig::from_env() {
+        auth = auth.with_oidc(Arc::new(OidcClient::discover(oidc_config).await?));
+    }
+    let auth = Arc::new(auth);
+
+    // 4. Configure routes
+    // Sessions and API keys scoped to `posts:write` may post
+    let posts = Router::new()
         .route("/api/posts", post(create_post))
+        .route_layer(middleware::from_fn_with_state("posts:write", require_permission))
+        .route_layer(middleware::from_fn(auth_middleware));
+    let app = Router::new()
+        .merge(posts)
+        .merge(api::auth::router())
+        .layer(middleware::from_fn(csrf_protect))
+        .layer(Extension(auth))
+        .layer(middleware::from_fn(audit::capture_request_meta))
+        .layer(middleware::from_fn(rate_limit))
+        .layer(Extension(limiter))
         .layer(Extension(pool))
         .layer(CorsLayer::permissive()); // Enable CORS for development
 
-    // 4. Start server
+    // 5. Start server
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await?;
     println!("Server running on http://localhost:8080");
-    axum::serve(listener, app).await?;
+    // Connection info gives the audit log the client address
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
@@ -37,14 +80,12 @@ async fn create_post(
     Extension(pool): Extension<PgPool>,
     Json(payload): Json<serde_json::Value>
 ) -> Result<(), StatusCode> {
-    sqlx::query!(
-        "INSERT INTO posts (title, body) VALUES ($1, $2)",
-        payload["title"].as_str().unwrap_or_default(),
-        payload["body"].as_str().unwrap_or_default()
-    )
-    .execute(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("INSERT INTO posts (title, body) VALUES ($1, $2)")
+        .bind(payload["title"].as_str().unwrap_or_default())
+        .bind(payload["body"].as_str().unwrap_or_default())
+        .execute(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,101 @@
+// components/auth/active_sessions.rs
+use dioxus::prelude::*;
+use uuid::Uuid;
+use crate::server::{AuthError, SessionInfo, use_auth};
+
+/// Settings page listing the devices the user is signed in on
+///
+/// Each other device can be signed out on its own, or all at once. The
+/// current device is signed out with the regular logout button.
+#[component]
+pub fn ActiveSessions() -> Element {
+    let mut sessions = use_signal::<Vec<SessionInfo>>(Vec::new);
+    let mut error = use_signal::<Option<AuthError>>(|| None);
+    let auth = use_auth();
+
+    let load_auth = auth.clone();
+    let reload = move || {
+        let auth = load_auth.clone();
+        spawn(async move {
+            match auth.list_sessions().await {
+                Ok(list) => {
+                    error.set(None);
+                    sessions.set(list);
+                },
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    let initial = reload.clone();
+    use_hook(move || initial());
+
+    let revoke_auth = auth.clone();
+    let revoke_reload = reload.clone();
+    let on_revoke = move |id: Uuid| {
+        let auth = revoke_auth.clone();
+        let reload = revoke_reload.clone();
+        spawn(async move {
+            match auth.revoke_session(id).await {
+                Ok(_) => reload(),
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    let on_revoke_others = move |_| {
+        let auth = auth.clone();
+        let reload = reload.clone();
+        spawn(async move {
+            match auth.revoke_other_sessions().await {
+                Ok(_) => reload(),
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    let has_others = sessions.read().iter().any(|s| !s.current);
+
+    rsx! {
+        div { class: "max-w-lg mx-auto py-2",
+            h1 { class: "text-3xl", "Active sessions" }
+            p { "These devices are signed in to your account." }
+            ul {
+                for session in sessions() {
+                    li { key: "{session.id}", class: "py-2",
+                        div { class: "font-semibold",
+                            "{session.device_label}"
+                            if session.current {
+                                span { " (this device)" }
+                            }
+                        }
+                        div { class: "text-sm",
+                            if let Some(ip) = session.ip.as_ref() {
+                                "{ip} · "
+                            }
+                            "Signed in {session.created_at.format(\"%Y-%m-%d %H:%M\")} · "
+                            "Last active {session.last_seen_at.format(\"%Y-%m-%d %H:%M\")}"
+                        }
+                        if !session.current {
+                            button {
+                                r#type: "button",
+                                onclick: {
+                                    let on_revoke = on_revoke.clone();
+                                    let id = session.id;
+                                    move |_| on_revoke(id)
+                                },
+                                "Sign out"
+                            }
+                        }
+                    }
+                }
+            }
+            if has_others {
+                button { r#type: "button", onclick: on_revoke_others, "Sign out all other sessions" }
+            }
+            if let Some(e) = error.read().as_ref() {
+                div { style: "color: red;", "{e}" }
+            }
+        }
+    }
+}
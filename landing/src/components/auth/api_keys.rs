@@ -0,0 +1,154 @@
+// components/auth/api_keys.rs
+use chrono::{Duration, Utc};
+use dioxus::prelude::*;
+use uuid::Uuid;
+use crate::server::{ApiKeyInfo, AuthError, use_auth};
+
+/// Settings page for creating and revoking personal API keys
+///
+/// A new key is shown once, right after it is created; only its first
+/// characters are kept for the list.
+#[component]
+pub fn ApiKeys() -> Element {
+    let mut keys = use_signal::<Vec<ApiKeyInfo>>(Vec::new);
+    let mut permissions = use_signal::<Vec<String>>(Vec::new);
+    let mut name = use_signal(|| String::new());
+    let mut scopes = use_signal::<Vec<String>>(Vec::new);
+    let mut expires_in_days = use_signal(|| 90i64);
+    let mut created = use_signal::<Option<String>>(|| None);
+    let mut error = use_signal::<Option<AuthError>>(|| None);
+    let auth = use_auth();
+
+    let load_auth = auth.clone();
+    let reload = move || {
+        let auth = load_auth.clone();
+        spawn(async move {
+            if let Some(user) = auth.current_user().await {
+                permissions.set(user.permissions);
+            }
+            match auth.list_api_keys().await {
+                Ok(list) => {
+                    error.set(None);
+                    keys.set(list);
+                },
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    let initial = reload.clone();
+    use_hook(move || initial());
+
+    let revoke_auth = auth.clone();
+    let revoke_reload = reload.clone();
+    let on_revoke = move |id: Uuid| {
+        let auth = revoke_auth.clone();
+        let reload = revoke_reload.clone();
+        spawn(async move {
+            match auth.revoke_api_key(id).await {
+                Ok(_) => reload(),
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    let on_create = move |_| {
+        let auth = auth.clone();
+        let reload = reload.clone();
+        let label = name.read().clone();
+        let selected = scopes.read().clone();
+        let days = *expires_in_days.read();
+        let expires_at = (days > 0).then(|| Utc::now() + Duration::days(days));
+        spawn(async move {
+            match auth.create_api_key(&label, &selected, expires_at).await {
+                Ok(new_key) => {
+                    name.set(String::new());
+                    scopes.set(Vec::new());
+                    created.set(Some(new_key.key));
+                    reload();
+                },
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    rsx! {
+        div { class: "max-w-lg mx-auto py-2",
+            h1 { class: "text-3xl", "API keys" }
+            p { "Keys let scripts call the API as you, limited to the scopes you pick." }
+            if let Some(key) = created.read().as_ref() {
+                div { class: "py-2",
+                    p { class: "font-semibold", "Copy your new key now, it won't be shown again:" }
+                    code { "{key}" }
+                }
+            }
+            ul {
+                for key in keys() {
+                    li { key: "{key.id}", class: "py-2",
+                        div { class: "font-semibold", "{key.name} ({key.prefix}…)" }
+                        div { class: "text-sm",
+                            "{key.scopes.join(\", \")} · "
+                            match key.expires_at {
+                                Some(at) => rsx! { "Expires {at.format(\"%Y-%m-%d\")}" },
+                                None => rsx! { "Never expires" },
+                            }
+                            if let Some(used) = key.last_used_at {
+                                " · Last used {used.format(\"%Y-%m-%d %H:%M\")}"
+                            }
+                        }
+                        button {
+                            r#type: "button",
+                            onclick: {
+                                let on_revoke = on_revoke.clone();
+                                let id = key.id;
+                                move |_| on_revoke(id)
+                            },
+                            "Revoke"
+                        }
+                    }
+                }
+            }
+            div {
+                label { "Name" }
+                input {
+                    r#type: "text",
+                    placeholder: "e.g. Deploy script",
+                    value: "{name}",
+                    oninput: move |e| name.set(e.value().clone()),
+                }
+                for permission in permissions() {
+                    label { key: "{permission}",
+                        input {
+                            r#type: "checkbox",
+                            checked: scopes.read().contains(&permission),
+                            onchange: {
+                                let permission = permission.clone();
+                                move |e: Event<FormData>| {
+                                    let mut selected = scopes.write();
+                                    selected.retain(|s| *s != permission);
+                                    if e.checked() {
+                                        selected.push(permission.clone());
+                                    }
+                                }
+                            },
+                        }
+                        "{permission}"
+                    }
+                }
+                label { "Expires" }
+                select {
+                    value: "{expires_in_days}",
+                    onchange: move |e| expires_in_days.set(e.value().parse().unwrap_or(0)),
+                    option { value: "30", "In 30 days" }
+                    option { value: "90", "In 90 days" }
+                    option { value: "365", "In a year" }
+                    option { value: "0", "Never" }
+                }
+                button { r#type: "button", onclick: on_create, "Create key" }
+            }
+            if let Some(e) = error.read().as_ref() {
+                div { style: "color: red;", "{e}" }
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+// components/auth/forgot_password.rs
+use dioxus::prelude::*;
+use crate::server::{AuthError, use_auth};
+use crate::views::routes::Routes;
+
+/// Asks for an email address and sends a password reset link
+#[component]
+pub fn ForgotPassword() -> Element {
+    let mut email = use_signal(|| String::new());
+    let mut sent = use_signal(|| false);
+    let mut error = use_signal::<Option<AuthError>>(|| None);
+    let auth = use_auth();
+
+    let onsubmit = move |_| {
+        let email = email.read().clone();
+        let auth = auth.clone();
+
+        spawn(async move {
+            match auth.request_password_reset(&email).await {
+                Ok(_) => sent.set(true),
+                Err(e) => {
+                    log::error!("Password reset request failed: {}", e);
+                    error.set(Some(e));
+                }
+            }
+        });
+    };
+
+    rsx! {
+        if sent() {
+            div {
+                p { "If an account exists for that address, a reset link is on its way." }
+                Link { to: Routes::Login {}, "Back to login" }
+            }
+        } else {
+            form { onsubmit,
+                div {
+                    label { "Email" }
+                    input {
+                        r#type: "email",
+                        value: "{email}",
+                        oninput: move |e| email.set(e.value().clone()),
+                    }
+                }
+                button { r#type: "submit", "Send reset link" }
+                if let Some(e) = error.read().as_ref() {
+                    div { style: "color: red;", "{e}" }
+                }
+            }
+        }
+    }
+}
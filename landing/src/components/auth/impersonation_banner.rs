@@ -0,0 +1,42 @@
+// components/auth/impersonation_banner.rs
+use dioxus::prelude::*;
+use crate::{views::routes::Routes, server::{use_auth, AuthError}};
+
+/// Bar shown on every page while an admin is acting as another user
+///
+/// Starting and stopping an impersonation both navigate, which re-renders
+/// the layout and with it this banner.
+#[component]
+pub fn ImpersonationBanner() -> Element {
+    let auth = use_auth();
+    let nav = use_navigator();
+    let mut error = use_signal::<Option<AuthError>>(|| None);
+    let _route = use_route::<Routes>();
+
+    if !auth.is_impersonating() {
+        return rsx! {};
+    }
+
+    let on_stop = move |_| {
+        let auth = auth.clone();
+        spawn(async move {
+            match auth.stop_impersonating().await {
+                Ok(_) => {
+                    error.set(None);
+                    nav.replace(Routes::Admin {});
+                },
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    rsx! {
+        div { class: "flex flex-row justify-between items-center p-2 bg-yellow-300 text-black",
+            span { "You are impersonating another user. Everything you do is logged under your name." }
+            button { r#type: "button", onclick: on_stop, "Stop impersonating" }
+            if let Some(e) = error.read().as_ref() {
+                span { style: "color: red;", "{e}" }
+            }
+        }
+    }
+}
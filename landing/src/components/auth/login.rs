@@ -1,15 +1,21 @@
 use dioxus::prelude::*;
-use crate::server::{Credentials, AuthError};
+use crate::server::{Credentials, AuthError, LoginStatus};
 use crate::views::routes::Routes;
 use crate::server::use_auth;
+use crate::components::auth::webauthn::get_passkey;
 
 #[component]
 pub fn Login() -> Element {
     let mut email = use_signal(|| String::new());
     let mut password = use_signal(|| String::new());
-    let error = use_signal::<Option<AuthError>>(|| None);
+    let mut error = use_signal::<Option<AuthError>>(|| None);
+    let mut notice = use_signal::<Option<String>>(|| None);
     let nav = use_navigator();
     let auth = use_auth();
+    let resend_auth = auth.clone();
+    let passkey_auth = auth.clone();
+    let magic_link_auth = auth.clone();
+    let passkey_nav = nav.clone();
 
     let onsubmit = move |_| {
         let email = email.read().clone();
@@ -19,12 +25,65 @@ pub fn Login() -> Element {
         
         spawn(async move {
             match auth.login(&email, &password).await {
-                Ok(_) => {
+                Ok(LoginStatus::Complete) => {
                     nav.push(Routes::Home {}).expect("Navigation should work");
                 },
+                Ok(LoginStatus::MfaPending) => {
+                    nav.push(Routes::MfaChallenge {});
+                },
                 Err(e) => {
                     log::error!("Login failed: {}", e);
-                    // Optionally show error to user
+                    error.set(Some(e));
+                }
+            }
+        });
+    };
+
+    let on_resend = move |_| {
+        let email = email.read().clone();
+        let auth = resend_auth.clone();
+
+        spawn(async move {
+            match auth.resend_verification(&email).await {
+                Ok(_) => notice.set(Some("Check your inbox for a new link.".into())),
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    let on_magic_link = move |_| {
+        let email = email.read().clone();
+        let auth = magic_link_auth.clone();
+
+        spawn(async move {
+            match auth.request_magic_link(&email).await {
+                Ok(_) => {
+                    error.set(None);
+                    notice.set(Some("Check your inbox and open the link in this browser.".into()));
+                },
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    let on_passkey = move |_| {
+        let auth = passkey_auth.clone();
+        let nav = passkey_nav.clone();
+
+        spawn(async move {
+            let result = async {
+                let options = auth.begin_passkey_login().await?;
+                let assertion = get_passkey(&options).await?;
+                auth.login_with_passkey(&assertion).await
+            }
+            .await;
+            match result {
+                Ok(()) => {
+                    nav.push(Routes::Home {}).expect("Navigation should work");
+                },
+                Err(e) => {
+                    log::error!("Passkey login failed: {}", e);
+                    error.set(Some(e));
                 }
             }
         });
@@ -50,11 +109,21 @@ pub fn Login() -> Element {
                     }
                 }
                 button { r#type: "submit", "Login" }
+                button { r#type: "button", onclick: on_passkey, "Sign in with a passkey" }
+                button { r#type: "button", onclick: on_magic_link, "Email me a sign-in link" }
+                Link { to: Routes::ForgotPassword {}, "Forgot your password?" }
+                Link { to: Routes::Register {}, "Create an account" }
                 if let Some(e) = error.read().as_ref() {
                     div {
                         div { style: "color: red;", "{e}" }
+                        if matches!(e, AuthError::EmailNotVerified) {
+                            button { r#type: "button", onclick: on_resend, "Resend verification email" }
+                        }
                     }
                 }
+                if let Some(n) = notice.read().as_ref() {
+                    div { "{n}" }
+                }
             }
         }
     }
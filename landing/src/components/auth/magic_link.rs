@@ -0,0 +1,43 @@
+// components/auth/magic_link.rs
+use dioxus::prelude::*;
+use crate::{views::routes::Routes, server::{use_auth, LoginStatus}};
+
+/// Landing page for the sign-in link sent by email
+///
+/// Only works in the browser the link was requested from.
+#[component]
+pub fn MagicLink(token: String) -> Element {
+    let auth = use_auth();
+    let nav = use_navigator();
+    let status = use_resource(move || {
+        let auth = auth.clone();
+        let token = token.clone();
+        async move { auth.login_with_magic_link(&token).await }
+    });
+
+    use_effect(move || match &*status.read() {
+        Some(Ok(LoginStatus::Complete)) => {
+            nav.replace(Routes::Home {});
+        }
+        Some(Ok(LoginStatus::MfaPending)) => {
+            nav.replace(Routes::MfaChallenge {});
+        }
+        _ => {}
+    });
+
+    rsx! {
+        div { class: "max-w-lg mx-auto py-2",
+            match &*status.read_unchecked() {
+                Some(Err(e)) => rsx! {
+                    h1 { class: "text-3xl", "Sign-in link not accepted" }
+                    div { style: "color: red;", "{e}" }
+                    p { "Links work once, for a short time, and only in the browser that asked for them." }
+                    Link { to: Routes::Login {}, "Request a new link" }
+                },
+                _ => rsx! {
+                    p { "Signing you in..." }
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+// components/auth/mfa_challenge.rs
+use dioxus::prelude::*;
+use crate::server::{AuthError, use_auth};
+use crate::views::routes::Routes;
+
+/// Second login step for accounts with two-factor enabled
+#[component]
+pub fn MfaChallenge() -> Element {
+    let mut code = use_signal(|| String::new());
+    let mut use_recovery = use_signal(|| false);
+    let mut error = use_signal::<Option<AuthError>>(|| None);
+    let nav = use_navigator();
+    let auth = use_auth();
+
+    // Nothing to finish, e.g. after a reload
+    if !auth.is_mfa_pending() {
+        nav.replace(Routes::Login {});
+    }
+
+    let cancel_auth = auth.clone();
+    let onsubmit = move |_| {
+        let code = code.read().clone();
+        let auth = auth.clone();
+
+        spawn(async move {
+            match auth.complete_mfa(&code).await {
+                Ok(_) => {
+                    nav.replace(Routes::Home {});
+                },
+                Err(AuthError::InvalidToken) => {
+                    // Challenge expired or too many attempts, start over
+                    nav.replace(Routes::Login {});
+                },
+                Err(e) => {
+                    log::error!("Two-factor verification failed: {}", e);
+                    error.set(Some(e));
+                }
+            }
+        });
+    };
+
+    let on_cancel = move |_| {
+        let auth = cancel_auth.clone();
+        spawn(async move {
+            auth.cancel_mfa().await;
+            nav.replace(Routes::Login {});
+        });
+    };
+
+    rsx! {
+        form { onsubmit,
+            h1 { class: "text-3xl", "Two-factor authentication" }
+            div {
+                if use_recovery() {
+                    label { "Recovery code" }
+                    input {
+                        r#type: "text",
+                        autocomplete: "off",
+                        placeholder: "xxxxx-xxxxx",
+                        value: "{code}",
+                        oninput: move |e| code.set(e.value().clone()),
+                    }
+                } else {
+                    label { "Code from your authenticator app" }
+                    input {
+                        r#type: "text",
+                        inputmode: "numeric",
+                        autocomplete: "one-time-code",
+                        maxlength: "6",
+                        value: "{code}",
+                        oninput: move |e| code.set(e.value().clone()),
+                    }
+                }
+            }
+            button { r#type: "submit", "Verify" }
+            button {
+                r#type: "button",
+                onclick: move |_| {
+                    use_recovery.toggle();
+                    code.set(String::new());
+                },
+                if use_recovery() { "Use authenticator app" } else { "Use a recovery code" }
+            }
+            button { r#type: "button", onclick: on_cancel, "Cancel" }
+            if let Some(e) = error.read().as_ref() {
+                div { style: "color: red;", "{e}" }
+            }
+        }
+    }
+}
@@ -1,8 +1,31 @@
 // mod.rs
 pub mod login;
 pub mod logout;
+pub mod register;
+pub mod verify_email;
+pub mod forgot_password;
+pub mod reset_password;
+pub mod mfa_challenge;
+pub mod two_factor_setup;
+pub mod active_sessions;
+pub mod passkeys;
+pub mod magic_link;
+pub mod api_keys;
+pub mod impersonation_banner;
+pub mod webauthn;
 
 // Re-export from button module
 pub use login::Login;
 pub use logout::Logout;
+pub use register::Register;
+pub use verify_email::VerifyEmail;
+pub use forgot_password::ForgotPassword;
+pub use reset_password::ResetPassword;
+pub use mfa_challenge::MfaChallenge;
+pub use two_factor_setup::TwoFactorSetup;
+pub use active_sessions::ActiveSessions;
+pub use passkeys::Passkeys;
+pub use magic_link::MagicLink;
+pub use api_keys::ApiKeys;
+pub use impersonation_banner::ImpersonationBanner;
 
@@ -0,0 +1,110 @@
+// components/auth/passkeys.rs
+use dioxus::prelude::*;
+use uuid::Uuid;
+use crate::components::auth::webauthn::create_passkey;
+use crate::server::{AuthError, PasskeyInfo, use_auth};
+
+/// Settings page for adding and removing passkeys
+///
+/// A passkey added here can be used from the login page instead of the
+/// password.
+#[component]
+pub fn Passkeys() -> Element {
+    let mut passkeys = use_signal::<Vec<PasskeyInfo>>(Vec::new);
+    let mut name = use_signal(|| String::new());
+    let mut error = use_signal::<Option<AuthError>>(|| None);
+    let auth = use_auth();
+
+    let load_auth = auth.clone();
+    let reload = move || {
+        let auth = load_auth.clone();
+        spawn(async move {
+            match auth.list_passkeys().await {
+                Ok(list) => {
+                    error.set(None);
+                    passkeys.set(list);
+                },
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    let initial = reload.clone();
+    use_hook(move || initial());
+
+    let delete_auth = auth.clone();
+    let delete_reload = reload.clone();
+    let on_delete = move |id: Uuid| {
+        let auth = delete_auth.clone();
+        let reload = delete_reload.clone();
+        spawn(async move {
+            match auth.delete_passkey(id).await {
+                Ok(_) => reload(),
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    let on_add = move |_| {
+        let auth = auth.clone();
+        let reload = reload.clone();
+        let label = name.read().clone();
+        spawn(async move {
+            let result = async {
+                let options = auth.begin_passkey_registration().await?;
+                let credential = create_passkey(&options).await?;
+                auth.finish_passkey_registration(&label, &credential).await
+            }
+            .await;
+            match result {
+                Ok(_) => {
+                    name.set(String::new());
+                    reload();
+                },
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    rsx! {
+        div { class: "max-w-lg mx-auto py-2",
+            h1 { class: "text-3xl", "Passkeys" }
+            p { "Sign in with your fingerprint, face or device PIN instead of a password." }
+            ul {
+                for passkey in passkeys() {
+                    li { key: "{passkey.id}", class: "py-2",
+                        div { class: "font-semibold", "{passkey.name}" }
+                        div { class: "text-sm",
+                            "Added {passkey.created_at.format(\"%Y-%m-%d %H:%M\")}"
+                            if let Some(used) = passkey.last_used_at {
+                                " · Last used {used.format(\"%Y-%m-%d %H:%M\")}"
+                            }
+                        }
+                        button {
+                            r#type: "button",
+                            onclick: {
+                                let on_delete = on_delete.clone();
+                                let id = passkey.id;
+                                move |_| on_delete(id)
+                            },
+                            "Remove"
+                        }
+                    }
+                }
+            }
+            div {
+                label { "Name" }
+                input {
+                    r#type: "text",
+                    placeholder: "e.g. Work laptop",
+                    value: "{name}",
+                    oninput: move |e| name.set(e.value().clone()),
+                }
+                button { r#type: "button", onclick: on_add, "Add a passkey" }
+            }
+            if let Some(e) = error.read().as_ref() {
+                div { style: "color: red;", "{e}" }
+            }
+        }
+    }
+}
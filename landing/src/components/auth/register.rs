@@ -0,0 +1,128 @@
+// components/auth/register.rs
+use dioxus::prelude::*;
+use crate::server::{AuthError, PasswordViolation, use_auth};
+use crate::views::routes::Routes;
+
+/// Sign-up form; each problem is shown next to the field it concerns
+#[component]
+pub fn Register() -> Element {
+    let mut email = use_signal(|| String::new());
+    let mut password = use_signal(|| String::new());
+    let mut confirm = use_signal(|| String::new());
+    let mut invite_code = use_signal(|| String::new());
+    let mut email_error = use_signal::<Option<String>>(|| None);
+    let mut violations = use_signal::<Vec<PasswordViolation>>(Vec::new);
+    let mut confirm_error = use_signal::<Option<String>>(|| None);
+    let mut invite_error = use_signal::<Option<String>>(|| None);
+    let mut error = use_signal::<Option<AuthError>>(|| None);
+    let mut registered = use_signal(|| false);
+    let auth = use_auth();
+
+    let onsubmit = move |_| {
+        let email = email.read().clone();
+        let password = password.read().clone();
+        let invite_code = invite_code.read().clone();
+        email_error.set(None);
+        invite_error.set(None);
+        violations.set(Vec::new());
+        error.set(None);
+        if password != *confirm.read() {
+            confirm_error.set(Some("Passwords do not match".into()));
+            return;
+        }
+        confirm_error.set(None);
+        let auth = auth.clone();
+
+        spawn(async move {
+            match auth.register(&email, &password, Some(&invite_code)).await {
+                Ok(()) => registered.set(true),
+                Err(AuthError::PasswordRequirements { violations: failed }) => violations.set(failed),
+                Err(AuthError::InvalidEmail) => {
+                    email_error.set(Some("Enter a valid email address".into()));
+                }
+                Err(AuthError::DisposableEmail) => {
+                    email_error.set(Some("Use a permanent email address".into()));
+                }
+                Err(AuthError::UserExists) => {
+                    email_error.set(Some("An account with this email already exists".into()));
+                }
+                Err(AuthError::InvitationRequired) => {
+                    invite_error.set(Some("Signing up needs an invitation code for now".into()));
+                }
+                Err(AuthError::InvalidInvitation) => {
+                    invite_error.set(Some("This invitation code is invalid, expired or used up".into()));
+                }
+                Err(e) => {
+                    log::error!("Registration failed: {}", e);
+                    error.set(Some(e));
+                }
+            }
+        });
+    };
+
+    if registered() {
+        return rsx! {
+            div { "Check your inbox for a link to confirm your email address." }
+            Link { to: Routes::Login {}, "Back to login" }
+        };
+    }
+
+    rsx! {
+        form { onsubmit,
+            div {
+                label { "Email" }
+                input {
+                    r#type: "email",
+                    value: "{email}",
+                    oninput: move |e| email.set(e.value().clone()),
+                }
+                if let Some(e) = email_error.read().as_ref() {
+                    div { style: "color: red;", "{e}" }
+                }
+            }
+            div {
+                label { "Password" }
+                input {
+                    r#type: "password",
+                    value: "{password}",
+                    oninput: move |e| password.set(e.value().clone()),
+                }
+                if !violations.read().is_empty() {
+                    ul { style: "color: red;",
+                        for violation in violations.read().iter() {
+                            li { "Password {violation}" }
+                        }
+                    }
+                }
+            }
+            div {
+                label { "Confirm password" }
+                input {
+                    r#type: "password",
+                    value: "{confirm}",
+                    oninput: move |e| confirm.set(e.value().clone()),
+                }
+                if let Some(e) = confirm_error.read().as_ref() {
+                    div { style: "color: red;", "{e}" }
+                }
+            }
+            div {
+                label { "Invitation code" }
+                input {
+                    r#type: "text",
+                    value: "{invite_code}",
+                    placeholder: "Only needed during the private beta",
+                    oninput: move |e| invite_code.set(e.value().clone()),
+                }
+                if let Some(e) = invite_error.read().as_ref() {
+                    div { style: "color: red;", "{e}" }
+                }
+            }
+            button { r#type: "submit", "Create account" }
+            if let Some(e) = error.read().as_ref() {
+                div { style: "color: red;", "{e}" }
+            }
+            Link { to: Routes::Login {}, "Already have an account? Log in" }
+        }
+    }
+}
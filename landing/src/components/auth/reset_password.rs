@@ -0,0 +1,68 @@
+// components/auth/reset_password.rs
+use dioxus::prelude::*;
+use crate::server::{AuthError, use_auth};
+use crate::views::routes::Routes;
+
+/// Landing page for the password reset link
+#[component]
+pub fn ResetPassword(token: String) -> Element {
+    let mut password = use_signal(|| String::new());
+    let mut confirm = use_signal(|| String::new());
+    let mut error = use_signal::<Option<String>>(|| None);
+    let nav = use_navigator();
+    let auth = use_auth();
+
+    let onsubmit = move |_| {
+        let password = password.read().clone();
+        if password != *confirm.read() {
+            error.set(Some("Passwords do not match".into()));
+            return;
+        }
+        let token = token.clone();
+        let auth = auth.clone();
+
+        spawn(async move {
+            match auth.reset_password(&token, &password).await {
+                Ok(_) => {
+                    nav.replace(Routes::Login {});
+                },
+                Err(e) => {
+                    log::error!("Password reset failed: {}", e);
+                    let message = match e {
+                        AuthError::InvalidToken => {
+                            "This link is invalid or has expired, request a new one.".to_string()
+                        }
+                        e => e.to_string(),
+                    };
+                    error.set(Some(message));
+                }
+            }
+        });
+    };
+
+    rsx! {
+        form { onsubmit,
+            div {
+                label { "New password" }
+                input {
+                    r#type: "password",
+                    value: "{password}",
+                    oninput: move |e| password.set(e.value().clone()),
+                }
+            }
+            div {
+                label { "Confirm password" }
+                input {
+                    r#type: "password",
+                    value: "{confirm}",
+                    oninput: move |e| confirm.set(e.value().clone()),
+                }
+            }
+            button { r#type: "submit", "Set password" }
+            if let Some(e) = error.read().as_ref() {
+                div { style: "color: red;", "{e}" }
+            }
+            Link { to: Routes::ForgotPassword {}, "Request a new link" }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+// components/auth/two_factor_setup.rs
+use dioxus::prelude::*;
+use crate::server::{AuthError, TotpEnrollment, use_auth};
+
+/// Settings page for turning TOTP two-factor on
+///
+/// Walks through three steps: generate a secret, confirm a first code,
+/// then show the recovery codes once.
+#[component]
+pub fn TwoFactorSetup() -> Element {
+    let mut enrollment = use_signal::<Option<TotpEnrollment>>(|| None);
+    let mut recovery_codes = use_signal::<Option<Vec<String>>>(|| None);
+    let mut otp_code = use_signal(|| String::new());
+    let mut error = use_signal::<Option<AuthError>>(|| None);
+    let auth = use_auth();
+
+    let begin_auth = auth.clone();
+    let on_begin = move |_| {
+        let auth = begin_auth.clone();
+        spawn(async move {
+            match auth.begin_totp_enrollment().await {
+                Ok(e) => {
+                    error.set(None);
+                    enrollment.set(Some(e));
+                },
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    let on_confirm = move |_| {
+        let code = otp_code.read().clone();
+        let auth = auth.clone();
+        spawn(async move {
+            match auth.confirm_totp_enrollment(&code).await {
+                Ok(codes) => {
+                    error.set(None);
+                    recovery_codes.set(Some(codes));
+                },
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    rsx! {
+        div { class: "max-w-lg mx-auto py-2",
+            h1 { class: "text-3xl", "Two-factor authentication" }
+            if let Some(codes) = recovery_codes() {
+                p { "Two-factor is on. Store these recovery codes somewhere safe, each works once:" }
+                ul { class: "font-mono",
+                    for c in codes {
+                        li { "{c}" }
+                    }
+                }
+            } else if let Some(e) = enrollment() {
+                p { "Scan this code with your authenticator app:" }
+                img {
+                    src: format!("data:image/png;base64,{}", e.qr_png_base64),
+                    alt: e.otpauth_uri.clone(),
+                }
+                p { "Or enter the key manually: " code { {e.secret.clone()} } }
+                form { onsubmit: on_confirm,
+                    label { "Code from the app" }
+                    input {
+                        r#type: "text",
+                        inputmode: "numeric",
+                        autocomplete: "one-time-code",
+                        maxlength: "6",
+                        value: "{otp_code}",
+                        oninput: move |e| otp_code.set(e.value().clone()),
+                    }
+                    button { r#type: "submit", "Turn on" }
+                }
+            } else {
+                p { "Protect your account with a code from an authenticator app." }
+                button { r#type: "button", onclick: on_begin, "Set up two-factor" }
+            }
+            if let Some(e) = error.read().as_ref() {
+                div { style: "color: red;", "{e}" }
+            }
+        }
+    }
+}
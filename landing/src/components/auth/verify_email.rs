@@ -0,0 +1,34 @@
+// components/auth/verify_email.rs
+use dioxus::prelude::*;
+use crate::{views::routes::Routes, server::use_auth};
+
+/// Landing page for the link sent by email after registration
+#[component]
+pub fn VerifyEmail(token: String) -> Element {
+    let auth = use_auth();
+    let status = use_resource(move || {
+        let auth = auth.clone();
+        let token = token.clone();
+        async move { auth.verify_email(&token).await }
+    });
+
+    rsx! {
+        div { class: "max-w-lg mx-auto py-2",
+            match &*status.read_unchecked() {
+                Some(Ok(())) => rsx! {
+                    h1 { class: "text-3xl", "Email verified" }
+                    p { "Your account is active." }
+                    Link { to: Routes::Login {}, "Continue to login" }
+                },
+                Some(Err(e)) => rsx! {
+                    h1 { class: "text-3xl", "Verification failed" }
+                    div { style: "color: red;", "{e}" }
+                    p { "Log in to request a new link." }
+                },
+                None => rsx! {
+                    p { "Verifying your email..." }
+                },
+            }
+        }
+    }
+}
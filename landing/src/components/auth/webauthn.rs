@@ -0,0 +1,167 @@
+// components/auth/webauthn.rs
+//! Browser side of the passkey ceremonies
+//!
+//! Turns the server's options into `navigator.credentials` calls and the
+//! authenticator's answer back into the base64url types the server checks.
+
+use crate::server::{
+    AuthError, PasskeyAssertion, PasskeyCreationOptions, PasskeyCredential, PasskeyRequestOptions,
+};
+
+/// Asks the browser to create a passkey for the signed-in user
+#[cfg(target_arch = "wasm32")]
+pub async fn create_passkey(options: &PasskeyCreationOptions) -> Result<PasskeyCredential, AuthError> {
+    use js_sys::{Array, Object};
+    use wasm_bindgen::JsCast;
+    use web_sys::{AuthenticatorAttestationResponse, CredentialCreationOptions, PublicKeyCredential};
+
+    let rp = Object::new();
+    set(&rp, "id", &options.rp_id.as_str().into())?;
+    set(&rp, "name", &options.rp_name.as_str().into())?;
+
+    let user = Object::new();
+    set(&user, "id", &bytes(&options.user_id)?)?;
+    set(&user, "name", &options.user_name.as_str().into())?;
+    set(&user, "displayName", &options.user_name.as_str().into())?;
+
+    let params = Array::new();
+    for alg in &options.algorithms {
+        let param = Object::new();
+        set(&param, "type", &"public-key".into())?;
+        set(&param, "alg", &(*alg as f64).into())?;
+        params.push(&param);
+    }
+
+    let exclude = Array::new();
+    for id in &options.exclude_credentials {
+        exclude.push(&descriptor(id)?);
+    }
+
+    // Discoverable, so signing in needs no email first
+    let selection = Object::new();
+    set(&selection, "residentKey", &"required".into())?;
+    set(&selection, "requireResidentKey", &true.into())?;
+    set(&selection, "userVerification", &"required".into())?;
+
+    let public_key = Object::new();
+    set(&public_key, "challenge", &bytes(&options.challenge)?)?;
+    set(&public_key, "rp", &rp)?;
+    set(&public_key, "user", &user)?;
+    set(&public_key, "pubKeyCredParams", &params)?;
+    set(&public_key, "excludeCredentials", &exclude)?;
+    set(&public_key, "authenticatorSelection", &selection)?;
+    set(&public_key, "attestation", &"none".into())?;
+    set(&public_key, "timeout", &options.timeout_ms.into())?;
+
+    let request = CredentialCreationOptions::new();
+    request.set_public_key(public_key.unchecked_ref());
+
+    let promise = credentials()?
+        .create_with_options(&request)
+        .map_err(|_| AuthError::InvalidPasskey)?;
+    let credential: PublicKeyCredential = await_credential(promise).await?;
+    let response: AuthenticatorAttestationResponse = credential
+        .response()
+        .dyn_into()
+        .map_err(|_| AuthError::InvalidPasskey)?;
+
+    Ok(PasskeyCredential {
+        credential_id: encode_buffer(&credential.raw_id()),
+        client_data_json: encode_buffer(&response.client_data_json()),
+        attestation_object: encode_buffer(&response.attestation_object()),
+    })
+}
+
+/// Asks the browser to sign the challenge with one of this site's passkeys
+#[cfg(target_arch = "wasm32")]
+pub async fn get_passkey(options: &PasskeyRequestOptions) -> Result<PasskeyAssertion, AuthError> {
+    use js_sys::Object;
+    use wasm_bindgen::JsCast;
+    use web_sys::{AuthenticatorAssertionResponse, CredentialRequestOptions, PublicKeyCredential};
+
+    let public_key = Object::new();
+    set(&public_key, "challenge", &bytes(&options.challenge)?)?;
+    set(&public_key, "rpId", &options.rp_id.as_str().into())?;
+    set(&public_key, "userVerification", &"required".into())?;
+    set(&public_key, "timeout", &options.timeout_ms.into())?;
+
+    let request = CredentialRequestOptions::new();
+    request.set_public_key(public_key.unchecked_ref());
+
+    let promise = credentials()?
+        .get_with_options(&request)
+        .map_err(|_| AuthError::InvalidPasskey)?;
+    let credential: PublicKeyCredential = await_credential(promise).await?;
+    let response: AuthenticatorAssertionResponse = credential
+        .response()
+        .dyn_into()
+        .map_err(|_| AuthError::InvalidPasskey)?;
+
+    Ok(PasskeyAssertion {
+        credential_id: encode_buffer(&credential.raw_id()),
+        client_data_json: encode_buffer(&response.client_data_json()),
+        authenticator_data: encode_buffer(&response.authenticator_data()),
+        signature: encode_buffer(&response.signature()),
+        user_handle: response.user_handle().map(|handle| encode_buffer(&handle)),
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn create_passkey(_options: &PasskeyCreationOptions) -> Result<PasskeyCredential, AuthError> {
+    // Passkeys need the browser's WebAuthn API
+    Err(AuthError::InvalidPasskey)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn get_passkey(_options: &PasskeyRequestOptions) -> Result<PasskeyAssertion, AuthError> {
+    Err(AuthError::InvalidPasskey)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn credentials() -> Result<web_sys::CredentialsContainer, AuthError> {
+    web_sys::window()
+        .map(|window| window.navigator().credentials())
+        .ok_or(AuthError::InvalidPasskey)
+}
+
+/// Waits for the browser prompt; cancelling it counts as a failed check
+#[cfg(target_arch = "wasm32")]
+async fn await_credential(promise: js_sys::Promise) -> Result<web_sys::PublicKeyCredential, AuthError> {
+    use wasm_bindgen::JsCast;
+
+    wasm_bindgen_futures::JsFuture::from(promise)
+        .await
+        .map_err(|e| {
+            log::error!("Passkey prompt failed: {:?}", e);
+            AuthError::InvalidPasskey
+        })?
+        .dyn_into()
+        .map_err(|_| AuthError::InvalidPasskey)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn set(target: &js_sys::Object, key: &str, value: &wasm_bindgen::JsValue) -> Result<(), AuthError> {
+    js_sys::Reflect::set(target, &key.into(), value)
+        .map(|_| ())
+        .map_err(|_| AuthError::InvalidPasskey)
+}
+
+/// base64url from the server as the `BufferSource` WebAuthn expects
+#[cfg(target_arch = "wasm32")]
+fn bytes(value: &str) -> Result<wasm_bindgen::JsValue, AuthError> {
+    let decoded = crate::server::auth::webauthn::decode(value)?;
+    Ok(js_sys::Uint8Array::from(decoded.as_slice()).into())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn descriptor(credential_id: &str) -> Result<js_sys::Object, AuthError> {
+    let descriptor = js_sys::Object::new();
+    set(&descriptor, "type", &"public-key".into())?;
+    set(&descriptor, "id", &bytes(credential_id)?)?;
+    Ok(descriptor)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn encode_buffer(buffer: &js_sys::ArrayBuffer) -> String {
+    crate::server::auth::webauthn::encode(&js_sys::Uint8Array::new(buffer).to_vec())
+}
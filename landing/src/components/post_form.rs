@@ -1,22 +1,30 @@
 use dioxus::prelude::*;
 use serde_json::json;
+use crate::server::use_auth;
 
 #[component]
 pub fn PostForm() -> Element {
     let title = use_signal( || String::new());
     let body = use_signal( || String::new());
+    let auth = use_auth();
 
     rsx! {
         form {
-            onsubmit: move |_| async {
-                let payload = json!(
-                    { "title" : title.current().as_str(), "body" : body.current().as_str() }
-                );
-                let _ = reqwest::Client::new()
-                    .post("http://localhost:8080/api/posts")
-                    .json(&payload)
-                    .send()
-                    .await;
+            onsubmit: move |_| {
+                let auth = auth.clone();
+                async move {
+                    let payload = json!(
+                        { "title" : title.current().as_str(), "body" : body.current().as_str() }
+                    );
+                    // Posting needs a signed-in user with `posts:write`
+                    let mut request = reqwest::Client::new()
+                        .post("http://localhost:8080/api/posts")
+                        .json(&payload);
+                    if let Some(user) = auth.current_user().await {
+                        request = request.bearer_auth(&user.bearer_token);
+                    }
+                    let _ = request.send().await;
+                }
             },
             input {
                 value: "{title}",
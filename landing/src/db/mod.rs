@@ -10,11 +10,16 @@ mod connection;
 mod errors;
 mod models;
 mod postgres;
+pub mod queries;
 
 // Public interface
 pub use connection::{create_pool, DbPool};
 pub use errors::DbError;
-pub use models::{DbUser, UserSession, UserProfile};
+pub use models::{
+    ApiKey, AuditEventRecord, DbRole, DbUser, EmailVerificationToken, Invitation, LoginAttempts,
+    MagicLink, MfaChallenge, Passkey, PasskeyChallenge, PasswordResetToken, RefreshToken, SigningKey,
+    UserIdentity, UserProfile, UserSession, UserTotp,
+};
 pub use postgres::run_migrations;
 
 /// Re-export for convenience
@@ -7,9 +7,15 @@ use uuid::Uuid;
 pub struct DbUser {
     pub id: Uuid,
     pub email: String,
+    pub username: Option<String>,
     pub password_hash: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// `email` in the form accounts are unique under
+    pub email_canonical: String,
+    /// Invitation the account was registered with
+    pub invitation_id: Option<Uuid>,
 }
 
 /// Active user session record
@@ -17,7 +23,191 @@ pub struct DbUser {
 pub struct UserSession {
     pub id: Uuid,
     pub user_id: Uuid,
-    pub token: String,
+    /// Keyed hash of the access token; the token itself is never stored
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    /// Refresh token family the session was issued from
+    pub family_id: Option<Uuid>,
+    /// Browser and OS derived from `user_agent`, e.g. "Firefox on Linux"
+    pub device_label: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// Last time the session was validated or refreshed
+    pub last_seen_at: DateTime<Utc>,
+    /// Admin acting as `user_id` through this session
+    pub impersonator_id: Option<Uuid>,
+}
+
+/// Pending email verification link, stored as a digest of the emailed token
+#[derive(Debug, sqlx::FromRow)]
+pub struct EmailVerificationToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Password reset request, stored as a digest of the emailed token
+#[derive(Debug, sqlx::FromRow)]
+pub struct PasswordResetToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Single-use sign-in link, bound to the browser that asked for it
+#[derive(Debug, sqlx::FromRow)]
+pub struct MagicLink {
+    pub id: Uuid,
+    pub email: String,
+    pub token_hash: String,
+    pub browser_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Named role that can be granted to users
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct DbRole {
+    pub id: Uuid,
+    pub name: String,
+    pub description: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// TOTP secret for a user, active once `enabled_at` is set
+#[derive(Debug, sqlx::FromRow)]
+pub struct UserTotp {
+    pub user_id: Uuid,
+    pub secret: String,
+    pub enabled_at: Option<DateTime<Utc>>,
+    pub last_used_step: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Pending second-factor step of a login
+#[derive(Debug, sqlx::FromRow)]
+pub struct MfaChallenge {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub attempts: i32,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Refresh token digest; every token issued from one login shares a family
+#[derive(Debug, sqlx::FromRow)]
+pub struct RefreshToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Failed login streak for an email address
+#[derive(Debug, sqlx::FromRow)]
+pub struct LoginAttempts {
+    pub email: String,
+    pub failed_count: i32,
+    pub last_failed_at: DateTime<Utc>,
+}
+
+/// Entry in the security audit log
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow, Serialize)]
+pub struct AuditEventRecord {
+    pub id: Uuid,
+    pub event_type: String,
+    pub user_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,
+    /// Admin acting as the user when the event happened
+    pub impersonator_id: Option<Uuid>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub metadata: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// External OpenID Connect account linked to a user
+#[derive(Debug, sqlx::FromRow)]
+pub struct UserIdentity {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Personal API key, stored as a digest
+#[derive(Debug, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub key_hash: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Registration invitation, with its code stored as a digest
+#[derive(Debug, sqlx::FromRow)]
+pub struct Invitation {
+    pub id: Uuid,
+    pub code_hash: String,
+    /// Canonical address the code is bound to
+    pub email: Option<String>,
+    pub max_uses: Option<i32>,
+    pub use_count: i32,
+    pub invited_by: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Key signing stateless access tokens; the secret is derived from `key_seed`
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct SigningKey {
+    pub kid: String,
+    pub key_seed: String,
+    pub created_at: DateTime<Utc>,
+    /// When tokens signed with this key stop verifying; `None` while it signs
+    pub retire_at: Option<DateTime<Utc>>,
+}
+
+/// WebAuthn credential registered to a user
+#[derive(Debug, sqlx::FromRow)]
+pub struct Passkey {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub credential_id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub sign_count: i64,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Challenge of a passkey ceremony that hasn't finished yet
+#[derive(Debug, sqlx::FromRow)]
+pub struct PasskeyChallenge {
+    pub id: Uuid,
+    pub challenge_hash: String,
+    pub ceremony: String,
+    pub user_id: Option<Uuid>,
     pub expires_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
 }
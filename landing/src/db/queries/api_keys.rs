@@ -0,0 +1,78 @@
+use super::{models::ApiKey, DbError, Result};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Stores a new API key digest
+pub async fn create_api_key(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    key_hash: &str,
+    prefix: &str,
+    scopes: &[String],
+    expires_at: Option<DateTime<Utc>>
+) -> Result<ApiKey> {
+    let key = sqlx::query_as::<_, ApiKey>(
+        r#"
+        INSERT INTO api_keys (user_id, name, key_hash, prefix, scopes, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(name)
+    .bind(key_hash)
+    .bind(prefix)
+    .bind(scopes)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(key)
+}
+
+/// Looks up a key by the digest of a presented key
+pub async fn get_api_key_by_hash(pool: &PgPool, key_hash: &str) -> Result<ApiKey> {
+    sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE key_hash = $1")
+        .bind(key_hash)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            _ => e.into()
+        })
+}
+
+/// Lists a user's keys, newest first
+pub async fn list_api_keys(pool: &PgPool, user_id: Uuid) -> Result<Vec<ApiKey>> {
+    let keys = sqlx::query_as::<_, ApiKey>(
+        "SELECT * FROM api_keys WHERE user_id = $1 ORDER BY created_at DESC",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(keys)
+}
+
+/// Records that a key was just used
+pub async fn touch_api_key(pool: &PgPool, id: Uuid) -> Result<()> {
+    sqlx::query("UPDATE api_keys SET last_used_at = NOW() WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes one of a user's keys
+pub async fn delete_api_key(pool: &PgPool, user_id: Uuid, id: Uuid) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM api_keys WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
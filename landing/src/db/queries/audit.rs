@@ -0,0 +1,67 @@
+use super::{models::AuditEventRecord, Result};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Appends an event to the audit log
+pub async fn insert_audit_event(
+    pool: &PgPool,
+    event_type: &str,
+    user_id: Option<Uuid>,
+    actor_id: Option<Uuid>,
+    impersonator_id: Option<Uuid>,
+    ip: Option<&str>,
+    user_agent: Option<&str>,
+    metadata: &serde_json::Value
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO audit_events (event_type, user_id, actor_id, impersonator_id, ip, user_agent, metadata)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+    )
+    .bind(event_type)
+    .bind(user_id)
+    .bind(actor_id)
+    .bind(impersonator_id)
+    .bind(ip)
+    .bind(user_agent)
+    .bind(metadata)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Reads events newest first; `None` leaves a criterion unrestricted
+///
+/// `since` is inclusive and `until` exclusive.
+pub async fn query_audit_events(
+    pool: &PgPool,
+    user_id: Option<Uuid>,
+    event_type: Option<&str>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    limit: i64
+) -> Result<Vec<AuditEventRecord>> {
+    sqlx::query_as::<_, AuditEventRecord>(
+        r#"
+        SELECT id, event_type, user_id, actor_id, impersonator_id, ip, user_agent, metadata, created_at
+        FROM audit_events
+        WHERE ($1::uuid IS NULL OR user_id = $1)
+          AND ($2::text IS NULL OR event_type = $2)
+          AND ($3::timestamptz IS NULL OR created_at >= $3)
+          AND ($4::timestamptz IS NULL OR created_at < $4)
+        ORDER BY created_at DESC
+        LIMIT $5
+        "#,
+    )
+    .bind(user_id)
+    .bind(event_type)
+    .bind(since)
+    .bind(until)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(Into::into)
+}
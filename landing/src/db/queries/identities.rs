@@ -0,0 +1,49 @@
+use super::{models::UserIdentity, DbError, Result};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Gets the link for an external account
+pub async fn get_identity(pool: &PgPool, issuer: &str, subject: &str) -> Result<UserIdentity> {
+    sqlx::query_as::<_, UserIdentity>(
+        "SELECT * FROM user_identities WHERE issuer = $1 AND subject = $2",
+    )
+    .bind(issuer)
+    .bind(subject)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => DbError::NotFound,
+        _ => e.into()
+    })
+}
+
+/// Links an external account to a user
+///
+/// Fails with `ConstraintViolation` if the account is already linked.
+pub async fn create_identity(
+    pool: &PgPool,
+    user_id: Uuid,
+    issuer: &str,
+    subject: &str,
+    email: Option<&str>
+) -> Result<UserIdentity> {
+    sqlx::query_as::<_, UserIdentity>(
+        r#"
+        INSERT INTO user_identities (user_id, issuer, subject, email)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(issuer)
+    .bind(subject)
+    .bind(email)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            DbError::ConstraintViolation(db.message().to_string())
+        }
+        _ => e.into()
+    })
+}
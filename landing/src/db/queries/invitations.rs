@@ -0,0 +1,113 @@
+use super::{models::{DbUser, Invitation}, DbError, Result};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Stores a new invitation code digest
+pub async fn create_invitation(
+    pool: &PgPool,
+    code_hash: &str,
+    email: Option<&str>,
+    max_uses: Option<i32>,
+    invited_by: Option<Uuid>,
+    expires_at: Option<DateTime<Utc>>
+) -> Result<Invitation> {
+    let invitation = sqlx::query_as::<_, Invitation>(
+        r#"
+        INSERT INTO invitations (code_hash, email, max_uses, invited_by, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING *
+        "#,
+    )
+    .bind(code_hash)
+    .bind(email)
+    .bind(max_uses)
+    .bind(invited_by)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(invitation)
+}
+
+/// Lists every invitation, newest first
+pub async fn list_invitations(pool: &PgPool) -> Result<Vec<Invitation>> {
+    let invitations =
+        sqlx::query_as::<_, Invitation>("SELECT * FROM invitations ORDER BY created_at DESC")
+            .fetch_all(pool)
+            .await?;
+
+    Ok(invitations)
+}
+
+/// Stops an invitation from being used; false if it was unknown or
+/// already revoked
+pub async fn revoke_invitation(pool: &PgPool, id: Uuid) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE invitations SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL",
+    )
+    .bind(id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Spends one use of an invitation and creates the account it lets in,
+/// together, so a code is neither overused nor burnt by a failed signup
+///
+/// `NotFound` if the code is unknown, revoked, expired, used up or bound
+/// to another address; `ConstraintViolation` if the email is taken.
+pub async fn redeem_invitation(
+    pool: &PgPool,
+    code_hash: &str,
+    email: &str,
+    email_canonical: &str,
+    password_hash: &str
+) -> Result<DbUser> {
+    let mut tx = pool.begin().await?;
+
+    let invitation = sqlx::query_as::<_, Invitation>(
+        r#"
+        UPDATE invitations
+        SET use_count = use_count + 1
+        WHERE code_hash = $1
+          AND revoked_at IS NULL
+          AND (expires_at IS NULL OR expires_at > NOW())
+          AND (max_uses IS NULL OR use_count < max_uses)
+          AND (email IS NULL OR email = $2)
+        RETURNING *
+        "#,
+    )
+    .bind(code_hash)
+    .bind(email_canonical)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => DbError::NotFound,
+        _ => e.into()
+    })?;
+
+    let user = sqlx::query_as::<_, DbUser>(
+        r#"
+        INSERT INTO users (email, email_canonical, password_hash, invitation_id)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *
+        "#,
+    )
+    .bind(email)
+    .bind(email_canonical)
+    .bind(password_hash)
+    .bind(invitation.id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            DbError::ConstraintViolation(db.message().to_string())
+        }
+        _ => e.into()
+    })?;
+
+    tx.commit().await?;
+    Ok(user)
+}
@@ -0,0 +1,56 @@
+use super::{models::LoginAttempts, DbError, Result};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+/// Gets the failed login streak for an email
+pub async fn get_login_attempts(pool: &PgPool, email: &str) -> Result<LoginAttempts> {
+    sqlx::query_as::<_, LoginAttempts>("SELECT * FROM login_attempts WHERE email = $1")
+        .bind(email)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            _ => e.into()
+        })
+}
+
+/// Counts a failed login and returns the updated streak
+///
+/// A streak already at `lockout_threshold` belongs to an expired lock and
+/// starts over at 1.
+pub async fn record_failed_login(
+    pool: &PgPool,
+    email: &str,
+    failed_at: DateTime<Utc>,
+    lockout_threshold: i32
+) -> Result<LoginAttempts> {
+    sqlx::query_as::<_, LoginAttempts>(
+        r#"
+        INSERT INTO login_attempts (email, failed_count, last_failed_at)
+        VALUES ($1, 1, $2)
+        ON CONFLICT (email) DO UPDATE
+            SET failed_count = CASE
+                    WHEN login_attempts.failed_count >= $3 THEN 1
+                    ELSE login_attempts.failed_count + 1
+                END,
+                last_failed_at = EXCLUDED.last_failed_at
+        RETURNING *
+        "#,
+    )
+    .bind(email)
+    .bind(failed_at)
+    .bind(lockout_threshold)
+    .fetch_one(pool)
+    .await
+    .map_err(Into::into)
+}
+
+/// Forgets the streak for an email, returning whether there was one
+pub async fn clear_login_attempts(pool: &PgPool, email: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM login_attempts WHERE email = $1")
+        .bind(email)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,60 @@
+use super::{models::MagicLink, DbError, Result};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+/// Stores a new sign-in link, replacing any earlier ones for the email
+pub async fn create_magic_link(
+    pool: &PgPool,
+    email: &str,
+    token_hash: &str,
+    browser_hash: &str,
+    expires_at: DateTime<Utc>
+) -> Result<MagicLink> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM magic_links WHERE email = $1")
+        .bind(email)
+        .execute(&mut *tx)
+        .await?;
+
+    let link = sqlx::query_as::<_, MagicLink>(
+        r#"
+        INSERT INTO magic_links (email, token_hash, browser_hash, expires_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *
+        "#,
+    )
+    .bind(email)
+    .bind(token_hash)
+    .bind(browser_hash)
+    .bind(expires_at)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(link)
+}
+
+/// Marks an unused, unexpired link opened in the right browser as used
+/// and returns it
+///
+/// A link presented without its browser secret is left untouched, so an
+/// intercepted link can't be burnt before its owner opens it either.
+pub async fn consume_magic_link(pool: &PgPool, token_hash: &str, browser_hash: &str) -> Result<MagicLink> {
+    sqlx::query_as::<_, MagicLink>(
+        r#"
+        UPDATE magic_links
+        SET used_at = NOW()
+        WHERE token_hash = $1 AND browser_hash = $2 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING *
+        "#,
+    )
+    .bind(token_hash)
+    .bind(browser_hash)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => DbError::NotFound,
+        _ => e.into()
+    })
+}
@@ -0,0 +1,179 @@
+use super::{models::{MfaChallenge, UserTotp}, DbError, Result};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Stores a new, not yet enabled, TOTP secret for a user
+///
+/// Replaces any earlier pending secret; an enabled one is left untouched.
+pub async fn upsert_pending_totp(pool: &PgPool, user_id: Uuid, secret: &str) -> Result<UserTotp> {
+    sqlx::query_as::<_, UserTotp>(
+        r#"
+        INSERT INTO user_totp (user_id, secret)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE
+            SET secret = EXCLUDED.secret, last_used_step = NULL, created_at = NOW()
+            WHERE user_totp.enabled_at IS NULL
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(secret)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        // The WHERE clause filtered out an enabled secret
+        sqlx::Error::RowNotFound => DbError::ConstraintViolation("totp already enabled".into()),
+        _ => e.into()
+    })
+}
+
+/// Gets the TOTP record for a user
+pub async fn get_totp(pool: &PgPool, user_id: Uuid) -> Result<UserTotp> {
+    sqlx::query_as::<_, UserTotp>("SELECT * FROM user_totp WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            _ => e.into()
+        })
+}
+
+/// Turns on 2FA and stores the recovery code digests in one transaction
+pub async fn enable_totp(
+    pool: &PgPool,
+    user_id: Uuid,
+    used_step: i64,
+    recovery_code_hashes: &[String]
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("UPDATE user_totp SET enabled_at = NOW(), last_used_step = $2 WHERE user_id = $1")
+        .bind(user_id)
+        .bind(used_step)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for code_hash in recovery_code_hashes {
+        sqlx::query("INSERT INTO recovery_codes (user_id, code_hash) VALUES ($1, $2)")
+            .bind(user_id)
+            .bind(code_hash)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Records the time step of an accepted code
+pub async fn set_last_used_step(pool: &PgPool, user_id: Uuid, step: i64) -> Result<()> {
+    sqlx::query("UPDATE user_totp SET last_used_step = $2 WHERE user_id = $1")
+        .bind(user_id)
+        .bind(step)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Removes the TOTP secret and every recovery code for a user
+pub async fn delete_totp(pool: &PgPool, user_id: Uuid) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM user_totp WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Marks an unused recovery code as used, returning whether one matched
+pub async fn consume_recovery_code(pool: &PgPool, user_id: Uuid, code_hash: &str) -> Result<bool> {
+    let result = sqlx::query(
+        r#"
+        UPDATE recovery_codes SET used_at = NOW()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .bind(code_hash)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Stores a pending second-factor challenge
+pub async fn create_challenge(
+    pool: &PgPool,
+    user_id: Uuid,
+    token_hash: &str,
+    expires_at: DateTime<Utc>
+) -> Result<MfaChallenge> {
+    sqlx::query_as::<_, MfaChallenge>(
+        r#"
+        INSERT INTO mfa_challenges (user_id, token_hash, expires_at)
+        VALUES ($1, $2, $3)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(token_hash)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await
+    .map_err(Into::into)
+}
+
+/// Records a failed attempt and returns the updated challenge
+pub async fn record_failed_attempt(pool: &PgPool, token_hash: &str) -> Result<MfaChallenge> {
+    sqlx::query_as::<_, MfaChallenge>(
+        r#"
+        UPDATE mfa_challenges SET attempts = attempts + 1
+        WHERE token_hash = $1
+        RETURNING *
+        "#,
+    )
+    .bind(token_hash)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => DbError::NotFound,
+        _ => e.into()
+    })
+}
+
+/// Gets a challenge by the digest of its token
+pub async fn get_challenge(pool: &PgPool, token_hash: &str) -> Result<MfaChallenge> {
+    sqlx::query_as::<_, MfaChallenge>("SELECT * FROM mfa_challenges WHERE token_hash = $1")
+        .bind(token_hash)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            _ => e.into()
+        })
+}
+
+/// Deletes a challenge once it's been completed or abandoned
+pub async fn delete_challenge(pool: &PgPool, token_hash: &str) -> Result<()> {
+    sqlx::query("DELETE FROM mfa_challenges WHERE token_hash = $1")
+        .bind(token_hash)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
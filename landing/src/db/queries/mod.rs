@@ -0,0 +1,19 @@
+//! Raw query operations, grouped by table
+
+use super::{models, DbError, Result};
+
+pub mod api_keys;
+pub mod audit;
+pub mod identities;
+pub mod invitations;
+pub mod lockout;
+pub mod magic_links;
+pub mod mfa;
+pub mod passkeys;
+pub mod password_reset;
+pub mod refresh;
+pub mod roles;
+pub mod session;
+pub mod signing_keys;
+pub mod users;
+pub mod verification;
@@ -0,0 +1,142 @@
+use super::{models::{Passkey, PasskeyChallenge}, DbError, Result};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Stores the challenge of a started passkey ceremony
+pub async fn create_challenge(
+    pool: &PgPool,
+    challenge_hash: &str,
+    ceremony: &str,
+    user_id: Option<Uuid>,
+    expires_at: DateTime<Utc>
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO passkey_challenges (challenge_hash, ceremony, user_id, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(challenge_hash)
+    .bind(ceremony)
+    .bind(user_id)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes and returns a challenge, so each one is answered at most once
+pub async fn take_challenge(pool: &PgPool, challenge_hash: &str, ceremony: &str) -> Result<PasskeyChallenge> {
+    sqlx::query_as::<_, PasskeyChallenge>(
+        "DELETE FROM passkey_challenges WHERE challenge_hash = $1 AND ceremony = $2 RETURNING *",
+    )
+    .bind(challenge_hash)
+    .bind(ceremony)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => DbError::NotFound,
+        _ => e.into()
+    })
+}
+
+/// Drops challenges nobody answered in time
+pub async fn delete_expired_challenges(pool: &PgPool, now: DateTime<Utc>) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM passkey_challenges WHERE expires_at <= $1")
+        .bind(now)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Stores a verified credential
+///
+/// Fails with `ConstraintViolation` if the credential is already registered.
+pub async fn create_passkey(
+    pool: &PgPool,
+    user_id: Uuid,
+    credential_id: &[u8],
+    public_key: &[u8],
+    sign_count: i64,
+    name: &str
+) -> Result<Passkey> {
+    sqlx::query_as::<_, Passkey>(
+        r#"
+        INSERT INTO passkeys (user_id, credential_id, public_key, sign_count, name)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(credential_id)
+    .bind(public_key)
+    .bind(sign_count)
+    .bind(name)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            DbError::ConstraintViolation(db.message().to_string())
+        }
+        _ => e.into()
+    })
+}
+
+/// Gets the credential an authenticator answered with
+pub async fn get_passkey_by_credential_id(pool: &PgPool, credential_id: &[u8]) -> Result<Passkey> {
+    sqlx::query_as::<_, Passkey>("SELECT * FROM passkeys WHERE credential_id = $1")
+        .bind(credential_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            _ => e.into()
+        })
+}
+
+/// Lists a user's passkeys, oldest first
+pub async fn list_passkeys(pool: &PgPool, user_id: Uuid) -> Result<Vec<Passkey>> {
+    let passkeys = sqlx::query_as::<_, Passkey>(
+        "SELECT * FROM passkeys WHERE user_id = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(passkeys)
+}
+
+/// Records a sign-in, advancing the signature counter
+///
+/// Returns false if a concurrent sign-in already moved the counter past
+/// `previous`, which means the same signature counter was used twice.
+pub async fn record_passkey_use(pool: &PgPool, id: Uuid, previous: i64, sign_count: i64) -> Result<bool> {
+    let result = sqlx::query(
+        r#"
+        UPDATE passkeys
+        SET sign_count = $3, last_used_at = NOW()
+        WHERE id = $1 AND sign_count = $2
+        "#,
+    )
+    .bind(id)
+    .bind(previous)
+    .bind(sign_count)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Deletes one of a user's passkeys
+pub async fn delete_passkey(pool: &PgPool, user_id: Uuid, id: Uuid) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM passkeys WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
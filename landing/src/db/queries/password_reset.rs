@@ -0,0 +1,73 @@
+use super::{models::PasswordResetToken, DbError, Result};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Stores a new reset token digest, replacing any earlier ones for the user
+pub async fn create_reset_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    token_hash: &str,
+    expires_at: DateTime<Utc>
+) -> Result<PasswordResetToken> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM password_reset_tokens WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let token = sqlx::query_as::<_, PasswordResetToken>(
+        r#"
+        INSERT INTO password_reset_tokens (user_id, token_hash, expires_at)
+        VALUES ($1, $2, $3)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(token_hash)
+    .bind(expires_at)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(token)
+}
+
+/// Looks up an unused, unexpired token without using it up
+pub async fn get_valid_reset_token(pool: &PgPool, token_hash: &str) -> Result<PasswordResetToken> {
+    sqlx::query_as::<_, PasswordResetToken>(
+        r#"
+        SELECT * FROM password_reset_tokens
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        "#,
+    )
+    .bind(token_hash)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => DbError::NotFound,
+        _ => e.into()
+    })
+}
+
+/// Marks an unused, unexpired token as used and returns it
+///
+/// Doing this in one statement guarantees a token is honored at most once.
+pub async fn consume_reset_token(pool: &PgPool, token_hash: &str) -> Result<PasswordResetToken> {
+    sqlx::query_as::<_, PasswordResetToken>(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = NOW()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING *
+        "#,
+    )
+    .bind(token_hash)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => DbError::NotFound,
+        _ => e.into()
+    })
+}
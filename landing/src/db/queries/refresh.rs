@@ -0,0 +1,92 @@
+use super::{models::RefreshToken, DbError, Result};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Stores the digest of a new refresh token in `family_id`
+pub async fn create_refresh_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    family_id: Uuid,
+    token_hash: &str,
+    expires_at: DateTime<Utc>
+) -> Result<RefreshToken> {
+    sqlx::query_as::<_, RefreshToken>(
+        r#"
+        INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(family_id)
+    .bind(token_hash)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await
+    .map_err(Into::into)
+}
+
+/// Gets a refresh token by its digest, spent or not
+pub async fn get_refresh_token(pool: &PgPool, token_hash: &str) -> Result<RefreshToken> {
+    sqlx::query_as::<_, RefreshToken>("SELECT * FROM refresh_tokens WHERE token_hash = $1")
+        .bind(token_hash)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            _ => e.into()
+        })
+}
+
+/// Spends a refresh token, returning false if it was already spent or revoked
+///
+/// The conditional update makes two concurrent refreshes with the same
+/// token race for a single winner.
+pub async fn mark_refresh_token_used(pool: &PgPool, id: Uuid) -> Result<bool> {
+    let result = sqlx::query(
+        r#"
+        UPDATE refresh_tokens SET used_at = NOW()
+        WHERE id = $1 AND used_at IS NULL AND revoked_at IS NULL
+        "#,
+    )
+    .bind(id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Revokes every refresh token in a family and deletes its access sessions,
+/// returning the ids of the sessions removed
+pub async fn revoke_family(pool: &PgPool, family_id: Uuid) -> Result<Vec<Uuid>> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        "UPDATE refresh_tokens SET revoked_at = NOW() WHERE family_id = $1 AND revoked_at IS NULL",
+    )
+    .bind(family_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let session_ids =
+        sqlx::query_scalar("DELETE FROM user_sessions WHERE family_id = $1 RETURNING id")
+            .bind(family_id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+    tx.commit().await?;
+    Ok(session_ids)
+}
+
+/// Revokes every refresh token a user holds
+pub async fn revoke_refresh_tokens_for_user(pool: &PgPool, user_id: Uuid) -> Result<u64> {
+    let result = sqlx::query(
+        "UPDATE refresh_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+    )
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
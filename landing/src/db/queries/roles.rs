@@ -0,0 +1,94 @@
+use super::{models::DbRole, DbError, Result};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Names of every role granted to a user
+pub async fn get_user_roles(pool: &PgPool, user_id: Uuid) -> Result<Vec<String>> {
+    let names = sqlx::query_scalar(
+        r#"
+        SELECT r.name FROM roles r
+        JOIN user_roles ur ON ur.role_id = r.id
+        WHERE ur.user_id = $1
+        ORDER BY r.name
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(names)
+}
+
+/// Names of every permission a user holds through their roles
+pub async fn get_user_permissions(pool: &PgPool, user_id: Uuid) -> Result<Vec<String>> {
+    let names = sqlx::query_scalar(
+        r#"
+        SELECT DISTINCT p.name FROM permissions p
+        JOIN role_permissions rp ON rp.permission_id = p.id
+        JOIN user_roles ur ON ur.role_id = rp.role_id
+        WHERE ur.user_id = $1
+        ORDER BY p.name
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(names)
+}
+
+/// Gets a role by name
+pub async fn get_role_by_name(pool: &PgPool, name: &str) -> Result<DbRole> {
+    sqlx::query_as::<_, DbRole>("SELECT * FROM roles WHERE name = $1")
+        .bind(name)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            _ => e.into()
+        })
+}
+
+/// Grants a role to a user, doing nothing if it's already granted
+///
+/// An unknown role is `DbError::NotFound`, an unknown user a
+/// `DbError::ConstraintViolation`.
+pub async fn assign_role(pool: &PgPool, user_id: Uuid, role: &str) -> Result<()> {
+    let role = get_role_by_name(pool, role).await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO user_roles (user_id, role_id)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(user_id)
+    .bind(role.id)
+    .execute(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => {
+            DbError::ConstraintViolation(db.message().to_string())
+        }
+        _ => e.into()
+    })?;
+
+    Ok(())
+}
+
+/// Removes a role from a user, returning whether it had been granted
+pub async fn revoke_role(pool: &PgPool, user_id: Uuid, role: &str) -> Result<bool> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM user_roles
+        WHERE user_id = $1 AND role_id = (SELECT id FROM roles WHERE name = $2)
+        "#,
+    )
+    .bind(user_id)
+    .bind(role)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
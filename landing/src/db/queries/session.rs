@@ -1,24 +1,210 @@
-use super::{models::UserSession, Result};
+use super::{models::UserSession, DbError, Result};
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
+use uuid::Uuid;
 
 pub async fn create_session(
     pool: &PgPool,
     user_id: Uuid,
-    token: &str,
-    expires_at: DateTime<Utc>
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+    family_id: Option<Uuid>,
+    device_label: Option<&str>,
+    user_agent: Option<&str>,
+    ip: Option<&str>
 ) -> Result<UserSession> {
-    sqlx::query_as!(
-        UserSession,
+    sqlx::query_as::<_, UserSession>(
         r#"
-        INSERT INTO user_sessions (user_id, token, expires_at)
-        VALUES ($1, $2, $3)
+        INSERT INTO user_sessions (user_id, token_hash, expires_at, family_id, device_label, user_agent, ip)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
         RETURNING *
         "#,
-        user_id,
-        token,
-        expires_at
     )
+    .bind(user_id)
+    .bind(token_hash)
+    .bind(expires_at)
+    .bind(family_id)
+    .bind(device_label)
+    .bind(user_agent)
+    .bind(ip)
     .fetch_one(pool)
     .await
     .map_err(Into::into)
-}
\ No newline at end of file
+}
+
+/// Creates a session for `impersonator_id` to act as `user_id`, outside any
+/// refresh token family
+pub async fn create_impersonation_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    impersonator_id: Uuid,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+    device_label: Option<&str>,
+    user_agent: Option<&str>,
+    ip: Option<&str>
+) -> Result<UserSession> {
+    sqlx::query_as::<_, UserSession>(
+        r#"
+        INSERT INTO user_sessions (user_id, impersonator_id, token_hash, expires_at, device_label, user_agent, ip)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(impersonator_id)
+    .bind(token_hash)
+    .bind(expires_at)
+    .bind(device_label)
+    .bind(user_agent)
+    .bind(ip)
+    .fetch_one(pool)
+    .await
+    .map_err(Into::into)
+}
+
+/// Looks up a session by the keyed hash of its token, regardless of expiry
+pub async fn get_session(pool: &PgPool, token_hash: &str) -> Result<UserSession> {
+    sqlx::query_as::<_, UserSession>("SELECT * FROM user_sessions WHERE token_hash = $1")
+        .bind(token_hash)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            _ => e.into()
+        })
+}
+
+/// Gets one of a user's sessions by id
+pub async fn get_user_session(pool: &PgPool, user_id: Uuid, id: Uuid) -> Result<UserSession> {
+    sqlx::query_as::<_, UserSession>("SELECT * FROM user_sessions WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            _ => e.into()
+        })
+}
+
+/// Lists a user's signed-in devices, most recently seen first
+///
+/// A session whose access token lapsed still counts while its refresh
+/// token family can renew it.
+pub async fn list_active_sessions(pool: &PgPool, user_id: Uuid) -> Result<Vec<UserSession>> {
+    sqlx::query_as::<_, UserSession>(
+        r#"
+        SELECT s.* FROM user_sessions s
+        WHERE s.user_id = $1
+          AND (
+            s.expires_at > NOW()
+            OR EXISTS (
+                SELECT 1 FROM refresh_tokens r
+                WHERE r.family_id = s.family_id
+                  AND r.used_at IS NULL
+                  AND r.revoked_at IS NULL
+                  AND r.expires_at > NOW()
+            )
+          )
+        ORDER BY s.last_seen_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(Into::into)
+}
+
+/// Moves a family's session onto a new access token, keeping its id and
+/// device details; returns `None` if the family has no session row
+pub async fn rotate_family_session(
+    pool: &PgPool,
+    family_id: Uuid,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+    ip: Option<&str>
+) -> Result<Option<UserSession>> {
+    sqlx::query_as::<_, UserSession>(
+        r#"
+        UPDATE user_sessions
+        SET token_hash = $2, expires_at = $3, ip = COALESCE($4, ip), last_seen_at = NOW()
+        WHERE family_id = $1
+        RETURNING *
+        "#,
+    )
+    .bind(family_id)
+    .bind(token_hash)
+    .bind(expires_at)
+    .bind(ip)
+    .fetch_optional(pool)
+    .await
+    .map_err(Into::into)
+}
+
+/// Records activity on a session, at most once a minute
+pub async fn touch_session(pool: &PgPool, id: Uuid, ip: Option<&str>) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE user_sessions SET last_seen_at = NOW(), ip = COALESCE($2, ip)
+        WHERE id = $1 AND last_seen_at < NOW() - INTERVAL '1 minute'
+        "#,
+    )
+    .bind(id)
+    .bind(ip)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Ends every session of a user except the one whose token hashes to
+/// `keep_token_hash`, revoking their refresh tokens too; returns how many
+/// sessions ended
+pub async fn revoke_other_sessions(pool: &PgPool, user_id: Uuid, keep_token_hash: &str) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        UPDATE refresh_tokens SET revoked_at = NOW()
+        WHERE user_id = $1
+          AND revoked_at IS NULL
+          AND family_id IS DISTINCT FROM (SELECT family_id FROM user_sessions WHERE token_hash = $2)
+        "#,
+    )
+    .bind(user_id)
+    .bind(keep_token_hash)
+    .execute(&mut *tx)
+    .await?;
+
+    let result = sqlx::query("DELETE FROM user_sessions WHERE user_id = $1 AND token_hash <> $2")
+        .bind(user_id)
+        .bind(keep_token_hash)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+/// Deletes a single session, returning whether a row was removed
+pub async fn delete_session(pool: &PgPool, token_hash: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM user_sessions WHERE token_hash = $1")
+        .bind(token_hash)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Deletes every session belonging to a user, including those they opened
+/// to impersonate someone else, returning the ids of those removed
+pub async fn delete_sessions_for_user(pool: &PgPool, user_id: Uuid) -> Result<Vec<Uuid>> {
+    sqlx::query_scalar(
+        "DELETE FROM user_sessions WHERE user_id = $1 OR impersonator_id = $1 RETURNING id",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(Into::into)
+}
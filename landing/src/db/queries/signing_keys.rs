@@ -0,0 +1,90 @@
+use super::{models::SigningKey, DbError, Result};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Stores a new signing key, which signs from now on
+pub async fn create_signing_key(pool: &PgPool, kid: &str, key_seed: &str) -> Result<SigningKey> {
+    sqlx::query_as::<_, SigningKey>(
+        r#"
+        INSERT INTO signing_keys (kid, key_seed)
+        VALUES ($1, $2)
+        RETURNING *
+        "#,
+    )
+    .bind(kid)
+    .bind(key_seed)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            DbError::ConstraintViolation("signing key id already exists".into())
+        }
+        _ => e.into()
+    })
+}
+
+/// Schedules every signing key except `keep_kid` to stop verifying at `retire_at`
+pub async fn retire_signing_keys(pool: &PgPool, keep_kid: &str, retire_at: DateTime<Utc>) -> Result<u64> {
+    let result =
+        sqlx::query("UPDATE signing_keys SET retire_at = $2 WHERE retire_at IS NULL AND kid <> $1")
+            .bind(keep_kid)
+            .bind(retire_at)
+            .execute(pool)
+            .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Lists the keys that still verify, newest first
+pub async fn list_signing_keys(pool: &PgPool) -> Result<Vec<SigningKey>> {
+    let keys = sqlx::query_as::<_, SigningKey>(
+        r#"
+        SELECT * FROM signing_keys
+        WHERE retire_at IS NULL OR retire_at > NOW()
+        ORDER BY created_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(keys)
+}
+
+/// Refuses signed tokens naming `session_id` until `expires_at`
+pub async fn revoke_token_session(pool: &PgPool, session_id: Uuid, expires_at: DateTime<Utc>) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO revoked_token_sessions (session_id, expires_at)
+        VALUES ($1, $2)
+        ON CONFLICT (session_id) DO UPDATE
+        SET expires_at = GREATEST(revoked_token_sessions.expires_at, EXCLUDED.expires_at)
+        "#,
+    )
+    .bind(session_id)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Sessions whose signed tokens are still refused
+pub async fn list_revoked_token_sessions(pool: &PgPool) -> Result<Vec<Uuid>> {
+    sqlx::query_scalar("SELECT session_id FROM revoked_token_sessions WHERE expires_at > NOW()")
+        .fetch_all(pool)
+        .await
+        .map_err(Into::into)
+}
+
+/// Drops revocations and keys that no token can need any more
+pub async fn delete_expired_signing_state(pool: &PgPool) -> Result<u64> {
+    let revocations = sqlx::query("DELETE FROM revoked_token_sessions WHERE expires_at <= NOW()")
+        .execute(pool)
+        .await?;
+    let keys = sqlx::query("DELETE FROM signing_keys WHERE retire_at <= NOW()")
+        .execute(pool)
+        .await?;
+
+    Ok(revocations.rows_affected() + keys.rows_affected())
+}
@@ -1,38 +1,106 @@
-use super::{models::DbUser, Result};
-use sqlx::{PgPool, postgres::PgQueryResult};
+use super::{models::DbUser, DbError, Result};
+use sqlx::PgPool;
+use uuid::Uuid;
 
 /// User-related queries
 pub async fn create_user(
     pool: &PgPool,
     email: &str,
+    email_canonical: &str,
     password_hash: &str
 ) -> Result<DbUser> {
-    sqlx::query_as!(
-        DbUser,
+    sqlx::query_as::<_, DbUser>(
         r#"
-        INSERT INTO users (email, password_hash)
-        VALUES ($1, $2)
+        INSERT INTO users (email, email_canonical, password_hash)
+        VALUES ($1, $2, $3)
         RETURNING *
         "#,
-        email,
-        password_hash
-    )
-    .fetch_one(pool)
-    .await
-    .map_err(Into::into)
-}
-
-/// Gets user by email with error handling
-pub async fn get_user_by_email(pool: &PgPool, email: &str) -> Result<DbUser> {
-    sqlx::query_as!(
-        DbUser,
-        "SELECT * FROM users WHERE email = $1",
-        email
     )
+    .bind(email)
+    .bind(email_canonical)
+    .bind(password_hash)
     .fetch_one(pool)
     .await
     .map_err(|e| match e {
-        sqlx::Error::RowNotFound => DbError::NotFound,
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            DbError::ConstraintViolation(db.message().to_string())
+        }
         _ => e.into()
     })
-}
\ No newline at end of file
+}
+
+/// Gets user by the canonical form of their email
+pub async fn get_user_by_email(pool: &PgPool, email_canonical: &str) -> Result<DbUser> {
+    sqlx::query_as::<_, DbUser>("SELECT * FROM users WHERE email_canonical = $1")
+        .bind(email_canonical)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            _ => e.into()
+        })
+}
+
+/// Gets user by primary key
+pub async fn get_user_by_id(pool: &PgPool, id: Uuid) -> Result<DbUser> {
+    sqlx::query_as::<_, DbUser>("SELECT * FROM users WHERE id = $1")
+        .bind(id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            _ => e.into()
+        })
+}
+
+/// Marks the user's email address as confirmed
+pub async fn mark_email_verified(pool: &PgPool, id: Uuid) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE users
+        SET email_verified_at = COALESCE(email_verified_at, NOW()), updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Replaces the stored password hash
+pub async fn update_password_hash(pool: &PgPool, id: Uuid, password_hash: &str) -> Result<()> {
+    sqlx::query("UPDATE users SET password_hash = $2, updated_at = NOW() WHERE id = $1")
+        .bind(id)
+        .bind(password_hash)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Lists every account, oldest first
+pub async fn list_users(pool: &PgPool) -> Result<Vec<DbUser>> {
+    sqlx::query_as::<_, DbUser>("SELECT * FROM users ORDER BY created_at")
+        .fetch_all(pool)
+        .await
+        .map_err(Into::into)
+}
+
+/// Replaces the canonical form of a user's email
+pub async fn update_email_canonical(pool: &PgPool, id: Uuid, email_canonical: &str) -> Result<()> {
+    sqlx::query("UPDATE users SET email_canonical = $2, updated_at = NOW() WHERE id = $1")
+        .bind(id)
+        .bind(email_canonical)
+        .execute(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+                DbError::ConstraintViolation(db.message().to_string())
+            }
+            _ => e.into()
+        })?;
+
+    Ok(())
+}
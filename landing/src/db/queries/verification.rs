@@ -0,0 +1,70 @@
+use super::{models::EmailVerificationToken, DbError, Result};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Stores the digest of a new verification token for a user
+pub async fn create_verification_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    token_hash: &str,
+    expires_at: DateTime<Utc>
+) -> Result<EmailVerificationToken> {
+    sqlx::query_as::<_, EmailVerificationToken>(
+        r#"
+        INSERT INTO email_verification_tokens (user_id, token_hash, expires_at)
+        VALUES ($1, $2, $3)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(token_hash)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await
+    .map_err(Into::into)
+}
+
+/// Deletes and returns a token so it can only ever be used once
+pub async fn take_verification_token(pool: &PgPool, token_hash: &str) -> Result<EmailVerificationToken> {
+    sqlx::query_as::<_, EmailVerificationToken>(
+        "DELETE FROM email_verification_tokens WHERE token_hash = $1 RETURNING *",
+    )
+    .bind(token_hash)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => DbError::NotFound,
+        _ => e.into()
+    })
+}
+
+/// Removes every outstanding token for a user
+pub async fn delete_verification_tokens_for_user(pool: &PgPool, user_id: Uuid) -> Result<()> {
+    sqlx::query("DELETE FROM email_verification_tokens WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Tokens issued to a user since `since`, newest first
+pub async fn recent_verification_tokens(
+    pool: &PgPool,
+    user_id: Uuid,
+    since: DateTime<Utc>
+) -> Result<Vec<EmailVerificationToken>> {
+    sqlx::query_as::<_, EmailVerificationToken>(
+        r#"
+        SELECT * FROM email_verification_tokens
+        WHERE user_id = $1 AND created_at > $2
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await
+    .map_err(Into::into)
+}
@@ -0,0 +1,633 @@
+//! HTTP endpoints for the account lifecycle
+//!
+//! Mounted by the server binary with `.merge(api::auth::router())`; every
+//! handler expects an `Extension<Arc<AuthContext>>` layer. The session,
+//! passkey and API key management and account linking routes run behind
+//! `auth_middleware` and are closed to API keys by `require_session`;
+//! starting an impersonation also needs the admin role.
+//! The OpenID Connect routes answer 404 unless `AuthContext::with_oidc`
+//! was called.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use uuid::Uuid;
+
+use crate::server::auth::{auth_middleware, cookies, require_role, require_session};
+use crate::server::auth::context::OidcOutcome;
+use crate::server::auth::oidc::AuthorizationRequest;
+use crate::server::{
+    ApiKeyInfo, AuthContext, AuthError, NewApiKey, PasskeyAssertion, PasskeyCreationOptions, PasskeyCredential,
+    PasskeyInfo, PasskeyRequestOptions, SessionConfig, SessionInfo, SessionMode, User, ADMIN_ROLE,
+};
+
+/// `invite_code` is only needed when `AuthConfig::invite_only` is set
+#[derive(Debug, Deserialize)]
+pub struct RegisterRequest {
+    pub email: String,
+    pub password: String,
+    #[serde(default)]
+    pub invite_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MfaRequest {
+    pub challenge: String,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MagicLinkRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MagicLinkLoginRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// In cookie mode the token comes from the refresh cookie and the body is `{}`
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+/// A new token pair; after a refresh the old refresh token is spent
+///
+/// In `SessionMode::Cookie` the tokens are only sent as HttpOnly cookies
+/// and left out of the body.
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Password accepted, post the challenge and a code to `/api/auth/mfa`
+#[derive(Debug, Serialize)]
+pub struct MfaChallengeResponse {
+    pub mfa_required: bool,
+    pub challenge: String,
+}
+
+/// Query string the identity provider redirects back with
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// Set instead of `code` when the provider refused the login
+    pub error: Option<String>,
+}
+
+/// Where to send the browser to link an external account
+#[derive(Debug, Serialize)]
+pub struct OidcLinkResponse {
+    pub url: String,
+}
+
+/// A new passkey from `navigator.credentials.create()` and what to call it
+#[derive(Debug, Deserialize)]
+pub struct PasskeyRegistrationRequest {
+    #[serde(default)]
+    pub name: String,
+    pub credential: PasskeyCredential,
+}
+
+/// A new API key's name, scopes and optional expiry
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyRequest {
+    #[serde(default)]
+    pub name: String,
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Sessions ended by `DELETE /api/auth/sessions`
+#[derive(Debug, Serialize)]
+pub struct RevokedSessionsResponse {
+    pub revoked: u64,
+}
+
+/// Routes for account management
+pub fn router() -> Router {
+    let sessions = Router::new()
+        .route("/api/auth/sessions", get(list_sessions).delete(revoke_other_sessions))
+        .route("/api/auth/sessions/{id}", delete(revoke_session))
+        .route("/api/auth/password", post(change_password))
+        .route("/api/auth/oidc/link", post(oidc_link))
+        .route("/api/auth/passkeys", get(list_passkeys))
+        .route("/api/auth/passkeys/{id}", delete(delete_passkey))
+        .route("/api/auth/passkeys/register/begin", post(begin_passkey_registration))
+        .route("/api/auth/passkeys/register/finish", post(finish_passkey_registration))
+        .route("/api/auth/api-keys", get(list_api_keys).post(create_api_key))
+        .route("/api/auth/api-keys/{id}", delete(revoke_api_key))
+        .route("/api/auth/impersonation/stop", post(stop_impersonating))
+        .route_layer(middleware::from_fn(require_session))
+        .route_layer(middleware::from_fn(auth_middleware));
+
+    let admin = Router::new()
+        .route("/api/auth/impersonate/{id}", post(impersonate))
+        .route_layer(middleware::from_fn_with_state(ADMIN_ROLE, require_role))
+        .route_layer(middleware::from_fn(require_session))
+        .route_layer(middleware::from_fn(auth_middleware));
+
+    Router::new()
+        .route("/api/auth/register", post(register))
+        .route("/api/auth/login", post(login))
+        .route("/api/auth/mfa", post(mfa))
+        .route("/api/auth/logout", post(logout))
+        .route("/api/auth/verify-email", post(verify_email))
+        .route("/api/auth/resend-verification", post(resend_verification))
+        .route("/api/auth/forgot-password", post(forgot_password))
+        .route("/api/auth/reset-password", post(reset_password))
+        .route("/api/auth/refresh", post(refresh))
+        .route("/api/auth/magic-link", post(magic_link))
+        .route("/api/auth/magic-link/login", post(magic_link_login))
+        .route("/api/auth/passkeys/login/begin", post(begin_passkey_login))
+        .route("/api/auth/passkeys/login/finish", post(finish_passkey_login))
+        .route("/api/auth/oidc/login", get(oidc_login))
+        .route("/api/auth/oidc/callback", get(oidc_callback))
+        .merge(sessions)
+        .merge(admin)
+}
+
+/// Answers with the new tokens, as cookies and/or in the body per `SessionMode`
+fn session_response(auth: &AuthContext, user: User) -> Response {
+    let config = auth.session_config();
+    let mut headers = HeaderMap::new();
+    if config.mode.accepts_cookie() {
+        cookies::set_cookies(&mut headers, cookies::session_cookies(&user, config));
+    }
+
+    let body = if config.mode == SessionMode::Cookie {
+        TokenResponse { access_token: None, refresh_token: None, expires_at: user.session_expires_at }
+    } else {
+        TokenResponse {
+            access_token: Some(user.bearer_token),
+            refresh_token: user.refresh_token,
+            expires_at: user.session_expires_at,
+        }
+    };
+    (headers, Json(body)).into_response()
+}
+
+/// Creates an account and mails its verification link. Policy failures
+/// answer 400 with the violated rules, a missing invitation 403 and an
+/// unknown or used-up one 400.
+async fn register(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Json(payload): Json<RegisterRequest>,
+) -> Result<StatusCode, AuthError> {
+    auth.register(&payload.email, &payload.password, payload.invite_code.as_deref()).await?;
+    Ok(StatusCode::CREATED)
+}
+
+/// Checks a password, answering 202 with a challenge when a second factor is needed
+async fn login(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Json(payload): Json<LoginRequest>,
+) -> Result<Response, AuthError> {
+    match auth.sign_in(&payload.email, &payload.password).await {
+        Ok(user) => Ok(session_response(&auth, user)),
+        Err(AuthError::MfaRequired { challenge }) => Ok((
+            StatusCode::ACCEPTED,
+            Json(MfaChallengeResponse { mfa_required: true, challenge }),
+        )
+            .into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Finishes a login with a TOTP or recovery code
+async fn mfa(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Json(payload): Json<MfaRequest>,
+) -> Result<Response, AuthError> {
+    let user = auth.verify_mfa(&payload.challenge, &payload.code).await?;
+    Ok(session_response(&auth, user))
+}
+
+/// The access token a request carries, from whichever of the
+/// `Authorization` header and session cookie the session mode accepts
+fn presented_access_token<'a>(headers: &'a HeaderMap, config: &SessionConfig) -> Option<&'a str> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .filter(|_| config.mode.accepts_bearer());
+    let cookie = cookies::read_cookie(headers, cookies::SESSION_COOKIE)
+        .filter(|_| config.mode.accepts_cookie());
+    bearer.or(cookie)
+}
+
+/// Ends the session and clears any session cookies
+async fn logout(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    headers: HeaderMap,
+) -> Result<Response, AuthError> {
+    let config = auth.session_config();
+    if let Some(token) = presented_access_token(&headers, config) {
+        auth.sign_out(token).await?;
+    }
+
+    let mut response_headers = HeaderMap::new();
+    if config.mode.accepts_cookie() {
+        cookies::set_cookies(&mut response_headers, cookies::clear_session_cookies(config));
+    }
+    Ok((StatusCode::NO_CONTENT, response_headers).into_response())
+}
+
+/// Consumes a verification token
+async fn verify_email(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Json(payload): Json<VerifyEmailRequest>,
+) -> Result<StatusCode, AuthError> {
+    auth.verify_email(&payload.token).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sends a new verification link, always answering 202 unless rate limited
+async fn resend_verification(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Json(payload): Json<ResendVerificationRequest>,
+) -> Result<StatusCode, AuthError> {
+    auth.resend_verification(&payload.email).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Emails a reset link, answering 202 whether or not the account exists
+async fn forgot_password(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Json(payload): Json<ForgotPasswordRequest>,
+) -> Result<StatusCode, AuthError> {
+    auth.request_password_reset(&payload.email).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Emails a sign-in link, answering 202 with a cookie that binds the link
+/// to this browser
+async fn magic_link(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Json(payload): Json<MagicLinkRequest>,
+) -> Result<Response, AuthError> {
+    let secret = auth.send_magic_link(&payload.email).await?;
+    let config = auth.session_config();
+    let mut headers = HeaderMap::new();
+    let cookie = cookies::magic_link_cookie(&secret, config.magic_link_cookie_max_age, config);
+    cookies::set_cookies(&mut headers, cookie.into_iter().collect());
+    Ok((StatusCode::ACCEPTED, headers).into_response())
+}
+
+/// Signs in with the token from a magic link, opened in the browser that
+/// asked for it. Answers as `login` does.
+async fn magic_link_login(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    headers: HeaderMap,
+    Json(payload): Json<MagicLinkLoginRequest>,
+) -> Result<Response, AuthError> {
+    let secret = cookies::magic_link_secret(&headers)?;
+    let mut response = match auth.sign_in_with_magic_link(&payload.token, secret).await {
+        Ok(user) => session_response(&auth, user),
+        Err(AuthError::MfaRequired { challenge }) => (
+            StatusCode::ACCEPTED,
+            Json(MfaChallengeResponse { mfa_required: true, challenge }),
+        )
+            .into_response(),
+        Err(e) => return Err(e),
+    };
+    // The link is spent, its secret is no use anymore
+    let clear = cookies::clear_magic_link_cookie(auth.session_config());
+    cookies::set_cookies(response.headers_mut(), clear.into_iter().collect());
+    Ok(response)
+}
+
+/// Consumes a reset token and sets the new password
+async fn reset_password(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Json(payload): Json<ResetPasswordRequest>,
+) -> Result<StatusCode, AuthError> {
+    auth.reset_password(&payload.token, &payload.new_password).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Changes the caller's password, signing out their other devices
+async fn change_password(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(user): Extension<User>,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> Result<StatusCode, AuthError> {
+    auth.change_password_for(&user, &payload.current_password, &payload.new_password)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Rotates a refresh token into a new access and refresh token pair
+///
+/// Takes the token from the body, or from the refresh cookie in cookie mode.
+async fn refresh(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    headers: HeaderMap,
+    Json(payload): Json<RefreshRequest>,
+) -> Result<Response, AuthError> {
+    let cookie = cookies::read_cookie(&headers, cookies::REFRESH_COOKIE)
+        .filter(|_| auth.session_config().mode.accepts_cookie())
+        .map(str::to_owned);
+    let refresh_token = payload.refresh_token.or(cookie).ok_or(AuthError::InvalidToken)?;
+
+    let replaced = presented_access_token(&headers, auth.session_config());
+    let user = auth.exchange_refresh_token(&refresh_token, replaced).await?;
+    Ok(session_response(&auth, user))
+}
+
+/// Lists the caller's signed-in devices
+async fn list_sessions(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<SessionInfo>>, AuthError> {
+    Ok(Json(auth.sessions_for(&user).await?))
+}
+
+/// Signs the caller out on one device, 404 if it isn't one of theirs
+async fn revoke_session(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AuthError> {
+    if auth.revoke_session_for(&user, id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Signs the caller out everywhere except the device making the request
+async fn revoke_other_sessions(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(user): Extension<User>,
+) -> Result<Json<RevokedSessionsResponse>, AuthError> {
+    let revoked = auth.revoke_other_sessions_for(&user).await?;
+    Ok(Json(RevokedSessionsResponse { revoked }))
+}
+
+/// Challenge for signing in with any passkey for this site
+async fn begin_passkey_login(
+    Extension(auth): Extension<Arc<AuthContext>>,
+) -> Result<Json<PasskeyRequestOptions>, AuthError> {
+    Ok(Json(auth.begin_passkey_login().await?))
+}
+
+/// Checks the signed challenge and answers with tokens as for a password login
+async fn finish_passkey_login(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Json(assertion): Json<PasskeyAssertion>,
+) -> Result<Response, AuthError> {
+    let user = auth.sign_in_with_passkey(&assertion).await?;
+    Ok(session_response(&auth, user))
+}
+
+/// Lists the caller's passkeys
+async fn list_passkeys(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<PasskeyInfo>>, AuthError> {
+    Ok(Json(auth.passkeys_for(&user).await?))
+}
+
+/// Options for creating a passkey for the caller
+async fn begin_passkey_registration(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(user): Extension<User>,
+) -> Result<Json<PasskeyCreationOptions>, AuthError> {
+    Ok(Json(auth.begin_passkey_registration_for(&user).await?))
+}
+
+/// Stores the passkey the browser created, answering 201
+async fn finish_passkey_registration(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(user): Extension<User>,
+    Json(payload): Json<PasskeyRegistrationRequest>,
+) -> Result<(StatusCode, Json<PasskeyInfo>), AuthError> {
+    let passkey = auth
+        .finish_passkey_registration_for(&user, &payload.name, &payload.credential)
+        .await?;
+    Ok((StatusCode::CREATED, Json(passkey)))
+}
+
+/// Removes one of the caller's passkeys, 404 if it isn't theirs
+async fn delete_passkey(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AuthError> {
+    if auth.delete_passkey_for(&user, id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Lists the caller's API keys, without the keys themselves
+async fn list_api_keys(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<ApiKeyInfo>>, AuthError> {
+    Ok(Json(auth.api_keys_for(&user).await?))
+}
+
+/// Creates an API key, answering 201 with the key; it isn't shown again
+async fn create_api_key(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(user): Extension<User>,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> Result<(StatusCode, Json<NewApiKey>), AuthError> {
+    let created = auth
+        .create_api_key_for(&user, &payload.name, &payload.scopes, payload.expires_at)
+        .await?;
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+/// Revokes one of the caller's API keys, 404 if it isn't theirs
+async fn revoke_api_key(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AuthError> {
+    if auth.revoke_api_key_for(&user, id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Starts acting as another user, answering with the impersonation
+/// session's tokens as a login does
+///
+/// The session has no refresh token, so in cookie mode the admin's own
+/// refresh cookie stays in place for `stop_impersonating`.
+async fn impersonate(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(admin): Extension<User>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, AuthError> {
+    let user = auth.impersonate_for(&admin, id).await?;
+    Ok(session_response(&auth, user))
+}
+
+/// Ends the caller's impersonation session
+///
+/// In cookie mode the admin's refresh cookie is exchanged to give them
+/// their own session back; otherwise, or if that fails, answers 204 with
+/// the session cookies cleared.
+async fn stop_impersonating(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(user): Extension<User>,
+    headers: HeaderMap,
+) -> Result<Response, AuthError> {
+    auth.stop_impersonating_for(&user).await?;
+
+    let config = auth.session_config();
+    if !config.mode.accepts_cookie() {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+    if let Some(refresh_token) = cookies::read_cookie(&headers, cookies::REFRESH_COOKIE) {
+        if let Ok(admin) = auth.exchange_refresh_token(refresh_token, None).await {
+            return Ok(session_response(&auth, admin));
+        }
+    }
+    let mut response_headers = HeaderMap::new();
+    cookies::set_cookies(&mut response_headers, cookies::clear_session_cookies(config));
+    Ok((StatusCode::NO_CONTENT, response_headers).into_response())
+}
+
+/// Headers binding a started OIDC login to this browser
+fn oidc_state_headers(auth: &AuthContext, request: &AuthorizationRequest) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(oidc) = auth.oidc() {
+        let cookie = cookies::oidc_state_cookie(&request.state, oidc.config().login_ttl, auth.session_config());
+        cookies::set_cookies(&mut headers, cookie.into_iter().collect());
+    }
+    headers
+}
+
+/// Redirects the browser to the identity provider
+async fn oidc_login(Extension(auth): Extension<Arc<AuthContext>>) -> Result<Response, AuthError> {
+    if auth.oidc().is_none() {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    let request = auth.begin_oidc_login(None)?;
+    Ok((oidc_state_headers(&auth, &request), Redirect::to(&request.url)).into_response())
+}
+
+/// Starts linking an external account to the caller, answering with the
+/// URL to send the browser to
+async fn oidc_link(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    Extension(user): Extension<User>,
+) -> Result<Response, AuthError> {
+    if auth.oidc().is_none() {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    let request = auth.begin_oidc_login(Some(&user))?;
+    let headers = oidc_state_headers(&auth, &request);
+    Ok((headers, Json(OidcLinkResponse { url: request.url })).into_response())
+}
+
+/// Finishes a login or link when the identity provider redirects back
+///
+/// In cookie mode the browser lands on the home page signed in; otherwise
+/// the tokens come back as for a password login. Accounts with two-factor
+/// get 202 with a challenge for `/api/auth/mfa`.
+async fn oidc_callback(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    headers: HeaderMap,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<Response, AuthError> {
+    if auth.oidc().is_none() {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    if let Some(error) = query.error {
+        return Err(AuthError::IdentityProvider(error));
+    }
+    let (Some(code), Some(state)) = (query.code, query.state) else {
+        return Err(AuthError::InvalidToken);
+    };
+    cookies::check_oidc_state(&headers, &state)?;
+
+    let config = auth.session_config();
+    let mut response_headers = HeaderMap::new();
+    cookies::set_cookies(&mut response_headers, cookies::clear_oidc_state_cookie(config).into_iter().collect());
+
+    match auth.complete_oidc_login(&code, &state).await {
+        Ok(OidcOutcome::SignedIn(user)) if config.mode == SessionMode::Cookie => {
+            cookies::set_cookies(&mut response_headers, cookies::session_cookies(&user, config));
+            Ok((response_headers, Redirect::to("/")).into_response())
+        }
+        Ok(OidcOutcome::SignedIn(user)) => {
+            // Appended rather than merged as a tuple, which would replace
+            // the session cookies' `Set-Cookie` headers
+            let mut response = session_response(&auth, user);
+            for cookie in response_headers.get_all(header::SET_COOKIE) {
+                response.headers_mut().append(header::SET_COOKIE, cookie.clone());
+            }
+            Ok(response)
+        }
+        Ok(OidcOutcome::Linked(_)) if config.mode == SessionMode::Cookie => {
+            Ok((response_headers, Redirect::to("/")).into_response())
+        }
+        Ok(OidcOutcome::Linked(_)) => Ok((StatusCode::NO_CONTENT, response_headers).into_response()),
+        Err(AuthError::MfaRequired { challenge }) => Ok((
+            StatusCode::ACCEPTED,
+            response_headers,
+            Json(MfaChallengeResponse { mfa_required: true, challenge }),
+        )
+            .into_response()),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,378 @@
+//! Audit log for security-relevant events
+//!
+//! `AuthContext` reports events through an `AuditRecorder`. The client IP
+//! and user agent aren't part of the `AuthContext` API, so the
+//! `capture_request_meta` middleware stashes them in a task-local for the
+//! duration of each request and the recorder picks them up from there.
+//! `auth_middleware` adds the impersonating admin, if any, so every event
+//! raised during an impersonated request names them.
+
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::db::{queries, AuditEventRecord, DbPool};
+use crate::server::error::AuthError;
+
+/// Kinds of events the audit log tracks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventType {
+    LoginSuccess,
+    LoginFailure,
+    Register,
+    Logout,
+    PasswordResetRequested,
+    PasswordChange,
+    RoleChange,
+    SessionRevoked,
+    MfaEnabled,
+    MfaDisabled,
+    AccountUnlocked,
+    IdentityLinked,
+    PasskeyAdded,
+    PasskeyRemoved,
+    MagicLinkRequested,
+    ApiKeyCreated,
+    ApiKeyRevoked,
+    SigningKeyRotated,
+    ImpersonationStarted,
+    ImpersonationEnded,
+    ImpersonatedRequest,
+    InvitationCreated,
+    InvitationRevoked,
+}
+
+impl AuditEventType {
+    pub const ALL: &'static [AuditEventType] = &[
+        AuditEventType::LoginSuccess,
+        AuditEventType::LoginFailure,
+        AuditEventType::Register,
+        AuditEventType::Logout,
+        AuditEventType::PasswordResetRequested,
+        AuditEventType::PasswordChange,
+        AuditEventType::RoleChange,
+        AuditEventType::SessionRevoked,
+        AuditEventType::MfaEnabled,
+        AuditEventType::MfaDisabled,
+        AuditEventType::AccountUnlocked,
+        AuditEventType::IdentityLinked,
+        AuditEventType::PasskeyAdded,
+        AuditEventType::PasskeyRemoved,
+        AuditEventType::MagicLinkRequested,
+        AuditEventType::ApiKeyCreated,
+        AuditEventType::ApiKeyRevoked,
+        AuditEventType::SigningKeyRotated,
+        AuditEventType::ImpersonationStarted,
+        AuditEventType::ImpersonationEnded,
+        AuditEventType::ImpersonatedRequest,
+        AuditEventType::InvitationCreated,
+        AuditEventType::InvitationRevoked,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventType::LoginSuccess => "login_success",
+            AuditEventType::LoginFailure => "login_failure",
+            AuditEventType::Register => "register",
+            AuditEventType::Logout => "logout",
+            AuditEventType::PasswordResetRequested => "password_reset_requested",
+            AuditEventType::PasswordChange => "password_change",
+            AuditEventType::RoleChange => "role_change",
+            AuditEventType::SessionRevoked => "session_revoked",
+            AuditEventType::MfaEnabled => "mfa_enabled",
+            AuditEventType::MfaDisabled => "mfa_disabled",
+            AuditEventType::AccountUnlocked => "account_unlocked",
+            AuditEventType::IdentityLinked => "identity_linked",
+            AuditEventType::PasskeyAdded => "passkey_added",
+            AuditEventType::PasskeyRemoved => "passkey_removed",
+            AuditEventType::MagicLinkRequested => "magic_link_requested",
+            AuditEventType::ApiKeyCreated => "api_key_created",
+            AuditEventType::ApiKeyRevoked => "api_key_revoked",
+            AuditEventType::SigningKeyRotated => "signing_key_rotated",
+            AuditEventType::ImpersonationStarted => "impersonation_started",
+            AuditEventType::ImpersonationEnded => "impersonation_ended",
+            AuditEventType::ImpersonatedRequest => "impersonated_request",
+            AuditEventType::InvitationCreated => "invitation_created",
+            AuditEventType::InvitationRevoked => "invitation_revoked",
+        }
+    }
+}
+
+impl std::fmt::Display for AuditEventType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for AuditEventType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|t| t.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown audit event type: {s}"))
+    }
+}
+
+/// Where a request came from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestMeta {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// Admin acting as the signed-in user, set by `auth_middleware`
+    pub impersonator_id: Option<Uuid>,
+}
+
+tokio::task_local! {
+    static REQUEST_META: RequestMeta;
+}
+
+impl RequestMeta {
+    /// Metadata of the request being handled, if called inside
+    /// `capture_request_meta`
+    pub fn current() -> RequestMeta {
+        REQUEST_META.try_with(|meta| meta.clone()).unwrap_or_default()
+    }
+
+    /// Runs `fut` with `self` as the current request metadata
+    pub async fn scope<F: std::future::Future>(self, fut: F) -> F::Output {
+        REQUEST_META.scope(self, fut).await
+    }
+}
+
+/// Middleware that records the client IP and user agent for audit events
+///
+/// The IP comes from `ConnectInfo`, so serve the app with
+/// `into_make_service_with_connect_info::<SocketAddr>()`.
+pub async fn capture_request_meta(request: Request<Body>, next: Next) -> Response {
+    let meta = RequestMeta {
+        ip: request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string()),
+        user_agent: request
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned),
+        impersonator_id: None,
+    };
+
+    meta.scope(next.run(request)).await
+}
+
+/// A new event to append to the log
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEvent {
+    pub event_type: AuditEventType,
+    /// User the event is about
+    pub user_id: Option<Uuid>,
+    /// User who performed the action; defaults to `user_id`, or to the
+    /// impersonator during impersonation
+    pub actor_id: Option<Uuid>,
+    /// Admin acting as the signed-in user when the event happened
+    pub impersonator_id: Option<Uuid>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub metadata: serde_json::Value,
+}
+
+impl AuditEvent {
+    /// Event about `user_id`, stamped with the current request metadata
+    pub fn new(event_type: AuditEventType, user_id: Option<Uuid>) -> Self {
+        let meta = RequestMeta::current();
+        Self {
+            event_type,
+            user_id,
+            actor_id: meta.impersonator_id.or(user_id),
+            impersonator_id: meta.impersonator_id,
+            ip: meta.ip,
+            user_agent: meta.user_agent,
+            metadata: serde_json::Value::Object(Default::default()),
+        }
+    }
+
+    pub fn actor(mut self, actor_id: Option<Uuid>) -> Self {
+        self.actor_id = actor_id;
+        self
+    }
+
+    pub fn metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Tags the event as done by `impersonator_id` while acting as someone
+    /// else, making them the actor
+    pub fn impersonated_by(mut self, impersonator_id: Uuid) -> Self {
+        self.actor_id = Some(impersonator_id);
+        self.impersonator_id = Some(impersonator_id);
+        self
+    }
+}
+
+/// Criteria for reading the log back, newest events first
+#[derive(Debug, Clone)]
+pub struct AuditFilter {
+    pub user_id: Option<Uuid>,
+    pub event_type: Option<AuditEventType>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: i64,
+}
+
+impl Default for AuditFilter {
+    fn default() -> Self {
+        Self {
+            user_id: None,
+            event_type: None,
+            since: None,
+            until: None,
+            limit: 100,
+        }
+    }
+}
+
+impl AuditFilter {
+    fn matches(&self, record: &AuditEventRecord) -> bool {
+        self.user_id.is_none_or(|id| record.user_id == Some(id))
+            && self.event_type.is_none_or(|t| record.event_type == t.as_str())
+            && self.since.is_none_or(|since| record.created_at >= since)
+            && self.until.is_none_or(|until| record.created_at < until)
+    }
+}
+
+/// Storage for audit events
+#[async_trait]
+pub trait AuditRecorder: Send + Sync {
+    /// Appends an event
+    async fn record(&self, event: AuditEvent) -> Result<(), AuthError>;
+
+    /// Reads events matching `filter`
+    async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEventRecord>, AuthError>;
+}
+
+/// Discards every event (the `AuthContext` default)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopAuditRecorder;
+
+#[async_trait]
+impl AuditRecorder for NoopAuditRecorder {
+    async fn record(&self, _event: AuditEvent) -> Result<(), AuthError> {
+        Ok(())
+    }
+
+    async fn query(&self, _filter: &AuditFilter) -> Result<Vec<AuditEventRecord>, AuthError> {
+        Ok(Vec::new())
+    }
+}
+
+/// Writes events to the `audit_events` table
+#[derive(Clone)]
+pub struct PgAuditRecorder {
+    pool: DbPool,
+}
+
+impl PgAuditRecorder {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AuditRecorder for PgAuditRecorder {
+    async fn record(&self, event: AuditEvent) -> Result<(), AuthError> {
+        queries::audit::insert_audit_event(
+            &self.pool,
+            event.event_type.as_str(),
+            event.user_id,
+            event.actor_id,
+            event.impersonator_id,
+            event.ip.as_deref(),
+            event.user_agent.as_deref(),
+            &event.metadata,
+        )
+        .await
+        .map_err(|_| AuthError::DatabaseError)
+    }
+
+    async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEventRecord>, AuthError> {
+        queries::audit::query_audit_events(
+            &self.pool,
+            filter.user_id,
+            filter.event_type.map(|t| t.as_str()),
+            filter.since,
+            filter.until,
+            filter.limit,
+        )
+        .await
+        .map_err(|_| AuthError::DatabaseError)
+    }
+}
+
+/// Keeps events in memory so tests can inspect them
+#[derive(Debug, Default)]
+pub struct MemoryAuditRecorder {
+    records: Mutex<Vec<AuditEventRecord>>,
+}
+
+impl MemoryAuditRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Types of every recorded event, oldest first
+    pub fn event_types(&self) -> Vec<String> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.event_type.clone())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl AuditRecorder for MemoryAuditRecorder {
+    async fn record(&self, event: AuditEvent) -> Result<(), AuthError> {
+        self.records.lock().unwrap().push(AuditEventRecord {
+            id: Uuid::new_v4(),
+            event_type: event.event_type.as_str().to_owned(),
+            user_id: event.user_id,
+            actor_id: event.actor_id,
+            impersonator_id: event.impersonator_id,
+            ip: event.ip,
+            user_agent: event.user_agent,
+            metadata: event.metadata,
+            created_at: Utc::now(),
+        });
+        Ok(())
+    }
+
+    async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEventRecord>, AuthError> {
+        Ok(self
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|r| filter.matches(r))
+            .take(filter.limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+}
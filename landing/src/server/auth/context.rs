@@ -1,14 +1,29 @@
 use dioxus::prelude::*;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use crate::server::{AuthError, AuthProvider, User};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use crate::server::auth::email::EmailAddress;
+use crate::server::auth::oidc::{AuthorizationRequest, OidcClient};
+use crate::server::auth::rate_limit::RateLimiter;
+use crate::server::auth::session_cache::{CacheStats, LruSessionCache, SessionCache};
+use crate::server::auth::signed_tokens::{self, TokenSigner};
+use crate::server::auth::token_store::{default_token_store, StoredTokens, TokenStore};
+use crate::server::config::SessionConfig;
+use crate::server::{
+    ApiKeyInfo, AuditEvent, AuditEventType, AuditRecorder, AuthError, AuthProvider, NewApiKey,
+    NoopAuditRecorder, PasskeyAssertion, PasskeyCreationOptions, PasskeyCredential, PasskeyInfo,
+    PasskeyRequestOptions, SessionInfo, TotpEnrollment, User, ADMIN_ROLE,
+};
 
 /// Core authentication context that manages user sessions and authentication state.
 ///
 /// This struct provides thread-safe operations for:
-/// - User login/logout
+/// - User login/logout, including the two-factor step
 /// - Session management
 /// - Current user state
+/// - Audit logging of security-relevant events
 ///
 /// # Type Parameters
 /// - `AuthProvider`: Trait defining authentication operations
@@ -19,12 +34,49 @@ pub struct AuthContext {
     auth_provider: Arc<dyn AuthProvider + Send + Sync>,
     /// The currently authenticated user (protected by RwLock for thread safety)
     current_user: RwLock<Option<User>>,
+    /// Challenge from a login that still needs its second factor
+    mfa_challenge: RwLock<Option<String>>,
+    /// Browser secret of the last magic link requested through this context
+    magic_link_secret: RwLock<Option<String>>,
+    /// Where security-relevant events are reported
+    audit: Arc<dyn AuditRecorder>,
+    /// Recently validated sessions, consulted by `resolve_session`
+    session_cache: Arc<dyn SessionCache>,
+    /// Per-account limit on password attempts, off unless configured
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// Whether sessions travel as bearer headers, cookies, or both
+    session_config: SessionConfig,
+    /// OpenID Connect provider users can log in with, if configured
+    oidc: Option<Arc<OidcClient>>,
+    /// Signs access tokens in place of opaque session tokens, if configured
+    token_signer: Option<Arc<TokenSigner>>,
+    /// The admin's own session, put aside while they impersonate the current user
+    impersonator: RwLock<Option<User>>,
+}
+
+/// Outcome of the password step of a login
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginStatus {
+    /// Session established
+    Complete,
+    /// Password accepted, call `complete_mfa` with a TOTP or recovery code
+    MfaPending,
+}
+
+/// Outcome of an OpenID Connect callback
+#[derive(Debug, Clone)]
+pub enum OidcOutcome {
+    /// Logged in through the external account
+    SignedIn(User),
+    /// The external account was linked to this already signed-in user
+    Linked(Uuid),
 }
 impl std::fmt::Debug for AuthContext {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AuthContext")
             .field("auth_provider", &"<dyn AuthProvider>")
             .field("current_user", &self.current_user)
+            .field("mfa_pending", &self.is_mfa_pending())
             .finish()
     }
 }
@@ -37,16 +89,175 @@ impl AuthContext {
     ///
     /// # Example
     /// ```rust
-    /// let provider = Arc::new(PostgresAuth::new(pool));
+    /// let provider = Arc::new(PgAuthProvider::new(pool));
     /// let auth_context = AuthContext::new(provider);
     /// ```
     pub fn new(auth_provider: Arc<dyn AuthProvider + Send + Sync>) -> Self {
         Self {
             auth_provider,
             current_user: RwLock::new(None),
+            mfa_challenge: RwLock::new(None),
+            magic_link_secret: RwLock::new(None),
+            audit: Arc::new(NoopAuditRecorder),
+            session_cache: Arc::new(LruSessionCache::default()),
+            rate_limiter: None,
+            session_config: SessionConfig::default(),
+            oidc: None,
+            token_signer: None,
+            impersonator: RwLock::new(None),
         }
     }
 
+    /// Offers login through an OpenID Connect provider.
+    ///
+    /// # Example
+    /// ```rust
+    /// if let Some(config) = OidcConfig::from_env() {
+    ///     auth_context = auth_context.with_oidc(Arc::new(OidcClient::discover(config).await?));
+    /// }
+    /// ```
+    pub fn with_oidc(mut self, oidc: Arc<OidcClient>) -> Self {
+        self.oidc = Some(oidc);
+        self
+    }
+
+    /// The OpenID Connect client, `None` unless `with_oidc` was called.
+    pub fn oidc(&self) -> Option<&Arc<OidcClient>> {
+        self.oidc.as_ref()
+    }
+
+    /// Sets how sessions are carried between browser and server.
+    ///
+    /// # Example
+    /// ```rust
+    /// let auth_context = AuthContext::new(provider)
+    ///     .with_session_config(SessionConfig::from_env());
+    /// ```
+    pub fn with_session_config(mut self, session_config: SessionConfig) -> Self {
+        self.session_config = session_config;
+        self
+    }
+
+    /// Session transport settings, read by the middleware and API routes.
+    pub fn session_config(&self) -> &SessionConfig {
+        &self.session_config
+    }
+
+    /// Issues signed access tokens that `resolve_session` checks without
+    /// asking the provider.
+    ///
+    /// # Example
+    /// ```rust
+    /// let signer = TokenSigner::new(Arc::new(PgSigningKeyStore::new(pool)), key);
+    /// let auth_context = AuthContext::new(provider).with_signed_tokens(Arc::new(signer));
+    /// ```
+    pub fn with_signed_tokens(mut self, token_signer: Arc<TokenSigner>) -> Self {
+        self.token_signer = Some(token_signer);
+        self
+    }
+
+    /// The signer, if `token` is a signed access token this context issues
+    fn signer_for(&self, token: &str) -> Option<&Arc<TokenSigner>> {
+        self.token_signer
+            .as_ref()
+            .filter(|_| signed_tokens::is_signed_token(token))
+    }
+
+    /// Swaps a freshly issued session token for a signed access token,
+    /// when signed tokens are on.
+    ///
+    /// The token names the provider session, found through the session
+    /// list, so logout and the sessions page can still reach it.
+    async fn issue(&self, user: User) -> Result<User, AuthError> {
+        let Some(signer) = &self.token_signer else {
+            return Ok(user);
+        };
+        let session_id = self
+            .auth_provider
+            .list_sessions(user.id, &user.bearer_token)
+            .await?
+            .into_iter()
+            .find(|session| session.current)
+            .map(|session| session.id)
+            .ok_or(AuthError::InvalidSession)?;
+        let bearer_token = signer.sign(&user, session_id).await?;
+        Ok(User { bearer_token, ..user })
+    }
+
+    /// Ends a session behind a signed access token and refuses its tokens.
+    async fn end_signed_session(&self, signer: &TokenSigner, user_id: Uuid, session_id: Uuid) -> Result<(), AuthError> {
+        self.auth_provider.revoke_session(user_id, session_id).await?;
+        signer.revoke_session(session_id).await
+    }
+
+    /// Refuses signed tokens of sessions the provider has already ended,
+    /// when signed tokens are on.
+    async fn revoke_signed_sessions(&self, session_ids: &[Uuid]) -> Result<(), AuthError> {
+        if let Some(signer) = &self.token_signer {
+            for session_id in session_ids {
+                signer.revoke_session(*session_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Limits password attempts per account using `rate_limiter`.
+    ///
+    /// Usually the same limiter the `rate_limit` middleware uses.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Replaces the default in-process session cache.
+    ///
+    /// # Example
+    /// ```rust
+    /// let cache = LruSessionCache::new(50_000, Duration::seconds(30));
+    /// let auth_context = AuthContext::new(provider).with_session_cache(Arc::new(cache));
+    /// ```
+    pub fn with_session_cache(mut self, session_cache: Arc<dyn SessionCache>) -> Self {
+        self.session_cache = session_cache;
+        self
+    }
+
+    /// Reports events to `audit` instead of discarding them.
+    ///
+    /// # Example
+    /// ```rust
+    /// let auth_context = AuthContext::new(provider)
+    ///     .with_audit(Arc::new(PgAuditRecorder::new(pool)));
+    /// ```
+    pub fn with_audit(mut self, audit: Arc<dyn AuditRecorder>) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// Records an event; a failing audit store never fails the operation.
+    ///
+    /// Events raised while the current user is impersonated are tagged
+    /// with the impersonator, as `auth_middleware` does for API requests.
+    async fn record(&self, mut event: AuditEvent) {
+        if event.impersonator_id.is_none() {
+            if let Some(impersonator_id) = self.try_current_user().and_then(|user| user.impersonator_id) {
+                event = event.impersonated_by(impersonator_id);
+            }
+        }
+        let event_type = event.event_type;
+        if let Err(e) = self.audit.record(event).await {
+            log::error!("Failed to record {event_type} audit event: {e}");
+        }
+    }
+
+    /// Records a failed login attempt against `email`.
+    async fn record_login_failure(&self, email: &str, error: &AuthError) {
+        self.record(
+            AuditEvent::new(AuditEventType::LoginFailure, None)
+                .metadata(json!({ "email": email, "reason": error.to_string() })),
+        )
+        .await;
+    }
+
     /// Authenticates a user and establishes a session.
     ///
     /// # Arguments
@@ -54,36 +265,406 @@ impl AuthContext {
     /// * `password` - User's password
     ///
     /// # Returns
-    /// - `Ok(())` on successful authentication
+    /// - `Ok(LoginStatus::Complete)` on successful authentication
+    /// - `Ok(LoginStatus::MfaPending)` if the account has two-factor enabled
+    /// - `Err(AuthError::RateLimited)` if the account had too many attempts
     /// - `Err(AuthError)` on failure
-    pub async fn login(&self, email: &str, password: &str) -> Result<(), AuthError> {
-        let user = self.auth_provider.authenticate(email, password).await?;
+    pub async fn login(&self, email: &str, password: &str) -> Result<LoginStatus, AuthError> {
+        *self.mfa_challenge.write().await = None;
+        match self.sign_in(email, password).await {
+            Ok(user) => {
+                *self.current_user.write().await = Some(user);
+                Ok(LoginStatus::Complete)
+            }
+            Err(AuthError::MfaRequired { challenge }) => {
+                *self.mfa_challenge.write().await = Some(challenge);
+                Ok(LoginStatus::MfaPending)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Checks a password and issues tokens without touching the current
+    /// user, for the login API endpoint.
+    ///
+    /// # Returns
+    /// - `Ok(User)` carrying the new tokens
+    /// - `Err(AuthError::MfaRequired)` with the challenge for `verify_mfa`
+    /// - `Err(AuthError::InvalidEmail)` if `email` isn't an address at all
+    pub async fn sign_in(&self, email: &str, password: &str) -> Result<User, AuthError> {
+        let address = EmailAddress::parse(email)?;
+        if let Some(limiter) = &self.rate_limiter {
+            if !limiter.check_login(&address).allowed {
+                self.record_login_failure(email, &AuthError::RateLimited).await;
+                return Err(AuthError::RateLimited);
+            }
+        }
+        match self.auth_provider.authenticate(email, password).await {
+            Ok(user) => {
+                self.record(AuditEvent::new(AuditEventType::LoginSuccess, Some(user.id))).await;
+                self.issue(user).await
+            }
+            Err(AuthError::MfaRequired { challenge }) => Err(AuthError::MfaRequired { challenge }),
+            Err(e) => {
+                self.record_login_failure(email, &e).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Emails a single-use sign-in link and returns the secret it is bound
+    /// to, without touching the current user, for the magic link API
+    /// endpoint. The caller keeps the secret in the requesting browser.
+    ///
+    /// # Returns
+    /// - `Ok(String)` with the browser secret, also for unknown emails
+    /// - `Err(AuthError::RateLimited)` if the account had too many attempts
+    /// - `Err(AuthError::InvalidEmail)` if `email` isn't an address at all
+    pub async fn send_magic_link(&self, email: &str) -> Result<String, AuthError> {
+        let address = EmailAddress::parse(email)?;
+        if let Some(limiter) = &self.rate_limiter {
+            if !limiter.check_login(&address).allowed {
+                return Err(AuthError::RateLimited);
+            }
+        }
+        let secret = self.auth_provider.request_magic_link(email).await?;
+        self.record(
+            AuditEvent::new(AuditEventType::MagicLinkRequested, None)
+                .metadata(json!({ "email": email })),
+        )
+        .await;
+        Ok(secret)
+    }
+
+    /// Emails a sign-in link that only works from this context.
+    ///
+    /// # Arguments
+    /// * `email` - Address to send the link to
+    pub async fn request_magic_link(&self, email: &str) -> Result<(), AuthError> {
+        let secret = self.send_magic_link(email).await?;
+        *self.magic_link_secret.write().await = Some(secret);
+        Ok(())
+    }
+
+    /// Checks a magic link and issues tokens without touching the current
+    /// user, for the magic link API endpoint.
+    ///
+    /// # Arguments
+    /// * `token` - Token taken from the link
+    /// * `browser_secret` - Secret from `send_magic_link`, held by the browser
+    ///
+    /// # Returns
+    /// - `Ok(User)` carrying the new tokens
+    /// - `Err(AuthError::MfaRequired)` with the challenge for `verify_mfa`
+    pub async fn sign_in_with_magic_link(&self, token: &str, browser_secret: &str) -> Result<User, AuthError> {
+        match self.auth_provider.login_with_magic_link(token, browser_secret).await {
+            Ok(user) => {
+                self.record(
+                    AuditEvent::new(AuditEventType::LoginSuccess, Some(user.id))
+                        .metadata(json!({ "method": "magic_link" })),
+                )
+                .await;
+                self.issue(user).await
+            }
+            Err(AuthError::MfaRequired { challenge }) => Err(AuthError::MfaRequired { challenge }),
+            Err(e) => {
+                self.record(
+                    AuditEvent::new(AuditEventType::LoginFailure, None)
+                        .metadata(json!({ "method": "magic_link", "reason": e.to_string() })),
+                )
+                .await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Logs in with a link from `request_magic_link` on this context.
+    ///
+    /// # Returns
+    /// - `Ok(LoginStatus)` as for `login`
+    /// - `Err(AuthError::InvalidToken)` if no link was requested here
+    pub async fn login_with_magic_link(&self, token: &str) -> Result<LoginStatus, AuthError> {
+        let secret = self.magic_link_secret.read().await.clone().ok_or(AuthError::InvalidToken)?;
+        *self.mfa_challenge.write().await = None;
+        match self.sign_in_with_magic_link(token, &secret).await {
+            Ok(user) => {
+                *self.magic_link_secret.write().await = None;
+                *self.current_user.write().await = Some(user);
+                Ok(LoginStatus::Complete)
+            }
+            Err(AuthError::MfaRequired { challenge }) => {
+                *self.magic_link_secret.write().await = None;
+                *self.mfa_challenge.write().await = Some(challenge);
+                Ok(LoginStatus::MfaPending)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Starts a login at the OpenID Connect provider.
+    ///
+    /// # Arguments
+    /// * `link_user` - Signed-in user to link the external account to
+    ///   instead of logging in with it
+    ///
+    /// # Returns
+    /// - `Ok(AuthorizationRequest)` with the URL to redirect the browser to
+    /// - `Err(AuthError::IdentityProvider)` if OIDC isn't configured
+    pub fn begin_oidc_login(&self, link_user: Option<&User>) -> Result<AuthorizationRequest, AuthError> {
+        let oidc = self.oidc.as_ref().ok_or_else(oidc_not_configured)?;
+        oidc.authorization_request(link_user.map(|user| user.id))
+    }
+
+    /// Finishes an OpenID Connect login from the provider's callback,
+    /// without touching the current user.
+    ///
+    /// # Arguments
+    /// * `code` - Authorization code from the callback
+    /// * `state` - State from the callback, already matched against the browser
+    ///
+    /// # Returns
+    /// - `Ok(OidcOutcome)` with the signed-in user or the linked account
+    /// - `Err(AuthError::MfaRequired)` with the challenge for `verify_mfa`
+    /// - `Err(AuthError::InvalidToken)` for an unknown, spent or expired state
+    pub async fn complete_oidc_login(&self, code: &str, state: &str) -> Result<OidcOutcome, AuthError> {
+        let oidc = self.oidc.as_ref().ok_or_else(oidc_not_configured)?;
+        let callback = match oidc.complete(code, state).await {
+            Ok(callback) => callback,
+            Err(e) => {
+                self.record(
+                    AuditEvent::new(AuditEventType::LoginFailure, None)
+                        .metadata(json!({ "method": "oidc", "reason": e.to_string() })),
+                )
+                .await;
+                return Err(e);
+            }
+        };
+        let identity = callback.identity;
+
+        if let Some(user_id) = callback.link_user {
+            self.auth_provider.link_identity(user_id, &identity).await?;
+            self.record(
+                AuditEvent::new(AuditEventType::IdentityLinked, Some(user_id))
+                    .metadata(json!({ "issuer": identity.issuer, "subject": identity.subject })),
+            )
+            .await;
+            return Ok(OidcOutcome::Linked(user_id));
+        }
+
+        match self.auth_provider.login_with_identity(&identity).await {
+            Ok(user) => {
+                self.record(
+                    AuditEvent::new(AuditEventType::LoginSuccess, Some(user.id))
+                        .metadata(json!({ "method": "oidc", "issuer": identity.issuer })),
+                )
+                .await;
+                Ok(OidcOutcome::SignedIn(self.issue(user).await?))
+            }
+            Err(AuthError::MfaRequired { challenge }) => Err(AuthError::MfaRequired { challenge }),
+            Err(e) => {
+                self.record(
+                    AuditEvent::new(AuditEventType::LoginFailure, None).metadata(json!({
+                        "method": "oidc",
+                        "email": identity.email,
+                        "subject": identity.subject,
+                        "reason": e.to_string(),
+                    })),
+                )
+                .await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Starts a passkey sign-in.
+    ///
+    /// # Returns
+    /// - `Ok(PasskeyRequestOptions)` to pass to `navigator.credentials.get()`
+    pub async fn begin_passkey_login(&self) -> Result<PasskeyRequestOptions, AuthError> {
+        self.auth_provider.begin_passkey_authentication().await
+    }
+
+    /// Checks a passkey assertion and issues tokens without touching the
+    /// current user, for the passkey login API endpoint.
+    pub async fn sign_in_with_passkey(&self, assertion: &PasskeyAssertion) -> Result<User, AuthError> {
+        match self.auth_provider.authenticate_passkey(assertion).await {
+            Ok(user) => {
+                self.record(
+                    AuditEvent::new(AuditEventType::LoginSuccess, Some(user.id))
+                        .metadata(json!({ "method": "passkey" })),
+                )
+                .await;
+                self.issue(user).await
+            }
+            Err(e) => {
+                self.record(
+                    AuditEvent::new(AuditEventType::LoginFailure, None)
+                        .metadata(json!({ "method": "passkey", "reason": e.to_string() })),
+                )
+                .await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Signs in with a passkey assertion from `begin_passkey_login`.
+    pub async fn login_with_passkey(&self, assertion: &PasskeyAssertion) -> Result<(), AuthError> {
+        let user = self.sign_in_with_passkey(assertion).await?;
+        *self.mfa_challenge.write().await = None;
         *self.current_user.write().await = Some(user);
         Ok(())
     }
 
+    /// Completes the second step of a `sign_in` without touching the
+    /// current user.
+    ///
+    /// # Arguments
+    /// * `challenge` - Challenge from `AuthError::MfaRequired`
+    /// * `code` - Current TOTP code or an unused recovery code
+    ///
+    /// # Returns
+    /// - `Err(AuthError::RateLimited)` if the account had too many attempts,
+    ///   counted together with its password attempts
+    pub async fn verify_mfa(&self, challenge: &str, code: &str) -> Result<User, AuthError> {
+        if let Some(limiter) = &self.rate_limiter {
+            let email = self.auth_provider.mfa_challenge_email(challenge).await?;
+            if !limiter.check_login(&EmailAddress::parse(&email)?).allowed {
+                self.record_login_failure(&email, &AuthError::RateLimited).await;
+                return Err(AuthError::RateLimited);
+            }
+        }
+        match self.auth_provider.verify_mfa(challenge, code).await {
+            Ok(user) => {
+                self.record(
+                    AuditEvent::new(AuditEventType::LoginSuccess, Some(user.id))
+                        .metadata(json!({ "mfa": true })),
+                )
+                .await;
+                self.issue(user).await
+            }
+            Err(AuthError::InvalidMfaCode) => {
+                self.record(
+                    AuditEvent::new(AuditEventType::LoginFailure, None)
+                        .metadata(json!({ "reason": AuthError::InvalidMfaCode.to_string() })),
+                )
+                .await;
+                Err(AuthError::InvalidMfaCode)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Finishes a login left in `LoginStatus::MfaPending`.
+    ///
+    /// # Arguments
+    /// * `code` - Current TOTP code or an unused recovery code
+    ///
+    /// # Returns
+    /// - `Err(AuthError::InvalidMfaCode)` for a wrong code, the challenge stays pending
+    /// - `Err(AuthError::InvalidToken)` once the challenge is gone; log in again
+    pub async fn complete_mfa(&self, code: &str) -> Result<(), AuthError> {
+        let challenge = self
+            .mfa_challenge
+            .read()
+            .await
+            .clone()
+            .ok_or(AuthError::InvalidToken)?;
+
+        match self.verify_mfa(&challenge, code).await {
+            Ok(user) => {
+                *self.mfa_challenge.write().await = None;
+                *self.current_user.write().await = Some(user);
+                Ok(())
+            }
+            Err(AuthError::InvalidMfaCode) => Err(AuthError::InvalidMfaCode),
+            Err(e) => {
+                *self.mfa_challenge.write().await = None;
+                Err(e)
+            }
+        }
+    }
+
+    /// Whether a login is waiting for its second factor
+    pub fn is_mfa_pending(&self) -> bool {
+        self.mfa_challenge
+            .try_read()
+            .map(|challenge| challenge.is_some())
+            .unwrap_or(false)
+    }
+
+    /// Abandons a pending two-factor login.
+    pub async fn cancel_mfa(&self) {
+        *self.mfa_challenge.write().await = None;
+    }
+
     /// Registers a new user account.
     ///
     /// # Arguments
     /// * `email` - User's email address
     /// * `password` - User's password
-    pub async fn register(&self, email: &str, password: &str) -> Result<(), AuthError> {
-        self.auth_provider.register(email, password).await
+    /// * `invite_code` - Invitation code, required while `invite_only` is on
+    pub async fn register(&self, email: &str, password: &str, invite_code: Option<&str>) -> Result<(), AuthError> {
+        self.auth_provider.register(email, password, invite_code).await?;
+        let invited = invite_code.is_some_and(|code| !code.trim().is_empty());
+        self.record(
+            AuditEvent::new(AuditEventType::Register, None)
+                .metadata(json!({ "email": email, "invited": invited })),
+        )
+        .await;
+        Ok(())
     }
 
     /// Terminates the current user session.
     ///
+    /// While impersonating, the admin's own session put aside by
+    /// `impersonate` ends as well.
+    ///
     /// # Returns
     /// - `Ok(())` on success
     /// - `Err(AuthError)` if logout fails
     pub async fn logout(&self) -> Result<(), AuthError> {
-        if let Some(user) = self.current_user.read().await.as_ref() {
-            self.auth_provider.logout(&user.bearer_token).await?;
+        let user = self.current_user.read().await.clone();
+        let impersonator = self.impersonator.write().await.take();
+        for user in [user, impersonator].into_iter().flatten() {
+            if self.signer_for(&user.bearer_token).is_some() {
+                self.sign_out(&user.bearer_token).await?;
+            } else {
+                self.session_cache.invalidate(&user.bearer_token).await;
+                self.auth_provider.logout(&user.bearer_token).await?;
+                self.record(session_ended(user.id, user.impersonator_id)).await;
+            }
         }
         *self.current_user.write().await = None;
         Ok(())
     }
 
+    /// Ends the session behind `token` without touching the current user,
+    /// for the logout API endpoint.
+    pub async fn sign_out(&self, token: &str) -> Result<(), AuthError> {
+        if let Some(signer) = self.signer_for(token) {
+            let claims = signer.claims(token).await.ok();
+            let event = match &claims {
+                Some(claims) => {
+                    self.end_signed_session(signer, claims.sub, claims.sid).await?;
+                    session_ended(claims.sub, claims.imp)
+                }
+                None => AuditEvent::new(AuditEventType::Logout, None),
+            };
+            self.record(event).await;
+            return Ok(());
+        }
+
+        let user = self.resolve_session(token).await.ok();
+        self.session_cache.invalidate(token).await;
+        self.auth_provider.logout(token).await?;
+        let event = match user {
+            Some(user) => session_ended(user.id, user.impersonator_id),
+            None => AuditEvent::new(AuditEventType::Logout, None),
+        };
+        self.record(event).await;
+        Ok(())
+    }
+
     /// Gets the currently authenticated user.
     ///
     /// # Returns
@@ -93,6 +674,15 @@ impl AuthContext {
         self.current_user.read().await.clone()
     }
 
+    /// Non-blocking snapshot of the current user, for synchronous callers
+    /// such as route guards.
+    ///
+    /// # Returns
+    /// - `None` if nobody is logged in or the state is being updated
+    pub fn try_current_user(&self) -> Option<User> {
+        self.current_user.try_read().ok().and_then(|user| user.clone())
+    }
+
     /// Validates and establishes a session from an existing token.
     ///
     /// # Arguments
@@ -102,10 +692,626 @@ impl AuthContext {
     /// - `Ok(User)` if the token is valid
     /// - `Err(AuthError)` if validation fails
     pub async fn validate_session(&self, token: &str) -> Result<User, AuthError> {
-        let user = self.auth_provider.validate_session(token).await?;
+        let user = self.resolve_session(token).await?;
         *self.current_user.write().await = Some(user.clone());
         Ok(user)
     }
+
+    /// Picks up a session saved by an earlier run of the client.
+    ///
+    /// The access token is validated first; if it has expired in the
+    /// meantime the refresh token, when there is one, is exchanged for a
+    /// new pair.
+    ///
+    /// # Returns
+    /// - `Ok(User)` with the tokens to save from now on
+    /// - `Err(AuthError)` if neither token is accepted any more
+    pub async fn restore_session(&self, bearer_token: &str, refresh_token: Option<&str>) -> Result<User, AuthError> {
+        let user = match self.resolve_session(bearer_token).await {
+            // Validated sessions don't carry their refresh token
+            Ok(user) => User { refresh_token: refresh_token.map(str::to_owned), ..user },
+            Err(e) => match refresh_token {
+                Some(refresh_token) => self.exchange_refresh_token(refresh_token, Some(bearer_token)).await?,
+                None => return Err(e),
+            },
+        };
+        *self.current_user.write().await = Some(user.clone());
+        Ok(user)
+    }
+
+    /// Looks up the user behind a bearer token without touching the
+    /// current user, for per-request checks such as `auth_middleware`.
+    ///
+    /// Served from the session cache when possible; signed access tokens
+    /// are checked by the `TokenSigner` alone.
+    ///
+    /// # Returns
+    /// - `Ok(User)` if the session is valid
+    /// - `Err(AuthError::InvalidSession)` if it expired or was revoked
+    pub async fn resolve_session(&self, token: &str) -> Result<User, AuthError> {
+        if let Some(signer) = self.signer_for(token) {
+            return Ok(signer.verify(token).await?.into_user(token));
+        }
+        if let Some(user) = self.session_cache.get(token).await {
+            return Ok(user);
+        }
+
+        let user = self.auth_provider.validate_session(token).await?;
+        self.session_cache.set(token, &user).await;
+        Ok(user)
+    }
+
+    /// Looks up the owner of an API key, narrowed to the key's scopes, for
+    /// `auth_middleware`. Not cached, so revoked keys stop at once.
+    ///
+    /// # Returns
+    /// - `Ok(User)` with only the permissions the key is scoped to
+    /// - `Err(AuthError::InvalidSession)` for an unknown or expired key
+    pub async fn resolve_api_key(&self, key: &str) -> Result<User, AuthError> {
+        self.auth_provider.authenticate_api_key(key).await
+    }
+
+    /// Exchanges a refresh token for a new token pair without touching
+    /// the current user, for the refresh API endpoint.
+    ///
+    /// # Arguments
+    /// * `replaced_token` - The access token the new pair replaces, if the
+    ///   caller has it. It is dropped from the session cache; without it
+    ///   every cached session of the user is.
+    ///
+    /// # Returns
+    /// - `Ok(User)` carrying the new `bearer_token` and `refresh_token`
+    /// - `Err(AuthError::TokenReuseDetected)` if the token was already spent;
+    ///   every session from that login has been revoked
+    pub async fn exchange_refresh_token(
+        &self,
+        refresh_token: &str,
+        replaced_token: Option<&str>,
+    ) -> Result<User, AuthError> {
+        match self.auth_provider.refresh(refresh_token).await {
+            Ok(user) => {
+                // The provider has moved the session off the old access token
+                match replaced_token {
+                    Some(token) => self.session_cache.invalidate(token).await,
+                    None => self.session_cache.invalidate_user(user.id).await,
+                }
+                self.issue(user).await
+            }
+            Err(AuthError::TokenReuseDetected { user_id, session_ids }) => {
+                self.session_cache.invalidate_user(user_id).await;
+                self.revoke_signed_sessions(&session_ids).await?;
+                self.record(
+                    AuditEvent::new(AuditEventType::SessionRevoked, Some(user_id))
+                        .metadata(json!({ "scope": "token_family", "reason": "refresh_token_reuse" })),
+                )
+                .await;
+                Err(AuthError::TokenReuseDetected { user_id, session_ids })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Renews the current user's access token with their refresh token.
+    ///
+    /// Call before `session_expires_at`; the client does this through
+    /// `use_session_refresh`. A refused refresh signs the user out; after a
+    /// transient failure (see `AuthError::is_transient`) they stay signed
+    /// in so it can be retried.
+    ///
+    /// # Returns
+    /// - `Err(AuthError::Unauthorized)` if nobody is logged in or there is
+    ///   no refresh token to use
+    pub async fn refresh(&self) -> Result<(), AuthError> {
+        let current = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        let refresh_token = current.refresh_token.clone().ok_or(AuthError::Unauthorized)?;
+
+        match self.exchange_refresh_token(&refresh_token, Some(&current.bearer_token)).await {
+            Ok(user) => {
+                *self.current_user.write().await = Some(user);
+                Ok(())
+            }
+            Err(e) if e.is_transient() => Err(e),
+            Err(e) => {
+                self.session_cache.invalidate(&current.bearer_token).await;
+                *self.current_user.write().await = None;
+                Err(e)
+            }
+        }
+    }
+
+    /// Hit/miss counters of the session cache.
+    pub async fn session_cache_stats(&self) -> CacheStats {
+        self.session_cache.stats().await
+    }
+
+    /// Lists the devices `user` is signed in on, without touching the
+    /// current user, for the sessions API endpoint.
+    ///
+    /// The session behind `user.bearer_token` is flagged as `current`.
+    pub async fn sessions_for(&self, user: &User) -> Result<Vec<SessionInfo>, AuthError> {
+        let Some(signer) = self.signer_for(&user.bearer_token) else {
+            return self.auth_provider.list_sessions(user.id, &user.bearer_token).await;
+        };
+        let current = signer.verify(&user.bearer_token).await?.sid;
+        let mut sessions = self.auth_provider.list_sessions(user.id, "").await?;
+        for session in &mut sessions {
+            session.current = session.id == current;
+        }
+        Ok(sessions)
+    }
+
+    /// Signs `user` out on one device.
+    ///
+    /// # Arguments
+    /// * `session_id` - `SessionInfo::id` from `sessions_for`
+    ///
+    /// # Returns
+    /// - `Ok(false)` if `user` has no such session
+    pub async fn revoke_session_for(&self, user: &User, session_id: Uuid) -> Result<bool, AuthError> {
+        let revoked = self.auth_provider.revoke_session(user.id, session_id).await?;
+        if revoked {
+            if let Some(signer) = &self.token_signer {
+                signer.revoke_session(session_id).await?;
+            }
+            self.session_cache.invalidate_user(user.id).await;
+            self.record(
+                AuditEvent::new(AuditEventType::SessionRevoked, Some(user.id))
+                    .metadata(json!({ "scope": "device", "session_id": session_id })),
+            )
+            .await;
+        }
+        Ok(revoked)
+    }
+
+    /// Signs `user` out on every device except the one making the request.
+    ///
+    /// # Returns
+    /// - `Ok(u64)` with the number of sessions ended
+    pub async fn revoke_other_sessions_for(&self, user: &User) -> Result<u64, AuthError> {
+        // Sessions signed in before signed tokens were turned on still have
+        // signed siblings, so go by the signer rather than the caller's token
+        let revoked = match &self.token_signer {
+            Some(signer) => {
+                let mut revoked = 0;
+                for session in self.sessions_for(user).await?.into_iter().filter(|s| !s.current) {
+                    self.end_signed_session(signer, user.id, session.id).await?;
+                    revoked += 1;
+                }
+                revoked
+            }
+            None => self.auth_provider.revoke_other_sessions(user.id, &user.bearer_token).await?,
+        };
+        self.session_cache.invalidate_user(user.id).await;
+        self.record(
+            AuditEvent::new(AuditEventType::SessionRevoked, Some(user.id))
+                .metadata(json!({ "scope": "others", "count": revoked })),
+        )
+        .await;
+        Ok(revoked)
+    }
+
+    /// Lists the devices the current user is signed in on.
+    ///
+    /// # Returns
+    /// - `Err(AuthError::Unauthorized)` if nobody is logged in
+    pub async fn list_sessions(&self) -> Result<Vec<SessionInfo>, AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.sessions_for(&user).await
+    }
+
+    /// Signs the current user out on another device; use `logout` for this one.
+    pub async fn revoke_session(&self, session_id: Uuid) -> Result<bool, AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.revoke_session_for(&user, session_id).await
+    }
+
+    /// Signs the current user out everywhere else.
+    pub async fn revoke_other_sessions(&self) -> Result<u64, AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.revoke_other_sessions_for(&user).await
+    }
+
+    /// Confirms an email address from a verification link.
+    ///
+    /// # Arguments
+    /// * `token` - Token taken from the link
+    pub async fn verify_email(&self, token: &str) -> Result<(), AuthError> {
+        self.auth_provider.verify_email(token).await
+    }
+
+    /// Sends a new verification link to an unverified account.
+    ///
+    /// # Returns
+    /// - `Err(AuthError::RateLimited)` if links were requested too often
+    pub async fn resend_verification(&self, email: &str) -> Result<(), AuthError> {
+        self.auth_provider.resend_verification(email).await
+    }
+
+    /// Emails a password reset link if the account exists.
+    ///
+    /// # Arguments
+    /// * `email` - User's email address
+    pub async fn request_password_reset(&self, email: &str) -> Result<(), AuthError> {
+        self.auth_provider.request_password_reset(email).await?;
+        self.record(
+            AuditEvent::new(AuditEventType::PasswordResetRequested, None)
+                .metadata(json!({ "email": email })),
+        )
+        .await;
+        Ok(())
+    }
+
+    /// Sets a new password from a reset link and signs out everywhere.
+    ///
+    /// # Arguments
+    /// * `token` - Token taken from the link
+    /// * `new_password` - Replacement password
+    pub async fn reset_password(&self, token: &str, new_password: &str) -> Result<(), AuthError> {
+        let (user_id, session_ids) = self.auth_provider.reset_password(token, new_password).await?;
+        self.session_cache.invalidate_user(user_id).await;
+        self.revoke_signed_sessions(&session_ids).await?;
+        self.record(
+            AuditEvent::new(AuditEventType::PasswordChange, Some(user_id))
+                .metadata(json!({ "method": "reset_link" })),
+        )
+        .await;
+        self.record(
+            AuditEvent::new(AuditEventType::SessionRevoked, Some(user_id))
+                .metadata(json!({ "scope": "all", "reason": "password_change" })),
+        )
+        .await;
+        *self.current_user.write().await = None;
+        Ok(())
+    }
+
+    /// Changes `user`'s password and signs them out on every other device,
+    /// keeping the session making the request.
+    ///
+    /// # Arguments
+    /// * `current_password` - Password the account has now
+    /// * `new_password` - Replacement password
+    ///
+    /// # Returns
+    /// - `Err(AuthError::AuthenticationFailed)` if `current_password` is wrong
+    /// - `Err(AuthError::Forbidden)` while impersonating
+    pub async fn change_password_for(
+        &self,
+        user: &User,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<(), AuthError> {
+        if user.is_impersonated() {
+            return Err(AuthError::Forbidden);
+        }
+        self.auth_provider
+            .change_password(user.id, current_password, new_password)
+            .await?;
+        self.record(
+            AuditEvent::new(AuditEventType::PasswordChange, Some(user.id))
+                .metadata(json!({ "method": "current_password" })),
+        )
+        .await;
+        // Ends refresh families too, so a stolen refresh token stops working
+        self.revoke_other_sessions_for(user).await?;
+        Ok(())
+    }
+
+    /// Changes the current user's password; see `change_password_for`.
+    pub async fn change_password(&self, current_password: &str, new_password: &str) -> Result<(), AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.change_password_for(&user, current_password, new_password).await
+    }
+
+    /// Grants a role to a user.
+    ///
+    /// # Arguments
+    /// * `user_id` - User receiving the role
+    /// * `role` - Role name
+    pub async fn assign_role(&self, user_id: Uuid, role: &str) -> Result<(), AuthError> {
+        self.auth_provider.assign_role(user_id, role).await?;
+        self.session_cache.invalidate_user(user_id).await;
+        self.record_role_change(user_id, role, "assign").await;
+        Ok(())
+    }
+
+    /// Removes a role from a user.
+    ///
+    /// # Arguments
+    /// * `user_id` - User losing the role
+    /// * `role` - Role name
+    pub async fn revoke_role(&self, user_id: Uuid, role: &str) -> Result<(), AuthError> {
+        self.auth_provider.revoke_role(user_id, role).await?;
+        self.session_cache.invalidate_user(user_id).await;
+        self.record_role_change(user_id, role, "revoke").await;
+        Ok(())
+    }
+
+    /// Records a role change made by the current user.
+    async fn record_role_change(&self, user_id: Uuid, role: &str, action: &str) {
+        let actor = self.current_user().await.map(|user| user.id);
+        self.record(
+            AuditEvent::new(AuditEventType::RoleChange, Some(user_id))
+                .actor(actor)
+                .metadata(json!({ "role": role, "action": action })),
+        )
+        .await;
+    }
+
+    /// Opens a session for `admin` to act as another user, without
+    /// touching the current user, for the impersonation API endpoint.
+    ///
+    /// The session is tagged with the admin, so `User::impersonator_id` is
+    /// set on every request it makes and the audit log names the admin as
+    /// the actor. It can't be refreshed and ends after
+    /// `AuthConfig::impersonation_ttl`, or sooner when a signed access
+    /// token's lifetime is shorter.
+    ///
+    /// # Returns
+    /// - `Ok(User)` carrying the impersonation session
+    /// - `Err(AuthError::Forbidden)` unless `admin` is an admin acting as
+    ///   themselves, or if the target is an admin
+    /// - `Err(AuthError::UserNotFound)` for an unknown user
+    pub async fn impersonate_for(&self, admin: &User, user_id: Uuid) -> Result<User, AuthError> {
+        if !admin.has_role(ADMIN_ROLE) || admin.is_impersonated() {
+            return Err(AuthError::Forbidden);
+        }
+        let user = self.auth_provider.impersonate(admin.id, user_id).await?;
+        let user = self.issue(user).await?;
+        self.record(
+            AuditEvent::new(AuditEventType::ImpersonationStarted, Some(user_id))
+                .impersonated_by(admin.id)
+                .metadata(json!({ "expires_at": user.session_expires_at })),
+        )
+        .await;
+        Ok(user)
+    }
+
+    /// Ends an impersonation session, for the API endpoint behind the
+    /// "stop impersonating" button.
+    ///
+    /// # Returns
+    /// - `Ok(Uuid)` with the id of the admin who was impersonating
+    /// - `Err(AuthError::Forbidden)` if `user` isn't being impersonated
+    pub async fn stop_impersonating_for(&self, user: &User) -> Result<Uuid, AuthError> {
+        let impersonator_id = user.impersonator_id.ok_or(AuthError::Forbidden)?;
+        self.sign_out(&user.bearer_token).await?;
+        Ok(impersonator_id)
+    }
+
+    /// Logs a state-changing request made on an impersonation session, for
+    /// `auth_middleware`, which has already put the admin in `RequestMeta`.
+    pub async fn record_impersonated_request(&self, user_id: Uuid, method: &str, path: &str) {
+        self.record(
+            AuditEvent::new(AuditEventType::ImpersonatedRequest, Some(user_id))
+                .metadata(json!({ "method": method, "path": path })),
+        )
+        .await;
+    }
+
+    /// Makes the current user, who must be an admin, act as another user
+    /// until `stop_impersonating`.
+    pub async fn impersonate(&self, user_id: Uuid) -> Result<(), AuthError> {
+        let admin = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        let user = self.impersonate_for(&admin, user_id).await?;
+        *self.impersonator.write().await = Some(admin);
+        *self.current_user.write().await = Some(user);
+        Ok(())
+    }
+
+    /// Ends the current impersonation and gives the admin their own
+    /// session back.
+    pub async fn stop_impersonating(&self) -> Result<(), AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.stop_impersonating_for(&user).await?;
+        let admin = self.impersonator.write().await.take();
+        *self.current_user.write().await = admin;
+        Ok(())
+    }
+
+    /// Lifts the failed-login lockout on an email address.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if there was a failure streak to clear
+    pub async fn unlock_account(&self, email: &str) -> Result<bool, AuthError> {
+        let unlocked = self.auth_provider.unlock_account(email).await?;
+        if unlocked {
+            let actor = self.current_user().await.map(|user| user.id);
+            self.record(
+                AuditEvent::new(AuditEventType::AccountUnlocked, None)
+                    .actor(actor)
+                    .metadata(json!({ "email": email })),
+            )
+            .await;
+        }
+        Ok(unlocked)
+    }
+
+    /// Starts adding a passkey to `user`'s account, without touching the
+    /// current user, for the passkey API endpoints.
+    pub async fn begin_passkey_registration_for(&self, user: &User) -> Result<PasskeyCreationOptions, AuthError> {
+        self.auth_provider.begin_passkey_registration(user.id).await
+    }
+
+    /// Stores the passkey created from `begin_passkey_registration_for`.
+    ///
+    /// # Arguments
+    /// * `name` - Label to tell the passkeys apart, e.g. "Work laptop"
+    /// * `credential` - Response from `navigator.credentials.create()`
+    pub async fn finish_passkey_registration_for(
+        &self,
+        user: &User,
+        name: &str,
+        credential: &PasskeyCredential,
+    ) -> Result<PasskeyInfo, AuthError> {
+        let passkey = self.auth_provider.finish_passkey_registration(user.id, name, credential).await?;
+        self.record(
+            AuditEvent::new(AuditEventType::PasskeyAdded, Some(user.id))
+                .metadata(json!({ "passkey_id": passkey.id, "name": passkey.name })),
+        )
+        .await;
+        Ok(passkey)
+    }
+
+    /// Lists `user`'s passkeys.
+    pub async fn passkeys_for(&self, user: &User) -> Result<Vec<PasskeyInfo>, AuthError> {
+        self.auth_provider.list_passkeys(user.id).await
+    }
+
+    /// Removes one of `user`'s passkeys.
+    ///
+    /// # Returns
+    /// - `Ok(false)` if `user` has no such passkey
+    pub async fn delete_passkey_for(&self, user: &User, passkey_id: Uuid) -> Result<bool, AuthError> {
+        let deleted = self.auth_provider.delete_passkey(user.id, passkey_id).await?;
+        if deleted {
+            self.record(
+                AuditEvent::new(AuditEventType::PasskeyRemoved, Some(user.id))
+                    .metadata(json!({ "passkey_id": passkey_id })),
+            )
+            .await;
+        }
+        Ok(deleted)
+    }
+
+    /// Starts adding a passkey for the current user.
+    ///
+    /// # Returns
+    /// - `Err(AuthError::Unauthorized)` if nobody is logged in
+    pub async fn begin_passkey_registration(&self) -> Result<PasskeyCreationOptions, AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.begin_passkey_registration_for(&user).await
+    }
+
+    /// Stores a passkey for the current user.
+    pub async fn finish_passkey_registration(
+        &self,
+        name: &str,
+        credential: &PasskeyCredential,
+    ) -> Result<PasskeyInfo, AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.finish_passkey_registration_for(&user, name, credential).await
+    }
+
+    /// Lists the current user's passkeys.
+    pub async fn list_passkeys(&self) -> Result<Vec<PasskeyInfo>, AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.passkeys_for(&user).await
+    }
+
+    /// Removes one of the current user's passkeys.
+    pub async fn delete_passkey(&self, passkey_id: Uuid) -> Result<bool, AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.delete_passkey_for(&user, passkey_id).await
+    }
+
+    /// Creates an API key for `user`, without touching the current user,
+    /// for the API key endpoints.
+    ///
+    /// # Arguments
+    /// * `name` - Label to tell the keys apart, e.g. "Deploy script"
+    /// * `scopes` - Permissions the key may use, e.g. `posts:write`
+    /// * `expires_at` - When the key stops working, `None` for never
+    ///
+    /// # Returns
+    /// - `Ok(NewApiKey)` with the key, which can't be shown again
+    /// - `Err(AuthError::InvalidScope)` for scopes `user` doesn't hold
+    pub async fn create_api_key_for(
+        &self,
+        user: &User,
+        name: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<NewApiKey, AuthError> {
+        let created = self.auth_provider.create_api_key(user.id, name, scopes, expires_at).await?;
+        self.record(
+            AuditEvent::new(AuditEventType::ApiKeyCreated, Some(user.id)).metadata(json!({
+                "api_key_id": created.api_key.id,
+                "name": created.api_key.name,
+                "scopes": created.api_key.scopes,
+            })),
+        )
+        .await;
+        Ok(created)
+    }
+
+    /// Lists `user`'s API keys.
+    pub async fn api_keys_for(&self, user: &User) -> Result<Vec<ApiKeyInfo>, AuthError> {
+        self.auth_provider.list_api_keys(user.id).await
+    }
+
+    /// Revokes one of `user`'s API keys.
+    ///
+    /// # Returns
+    /// - `Ok(false)` if `user` has no such key
+    pub async fn revoke_api_key_for(&self, user: &User, key_id: Uuid) -> Result<bool, AuthError> {
+        let revoked = self.auth_provider.revoke_api_key(user.id, key_id).await?;
+        if revoked {
+            self.record(
+                AuditEvent::new(AuditEventType::ApiKeyRevoked, Some(user.id))
+                    .metadata(json!({ "api_key_id": key_id })),
+            )
+            .await;
+        }
+        Ok(revoked)
+    }
+
+    /// Creates an API key for the current user.
+    ///
+    /// # Returns
+    /// - `Err(AuthError::Unauthorized)` if nobody is logged in
+    pub async fn create_api_key(
+        &self,
+        name: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<NewApiKey, AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.create_api_key_for(&user, name, scopes, expires_at).await
+    }
+
+    /// Lists the current user's API keys.
+    pub async fn list_api_keys(&self) -> Result<Vec<ApiKeyInfo>, AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.api_keys_for(&user).await
+    }
+
+    /// Revokes one of the current user's API keys.
+    pub async fn revoke_api_key(&self, key_id: Uuid) -> Result<bool, AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.revoke_api_key_for(&user, key_id).await
+    }
+
+    /// Starts TOTP enrollment for the current user.
+    ///
+    /// # Returns
+    /// - `Ok(TotpEnrollment)` with the otpauth URI and QR code to scan
+    /// - `Err(AuthError::Unauthorized)` if nobody is logged in
+    pub async fn begin_totp_enrollment(&self) -> Result<TotpEnrollment, AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.auth_provider.begin_totp_enrollment(user.id).await
+    }
+
+    /// Enables two-factor for the current user after checking a first code.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<String>)` with recovery codes to show exactly once
+    pub async fn confirm_totp_enrollment(&self, code: &str) -> Result<Vec<String>, AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        let recovery_codes = self.auth_provider.confirm_totp_enrollment(user.id, code).await?;
+        self.record(AuditEvent::new(AuditEventType::MfaEnabled, Some(user.id))).await;
+        Ok(recovery_codes)
+    }
+
+    /// Disables two-factor for the current user.
+    ///
+    /// # Arguments
+    /// * `code` - Current TOTP code or an unused recovery code
+    pub async fn disable_totp(&self, code: &str) -> Result<(), AuthError> {
+        let user = self.current_user().await.ok_or(AuthError::Unauthorized)?;
+        self.auth_provider.disable_totp(user.id, code).await?;
+        self.record(AuditEvent::new(AuditEventType::MfaDisabled, Some(user.id))).await;
+        Ok(())
+    }
 }
 
 // Removed PartialEq implementation as it's not meaningful for AuthContext
@@ -115,44 +1321,349 @@ impl AuthContext {
 
 
 // Dioxus-compatible client wrapper
+//
+// Every change of the signed-in user is mirrored to the `TokenStore`, which
+// `restore_session` reads back when the app starts.
 #[derive(Clone)]
 pub struct AuthClient {
     inner: Arc<AuthContext>,
+    token_store: Arc<dyn TokenStore>,
 }
 
 impl AuthClient {
     pub fn new(auth_provider: Arc<dyn AuthProvider + Send + Sync>) -> Self {
         Self {
             inner: Arc::new(AuthContext::new(auth_provider)),
+            token_store: default_token_store(),
         }
     }
 
-    pub async fn login(&self, email: &str, password: &str) -> Result<(), AuthError> {
-        self.inner.login(email, password).await
+    /// Keeps tokens in `token_store` instead of the target's default
+    ///
+    /// # Example
+    /// ```rust
+    /// // Forget the session when the tab is closed
+    /// let auth = AuthClient::new(provider).with_token_store(Arc::new(WebStorageTokenStore::session()));
+    /// ```
+    pub fn with_token_store(mut self, token_store: Arc<dyn TokenStore>) -> Self {
+        self.token_store = token_store;
+        self
+    }
+
+    /// Signs back in with the tokens saved by an earlier run; call once at
+    /// startup, as `use_session_refresh` does.
+    ///
+    /// Saved tokens the server no longer accepts are forgotten, while
+    /// those it couldn't check for the moment are kept for the next start.
+    ///
+    /// # Returns
+    /// - `Ok(Some(User))` if the saved session is still good
+    /// - `Ok(None)` if no session was saved
+    pub async fn restore_session(&self) -> Result<Option<User>, AuthError> {
+        let Some(tokens) = self.token_store.load()? else {
+            return Ok(None);
+        };
+        match self.inner.restore_session(&tokens.bearer_token, tokens.refresh_token.as_deref()).await {
+            Ok(user) => {
+                self.persist().await;
+                Ok(Some(user))
+            }
+            Err(e @ (AuthError::DatabaseError | AuthError::RateLimited)) => Err(e),
+            Err(e) => {
+                self.forget();
+                Err(e)
+            }
+        }
+    }
+
+    /// Saves the current user's tokens, or forgets them once nobody is
+    /// signed in. Impersonation sessions aren't saved, so the admin's own
+    /// session is the one a reload comes back to.
+    async fn persist(&self) {
+        let result = match self.inner.current_user().await {
+            Some(user) if user.is_impersonated() => return,
+            Some(user) => self.token_store.save(&StoredTokens::from(&user)),
+            None => self.token_store.clear(),
+        };
+        if let Err(e) = result {
+            log::warn!("Could not update the saved session: {e}");
+        }
+    }
+
+    fn forget(&self) {
+        if let Err(e) = self.token_store.clear() {
+            log::warn!("Could not clear the saved session: {e}");
+        }
+    }
+
+    pub async fn login(&self, email: &str, password: &str) -> Result<LoginStatus, AuthError> {
+        let status = self.inner.login(email, password).await?;
+        self.persist().await;
+        Ok(status)
+    }
+
+    pub async fn complete_mfa(&self, code: &str) -> Result<(), AuthError> {
+        self.inner.complete_mfa(code).await?;
+        self.persist().await;
+        Ok(())
+    }
+
+    pub async fn begin_passkey_login(&self) -> Result<PasskeyRequestOptions, AuthError> {
+        self.inner.begin_passkey_login().await
+    }
+
+    pub async fn login_with_passkey(&self, assertion: &PasskeyAssertion) -> Result<(), AuthError> {
+        self.inner.login_with_passkey(assertion).await?;
+        self.persist().await;
+        Ok(())
+    }
+
+    pub async fn begin_passkey_registration(&self) -> Result<PasskeyCreationOptions, AuthError> {
+        self.inner.begin_passkey_registration().await
+    }
+
+    pub async fn finish_passkey_registration(
+        &self,
+        name: &str,
+        credential: &PasskeyCredential,
+    ) -> Result<PasskeyInfo, AuthError> {
+        self.inner.finish_passkey_registration(name, credential).await
+    }
+
+    pub async fn list_passkeys(&self) -> Result<Vec<PasskeyInfo>, AuthError> {
+        self.inner.list_passkeys().await
+    }
+
+    pub async fn delete_passkey(&self, passkey_id: Uuid) -> Result<bool, AuthError> {
+        self.inner.delete_passkey(passkey_id).await
+    }
+
+    pub async fn create_api_key(
+        &self,
+        name: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<NewApiKey, AuthError> {
+        self.inner.create_api_key(name, scopes, expires_at).await
+    }
+
+    pub async fn list_api_keys(&self) -> Result<Vec<ApiKeyInfo>, AuthError> {
+        self.inner.list_api_keys().await
+    }
+
+    pub async fn revoke_api_key(&self, key_id: Uuid) -> Result<bool, AuthError> {
+        self.inner.revoke_api_key(key_id).await
+    }
+
+    pub fn is_mfa_pending(&self) -> bool {
+        self.inner.is_mfa_pending()
+    }
+
+    pub async fn cancel_mfa(&self) {
+        self.inner.cancel_mfa().await
     }
 
     pub async fn logout(&self) -> Result<(), AuthError> {
+        // Forgotten locally even if the server can't be told
+        self.forget();
         self.inner.logout().await
     }
 
+    pub async fn refresh(&self) -> Result<(), AuthError> {
+        // A refused refresh signs the user out, which `persist` mirrors
+        let result = self.inner.refresh().await;
+        self.persist().await;
+        result
+    }
+
     pub async fn current_user(&self) -> Option<User> {
         self.inner.current_user().await
     }
 
-    pub async fn register(&self, email: &str, password: &str) -> Result<(), AuthError> {
-        self.inner.register(email, password).await
+    pub async fn list_sessions(&self) -> Result<Vec<SessionInfo>, AuthError> {
+        self.inner.list_sessions().await
+    }
+
+    pub async fn revoke_session(&self, session_id: Uuid) -> Result<bool, AuthError> {
+        self.inner.revoke_session(session_id).await
+    }
+
+    pub async fn revoke_other_sessions(&self) -> Result<u64, AuthError> {
+        self.inner.revoke_other_sessions().await
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.inner.try_current_user().is_some()
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.inner
+            .try_current_user()
+            .is_some_and(|user| user.has_role(role))
+    }
+
+    pub fn is_impersonating(&self) -> bool {
+        self.inner
+            .try_current_user()
+            .is_some_and(|user| user.is_impersonated())
+    }
+
+    pub async fn impersonate(&self, user_id: Uuid) -> Result<(), AuthError> {
+        self.inner.impersonate(user_id).await
+    }
+
+    pub async fn stop_impersonating(&self) -> Result<(), AuthError> {
+        self.inner.stop_impersonating().await
+    }
+
+    pub async fn register(&self, email: &str, password: &str, invite_code: Option<&str>) -> Result<(), AuthError> {
+        self.inner.register(email, password, invite_code).await
     }
 
     pub async fn validate_session(&self, token: &str) -> Result<User, AuthError> {
-        self.inner.validate_session(token).await
+        let user = self.inner.validate_session(token).await?;
+        self.persist().await;
+        Ok(user)
+    }
+
+    pub async fn verify_email(&self, token: &str) -> Result<(), AuthError> {
+        self.inner.verify_email(token).await
+    }
+
+    pub async fn resend_verification(&self, email: &str) -> Result<(), AuthError> {
+        self.inner.resend_verification(email).await
+    }
+
+    pub async fn request_password_reset(&self, email: &str) -> Result<(), AuthError> {
+        self.inner.request_password_reset(email).await
+    }
+
+    pub async fn request_magic_link(&self, email: &str) -> Result<(), AuthError> {
+        self.inner.request_magic_link(email).await
+    }
+
+    pub async fn login_with_magic_link(&self, token: &str) -> Result<LoginStatus, AuthError> {
+        let status = self.inner.login_with_magic_link(token).await?;
+        self.persist().await;
+        Ok(status)
+    }
+
+    pub async fn reset_password(&self, token: &str, new_password: &str) -> Result<(), AuthError> {
+        self.inner.reset_password(token, new_password).await?;
+        self.persist().await;
+        Ok(())
+    }
+
+    pub async fn change_password(&self, current_password: &str, new_password: &str) -> Result<(), AuthError> {
+        self.inner.change_password(current_password, new_password).await
+    }
+
+    pub async fn begin_totp_enrollment(&self) -> Result<TotpEnrollment, AuthError> {
+        self.inner.begin_totp_enrollment().await
+    }
+
+    pub async fn confirm_totp_enrollment(&self, code: &str) -> Result<Vec<String>, AuthError> {
+        self.inner.confirm_totp_enrollment(code).await
+    }
+
+    pub async fn disable_totp(&self, code: &str) -> Result<(), AuthError> {
+        self.inner.disable_totp(code).await
     }
 }
 
+/// Audit event for the end of `user_id`'s session, which is the end of an
+/// impersonation if the session had an impersonator
+fn session_ended(user_id: Uuid, impersonator_id: Option<Uuid>) -> AuditEvent {
+    match impersonator_id {
+        Some(impersonator_id) => {
+            AuditEvent::new(AuditEventType::ImpersonationEnded, Some(user_id)).impersonated_by(impersonator_id)
+        }
+        None => AuditEvent::new(AuditEventType::Logout, Some(user_id)),
+    }
+}
+
+fn oidc_not_configured() -> AuthError {
+    AuthError::IdentityProvider("OpenID Connect is not configured".to_string())
+}
+
 // Dioxus hooks and provider
 pub fn use_auth() -> AuthClient {
     use_context::<AuthClient>()
 }
 
+/// How long before expiry `use_session_refresh` renews the access token
+const REFRESH_MARGIN_SECS: i64 = 60;
+
+/// Shortest pause after a refresh, so access tokens that live no longer
+/// than the margin don't make the loop spin
+const MIN_REFRESH_INTERVAL_SECS: i64 = 5;
+
+/// Longest pause between retries of a refresh that keeps failing
+const MAX_REFRESH_BACKOFF_SECS: i64 = 300;
+
+/// Restores the session saved by the last run, then keeps the signed-in
+/// user's access token fresh by refreshing it shortly before it expires.
+/// Mount once, near the root of the app.
+pub fn use_session_refresh() {
+    let auth = use_auth();
+    use_future(move || {
+        let auth = auth.clone();
+        async move {
+            if let Err(e) = auth.restore_session().await {
+                log::warn!("Saved session could not be restored: {e}");
+            }
+            let mut failures = 0;
+            loop {
+                let wait_secs = match auth.current_user().await {
+                    Some(user) if user.refresh_token.is_some() => {
+                        let due = user.session_expires_at - chrono::Duration::seconds(REFRESH_MARGIN_SECS);
+                        let wait = (due - chrono::Utc::now()).num_seconds();
+                        if wait > 0 {
+                            wait.min(REFRESH_MARGIN_SECS)
+                        } else {
+                            match auth.refresh().await {
+                                Ok(()) => {
+                                    failures = 0;
+                                    MIN_REFRESH_INTERVAL_SECS
+                                }
+                                // Refused refreshes have signed the user out,
+                                // transient ones are retried with backoff
+                                Err(e) => {
+                                    log::warn!("Session refresh failed: {e}");
+                                    failures += 1;
+                                    refresh_backoff_secs(failures)
+                                }
+                            }
+                        }
+                    }
+                    // Nothing to refresh; look again after a login
+                    _ => {
+                        failures = 0;
+                        REFRESH_MARGIN_SECS
+                    }
+                };
+                sleep_secs(wait_secs as u64).await;
+            }
+        }
+    });
+}
+
+/// Pause before retrying after `failures` failed refreshes in a row,
+/// doubling from `MIN_REFRESH_INTERVAL_SECS` up to `MAX_REFRESH_BACKOFF_SECS`
+fn refresh_backoff_secs(failures: u32) -> i64 {
+    (MIN_REFRESH_INTERVAL_SECS << failures.min(10)).min(MAX_REFRESH_BACKOFF_SECS)
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn sleep_secs(secs: u64) {
+    gloo_timers::future::sleep(std::time::Duration::from_secs(secs)).await;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn sleep_secs(secs: u64) {
+    tokio::time::sleep(std::time::Duration::from_secs(secs)).await;
+}
+
 
 
 
@@ -0,0 +1,172 @@
+//! Cookie sessions and double-submit CSRF tokens
+//!
+//! In `SessionMode::Cookie` the access and refresh tokens live in HttpOnly
+//! cookies the page's scripts can't read. Because browsers attach cookies
+//! to cross-site requests too, every state-changing request authenticated
+//! by cookie must also echo the readable `csrf_token` cookie in the
+//! `X-CSRF-Token` header; another site can't read the cookie, so it can't
+//! forge the header.
+
+use axum::http::{header, HeaderMap, HeaderValue, Method};
+use chrono::{Duration, Utc};
+
+use crate::server::auth::utils::generate_random_token;
+use crate::server::config::SessionConfig;
+use crate::server::error::AuthError;
+use crate::server::models::User;
+
+/// HttpOnly cookie carrying the access token
+pub const SESSION_COOKIE: &str = "session";
+/// HttpOnly cookie carrying the refresh token, only sent to `/api/auth`
+pub const REFRESH_COOKIE: &str = "refresh_token";
+/// Script-readable cookie holding the CSRF token
+pub const CSRF_COOKIE: &str = "csrf_token";
+/// Header the CSRF token must be echoed in
+pub const CSRF_HEADER: &str = "x-csrf-token";
+/// HttpOnly cookie tying an OpenID Connect login to the browser that started it
+pub const OIDC_STATE_COOKIE: &str = "oidc_state";
+/// Secret binding a requested magic link to the browser that asked for it
+pub const MAGIC_LINK_COOKIE: &str = "magic_link";
+
+/// Path the refresh cookie is scoped to
+const REFRESH_COOKIE_PATH: &str = "/api/auth";
+/// Path the OIDC state cookie is scoped to
+const OIDC_COOKIE_PATH: &str = "/api/auth/oidc";
+/// Path the magic link cookie is scoped to
+const MAGIC_LINK_COOKIE_PATH: &str = "/api/auth/magic-link";
+
+/// Value of cookie `name` on a request
+pub fn read_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn build(name: &str, value: &str, path: &str, max_age: i64, http_only: bool, same_site: &str, secure: bool) -> String {
+    let mut cookie = format!("{name}={value}; Path={path}; Max-Age={}; SameSite={same_site}", max_age.max(0));
+    if http_only {
+        cookie.push_str("; HttpOnly");
+    }
+    if secure {
+        cookie.push_str("; Secure");
+    }
+    cookie
+}
+
+/// `Set-Cookie` values that establish a cookie session for `user`
+///
+/// A new CSRF token is minted with every session so tokens don't outlive logins.
+pub fn session_cookies(user: &User, config: &SessionConfig) -> Vec<HeaderValue> {
+    let secure = config.secure_cookies;
+    let refresh_max_age = config.refresh_cookie_max_age.num_seconds();
+    let mut cookies = vec![build(
+        SESSION_COOKIE,
+        &user.bearer_token,
+        "/",
+        (user.session_expires_at - Utc::now()).num_seconds(),
+        true,
+        "Lax",
+        secure,
+    )];
+    if let Some(refresh_token) = &user.refresh_token {
+        cookies.push(build(REFRESH_COOKIE, refresh_token, REFRESH_COOKIE_PATH, refresh_max_age, true, "Strict", secure));
+    }
+    cookies.push(build(CSRF_COOKIE, &generate_random_token(), "/", refresh_max_age, false, "Lax", secure));
+
+    cookies
+        .into_iter()
+        .filter_map(|cookie| HeaderValue::from_str(&cookie).ok())
+        .collect()
+}
+
+/// `Set-Cookie` values that remove every session cookie
+pub fn clear_session_cookies(config: &SessionConfig) -> Vec<HeaderValue> {
+    let secure = config.secure_cookies;
+    [
+        build(SESSION_COOKIE, "", "/", 0, true, "Lax", secure),
+        build(REFRESH_COOKIE, "", REFRESH_COOKIE_PATH, 0, true, "Strict", secure),
+        build(CSRF_COOKIE, "", "/", 0, false, "Lax", secure),
+    ]
+    .into_iter()
+    .filter_map(|cookie| HeaderValue::from_str(&cookie).ok())
+    .collect()
+}
+
+/// `Set-Cookie` value remembering the state of a started OIDC login
+///
+/// SameSite=Lax, as the provider's redirect back is a cross-site navigation.
+pub fn oidc_state_cookie(state: &str, max_age: Duration, config: &SessionConfig) -> Option<HeaderValue> {
+    let cookie = build(OIDC_STATE_COOKIE, state, OIDC_COOKIE_PATH, max_age.num_seconds(), true, "Lax", config.secure_cookies);
+    HeaderValue::from_str(&cookie).ok()
+}
+
+/// `Set-Cookie` value removing the OIDC state cookie
+pub fn clear_oidc_state_cookie(config: &SessionConfig) -> Option<HeaderValue> {
+    HeaderValue::from_str(&build(OIDC_STATE_COOKIE, "", OIDC_COOKIE_PATH, 0, true, "Lax", config.secure_cookies)).ok()
+}
+
+/// Checks that an OIDC callback's `state` belongs to this browser
+pub fn check_oidc_state(headers: &HeaderMap, state: &str) -> Result<(), AuthError> {
+    match read_cookie(headers, OIDC_STATE_COOKIE) {
+        Some(cookie) if !cookie.is_empty() && constant_time_eq(cookie.as_bytes(), state.as_bytes()) => Ok(()),
+        _ => Err(AuthError::InvalidToken),
+    }
+}
+
+/// `Set-Cookie` value for the secret a magic link only works with
+///
+/// `SameSite=Strict`: the link opens the app, and the app's own request
+/// to sign in is the only one that needs the cookie.
+pub fn magic_link_cookie(secret: &str, max_age: Duration, config: &SessionConfig) -> Option<HeaderValue> {
+    let cookie = build(MAGIC_LINK_COOKIE, secret, MAGIC_LINK_COOKIE_PATH, max_age.num_seconds(), true, "Strict", config.secure_cookies);
+    HeaderValue::from_str(&cookie).ok()
+}
+
+/// `Set-Cookie` value removing the magic link cookie
+pub fn clear_magic_link_cookie(config: &SessionConfig) -> Option<HeaderValue> {
+    HeaderValue::from_str(&build(MAGIC_LINK_COOKIE, "", MAGIC_LINK_COOKIE_PATH, 0, true, "Strict", config.secure_cookies)).ok()
+}
+
+/// The magic link secret this browser holds, if any
+pub fn magic_link_secret(headers: &HeaderMap) -> Result<&str, AuthError> {
+    read_cookie(headers, MAGIC_LINK_COOKIE)
+        .filter(|secret| !secret.is_empty())
+        .ok_or(AuthError::InvalidToken)
+}
+
+/// Appends `Set-Cookie` headers
+pub fn set_cookies(headers: &mut HeaderMap, cookies: Vec<HeaderValue>) {
+    for cookie in cookies {
+        headers.append(header::SET_COOKIE, cookie);
+    }
+}
+
+/// Whether a request carries any session cookie, making it subject to CSRF checks
+pub fn has_session_cookie(headers: &HeaderMap) -> bool {
+    read_cookie(headers, SESSION_COOKIE).is_some() || read_cookie(headers, REFRESH_COOKIE).is_some()
+}
+
+/// Enforces the double-submit check on state-changing requests
+///
+/// Safe methods pass, as they must not change state anyway.
+pub fn check_csrf(method: &Method, headers: &HeaderMap) -> Result<(), AuthError> {
+    if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE) {
+        return Ok(());
+    }
+
+    let cookie = read_cookie(headers, CSRF_COOKIE).filter(|c| !c.is_empty());
+    let header = headers.get(CSRF_HEADER).and_then(|v| v.to_str().ok());
+    match (cookie, header) {
+        (Some(cookie), Some(header)) if constant_time_eq(cookie.as_bytes(), header.as_bytes()) => Ok(()),
+        _ => Err(AuthError::InvalidCsrfToken),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
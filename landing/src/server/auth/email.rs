@@ -0,0 +1,235 @@
+//! Email address parsing and canonical forms
+//!
+//! `EmailAddress::parse` accepts the dot-atom addresses people actually
+//! use: an ASCII local part and a domain of at least two labels, which may
+//! be internationalized and is stored in its lowercase ASCII (punycode)
+//! form. Quoted local parts and IP literal domains are rejected.
+//!
+//! Accounts are unique under `EmailPolicy::canonicalize`, so
+//! `Bob@Example.com` and `bob@example.com` are the same account, as are
+//! `bob+news@example.com` and `bob@example.com` when plus tags are folded.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::server::config::env_parse;
+use crate::server::error::AuthError;
+
+/// Longest address SMTP can carry in a `MAIL FROM` or `RCPT TO` path
+const MAX_ADDRESS_LENGTH: usize = 254;
+const MAX_LOCAL_LENGTH: usize = 64;
+const MAX_DOMAIN_LENGTH: usize = 253;
+const MAX_LABEL_LENGTH: usize = 63;
+
+/// Printable characters a dot-atom local part may hold besides letters, digits and dots
+const LOCAL_SPECIALS: &str = "!#$%&'*+-/=?^_`{|}~";
+
+/// A syntactically valid email address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailAddress {
+    local: String,
+    domain: String,
+}
+
+impl EmailAddress {
+    /// Parses an address, ignoring surrounding whitespace
+    pub fn parse(input: &str) -> Result<Self, AuthError> {
+        let (local, domain) = input.trim().rsplit_once('@').ok_or(AuthError::InvalidEmail)?;
+        if !is_valid_local(local) {
+            return Err(AuthError::InvalidEmail);
+        }
+        let domain = idna::domain_to_ascii(domain).map_err(|_| AuthError::InvalidEmail)?;
+        if !is_valid_domain(&domain) || local.len() + 1 + domain.len() > MAX_ADDRESS_LENGTH {
+            return Err(AuthError::InvalidEmail);
+        }
+
+        Ok(Self { local: local.to_owned(), domain })
+    }
+
+    /// Local part as given, with its case preserved
+    pub fn local(&self) -> &str {
+        &self.local
+    }
+
+    /// Lowercase ASCII domain, IDN labels in punycode
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+}
+
+impl std::fmt::Display for EmailAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.local, self.domain)
+    }
+}
+
+fn is_valid_local(local: &str) -> bool {
+    !local.is_empty()
+        && local.len() <= MAX_LOCAL_LENGTH
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && !local.contains("..")
+        && local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || LOCAL_SPECIALS.contains(c))
+}
+
+/// Checks an already ASCII domain: hostname labels, and a top-level
+/// domain that isn't all digits so IPv4 addresses don't pass
+fn is_valid_domain(domain: &str) -> bool {
+    let labels: Vec<&str> = domain.split('.').collect();
+    domain.len() <= MAX_DOMAIN_LENGTH
+        && labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= MAX_LABEL_LENGTH
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+        && labels.last().is_some_and(|tld| !tld.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// How addresses are accepted and compared
+#[derive(Debug, Clone, Default)]
+pub struct EmailPolicy {
+    /// Treat `name+tag@domain` as `name@domain` when comparing addresses.
+    /// Existing accounts keep their stored canonical form until the
+    /// `admin canonicalize-emails` command is run.
+    pub fold_plus_tags: bool,
+    /// Domains new accounts can't be created with, typically disposable
+    /// mail services
+    pub blocked_domains: Option<Arc<DomainBlocklist>>,
+}
+
+impl EmailPolicy {
+    /// Builds a policy from the environment, keeping defaults for unset keys
+    ///
+    /// | Variable                  | Field             |
+    /// |---------------------------|-------------------|
+    /// | `EMAIL_FOLD_PLUS_TAGS`    | `fold_plus_tags`  |
+    /// | `DISPOSABLE_DOMAINS_FILE` | `blocked_domains` |
+    ///
+    /// A domain list file that can't be read is logged and skipped.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let blocked_domains = match std::env::var("DISPOSABLE_DOMAINS_FILE") {
+            Ok(path) if !path.is_empty() => match DomainBlocklist::load(&path) {
+                Ok(list) => Some(Arc::new(list)),
+                Err(e) => {
+                    log::warn!("Could not read disposable domains from {path}: {e}");
+                    None
+                }
+            },
+            _ => None,
+        };
+        Self {
+            fold_plus_tags: env_parse("EMAIL_FOLD_PLUS_TAGS", defaults.fold_plus_tags),
+            blocked_domains,
+        }
+    }
+
+    /// Refuses new accounts at the domains on `list`
+    pub fn with_blocked_domains(mut self, list: DomainBlocklist) -> Self {
+        self.blocked_domains = Some(Arc::new(list));
+        self
+    }
+
+    /// Parses the address of a new account, rejecting blocked domains
+    pub fn parse_new(&self, email: &str) -> Result<EmailAddress, AuthError> {
+        let address = EmailAddress::parse(email)?;
+        self.check_allowed(&address)?;
+        Ok(address)
+    }
+
+    /// Whether a new account may be created at `address`
+    pub fn check_allowed(&self, address: &EmailAddress) -> Result<(), AuthError> {
+        if self.blocked_domains.as_ref().is_some_and(|list| list.contains(address.domain())) {
+            return Err(AuthError::DisposableEmail);
+        }
+        Ok(())
+    }
+
+    /// Form of `address` accounts are unique under: lowercased, with the
+    /// plus tag dropped if `fold_plus_tags` is on
+    pub fn canonical(&self, address: &EmailAddress) -> String {
+        let local = address.local().to_ascii_lowercase();
+        let local = match local.split_once('+') {
+            Some((name, _)) if self.fold_plus_tags && !name.is_empty() => name,
+            _ => local.as_str(),
+        };
+        format!("{local}@{}", address.domain())
+    }
+
+    /// Canonical form of user input, to look accounts up by
+    ///
+    /// Input that doesn't parse is only trimmed and lowercased, which still
+    /// serves as a lockout key.
+    pub fn canonicalize(&self, email: &str) -> String {
+        match EmailAddress::parse(email) {
+            Ok(address) => self.canonical(&address),
+            Err(_) => email.trim().to_lowercase(),
+        }
+    }
+}
+
+/// Blocked domains loaded from a local list, one per line
+///
+/// A listed domain blocks its subdomains too.
+#[derive(Default)]
+pub struct DomainBlocklist(HashSet<String>);
+
+impl DomainBlocklist {
+    /// Reads a list file; blank lines and lines starting with `#` are skipped
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect())
+    }
+
+    /// Whether `domain`, lowercase ASCII as in `EmailAddress::domain`, or
+    /// one of its parents is listed
+    pub fn contains(&self, domain: &str) -> bool {
+        let mut rest = domain;
+        loop {
+            if self.0.contains(rest) {
+                return true;
+            }
+            match rest.split_once('.') {
+                Some((_, parent)) => rest = parent,
+                None => return false,
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for DomainBlocklist {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|domain| {
+                    let domain = domain.as_ref();
+                    idna::domain_to_ascii(domain).unwrap_or_else(|_| domain.to_ascii_lowercase())
+                })
+                .collect(),
+        )
+    }
+}
+
+impl std::fmt::Debug for DomainBlocklist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DomainBlocklist({} entries)", self.0.len())
+    }
+}
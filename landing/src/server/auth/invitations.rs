@@ -0,0 +1,54 @@
+//! Invitation codes for invite-only registration
+//!
+//! While `AuthConfig::invite_only` is on, `register` only creates accounts
+//! for people holding a code, created with `admin create-invite`. A code
+//! can be bound to one address, limited to a number of uses and set to
+//! expire, and is revoked by its id. Only a SHA-256 digest of the code is
+//! stored, like API keys.
+
+use crate::server::auth::email::{EmailAddress, EmailPolicy};
+use crate::server::auth::utils::{generate_random_token, hash_token};
+use crate::server::config::AuthConfig;
+use crate::server::error::AuthError;
+
+/// Characters in a code, few enough to type from an email
+const CODE_LEN: usize = 16;
+
+/// A fresh code
+pub fn generate_invitation_code() -> String {
+    generate_random_token().chars().take(CODE_LEN).collect()
+}
+
+/// Digest a code is stored and looked up under, ignoring whitespace
+/// picked up when it was copied
+pub fn hash_invitation_code(code: &str) -> String {
+    hash_token(code.trim())
+}
+
+/// The code `register` has to redeem, if any
+///
+/// A blank code counts as none, which `invite_only` refuses with
+/// `AuthError::InvitationRequired`.
+pub fn code_to_redeem<'a>(config: &AuthConfig, invite_code: Option<&'a str>) -> Result<Option<&'a str>, AuthError> {
+    let code = invite_code.map(str::trim).filter(|code| !code.is_empty());
+    if code.is_none() && config.invite_only {
+        return Err(AuthError::InvitationRequired);
+    }
+    Ok(code)
+}
+
+/// Checks the use limit of a new invitation; a code nobody can use is a mistake
+pub fn check_max_uses(max_uses: Option<u32>) -> Result<Option<u32>, AuthError> {
+    match max_uses {
+        Some(0) => Err(AuthError::InvalidInvitation),
+        max_uses => Ok(max_uses),
+    }
+}
+
+/// Canonical form of the address a new invitation is bound to, compared
+/// with the canonical email of the account registering
+pub fn bound_email(policy: &EmailPolicy, email: Option<&str>) -> Result<Option<String>, AuthError> {
+    email
+        .map(|email| EmailAddress::parse(email).map(|address| policy.canonical(&address)))
+        .transpose()
+}
@@ -0,0 +1,65 @@
+//! Progressive backoff and lockout after failed logins
+//!
+//! Failures are counted per normalised email, whether or not an account
+//! exists, so the lockout response can't be used to discover accounts.
+//! Wrong second-factor codes count toward the same streak, and only a
+//! complete login clears it, so starting a new challenge with the password
+//! doesn't buy more guesses.
+//! The first `login_backoff_after` failures are free; after that each one
+//! doubles the wait before the next attempt, until `lockout_threshold`
+//! locks the address for `lockout_duration`.
+
+use chrono::{DateTime, Utc};
+
+use crate::server::config::AuthConfig;
+use crate::server::error::AuthError;
+
+/// Key failures are counted under; providers pass the canonical email, so
+/// variants of one address share a streak
+pub fn lockout_key(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Earliest time another password is accepted after `failed_count`
+/// consecutive failures, the last at `last_failed_at`
+pub fn next_attempt_at(
+    failed_count: i32,
+    last_failed_at: DateTime<Utc>,
+    config: &AuthConfig,
+) -> Option<DateTime<Utc>> {
+    if failed_count >= config.lockout_threshold {
+        return Some(last_failed_at + config.lockout_duration);
+    }
+    if failed_count < config.login_backoff_after {
+        return None;
+    }
+
+    let doublings = (failed_count - config.login_backoff_after).min(20) as u32;
+    let delay = (config.login_backoff_base * 2_i32.pow(doublings)).min(config.lockout_duration);
+    Some(last_failed_at + delay)
+}
+
+/// Refuses the attempt while the backoff or lockout is running
+pub fn check(
+    failed_count: i32,
+    last_failed_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+    config: &AuthConfig,
+) -> Result<(), AuthError> {
+    match next_attempt_at(failed_count, last_failed_at, config) {
+        Some(at) if now < at => Err(AuthError::AccountLocked),
+        _ => Ok(()),
+    }
+}
+
+/// Failure count after one more failure
+///
+/// A count at the threshold means the lock has run out (locked attempts
+/// are refused before they're counted), so counting starts over.
+pub fn next_failed_count(failed_count: i32, config: &AuthConfig) -> i32 {
+    if failed_count >= config.lockout_threshold {
+        1
+    } else {
+        failed_count + 1
+    }
+}
@@ -0,0 +1,1504 @@
+//! In-memory authentication provider
+//!
+//! Applies the same rules as `PgAuthProvider` (unique emails, password
+//! policy, session expiry, refresh token rotation, email verification,
+//! password reset, two-factor, login lockout, per-device sessions,
+//! external identities, passkeys, magic links, API keys, invitations)
+//! without a database. Useful for tests, demos and offline development. Time comes from
+//! a pluggable `Clock` so expiry can be exercised without sleeping.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::server::audit::RequestMeta;
+use crate::server::auth::api_keys;
+use crate::server::auth::email::EmailAddress;
+use crate::server::auth::invitations;
+use crate::server::auth::lockout;
+use crate::server::auth::oidc::ExternalIdentity;
+use crate::server::auth::password::{Argon2Hasher, PasswordHasher, UNUSABLE_PASSWORD_HASH};
+use crate::server::auth::provider::AuthProvider;
+use crate::server::auth::totp;
+use crate::server::auth::webauthn::{self, Ceremony};
+use crate::server::auth::utils::{
+    device_label, generate_random_token, hash_session_token, hash_token,
+};
+use crate::server::config::AuthConfig;
+use crate::server::error::AuthError;
+use crate::server::mailer::{
+    magic_link_email, password_reset_email, verification_email, LogMailer, Mailer,
+};
+use crate::server::models::{
+    ApiKeyInfo, InvitationInfo, NewApiKey, NewInvitation, PasskeyAssertion, PasskeyCreationOptions, PasskeyCredential,
+    PasskeyInfo, PasskeyRequestOptions, SessionInfo, TotpEnrollment, User, ADMIN_ROLE, DEFAULT_ROLE,
+};
+
+/// Argon2id's minimum costs (8 KiB, 1 pass, 1 lane), plenty for throwaway
+/// in-memory accounts
+fn in_memory_hasher() -> Argon2Hasher {
+    Argon2Hasher::new(8, 1, 1).expect("minimum Argon2 parameters are valid")
+}
+
+/// Built-in roles and their permissions, matching the `0005_roles` seed data
+const BUILTIN_ROLES: &[(&str, &[&str])] = &[
+    (DEFAULT_ROLE, &["posts:write"]),
+    (ADMIN_ROLE, &["posts:write", "users:read", "users:manage"]),
+];
+
+/// Source of the current time
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Wall-clock time
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to
+///
+/// # Example
+/// ```rust
+/// let clock = Arc::new(ManualClock::new(Utc::now()));
+/// let provider = InMemoryAuthProvider::with_clock(clock.clone());
+/// clock.advance(Duration::days(8)); // every session is now expired
+/// ```
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self { now: Mutex::new(start) }
+    }
+
+    /// Moves the clock forward by `by`
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+
+    /// Jumps the clock to an absolute instant
+    pub fn set(&self, to: DateTime<Utc>) {
+        *self.now.lock().unwrap() = to;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StoredUser {
+    id: Uuid,
+    email: String,
+    password_hash: String,
+    email_verified: bool,
+    roles: Vec<String>,
+}
+
+impl StoredUser {
+    fn to_user(&self, bearer_token: String, session_expires_at: DateTime<Utc>) -> User {
+        let mut permissions: Vec<String> = BUILTIN_ROLES
+            .iter()
+            .filter(|(role, _)| self.roles.iter().any(|r| r == role))
+            .flat_map(|(_, perms)| perms.iter().map(|p| p.to_string()))
+            .collect();
+        permissions.sort();
+        permissions.dedup();
+
+        User {
+            id: self.id,
+            email: self.email.clone(),
+            bearer_token,
+            session_expires_at,
+            refresh_token: None,
+            roles: self.roles.clone(),
+            permissions,
+            impersonator_id: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StoredSession {
+    /// Stays the same when a refresh moves the session to a new token
+    id: Uuid,
+    user_id: Uuid,
+    expires_at: DateTime<Utc>,
+    family_id: Option<Uuid>,
+    device_label: String,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    created_at: DateTime<Utc>,
+    last_seen_at: DateTime<Utc>,
+    /// Admin acting as `user_id` through this session
+    impersonator_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone)]
+struct StoredToken {
+    user_id: Uuid,
+    expires_at: DateTime<Utc>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct StoredMagicLink {
+    email: String,
+    /// `hash_token` digest of the secret the requesting browser holds
+    browser_hash: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct StoredTotp {
+    secret: String,
+    enabled: bool,
+    last_used_step: Option<i64>,
+    /// Digests of the recovery codes that haven't been used yet
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct StoredChallenge {
+    user_id: Uuid,
+    attempts: i32,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct StoredRefreshToken {
+    user_id: Uuid,
+    family_id: Uuid,
+    expires_at: DateTime<Utc>,
+    used: bool,
+    revoked: bool,
+}
+
+#[derive(Debug, Clone)]
+struct StoredPasskey {
+    id: Uuid,
+    user_id: Uuid,
+    /// COSE_Key bytes
+    public_key: Vec<u8>,
+    sign_count: u32,
+    name: String,
+    created_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+impl StoredPasskey {
+    fn info(&self) -> PasskeyInfo {
+        PasskeyInfo {
+            id: self.id,
+            name: self.name.clone(),
+            created_at: self.created_at,
+            last_used_at: self.last_used_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StoredPasskeyChallenge {
+    ceremony: Ceremony,
+    user_id: Option<Uuid>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct StoredApiKey {
+    user_id: Uuid,
+    info: ApiKeyInfo,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StoredAttempts {
+    failed_count: i32,
+    last_failed_at: DateTime<Utc>,
+}
+
+/// Authentication provider that keeps users and sessions in process memory
+pub struct InMemoryAuthProvider {
+    /// Users keyed by the canonical form of their email
+    users: RwLock<HashMap<String, StoredUser>>,
+    /// Sessions keyed by `hash_session_token` digest
+    sessions: RwLock<HashMap<String, StoredSession>>,
+    /// Email verification tokens keyed by `hash_token` digest
+    verification_tokens: RwLock<HashMap<String, StoredToken>>,
+    /// Password reset tokens keyed by `hash_token` digest
+    reset_tokens: RwLock<HashMap<String, StoredToken>>,
+    /// Magic sign-in links keyed by `hash_token` digest
+    magic_links: RwLock<HashMap<String, StoredMagicLink>>,
+    /// TOTP secrets keyed by user id
+    totp: RwLock<HashMap<Uuid, StoredTotp>>,
+    /// Pending second-factor logins keyed by `hash_token` digest
+    mfa_challenges: RwLock<HashMap<String, StoredChallenge>>,
+    /// Refresh tokens keyed by `hash_token` digest
+    refresh_tokens: RwLock<HashMap<String, StoredRefreshToken>>,
+    /// Failed login streaks keyed by `lockout::lockout_key`
+    login_attempts: RwLock<HashMap<String, StoredAttempts>>,
+    /// Linked external accounts, user id keyed by issuer and subject
+    identities: RwLock<HashMap<(String, String), Uuid>>,
+    /// Passkeys keyed by credential id
+    passkeys: RwLock<HashMap<Vec<u8>, StoredPasskey>>,
+    /// Pending passkey ceremonies keyed by `hash_token` digest of the challenge
+    passkey_challenges: RwLock<HashMap<String, StoredPasskeyChallenge>>,
+    /// API keys keyed by `hash_token` digest
+    api_keys: RwLock<HashMap<String, StoredApiKey>>,
+    /// Invitations keyed by `hash_invitation_code` digest
+    invitations: RwLock<HashMap<String, InvitationInfo>>,
+    clock: Arc<dyn Clock>,
+    config: AuthConfig,
+    mailer: Arc<dyn Mailer>,
+    hasher: Arc<dyn PasswordHasher>,
+}
+
+impl Default for InMemoryAuthProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InMemoryAuthProvider {
+    /// Creates an empty provider running on wall-clock time
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    /// Creates an empty provider that reads time from `clock`
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            users: RwLock::new(HashMap::new()),
+            sessions: RwLock::new(HashMap::new()),
+            verification_tokens: RwLock::new(HashMap::new()),
+            reset_tokens: RwLock::new(HashMap::new()),
+            magic_links: RwLock::new(HashMap::new()),
+            totp: RwLock::new(HashMap::new()),
+            mfa_challenges: RwLock::new(HashMap::new()),
+            refresh_tokens: RwLock::new(HashMap::new()),
+            login_attempts: RwLock::new(HashMap::new()),
+            identities: RwLock::new(HashMap::new()),
+            passkeys: RwLock::new(HashMap::new()),
+            passkey_challenges: RwLock::new(HashMap::new()),
+            api_keys: RwLock::new(HashMap::new()),
+            invitations: RwLock::new(HashMap::new()),
+            clock,
+            config: AuthConfig::default(),
+            mailer: Arc::new(LogMailer),
+            hasher: Arc::new(in_memory_hasher()),
+        }
+    }
+
+    /// Replaces the configuration
+    pub fn with_config(mut self, config: AuthConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Replaces the mail transport
+    pub fn with_mailer(mut self, mailer: Arc<dyn Mailer>) -> Self {
+        self.mailer = mailer;
+        self
+    }
+
+    /// Replaces the password hasher; existing hashes are upgraded to it
+    /// at their next login
+    pub fn with_hasher(mut self, hasher: Arc<dyn PasswordHasher>) -> Self {
+        self.hasher = hasher;
+        self
+    }
+
+    /// Stored password hash of an account
+    pub async fn password_hash(&self, email: &str) -> Option<String> {
+        let email = self.config.email_policy.canonicalize(email);
+        self.users.read().await.get(&email).map(|u| u.password_hash.clone())
+    }
+
+    /// Overrides how long issued sessions (access tokens) stay valid
+    pub fn with_session_ttl(mut self, ttl: Duration) -> Self {
+        self.config.access_token_ttl = ttl;
+        self
+    }
+
+    /// Number of sessions currently stored, expired or not
+    pub async fn session_count(&self) -> usize {
+        self.sessions.read().await.len()
+    }
+
+    /// Starts a new refresh token family and returns the signed-in user
+    async fn issue_session(&self, user: &StoredUser) -> User {
+        let family_id = Uuid::new_v4();
+        let token = generate_random_token();
+        let expires_at = self.clock.now() + self.config.access_token_ttl;
+        self.sessions
+            .write()
+            .await
+            .insert(self.session_hash(&token), self.new_session(user.id, Some(family_id), expires_at));
+        self.issue_refresh_token(user, family_id, token, expires_at).await
+    }
+
+    /// Keyed hash an access token is stored under
+    fn session_hash(&self, token: &str) -> String {
+        hash_session_token(token, &self.config.session_token_key)
+    }
+
+    /// A session for `family_id`, describing the device from the current request
+    fn new_session(&self, user_id: Uuid, family_id: Option<Uuid>, expires_at: DateTime<Utc>) -> StoredSession {
+        let now = self.clock.now();
+        let meta = RequestMeta::current();
+        StoredSession {
+            id: Uuid::new_v4(),
+            user_id,
+            expires_at,
+            family_id,
+            device_label: device_label(meta.user_agent.as_deref()),
+            user_agent: meta.user_agent,
+            ip: meta.ip,
+            created_at: now,
+            last_seen_at: now,
+            impersonator_id: None,
+        }
+    }
+
+    /// Stores the next refresh token in `family_id` to go with access `token`
+    async fn issue_refresh_token(
+        &self,
+        user: &StoredUser,
+        family_id: Uuid,
+        token: String,
+        expires_at: DateTime<Utc>,
+    ) -> User {
+        let now = self.clock.now();
+        let refresh_token = generate_random_token();
+        self.refresh_tokens.write().await.insert(
+            hash_token(&refresh_token),
+            StoredRefreshToken {
+                user_id: user.id,
+                family_id,
+                expires_at: now + self.config.refresh_token_ttl,
+                used: false,
+                revoked: false,
+            },
+        );
+
+        let mut signed_in = user.to_user(token, expires_at);
+        signed_in.refresh_token = Some(refresh_token);
+        signed_in
+    }
+
+    /// Ends every token of a family after a replayed refresh token,
+    /// returning the ids of the sessions removed
+    async fn revoke_family(&self, family_id: Uuid) -> Vec<Uuid> {
+        for record in self.refresh_tokens.write().await.values_mut() {
+            if record.family_id == family_id {
+                record.revoked = true;
+            }
+        }
+        let mut sessions = self.sessions.write().await;
+        let session_ids = sessions
+            .values()
+            .filter(|s| s.family_id == Some(family_id))
+            .map(|s| s.id)
+            .collect();
+        sessions.retain(|_, s| s.family_id != Some(family_id));
+        session_ids
+    }
+
+    /// Counts a wrong password, unknown email or wrong second-factor code
+    async fn record_login_failure(&self, lockout_key: String) {
+        let now = self.clock.now();
+        let mut attempts = self.login_attempts.write().await;
+        let entry = attempts.entry(lockout_key).or_insert(StoredAttempts {
+            failed_count: 0,
+            last_failed_at: now,
+        });
+        entry.failed_count = lockout::next_failed_count(entry.failed_count, &self.config);
+        entry.last_failed_at = now;
+    }
+
+    /// Refuses the attempt while `lockout_key` is backing off or locked
+    async fn check_lockout(&self, lockout_key: &str) -> Result<(), AuthError> {
+        match self.login_attempts.read().await.get(lockout_key) {
+            Some(attempts) => lockout::check(
+                attempts.failed_count,
+                attempts.last_failed_at,
+                self.clock.now(),
+                &self.config,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Key the account's failed logins are counted under, as `authenticate`
+    /// derives it from the canonical email
+    async fn lockout_key_for(&self, id: Uuid) -> Option<String> {
+        self.users
+            .read()
+            .await
+            .iter()
+            .find(|(_, u)| u.id == id)
+            .map(|(canonical, _)| lockout::lockout_key(canonical))
+    }
+
+    async fn user_by_id(&self, id: Uuid) -> Option<StoredUser> {
+        self.users.read().await.values().find(|u| u.id == id).cloned()
+    }
+
+    /// Accepts a current TOTP code or burns an unused recovery code
+    async fn check_second_factor(&self, user_id: Uuid, code: &str) -> Result<bool, AuthError> {
+        let mut all = self.totp.write().await;
+        let Some(totp) = all.get_mut(&user_id).filter(|t| t.enabled) else {
+            return Ok(false);
+        };
+
+        if totp::is_totp_code(code) {
+            let step = totp::verify_code(&totp.secret, code, self.clock.now(), totp.last_used_step)?;
+            if step.is_some() {
+                totp.last_used_step = step;
+            }
+            return Ok(step.is_some());
+        }
+
+        let digest = totp::hash_recovery_code(code);
+        let before = totp.recovery_codes.len();
+        totp.recovery_codes.retain(|c| *c != digest);
+        Ok(totp.recovery_codes.len() < before)
+    }
+
+    /// Asks for the second factor if the user has one, otherwise signs them in
+    async fn finish_login(&self, user: &StoredUser) -> Result<User, AuthError> {
+        let has_totp = self
+            .totp
+            .read()
+            .await
+            .get(&user.id)
+            .is_some_and(|t| t.enabled);
+        if has_totp {
+            let challenge = generate_random_token();
+            self.mfa_challenges.write().await.insert(
+                hash_token(&challenge),
+                StoredChallenge {
+                    user_id: user.id,
+                    attempts: 0,
+                    expires_at: self.clock.now() + self.config.mfa_challenge_ttl,
+                },
+            );
+            return Err(AuthError::MfaRequired { challenge });
+        }
+
+        Ok(self.issue_session(user).await)
+    }
+
+    /// Creates an account without a usable password, for sign-in methods
+    /// that prove the user some other way
+    async fn create_passwordless_user(
+        &self,
+        address: &EmailAddress,
+        email_verified: bool,
+    ) -> Result<StoredUser, AuthError> {
+        if !self.config.allows_uninvited_signup() {
+            return Err(AuthError::RegistrationClosed);
+        }
+        self.config.email_policy.check_allowed(address)?;
+        let user = StoredUser {
+            id: Uuid::new_v4(),
+            email: address.to_string(),
+            password_hash: UNUSABLE_PASSWORD_HASH.to_owned(),
+            email_verified,
+            roles: vec![DEFAULT_ROLE.to_string()],
+        };
+        let canonical = self.config.email_policy.canonical(address);
+        let mut users = self.users.write().await;
+        if users.contains_key(&canonical) {
+            return Err(AuthError::UserExists);
+        }
+        users.insert(canonical, user.clone());
+        Ok(user)
+    }
+
+    /// Spends one use of a usable invitation for the account at `canonical`
+    async fn redeem_invitation(&self, code: &str, canonical: &str) -> Result<(), AuthError> {
+        let now = self.clock.now();
+        let mut invitations = self.invitations.write().await;
+        let invitation = invitations
+            .get_mut(&invitations::hash_invitation_code(code))
+            .filter(|i| i.is_usable(now) && i.email.as_deref().is_none_or(|email| email == canonical))
+            .ok_or(AuthError::InvalidInvitation)?;
+        invitation.use_count += 1;
+        Ok(())
+    }
+
+    /// Stores the challenge of a new passkey ceremony
+    async fn store_passkey_challenge(&self, ceremony: Ceremony, user_id: Option<Uuid>) -> String {
+        let challenge = webauthn::new_challenge();
+        let now = self.clock.now();
+        let mut challenges = self.passkey_challenges.write().await;
+        challenges.retain(|_, c| c.expires_at > now);
+        challenges.insert(
+            hash_token(&challenge),
+            StoredPasskeyChallenge {
+                ceremony,
+                user_id,
+                expires_at: now + self.config.passkey_challenge_ttl,
+            },
+        );
+        challenge
+    }
+
+    /// Spends the challenge a passkey response answers
+    async fn take_passkey_challenge(
+        &self,
+        client_data_json: &str,
+        ceremony: Ceremony,
+    ) -> Result<(String, Option<Uuid>), AuthError> {
+        let challenge = webauthn::response_challenge(client_data_json)?;
+        let mut challenges = self.passkey_challenges.write().await;
+        let key = hash_token(&challenge);
+        if challenges.get(&key).map(|c| c.ceremony) != Some(ceremony) {
+            return Err(AuthError::InvalidToken);
+        }
+        let record = challenges.remove(&key).ok_or(AuthError::InvalidToken)?;
+        if record.expires_at <= self.clock.now() {
+            return Err(AuthError::InvalidToken);
+        }
+        Ok((challenge, record.user_id))
+    }
+
+    /// Issues a verification token and mails the link
+    async fn send_verification(&self, user_id: Uuid, email: &str) -> Result<(), AuthError> {
+        let token = generate_random_token();
+        let now = self.clock.now();
+        self.verification_tokens.write().await.insert(
+            hash_token(&token),
+            StoredToken {
+                user_id,
+                expires_at: now + self.config.verification_ttl,
+                created_at: now,
+            },
+        );
+
+        self.mailer
+            .send(verification_email(email, &self.config.app_url, &token))
+            .await
+    }
+}
+
+#[async_trait]
+impl AuthProvider for InMemoryAuthProvider {
+    async fn register(&self, email: &str, password: &str, invite_code: Option<&str>) -> Result<(), AuthError> {
+        if !self.config.open_registration {
+            return Err(AuthError::RegistrationClosed);
+        }
+        let invite_code = invitations::code_to_redeem(&self.config, invite_code)?;
+        let address = self.config.email_policy.parse_new(email)?;
+        self.config.password_policy.validate(password, email)?;
+
+        let id = Uuid::new_v4();
+        let email = address.to_string();
+        {
+            let canonical = self.config.email_policy.canonical(&address);
+            let mut users = self.users.write().await;
+            if users.contains_key(&canonical) {
+                return Err(AuthError::UserExists);
+            }
+
+            let password_hash = self.hasher.hash(password)?;
+            if let Some(code) = invite_code {
+                self.redeem_invitation(code, &canonical).await?;
+            }
+            users.insert(
+                canonical,
+                StoredUser {
+                    id,
+                    email: email.clone(),
+                    password_hash,
+                    email_verified: false,
+                    roles: vec![DEFAULT_ROLE.to_string()],
+                },
+            );
+        }
+
+        self.send_verification(id, &email).await
+    }
+
+    async fn authenticate(&self, email: &str, password: &str) -> Result<User, AuthError> {
+        let email = self.config.email_policy.canonicalize(email);
+        let lockout_key = lockout::lockout_key(&email);
+        self.check_lockout(&lockout_key).await?;
+
+        let user = self.users.read().await.get(&email).cloned();
+        let Some(user) = user.filter(|u| self.hasher.verify(password, &u.password_hash)) else {
+            self.record_login_failure(lockout_key).await;
+            return Err(AuthError::AuthenticationFailed);
+        };
+        if self.hasher.needs_rehash(&user.password_hash) {
+            let password_hash = self.hasher.hash(password)?;
+            if let Some(stored) = self.users.write().await.get_mut(&email) {
+                stored.password_hash = password_hash;
+            }
+        }
+        if self.config.require_email_verification && !user.email_verified {
+            return Err(AuthError::EmailNotVerified);
+        }
+
+        // The streak only ends once any second factor has been passed too
+        let session = self.finish_login(&user).await?;
+        self.login_attempts.write().await.remove(&lockout_key);
+        Ok(session)
+    }
+
+    async fn request_magic_link(&self, email: &str) -> Result<String, AuthError> {
+        let address = EmailAddress::parse(email)?;
+        let browser_secret = generate_random_token();
+        let canonical = self.config.email_policy.canonical(&address);
+        let exists = self.users.read().await.contains_key(&canonical);
+        if !exists {
+            if !self.config.allows_uninvited_signup() {
+                return Ok(browser_secret);
+            }
+            self.config.email_policy.check_allowed(&address)?;
+        }
+        let email = address.to_string();
+
+        let token = generate_random_token();
+        {
+            let mut links = self.magic_links.write().await;
+            // Only the most recent link works
+            links.retain(|_, l| self.config.email_policy.canonicalize(&l.email) != canonical);
+            links.insert(
+                hash_token(&token),
+                StoredMagicLink {
+                    email: email.clone(),
+                    browser_hash: hash_token(&browser_secret),
+                    expires_at: self.clock.now() + self.config.magic_link_ttl,
+                },
+            );
+        }
+
+        self.mailer
+            .send(magic_link_email(&email, &self.config.app_url, &token))
+            .await?;
+        Ok(browser_secret)
+    }
+
+    async fn login_with_magic_link(&self, token: &str, browser_secret: &str) -> Result<User, AuthError> {
+        let link = {
+            let mut links = self.magic_links.write().await;
+            let key = hash_token(token);
+            // Opened elsewhere: leave the link for the browser that asked
+            if links.get(&key).map(|l| l.browser_hash.as_str()) != Some(hash_token(browser_secret).as_str()) {
+                return Err(AuthError::InvalidToken);
+            }
+            links.remove(&key).ok_or(AuthError::InvalidToken)?
+        };
+        if link.expires_at <= self.clock.now() {
+            return Err(AuthError::InvalidToken);
+        }
+
+        let address = EmailAddress::parse(&link.email)?;
+        let canonical = self.config.email_policy.canonical(&address);
+        let existing = self.users.write().await.get_mut(&canonical).map(|user| {
+            user.email_verified = true;
+            user.clone()
+        });
+        let user = match existing {
+            Some(user) => user,
+            None => self.create_passwordless_user(&address, true).await?,
+        };
+
+        self.finish_login(&user).await
+    }
+
+    async fn login_with_identity(&self, identity: &ExternalIdentity) -> Result<User, AuthError> {
+        let key = (identity.issuer.clone(), identity.subject.clone());
+        let linked = self.identities.read().await.get(&key).copied();
+        if let Some(user_id) = linked {
+            let user = self.user_by_id(user_id).await.ok_or(AuthError::AuthenticationFailed)?;
+            return self.finish_login(&user).await;
+        }
+
+        let address = EmailAddress::parse(identity.email.as_deref().unwrap_or_default())?;
+        let canonical = self.config.email_policy.canonical(&address);
+        let existing = self.users.read().await.get(&canonical).cloned();
+        if let Some(user) = existing {
+            // Only link when both sides vouch for the address
+            if !(identity.email_verified && user.email_verified) {
+                return Err(AuthError::UserExists);
+            }
+            self.identities.write().await.insert(key, user.id);
+            return self.finish_login(&user).await;
+        }
+
+        let user = self.create_passwordless_user(&address, identity.email_verified).await?;
+        self.identities.write().await.insert(key, user.id);
+
+        if !user.email_verified && self.config.require_email_verification {
+            self.send_verification(user.id, &user.email).await?;
+            return Err(AuthError::EmailNotVerified);
+        }
+        self.finish_login(&user).await
+    }
+
+    async fn link_identity(&self, user_id: Uuid, identity: &ExternalIdentity) -> Result<(), AuthError> {
+        let mut identities = self.identities.write().await;
+        let key = (identity.issuer.clone(), identity.subject.clone());
+        match identities.get(&key) {
+            Some(owner) if *owner != user_id => Err(AuthError::UserExists),
+            _ => {
+                identities.insert(key, user_id);
+                Ok(())
+            }
+        }
+    }
+
+    async fn begin_passkey_registration(&self, user_id: Uuid) -> Result<PasskeyCreationOptions, AuthError> {
+        let user = self.user_by_id(user_id).await.ok_or(AuthError::Unauthorized)?;
+        let existing: Vec<Vec<u8>> = self
+            .passkeys
+            .read()
+            .await
+            .iter()
+            .filter(|(_, p)| p.user_id == user_id)
+            .map(|(credential_id, _)| credential_id.clone())
+            .collect();
+
+        let challenge = self.store_passkey_challenge(Ceremony::Register, Some(user_id)).await;
+        Ok(webauthn::creation_options(&self.config, user_id, &user.email, challenge, &existing))
+    }
+
+    async fn finish_passkey_registration(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        credential: &PasskeyCredential,
+    ) -> Result<PasskeyInfo, AuthError> {
+        let (challenge, owner) = self
+            .take_passkey_challenge(&credential.client_data_json, Ceremony::Register)
+            .await?;
+        if owner != Some(user_id) {
+            return Err(AuthError::InvalidToken);
+        }
+        let verified = webauthn::verify_registration(&self.config, &challenge, credential)?;
+
+        let mut passkeys = self.passkeys.write().await;
+        if passkeys.contains_key(&verified.credential_id) {
+            return Err(AuthError::InvalidPasskey);
+        }
+        let passkey = StoredPasskey {
+            id: Uuid::new_v4(),
+            user_id,
+            public_key: verified.public_key,
+            sign_count: verified.sign_count,
+            name: match name.trim() {
+                "" => "Passkey".to_owned(),
+                name => name.to_owned(),
+            },
+            created_at: self.clock.now(),
+            last_used_at: None,
+        };
+        let info = passkey.info();
+        passkeys.insert(verified.credential_id, passkey);
+        Ok(info)
+    }
+
+    async fn begin_passkey_authentication(&self) -> Result<PasskeyRequestOptions, AuthError> {
+        let challenge = self.store_passkey_challenge(Ceremony::Authenticate, None).await;
+        Ok(webauthn::request_options(&self.config, challenge))
+    }
+
+    async fn authenticate_passkey(&self, assertion: &PasskeyAssertion) -> Result<User, AuthError> {
+        let (challenge, _) = self
+            .take_passkey_challenge(&assertion.client_data_json, Ceremony::Authenticate)
+            .await?;
+        let credential_id = webauthn::decode(&assertion.credential_id)?;
+
+        let user_id = {
+            let mut passkeys = self.passkeys.write().await;
+            let passkey = passkeys
+                .get_mut(&credential_id)
+                .ok_or(AuthError::AuthenticationFailed)?;
+            if let Some(handle) = &assertion.user_handle {
+                if webauthn::decode(handle)? != passkey.user_id.as_bytes() {
+                    return Err(AuthError::AuthenticationFailed);
+                }
+            }
+            passkey.sign_count = webauthn::verify_assertion(
+                &self.config,
+                &challenge,
+                assertion,
+                &passkey.public_key,
+                passkey.sign_count,
+            )?;
+            passkey.last_used_at = Some(self.clock.now());
+            passkey.user_id
+        };
+
+        let user = self.user_by_id(user_id).await.ok_or(AuthError::AuthenticationFailed)?;
+        Ok(self.issue_session(&user).await)
+    }
+
+    async fn list_passkeys(&self, user_id: Uuid) -> Result<Vec<PasskeyInfo>, AuthError> {
+        let mut passkeys: Vec<PasskeyInfo> = self
+            .passkeys
+            .read()
+            .await
+            .values()
+            .filter(|p| p.user_id == user_id)
+            .map(StoredPasskey::info)
+            .collect();
+        passkeys.sort_by_key(|p| p.created_at);
+        Ok(passkeys)
+    }
+
+    async fn delete_passkey(&self, user_id: Uuid, passkey_id: Uuid) -> Result<bool, AuthError> {
+        let mut passkeys = self.passkeys.write().await;
+        let before = passkeys.len();
+        passkeys.retain(|_, p| !(p.id == passkey_id && p.user_id == user_id));
+        Ok(passkeys.len() < before)
+    }
+
+    async fn create_api_key(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<NewApiKey, AuthError> {
+        let owner = self.user_by_id(user_id).await.ok_or(AuthError::Unauthorized)?;
+        let permissions = owner.to_user(String::new(), self.clock.now()).permissions;
+        let scopes = api_keys::check_scopes(scopes, &permissions)?;
+
+        let key = api_keys::generate_api_key();
+        let info = ApiKeyInfo {
+            id: Uuid::new_v4(),
+            name: match name.trim() {
+                "" => "API key".to_owned(),
+                name => name.to_owned(),
+            },
+            prefix: api_keys::display_prefix(&key),
+            scopes,
+            expires_at,
+            last_used_at: None,
+            created_at: self.clock.now(),
+        };
+        self.api_keys
+            .write()
+            .await
+            .insert(hash_token(&key), StoredApiKey { user_id, info: info.clone() });
+        Ok(NewApiKey { key, api_key: info })
+    }
+
+    async fn list_api_keys(&self, user_id: Uuid) -> Result<Vec<ApiKeyInfo>, AuthError> {
+        let mut keys: Vec<ApiKeyInfo> = self
+            .api_keys
+            .read()
+            .await
+            .values()
+            .filter(|k| k.user_id == user_id)
+            .map(|k| k.info.clone())
+            .collect();
+        keys.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(keys)
+    }
+
+    async fn revoke_api_key(&self, user_id: Uuid, key_id: Uuid) -> Result<bool, AuthError> {
+        let mut keys = self.api_keys.write().await;
+        let before = keys.len();
+        keys.retain(|_, k| !(k.info.id == key_id && k.user_id == user_id));
+        Ok(keys.len() < before)
+    }
+
+    async fn create_invitation(
+        &self,
+        email: Option<&str>,
+        max_uses: Option<u32>,
+        expires_at: Option<DateTime<Utc>>,
+        invited_by: Option<Uuid>,
+    ) -> Result<NewInvitation, AuthError> {
+        let max_uses = invitations::check_max_uses(max_uses)?;
+        let email = invitations::bound_email(&self.config.email_policy, email)?;
+        let code = invitations::generate_invitation_code();
+        let invitation = InvitationInfo {
+            id: Uuid::new_v4(),
+            email,
+            max_uses,
+            use_count: 0,
+            invited_by,
+            expires_at,
+            revoked_at: None,
+            created_at: self.clock.now(),
+        };
+        self.invitations
+            .write()
+            .await
+            .insert(invitations::hash_invitation_code(&code), invitation.clone());
+        Ok(NewInvitation { code, invitation })
+    }
+
+    async fn list_invitations(&self) -> Result<Vec<InvitationInfo>, AuthError> {
+        let mut invitations: Vec<InvitationInfo> = self.invitations.read().await.values().cloned().collect();
+        invitations.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(invitations)
+    }
+
+    async fn revoke_invitation(&self, invitation_id: Uuid) -> Result<bool, AuthError> {
+        let mut invitations = self.invitations.write().await;
+        match invitations.values_mut().find(|i| i.id == invitation_id && i.revoked_at.is_none()) {
+            Some(invitation) => {
+                invitation.revoked_at = Some(self.clock.now());
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn authenticate_api_key(&self, key: &str) -> Result<User, AuthError> {
+        let now = self.clock.now();
+        let stored = {
+            let mut keys = self.api_keys.write().await;
+            let stored = keys.get_mut(&hash_token(key)).ok_or(AuthError::InvalidSession)?;
+            if stored.info.expires_at.is_some_and(|expires_at| expires_at <= now) {
+                return Err(AuthError::InvalidSession);
+            }
+            stored.info.last_used_at = Some(now);
+            stored.clone()
+        };
+
+        let user = self.user_by_id(stored.user_id).await.ok_or(AuthError::InvalidSession)?;
+        let expires_at =
+            api_keys::session_expires_at(stored.info.expires_at, now, self.config.access_token_ttl);
+        Ok(api_keys::scoped_user(user.to_user(key.to_owned(), expires_at), &stored.info.scopes))
+    }
+
+    async fn validate_session(&self, token: &str) -> Result<User, AuthError> {
+        let token_hash = self.session_hash(token);
+        let session = self
+            .sessions
+            .read()
+            .await
+            .get(&token_hash)
+            .cloned()
+            .ok_or(AuthError::InvalidSession)?;
+
+        let now = self.clock.now();
+        if session.expires_at <= now {
+            // Sessions with a refresh family stay listed until the family ends
+            if session.family_id.is_none() {
+                self.sessions.write().await.remove(&token_hash);
+            }
+            return Err(AuthError::InvalidSession);
+        }
+        if let Some(stored) = self.sessions.write().await.get_mut(&token_hash) {
+            stored.last_seen_at = now;
+            if let Some(ip) = RequestMeta::current().ip {
+                stored.ip = Some(ip);
+            }
+        }
+
+        let users = self.users.read().await;
+        let user = users
+            .values()
+            .find(|u| u.id == session.user_id)
+            .ok_or(AuthError::InvalidSession)?;
+
+        let mut user = user.to_user(token.to_owned(), session.expires_at);
+        user.impersonator_id = session.impersonator_id;
+        Ok(user)
+    }
+
+    async fn logout(&self, token: &str) -> Result<(), AuthError> {
+        let session = self
+            .sessions
+            .write()
+            .await
+            .remove(&self.session_hash(token))
+            .ok_or(AuthError::InvalidSession)?;
+
+        if let Some(family_id) = session.family_id {
+            self.revoke_family(family_id).await;
+        }
+        Ok(())
+    }
+
+    async fn refresh(&self, refresh_token: &str) -> Result<User, AuthError> {
+        let record = {
+            let mut tokens = self.refresh_tokens.write().await;
+            let record = tokens
+                .get_mut(&hash_token(refresh_token))
+                .ok_or(AuthError::InvalidToken)?;
+            if record.revoked {
+                return Err(AuthError::InvalidToken);
+            }
+            let replayed = record.used;
+            record.used = true;
+            let record = record.clone();
+            if replayed {
+                drop(tokens);
+                let session_ids = self.revoke_family(record.family_id).await;
+                return Err(AuthError::TokenReuseDetected { user_id: record.user_id, session_ids });
+            }
+            record
+        };
+        if record.expires_at <= self.clock.now() {
+            return Err(AuthError::InvalidToken);
+        }
+
+        let user = self
+            .user_by_id(record.user_id)
+            .await
+            .ok_or(AuthError::InvalidToken)?;
+
+        // The session moves onto the new access token, the old one stops working
+        let token = generate_random_token();
+        let now = self.clock.now();
+        let expires_at = now + self.config.access_token_ttl;
+        {
+            let mut sessions = self.sessions.write().await;
+            let old_hash = sessions
+                .iter()
+                .find(|(_, s)| s.family_id == Some(record.family_id))
+                .map(|(h, _)| h.clone());
+            let session = match old_hash.and_then(|h| sessions.remove(&h)) {
+                Some(mut session) => {
+                    session.expires_at = expires_at;
+                    session.last_seen_at = now;
+                    if let Some(ip) = RequestMeta::current().ip {
+                        session.ip = Some(ip);
+                    }
+                    session
+                }
+                None => self.new_session(user.id, Some(record.family_id), expires_at),
+            };
+            sessions.insert(self.session_hash(&token), session);
+        }
+        Ok(self.issue_refresh_token(&user, record.family_id, token, expires_at).await)
+    }
+
+    async fn list_sessions(&self, user_id: Uuid, current_token: &str) -> Result<Vec<SessionInfo>, AuthError> {
+        let now = self.clock.now();
+        let current_hash = self.session_hash(current_token);
+        let refresh_tokens = self.refresh_tokens.read().await;
+        let renewable = |family_id: Option<Uuid>| {
+            refresh_tokens.values().any(|r| {
+                Some(r.family_id) == family_id && !r.used && !r.revoked && r.expires_at > now
+            })
+        };
+
+        let mut sessions: Vec<SessionInfo> = self
+            .sessions
+            .read()
+            .await
+            .iter()
+            .filter(|(_, s)| s.user_id == user_id)
+            .filter(|(_, s)| s.expires_at > now || renewable(s.family_id))
+            .map(|(token_hash, s)| SessionInfo {
+                id: s.id,
+                device_label: s.device_label.clone(),
+                user_agent: s.user_agent.clone(),
+                ip: s.ip.clone(),
+                created_at: s.created_at,
+                last_seen_at: s.last_seen_at,
+                current: *token_hash == current_hash,
+            })
+            .collect();
+        sessions.sort_by(|a, b| b.last_seen_at.cmp(&a.last_seen_at));
+        Ok(sessions)
+    }
+
+    async fn revoke_session(&self, user_id: Uuid, session_id: Uuid) -> Result<bool, AuthError> {
+        let session = self
+            .sessions
+            .read()
+            .await
+            .iter()
+            .find(|(_, s)| s.id == session_id && s.user_id == user_id)
+            .map(|(token_hash, s)| (token_hash.clone(), s.family_id));
+
+        match session {
+            Some((_, Some(family_id))) => {
+                self.revoke_family(family_id).await;
+            }
+            Some((token_hash, None)) => {
+                self.sessions.write().await.remove(&token_hash);
+            }
+            None => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    async fn revoke_other_sessions(&self, user_id: Uuid, keep_token: &str) -> Result<u64, AuthError> {
+        let keep_hash = self.session_hash(keep_token);
+        let kept_family = self.sessions.read().await.get(&keep_hash).and_then(|s| s.family_id);
+        for record in self.refresh_tokens.write().await.values_mut() {
+            if record.user_id == user_id && Some(record.family_id) != kept_family {
+                record.revoked = true;
+            }
+        }
+
+        let mut sessions = self.sessions.write().await;
+        let before = sessions.len();
+        sessions.retain(|token_hash, s| s.user_id != user_id || *token_hash == keep_hash);
+        Ok((before - sessions.len()) as u64)
+    }
+
+    async fn impersonate(&self, impersonator_id: Uuid, user_id: Uuid) -> Result<User, AuthError> {
+        if impersonator_id == user_id {
+            return Err(AuthError::Forbidden);
+        }
+        let target = self.user_by_id(user_id).await.ok_or(AuthError::UserNotFound)?;
+        if target.roles.iter().any(|r| r == ADMIN_ROLE) {
+            return Err(AuthError::Forbidden);
+        }
+
+        let token = generate_random_token();
+        let expires_at = self.clock.now() + self.config.impersonation_ttl;
+        let mut session = self.new_session(user_id, None, expires_at);
+        session.impersonator_id = Some(impersonator_id);
+        self.sessions.write().await.insert(self.session_hash(&token), session);
+
+        let mut user = target.to_user(token, expires_at);
+        user.impersonator_id = Some(impersonator_id);
+        Ok(user)
+    }
+
+    async fn verify_email(&self, token: &str) -> Result<(), AuthError> {
+        let record = self
+            .verification_tokens
+            .write()
+            .await
+            .remove(&hash_token(token))
+            .ok_or(AuthError::InvalidToken)?;
+
+        if record.expires_at <= self.clock.now() {
+            return Err(AuthError::InvalidToken);
+        }
+
+        if let Some(user) = self
+            .users
+            .write()
+            .await
+            .values_mut()
+            .find(|u| u.id == record.user_id)
+        {
+            user.email_verified = true;
+        }
+        self.verification_tokens
+            .write()
+            .await
+            .retain(|_, t| t.user_id != record.user_id);
+
+        Ok(())
+    }
+
+    async fn resend_verification(&self, email: &str) -> Result<(), AuthError> {
+        let email = self.config.email_policy.canonicalize(email);
+        let Some(user) = self.users.read().await.get(&email).cloned() else {
+            return Ok(());
+        };
+        if user.email_verified {
+            return Ok(());
+        }
+
+        let now = self.clock.now();
+        let recent: Vec<DateTime<Utc>> = self
+            .verification_tokens
+            .read()
+            .await
+            .values()
+            .filter(|t| t.user_id == user.id && t.created_at > now - Duration::hours(24))
+            .map(|t| t.created_at)
+            .collect();
+
+        let too_soon = recent
+            .iter()
+            .max()
+            .is_some_and(|last| now - *last < self.config.verification_resend_interval);
+        if too_soon || recent.len() as i64 >= self.config.verification_max_per_day {
+            return Err(AuthError::RateLimited);
+        }
+
+        self.send_verification(user.id, &user.email).await
+    }
+
+    async fn request_password_reset(&self, email: &str) -> Result<(), AuthError> {
+        let email = self.config.email_policy.canonicalize(email);
+        let Some(user) = self.users.read().await.get(&email).cloned() else {
+            return Ok(());
+        };
+
+        let token = generate_random_token();
+        let now = self.clock.now();
+        {
+            let mut reset_tokens = self.reset_tokens.write().await;
+            // Only the most recent link works
+            reset_tokens.retain(|_, t| t.user_id != user.id);
+            reset_tokens.insert(
+                hash_token(&token),
+                StoredToken {
+                    user_id: user.id,
+                    expires_at: now + self.config.password_reset_ttl,
+                    created_at: now,
+                },
+            );
+        }
+
+        self.mailer
+            .send(password_reset_email(&user.email, &self.config.app_url, &token))
+            .await
+    }
+
+    async fn reset_password(&self, token: &str, new_password: &str) -> Result<(Uuid, Vec<Uuid>), AuthError> {
+        // Validate against the account first so a weak password doesn't burn the token
+        let token_hash = hash_token(token);
+        let user_id = self
+            .reset_tokens
+            .read()
+            .await
+            .get(&token_hash)
+            .filter(|record| record.expires_at > self.clock.now())
+            .map(|record| record.user_id)
+            .ok_or(AuthError::InvalidToken)?;
+        let email = self
+            .users
+            .read()
+            .await
+            .values()
+            .find(|u| u.id == user_id)
+            .map(|u| u.email.clone())
+            .ok_or(AuthError::InvalidToken)?;
+        self.config.password_policy.validate(new_password, &email)?;
+
+        let record = self
+            .reset_tokens
+            .write()
+            .await
+            .remove(&token_hash)
+            .ok_or(AuthError::InvalidToken)?;
+        if record.expires_at <= self.clock.now() {
+            return Err(AuthError::InvalidToken);
+        }
+
+        let password_hash = self.hasher.hash(new_password)?;
+        if let Some(user) = self
+            .users
+            .write()
+            .await
+            .values_mut()
+            .find(|u| u.id == record.user_id)
+        {
+            user.password_hash = password_hash;
+        }
+        let mut sessions = self.sessions.write().await;
+        let session_ids = sessions
+            .values()
+            .filter(|s| s.user_id == record.user_id || s.impersonator_id == Some(record.user_id))
+            .map(|s| s.id)
+            .collect();
+        sessions.retain(|_, s| s.user_id != record.user_id && s.impersonator_id != Some(record.user_id));
+        drop(sessions);
+        for token in self.refresh_tokens.write().await.values_mut() {
+            if token.user_id == record.user_id {
+                token.revoked = true;
+            }
+        }
+
+        Ok((record.user_id, session_ids))
+    }
+
+    async fn change_password(
+        &self,
+        user_id: Uuid,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<(), AuthError> {
+        let user = self.user_by_id(user_id).await.ok_or(AuthError::UserNotFound)?;
+        let lockout_key = self
+            .lockout_key_for(user_id)
+            .await
+            .ok_or(AuthError::UserNotFound)?;
+        self.check_lockout(&lockout_key).await?;
+
+        if !self.hasher.verify(current_password, &user.password_hash) {
+            self.record_login_failure(lockout_key).await;
+            return Err(AuthError::AuthenticationFailed);
+        }
+        self.config.password_policy.validate(new_password, &user.email)?;
+
+        let password_hash = self.hasher.hash(new_password)?;
+        if let Some(user) = self.users.write().await.values_mut().find(|u| u.id == user_id) {
+            user.password_hash = password_hash;
+        }
+        Ok(())
+    }
+
+    async fn assign_role(&self, user_id: Uuid, role: &str) -> Result<(), AuthError> {
+        if !BUILTIN_ROLES.iter().any(|(name, _)| *name == role) {
+            return Err(AuthError::UnknownRole);
+        }
+
+        let mut users = self.users.write().await;
+        let user = users
+            .values_mut()
+            .find(|u| u.id == user_id)
+            .ok_or(AuthError::UserNotFound)?;
+        if !user.roles.iter().any(|r| r == role) {
+            user.roles.push(role.to_owned());
+            user.roles.sort();
+        }
+        Ok(())
+    }
+
+    async fn revoke_role(&self, user_id: Uuid, role: &str) -> Result<(), AuthError> {
+        if let Some(user) = self
+            .users
+            .write()
+            .await
+            .values_mut()
+            .find(|u| u.id == user_id)
+        {
+            user.roles.retain(|r| r != role);
+        }
+        Ok(())
+    }
+
+    async fn verify_mfa(&self, challenge: &str, code: &str) -> Result<User, AuthError> {
+        let digest = hash_token(challenge);
+        let record = self
+            .mfa_challenges
+            .read()
+            .await
+            .get(&digest)
+            .cloned()
+            .ok_or(AuthError::InvalidToken)?;
+        if record.expires_at <= self.clock.now() {
+            self.mfa_challenges.write().await.remove(&digest);
+            return Err(AuthError::InvalidToken);
+        }
+        let lockout_key = self
+            .lockout_key_for(record.user_id)
+            .await
+            .ok_or(AuthError::InvalidToken)?;
+        self.check_lockout(&lockout_key).await?;
+
+        if !self.check_second_factor(record.user_id, code).await? {
+            self.record_login_failure(lockout_key).await;
+            let mut challenges = self.mfa_challenges.write().await;
+            if let Some(pending) = challenges.get_mut(&digest) {
+                pending.attempts += 1;
+                if pending.attempts >= self.config.mfa_max_attempts {
+                    challenges.remove(&digest);
+                    return Err(AuthError::InvalidToken);
+                }
+            }
+            return Err(AuthError::InvalidMfaCode);
+        }
+
+        self.mfa_challenges.write().await.remove(&digest);
+        self.login_attempts.write().await.remove(&lockout_key);
+        let user = self
+            .user_by_id(record.user_id)
+            .await
+            .ok_or(AuthError::InvalidToken)?;
+        Ok(self.issue_session(&user).await)
+    }
+
+    async fn mfa_challenge_email(&self, challenge: &str) -> Result<String, AuthError> {
+        let record = self
+            .mfa_challenges
+            .read()
+            .await
+            .get(&hash_token(challenge))
+            .cloned()
+            .ok_or(AuthError::InvalidToken)?;
+        if record.expires_at <= self.clock.now() {
+            return Err(AuthError::InvalidToken);
+        }
+        let user = self
+            .user_by_id(record.user_id)
+            .await
+            .ok_or(AuthError::InvalidToken)?;
+        Ok(user.email)
+    }
+
+    async fn begin_totp_enrollment(&self, user_id: Uuid) -> Result<TotpEnrollment, AuthError> {
+        let user = self.user_by_id(user_id).await.ok_or(AuthError::Unauthorized)?;
+
+        let mut all = self.totp.write().await;
+        if all.get(&user_id).is_some_and(|t| t.enabled) {
+            return Err(AuthError::MfaAlreadyEnabled);
+        }
+
+        let secret = totp::generate_secret();
+        all.insert(
+            user_id,
+            StoredTotp {
+                secret: secret.clone(),
+                enabled: false,
+                last_used_step: None,
+                recovery_codes: Vec::new(),
+            },
+        );
+
+        totp::enrollment(&secret, &self.config.totp_issuer, &user.email)
+    }
+
+    async fn confirm_totp_enrollment(&self, user_id: Uuid, code: &str) -> Result<Vec<String>, AuthError> {
+        let mut all = self.totp.write().await;
+        let pending = all.get_mut(&user_id).ok_or(AuthError::MfaNotEnrolled)?;
+        if pending.enabled {
+            return Err(AuthError::MfaAlreadyEnabled);
+        }
+
+        let step = totp::verify_code(&pending.secret, code, self.clock.now(), None)?
+            .ok_or(AuthError::InvalidMfaCode)?;
+
+        let codes = totp::generate_recovery_codes();
+        pending.enabled = true;
+        pending.last_used_step = Some(step);
+        pending.recovery_codes = codes.iter().map(|c| totp::hash_recovery_code(c)).collect();
+
+        Ok(codes)
+    }
+
+    async fn disable_totp(&self, user_id: Uuid, code: &str) -> Result<(), AuthError> {
+        let enabled = self
+            .totp
+            .read()
+            .await
+            .get(&user_id)
+            .is_some_and(|t| t.enabled);
+        if !enabled {
+            return Err(AuthError::MfaNotEnrolled);
+        }
+
+        if !self.check_second_factor(user_id, code).await? {
+            return Err(AuthError::InvalidMfaCode);
+        }
+
+        self.totp.write().await.remove(&user_id);
+        Ok(())
+    }
+
+    async fn unlock_account(&self, email: &str) -> Result<bool, AuthError> {
+        let email = self.config.email_policy.canonicalize(email);
+        Ok(self
+            .login_attempts
+            .write()
+            .await
+            .remove(&lockout::lockout_key(&email))
+            .is_some())
+    }
+}
@@ -1,84 +1,167 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
 use axum::{
-    http::{Request, StatusCode},
+    body::Body,
+    extract::{ConnectInfo, MatchedPath, State},
+    http::{header, Request, StatusCode},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
     Extension,
 };
-use crate::{
-    server::auth::{AuthContext, AuthError},
-    db::models::UserSession,
+use crate::server::{
+    audit::RequestMeta,
+    auth::{api_keys::{self, ApiKeyRequest}, cookies, rate_limit::RateLimiter, AuthContext},
+    AuthError, User,
 };
 
-use crate::utils:session_cache;
 /// Authentication middleware for Axum routes
 ///
 /// # Flow
-/// 1. Extracts token from headers
-/// 2. Validates session
+/// 1. Extracts token from the header or session cookie, per `SessionMode`
+/// 2. Validates session, through the `AuthContext` session cache, or
+///    checks the signature alone when signed tokens are on
 /// 3. Attaches user to request
-pub async fn auth_middleware<B>(
+///
+/// Cookie-authenticated requests must also pass the CSRF check. A bearer
+/// API key is accepted in any `SessionMode`; the attached user then only
+/// has the key's scopes as permissions, and the request carries an
+/// `ApiKeyRequest` extension.
+///
+/// Requests on an impersonation session run with the admin in
+/// `RequestMeta`, so every audit event they raise names them, and each
+/// state-changing request is itself logged as `impersonated_request`.
+pub async fn auth_middleware(
     Extension(auth): Extension<Arc<AuthContext>>,
-    mut request: Request<B>,
-    next: Next<B>,
+    mut request: Request<Body>,
+    next: Next,
 ) -> Result<Response, AuthError> {
-    // 1. Extract token from Authorization header
-    let token = extract_bearer_token(&request)
-        .ok_or(AuthError::InvalidToken)?;
+    // API keys are for scripts and work whatever the session mode
+    let bearer = extract_bearer_token(&request);
+    if let Some(key) = bearer.as_deref().filter(|token| api_keys::is_api_key(token)) {
+        let user = auth.resolve_api_key(key).await?;
+        request.extensions_mut().insert(ApiKeyRequest);
+        request.extensions_mut().insert(user);
+        return Ok(next.run(request).await);
+    }
+
+    // 1. Extract token from Authorization header or session cookie
+    let mode = auth.session_config().mode;
+    let bearer = bearer.filter(|_| mode.accepts_bearer());
+    let token = match bearer {
+        Some(token) => token,
+        None if mode.accepts_cookie() => {
+            let token = cookies::read_cookie(request.headers(), cookies::SESSION_COOKIE)
+                .ok_or(AuthError::Unauthorized)?
+                .to_owned();
+            cookies::check_csrf(request.method(), request.headers())?;
+            token
+        }
+        None => return Err(AuthError::Unauthorized),
+    };
 
     // 2. Validate session
-    let session = validate_session(&auth, &token).await?;
+    let user = auth.resolve_session(&token).await?;
 
     // 3. Attach user to request extensions
-    request.extensions_mut().insert(session.user);
+    let user_id = user.id;
+    let impersonator_id = user.impersonator_id;
+    request.extensions_mut().insert(user);
 
-    Ok(next.run(request).await)
+    let Some(impersonator_id) = impersonator_id else {
+        return Ok(next.run(request).await);
+    };
+    let meta = RequestMeta { impersonator_id: Some(impersonator_id), ..RequestMeta::current() };
+    Ok(meta
+        .scope(async move {
+            if !request.method().is_safe() {
+                auth.record_impersonated_request(user_id, request.method().as_str(), request.uri().path())
+                    .await;
+            }
+            next.run(request).await
+        })
+        .await)
 }
 
 /// Extracts Bearer token from Authorization header
 fn extract_bearer_token<B>(request: &Request<B>) -> Option<String> {
     request.headers()
-        .get("Authorization")?
+        .get(header::AUTHORIZATION)?
         .to_str()
         .ok()?
         .strip_prefix("Bearer ")
         .map(|s| s.to_string())
 }
 
-/// Validates session against database
-async fn validate_session(
-    auth: &AuthContext,
-    token: &str
-) -> Result<UserSession, AuthError> {
-    // Check cache first
-    if let Some(session) = auth.session_cache.get(token).await {
-        return Ok(session);
+/// CSRF middleware for cookie sessions
+///
+/// Rejects state-changing requests that carry a session cookie but no
+/// matching `X-CSRF-Token` header, including routes without
+/// `auth_middleware` such as logout and refresh. Requests authenticated
+/// by bearer header alone aren't sent automatically by browsers and pass.
+pub async fn csrf_protect(
+    Extension(auth): Extension<Arc<AuthContext>>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response, AuthError> {
+    if auth.session_config().mode.accepts_cookie() && cookies::has_session_cookie(request.headers()) {
+        cookies::check_csrf(request.method(), request.headers())?;
     }
 
-    // Fallback to database
-    let session = auth.db.get_session(token).await?;
-    
-    // Validate expiration
-    if session.expires_at < Utc::now() {
-        return Err(AuthError::ExpiredToken);
-    }
+    Ok(next.run(request).await)
+}
 
-    // Cache valid session
-    auth.session_cache.set(token, &session).await;
+/// Closes a route to API keys
+///
+/// For routes that manage the account itself, such as sessions and keys,
+/// which a leaked key must not be able to extend. Must run after
+/// `auth_middleware`.
+pub async fn require_session(request: Request<Body>, next: Next) -> Result<Response, AuthError> {
+    if request.extensions().get::<ApiKeyRequest>().is_some() {
+        return Err(AuthError::Forbidden);
+    }
 
-    Ok(session)
+    Ok(next.run(request).await)
 }
 
 /// Role-based access control middleware
-pub async fn require_role<B>(
-    request: Request<B>,
-    next: Next<B>,
-    required_role: &str,
+///
+/// Must run after `auth_middleware`, which attaches the `User`. Bind the
+/// role with `from_fn_with_state`:
+///
+/// ```rust
+/// let admin = Router::new()
+///     .route("/api/admin/users", get(list_users))
+///     .route_layer(middleware::from_fn_with_state(ADMIN_ROLE, require_role))
+///     .route_layer(middleware::from_fn(auth_middleware));
+/// ```
+pub async fn require_role(
+    State(required_role): State<&'static str>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let user = request.extensions()
+        .get::<User>()
+        .ok_or(AuthError::Unauthorized)?;
+
+    if !user.has_role(required_role) {
+        return Err(AuthError::Forbidden);
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Permission-based access control middleware, used like `require_role`
+pub async fn require_permission(
+    State(required_permission): State<&'static str>,
+    request: Request<Body>,
+    next: Next,
 ) -> Result<Response, AuthError> {
     let user = request.extensions()
         .get::<User>()
         .ok_or(AuthError::Unauthorized)?;
 
-    if !user.roles.contains(required_role) {
+    if !user.has_permission(required_permission) {
         return Err(AuthError::Forbidden);
     }
 
@@ -86,17 +169,34 @@ pub async fn require_role<B>(
 }
 
 /// Rate limiting middleware
-pub async fn rate_limit<B>(
+///
+/// Takes one token from the bucket for this route and client IP, and
+/// reports the bucket state in `RateLimit-*` headers. Needs an
+/// `Extension<Arc<RateLimiter>>` layer outside it, and the server started
+/// with `into_make_service_with_connect_info::<SocketAddr>()`.
+pub async fn rate_limit(
     Extension(limiter): Extension<Arc<RateLimiter>>,
-    request: Request<B>,
-    next: Next<B>,
-) -> Result<Response, StatusCode> {
-    let ip = request.extensions()
-        .get::<IpAddr>()
-        .ok_or(StatusCode::BAD_REQUEST)?;
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let peer = request.extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let Some(ip) = limiter.client_ip(peer, request.headers()) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
 
-    limiter.check(ip)
-        .map_err(|_| StatusCode::TOO_MANY_REQUESTS)?;
+    let path = request.extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_else(|| request.uri().path().to_owned());
+    let decision = limiter.check_route(&path, ip);
 
-    Ok(next.run(request).await)
-}
\ No newline at end of file
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        AuthError::RateLimited.into_response()
+    };
+    decision.apply_headers(response.headers_mut());
+    response
+}
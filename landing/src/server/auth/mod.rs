@@ -2,10 +2,40 @@ pub mod context;
 pub mod middleware;
 
 pub mod provider;
+pub mod postgres;
+pub mod memory;
 pub mod utils;
+pub mod session_cache;
+pub mod rate_limit;
+pub mod totp;
+pub mod lockout;
+pub mod cookies;
+pub mod email;
+pub mod invitations;
+pub mod password;
+pub mod password_policy;
+pub mod oidc;
+pub mod webauthn;
+pub mod api_keys;
+pub mod signed_tokens;
+pub mod token_store;
 
 
-pub use context::{AuthContext,AuthClient};
+pub use context::{AuthContext,AuthClient,LoginStatus,OidcOutcome,use_auth,use_session_refresh};
 pub use provider::AuthProvider;
-pub use utils::{generate_session_token,generate_random_token,verify_password,meets_password_requirements,is_valid_email};
-pub use middleware::{auth_middleware, require_role, rate_limit};
\ No newline at end of file
+pub use postgres::PgAuthProvider;
+pub use memory::{Clock, InMemoryAuthProvider, ManualClock, SystemClock};
+pub use session_cache::{CacheStats, LruSessionCache, SessionCache};
+pub use rate_limit::{RateLimitDecision, RateLimiter};
+pub use password::{Argon2Hasher, BcryptHasher, PasswordHasher};
+pub use email::{DomainBlocklist, EmailAddress, EmailPolicy};
+pub use password_policy::{estimate_strength, BreachedPasswords, PasswordPolicy};
+pub use oidc::{ExternalIdentity, OidcClient};
+pub use signed_tokens::{AccessClaims, MemorySigningKeyStore, PgSigningKeyStore, SigningKeyStore, TokenSigner};
+pub use token_store::{default_token_store, MemoryTokenStore, StoredTokens, TokenStore};
+#[cfg(target_arch = "wasm32")]
+pub use token_store::WebStorageTokenStore;
+#[cfg(not(target_arch = "wasm32"))]
+pub use token_store::KeyringTokenStore;
+pub use utils::{generate_random_token,hash_password,hash_token,hash_session_token,verify_password,meets_password_requirements,is_valid_email,device_label};
+pub use middleware::{auth_middleware, csrf_protect, require_role, require_permission, require_session, rate_limit};
\ No newline at end of file
@@ -0,0 +1,354 @@
+//! OpenID Connect login
+//!
+//! `OidcClient` runs the authorization code flow with PKCE against one
+//! provider. Discovery supplies the endpoints and the JWKS the ID token
+//! signature is checked against. Each started login keeps its state,
+//! nonce and PKCE verifier server-side until the provider redirects back;
+//! the state is single-use and expires after `OidcConfig::login_ttl`. At
+//! most `MAX_PENDING_LOGINS` are kept, the oldest giving way first.
+//!
+//! The verified `ExternalIdentity` is handed to
+//! `AuthProvider::login_with_identity`, which resolves it to a local user
+//! through `user_identities`.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use base64::engine::general_purpose;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::server::auth::utils::{generate_random_token, hash_token};
+use crate::server::config::OidcConfig;
+use crate::server::error::AuthError;
+
+/// Signature algorithms accepted on ID tokens; never the symmetric ones
+const ALLOWED_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// Minimum time between two JWKS downloads triggered by unknown key ids
+const JWKS_REFRESH_INTERVAL_SECS: i64 = 60;
+
+/// Most started logins kept waiting for the provider's redirect
+pub const MAX_PENDING_LOGINS: usize = 10_000;
+
+/// Endpoints from the provider's discovery document
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// An account at the identity provider, as vouched for by a verified ID token
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalIdentity {
+    pub issuer: String,
+    /// The provider's stable user id, the `sub` claim
+    pub subject: String,
+    pub email: Option<String>,
+    /// Whether the provider has confirmed `email`
+    pub email_verified: bool,
+}
+
+/// A login the browser was sent off to the provider for
+#[derive(Debug, Clone)]
+pub struct AuthorizationRequest {
+    /// Where to redirect the browser
+    pub url: String,
+    /// Opaque value the provider hands back; bind it to the browser, e.g.
+    /// in a cookie, so a callback can't be replayed into another session
+    pub state: String,
+}
+
+/// A completed callback
+#[derive(Debug, Clone)]
+pub struct OidcCallback {
+    pub identity: ExternalIdentity,
+    /// Local user that started the flow to link the identity, if any
+    pub link_user: Option<Uuid>,
+}
+
+struct PendingLogin {
+    nonce: String,
+    code_verifier: String,
+    link_user: Option<Uuid>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct TokenEndpointResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+}
+
+/// Client for one OpenID Connect provider
+pub struct OidcClient {
+    config: OidcConfig,
+    metadata: ProviderMetadata,
+    http: reqwest::Client,
+    jwks: RwLock<JwkSet>,
+    jwks_fetched_at: Mutex<DateTime<Utc>>,
+    /// Started logins keyed by `hash_token` digest of their state
+    pending: Mutex<HashMap<String, PendingLogin>>,
+}
+
+impl std::fmt::Debug for OidcClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OidcClient")
+            .field("issuer", &self.metadata.issuer)
+            .field("client_id", &self.config.client_id)
+            .finish()
+    }
+}
+
+fn idp_error(message: impl Into<String>) -> AuthError {
+    AuthError::IdentityProvider(message.into())
+}
+
+async fn fetch_json<T: DeserializeOwned>(http: &reqwest::Client, url: &str) -> Result<T, AuthError> {
+    http.get(url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| idp_error(format!("fetching {url}: {e}")))?
+        .json()
+        .await
+        .map_err(|e| idp_error(format!("parsing {url}: {e}")))
+}
+
+/// S256 PKCE challenge for a verifier
+fn pkce_challenge(code_verifier: &str) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Shrinks a full map to 90% of `MAX_PENDING_LOGINS`, expired logins first
+/// and then the oldest, so a flood of `/oidc/start` requests can't grow it
+fn make_room(pending: &mut HashMap<String, PendingLogin>, now: DateTime<Utc>) {
+    pending.retain(|_, p| p.expires_at > now);
+
+    let target = MAX_PENDING_LOGINS - MAX_PENDING_LOGINS / 10;
+    if pending.len() > target {
+        let mut by_age: Vec<_> = pending.iter().map(|(key, p)| (p.expires_at, key.clone())).collect();
+        by_age.sort_unstable();
+        let excess = pending.len() - target;
+        for (_, key) in by_age.into_iter().take(excess) {
+            pending.remove(&key);
+        }
+    }
+}
+
+impl OidcClient {
+    /// Reads the provider's discovery document and signing keys
+    ///
+    /// # Example
+    /// ```rust
+    /// let config = OidcConfig::from_env().expect("OIDC_ISSUER is set");
+    /// let oidc = Arc::new(OidcClient::discover(config).await?);
+    /// let auth_context = AuthContext::new(provider).with_oidc(oidc);
+    /// ```
+    pub async fn discover(config: OidcConfig) -> Result<Self, AuthError> {
+        let http = reqwest::Client::new();
+        let issuer = config.issuer.trim_end_matches('/');
+        let metadata: ProviderMetadata =
+            fetch_json(&http, &format!("{issuer}/.well-known/openid-configuration")).await?;
+        if metadata.issuer.trim_end_matches('/') != issuer {
+            return Err(idp_error("discovery document belongs to another issuer"));
+        }
+        let jwks = fetch_json(&http, &metadata.jwks_uri).await?;
+
+        Ok(Self {
+            config,
+            metadata,
+            http,
+            jwks: RwLock::new(jwks),
+            jwks_fetched_at: Mutex::new(Utc::now()),
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn metadata(&self) -> &ProviderMetadata {
+        &self.metadata
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    /// Starts a login and returns where to send the browser
+    ///
+    /// # Arguments
+    /// * `link_user` - Signed-in user to link the identity to, instead of logging in
+    pub fn authorization_request(&self, link_user: Option<Uuid>) -> Result<AuthorizationRequest, AuthError> {
+        let state = generate_random_token();
+        let nonce = generate_random_token();
+        // 60 characters, within PKCE's 43..=128
+        let code_verifier = format!("{}{}", generate_random_token(), generate_random_token());
+
+        let scope = std::iter::once("openid")
+            .chain(self.config.scopes.iter().map(String::as_str).filter(|s| *s != "openid"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut url = Url::parse(&self.metadata.authorization_endpoint)
+            .map_err(|_| idp_error("invalid authorization endpoint"))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_uri)
+            .append_pair("scope", &scope)
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair("code_challenge", &pkce_challenge(&code_verifier))
+            .append_pair("code_challenge_method", "S256");
+
+        let now = Utc::now();
+        let mut pending = self.pending.lock().unwrap();
+        if pending.len() >= MAX_PENDING_LOGINS {
+            make_room(&mut pending, now);
+        }
+        pending.insert(
+            hash_token(&state),
+            PendingLogin {
+                nonce,
+                code_verifier,
+                link_user,
+                expires_at: now + self.config.login_ttl,
+            },
+        );
+
+        Ok(AuthorizationRequest { url: url.into(), state })
+    }
+
+    /// Finishes a login from the provider's redirect
+    ///
+    /// # Returns
+    /// - `Err(AuthError::InvalidToken)` for an unknown, spent or expired state
+    /// - `Err(AuthError::IdentityProvider)` if the code exchange fails or
+    ///   the ID token doesn't verify
+    pub async fn complete(&self, code: &str, state: &str) -> Result<OidcCallback, AuthError> {
+        let pending = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&hash_token(state))
+            .filter(|p| p.expires_at > Utc::now())
+            .ok_or(AuthError::InvalidToken)?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", pending.code_verifier.as_str()),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        let tokens: TokenEndpointResponse = self
+            .http
+            .post(&self.metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| idp_error(format!("code exchange failed: {e}")))?
+            .json()
+            .await
+            .map_err(|e| idp_error(format!("unreadable token response: {e}")))?;
+
+        let claims = self.verify_id_token(&tokens.id_token).await?;
+        if claims.nonce.as_deref() != Some(pending.nonce.as_str()) {
+            return Err(idp_error("ID token nonce doesn't match the login"));
+        }
+
+        Ok(OidcCallback {
+            identity: ExternalIdentity {
+                issuer: self.metadata.issuer.clone(),
+                subject: claims.sub,
+                email: claims.email,
+                email_verified: claims.email_verified,
+            },
+            link_user: pending.link_user,
+        })
+    }
+
+    /// Checks signature, issuer, audience and expiry of an ID token
+    async fn verify_id_token(&self, id_token: &str) -> Result<IdTokenClaims, AuthError> {
+        let header = decode_header(id_token).map_err(|e| idp_error(format!("malformed ID token: {e}")))?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(idp_error(format!("ID token signed with {:?}", header.alg)));
+        }
+        let kid = header.kid.ok_or_else(|| idp_error("ID token names no signing key"))?;
+
+        let key = match self.decoding_key(&kid).await? {
+            Some(key) => key,
+            // The provider may have rotated its keys since discovery
+            None => {
+                self.refresh_jwks().await?;
+                self.decoding_key(&kid)
+                    .await?
+                    .ok_or_else(|| idp_error("ID token signed with an unknown key"))?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.metadata.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        validation.leeway = self.config.leeway.num_seconds().max(0) as u64;
+
+        decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| idp_error(format!("ID token rejected: {e}")))
+    }
+
+    async fn decoding_key(&self, kid: &str) -> Result<Option<DecodingKey>, AuthError> {
+        self.jwks
+            .read()
+            .await
+            .find(kid)
+            .map(DecodingKey::from_jwk)
+            .transpose()
+            .map_err(|e| idp_error(format!("unusable signing key: {e}")))
+    }
+
+    async fn refresh_jwks(&self) -> Result<(), AuthError> {
+        {
+            let mut fetched_at = self.jwks_fetched_at.lock().unwrap();
+            let now = Utc::now();
+            if now - *fetched_at < Duration::seconds(JWKS_REFRESH_INTERVAL_SECS) {
+                return Ok(());
+            }
+            *fetched_at = now;
+        }
+        let jwks = fetch_json(&self.http, &self.metadata.jwks_uri).await?;
+        *self.jwks.write().await = jwks;
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+//! Password hashing
+//!
+//! New hashes are Argon2id PHC strings (`$argon2id$v=19$m=...`) with the
+//! cost parameters from `AuthConfig`. Hashes from before the switch are
+//! bcrypt and still verify; `needs_rehash` flags them, and any Argon2 hash
+//! with other parameters, so providers can upgrade them on the next
+//! successful login.
+
+use argon2::password_hash::{PasswordHash, PasswordHasher as _, PasswordVerifier as _, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+
+use crate::server::config::AuthConfig;
+use crate::server::error::AuthError;
+
+/// Stored for accounts created through an external identity provider;
+/// it isn't a valid hash, so no password ever verifies against it
+pub const UNUSABLE_PASSWORD_HASH: &str = "!";
+
+/// Hashes and checks passwords for an `AuthProvider`
+pub trait PasswordHasher: Send + Sync {
+    /// Hashes `password` with a fresh salt
+    fn hash(&self, password: &str) -> Result<String, AuthError>;
+
+    /// Checks `password` against a stored hash of any supported algorithm
+    fn verify(&self, password: &str, hash: &str) -> bool {
+        verify_any(password, hash)
+    }
+
+    /// Whether a hash that just verified should be replaced with `hash`
+    fn needs_rehash(&self, hash: &str) -> bool;
+}
+
+/// Checks `password` against an Argon2 or bcrypt hash
+pub fn verify_any(password: &str, hash: &str) -> bool {
+    if is_bcrypt(hash) {
+        return bcrypt::verify(password, hash).unwrap_or(false);
+    }
+    // Argon2 reads the algorithm and parameters from the hash itself
+    PasswordHash::new(hash)
+        .is_ok_and(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+}
+
+fn is_bcrypt(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"].iter().any(|prefix| hash.starts_with(prefix))
+}
+
+/// Argon2id hasher, the default
+#[derive(Debug, Clone)]
+pub struct Argon2Hasher {
+    params: Params,
+}
+
+impl Default for Argon2Hasher {
+    /// OWASP's baseline: 19 MiB, 2 iterations, 1 lane
+    fn default() -> Self {
+        Self { params: Params::default() }
+    }
+}
+
+impl Argon2Hasher {
+    /// Creates a hasher with explicit cost parameters
+    ///
+    /// # Arguments
+    /// * `memory_kib` - Memory per hash in KiB, at least 8 per lane
+    /// * `iterations` - Passes over the memory, at least 1
+    /// * `parallelism` - Lanes, at least 1
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Result<Self, argon2::Error> {
+        Ok(Self {
+            params: Params::new(memory_kib, iterations, parallelism, None)?,
+        })
+    }
+
+    /// Creates a hasher with the parameters from `config`, falling back to
+    /// the defaults if they are out of range
+    pub fn from_config(config: &AuthConfig) -> Self {
+        Self::new(config.argon2_memory_kib, config.argon2_iterations, config.argon2_parallelism)
+            .unwrap_or_else(|e| {
+                log::error!("Invalid Argon2 parameters, using defaults: {e}");
+                Self::default()
+            })
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+}
+
+impl PasswordHasher for Argon2Hasher {
+    fn hash(&self, password: &str) -> Result<String, AuthError> {
+        // A hashing failure means the credentials can't be persisted
+        let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>())
+            .map_err(|_| AuthError::DatabaseError)?;
+        self.argon2()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        let Ok(parsed) = PasswordHash::new(hash) else {
+            return true;
+        };
+        if parsed.algorithm != argon2::ARGON2ID_IDENT || parsed.version != Some(Version::V0x13 as u32) {
+            return true;
+        }
+        match Params::try_from(&parsed) {
+            Ok(params) => {
+                params.m_cost() != self.params.m_cost()
+                    || params.t_cost() != self.params.t_cost()
+                    || params.p_cost() != self.params.p_cost()
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+/// bcrypt hasher, for deployments that can't afford Argon2's memory
+#[derive(Debug, Clone, Copy)]
+pub struct BcryptHasher {
+    cost: u32,
+}
+
+impl Default for BcryptHasher {
+    fn default() -> Self {
+        Self { cost: bcrypt::DEFAULT_COST }
+    }
+}
+
+impl BcryptHasher {
+    /// Creates a hasher with a work factor between 4 and 31
+    pub fn new(cost: u32) -> Self {
+        Self { cost }
+    }
+}
+
+impl PasswordHasher for BcryptHasher {
+    fn hash(&self, password: &str) -> Result<String, AuthError> {
+        bcrypt::hash(password, self.cost).map_err(|_| AuthError::DatabaseError)
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        // `$2b$12$...`: the cost sits between the second and third `$`
+        !is_bcrypt(hash) || hash.get(4..6).and_then(|c| c.parse::<u32>().ok()) != Some(self.cost)
+    }
+}
@@ -0,0 +1,234 @@
+//! Password policy checks
+//!
+//! A `PasswordPolicy` reports every rule a password fails rather than a
+//! single yes or no, so forms can list what to fix. Strength is a rough
+//! entropy estimate that discounts repeated and sequential characters;
+//! common passwords are caught by the breached-password list instead.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::server::config::env_parse;
+use crate::server::error::AuthError;
+use crate::server::models::PasswordViolation;
+
+/// Rules a new password has to satisfy
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    /// Minimum length in characters
+    pub min_length: usize,
+    /// Maximum length in characters, which bounds hashing work
+    pub max_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    /// Require a character that is neither a letter, a digit nor whitespace
+    pub require_symbol: bool,
+    /// Minimum `estimate_strength` score, 0 to disable
+    pub min_strength: u8,
+    /// Reject passwords containing the email address or its local part
+    pub reject_personal_info: bool,
+    /// Known leaked passwords, compared ignoring case
+    pub breached: Option<Arc<BreachedPasswords>>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            max_length: 128,
+            require_uppercase: true,
+            require_lowercase: true,
+            require_digit: true,
+            require_symbol: false,
+            min_strength: 2,
+            reject_personal_info: true,
+            breached: None,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Builds a policy from the environment, keeping defaults for unset keys
+    ///
+    /// | Variable                  | Field             |
+    /// |---------------------------|-------------------|
+    /// | `PASSWORD_MIN_LENGTH`     | `min_length`      |
+    /// | `PASSWORD_MAX_LENGTH`     | `max_length`      |
+    /// | `PASSWORD_REQUIRE_SYMBOL` | `require_symbol`  |
+    /// | `PASSWORD_MIN_STRENGTH`   | `min_strength`    |
+    /// | `BREACHED_PASSWORDS_FILE` | `breached`        |
+    ///
+    /// A breached-password file that can't be read is logged and skipped.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let breached = match std::env::var("BREACHED_PASSWORDS_FILE") {
+            Ok(path) if !path.is_empty() => match BreachedPasswords::load(&path) {
+                Ok(list) => Some(Arc::new(list)),
+                Err(e) => {
+                    log::warn!("Could not read breached passwords from {path}: {e}");
+                    None
+                }
+            },
+            _ => None,
+        };
+        Self {
+            min_length: env_parse("PASSWORD_MIN_LENGTH", defaults.min_length),
+            max_length: env_parse("PASSWORD_MAX_LENGTH", defaults.max_length),
+            require_symbol: env_parse("PASSWORD_REQUIRE_SYMBOL", defaults.require_symbol),
+            min_strength: env_parse("PASSWORD_MIN_STRENGTH", defaults.min_strength),
+            breached,
+            ..defaults
+        }
+    }
+
+    /// Rejects passwords on `list`
+    pub fn with_breached_passwords(mut self, list: BreachedPasswords) -> Self {
+        self.breached = Some(Arc::new(list));
+        self
+    }
+
+    /// Every rule `password` fails for the account at `email`, empty if it's acceptable
+    pub fn check(&self, password: &str, email: &str) -> Vec<PasswordViolation> {
+        let mut violations = Vec::new();
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            violations.push(PasswordViolation::TooShort { min: self.min_length });
+        }
+        if length > self.max_length {
+            violations.push(PasswordViolation::TooLong { max: self.max_length });
+        }
+        if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+            violations.push(PasswordViolation::MissingUppercase);
+        }
+        if self.require_lowercase && !password.chars().any(char::is_lowercase) {
+            violations.push(PasswordViolation::MissingLowercase);
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            violations.push(PasswordViolation::MissingDigit);
+        }
+        if self.require_symbol && !password.chars().any(is_symbol) {
+            violations.push(PasswordViolation::MissingSymbol);
+        }
+        if self.reject_personal_info && contains_personal_info(password, email) {
+            violations.push(PasswordViolation::ContainsPersonalInfo);
+        }
+        if self.breached.as_ref().is_some_and(|list| list.contains(password)) {
+            violations.push(PasswordViolation::Breached);
+        }
+        let score = estimate_strength(password);
+        if score < self.min_strength {
+            violations.push(PasswordViolation::TooWeak { score, min: self.min_strength });
+        }
+
+        violations
+    }
+
+    /// Like `check`, but as the error registration and password reset return
+    pub fn validate(&self, password: &str, email: &str) -> Result<(), AuthError> {
+        let violations = self.check(password, email);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(AuthError::PasswordRequirements { violations })
+        }
+    }
+}
+
+/// Leaked passwords loaded from a local list, one per line
+///
+/// Stored lowercased so case variations of a leaked password are caught too.
+#[derive(Default)]
+pub struct BreachedPasswords(HashSet<String>);
+
+impl BreachedPasswords {
+    /// Reads a list file; blank lines and lines starting with `#` are skipped
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect())
+    }
+
+    pub fn contains(&self, password: &str) -> bool {
+        self.0.contains(&password.to_lowercase())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for BreachedPasswords {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Self(iter.into_iter().map(|p| p.as_ref().to_lowercase()).collect())
+    }
+}
+
+impl std::fmt::Debug for BreachedPasswords {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BreachedPasswords({} entries)", self.0.len())
+    }
+}
+
+/// Rough guessing resistance on a 0 (trivial) to 4 (strong) scale
+///
+/// Each character is worth the bits of the character classes the password
+/// draws from, except one that repeats or continues a run from the
+/// previous character (`aa`, `abc`, `321`), which is worth a single bit.
+pub fn estimate_strength(password: &str) -> u8 {
+    let chars: Vec<char> = password.chars().collect();
+    let pool: u32 = [
+        (chars.iter().any(|c| c.is_ascii_lowercase()), 26),
+        (chars.iter().any(|c| c.is_ascii_uppercase()), 26),
+        (chars.iter().any(|c| c.is_ascii_digit()), 10),
+        (chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' '), 33),
+        (chars.iter().any(|c| !c.is_ascii()), 100),
+    ]
+    .iter()
+    .filter(|(present, _)| *present)
+    .map(|(_, size)| size)
+    .sum();
+    let per_char = f64::from(pool.max(1)).log2();
+
+    let bits: f64 = chars
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| {
+            let predictable = i > 0 && (c as i64 - chars[i - 1] as i64).abs() <= 1;
+            if predictable { 1.0 } else { per_char }
+        })
+        .sum();
+
+    match bits {
+        b if b < 28.0 => 0,
+        b if b < 36.0 => 1,
+        b if b < 60.0 => 2,
+        b if b < 80.0 => 3,
+        _ => 4,
+    }
+}
+
+fn is_symbol(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace()
+}
+
+/// Whether the password contains the email's local part, or any word of it
+/// at least 3 characters long, ignoring case
+fn contains_personal_info(password: &str, email: &str) -> bool {
+    let password = password.to_lowercase();
+    let local = email.split('@').next().unwrap_or_default().to_lowercase();
+
+    std::iter::once(local.as_str())
+        .chain(local.split(|c: char| !c.is_alphanumeric()))
+        .filter(|part| part.chars().count() >= 3)
+        .any(|part| password.contains(part))
+}
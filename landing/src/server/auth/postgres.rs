@@ -0,0 +1,1338 @@
+//! PostgreSQL-backed authentication provider
+//!
+//! Users live in `users`, sessions in `user_sessions`. Passwords are hashed
+//! with Argon2id, older bcrypt hashes are upgraded at login, and every
+//! failure is reported as an `AuthError`. New accounts
+//! must confirm their email through `email_verification_tokens` before they
+//! can log in. Password reset tokens are only stored as digests. Accounts
+//! with TOTP enabled log in in two steps through `mfa_challenges`. Failed
+//! passwords are counted in `login_attempts` for backoff and lockout.
+//! Sessions are short-lived access tokens, stored under a keyed hash and renewed with single-use refresh
+//! tokens from `refresh_tokens`. A session row keeps its id and device
+//! details across refreshes, so it stands for one signed-in device.
+//! Accounts at OpenID Connect providers are linked in `user_identities`,
+//! passkeys live in `passkeys` with their pending ceremonies in
+//! `passkey_challenges`. Magic sign-in links are stored in `magic_links`,
+//! as digests bound to the browser that asked for them. Personal API keys
+//! are stored as digests in `api_keys`, registration invitation codes in
+//! `invitations`.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::db::{self, queries, ApiKey, DbError, DbPool, DbUser, Invitation, Passkey, UserSession, UserTotp};
+use crate::server::audit::RequestMeta;
+use crate::server::auth::api_keys;
+use crate::server::auth::email::EmailAddress;
+use crate::server::auth::invitations;
+use crate::server::auth::lockout;
+use crate::server::auth::oidc::ExternalIdentity;
+use crate::server::auth::password::{Argon2Hasher, PasswordHasher, UNUSABLE_PASSWORD_HASH};
+use crate::server::auth::provider::AuthProvider;
+use crate::server::auth::totp;
+use crate::server::auth::webauthn::{self, Ceremony};
+use crate::server::auth::utils::{
+    device_label, generate_random_token, hash_session_token, hash_token,
+};
+use crate::server::config::AuthConfig;
+use crate::server::error::AuthError;
+use crate::server::mailer::{
+    magic_link_email, password_reset_email, verification_email, LogMailer, Mailer,
+};
+use crate::server::models::{
+    ApiKeyInfo, InvitationInfo, NewApiKey, NewInvitation, PasskeyAssertion, PasskeyCreationOptions, PasskeyCredential,
+    PasskeyInfo, PasskeyRequestOptions, SessionInfo, TotpEnrollment, User, ADMIN_ROLE, DEFAULT_ROLE,
+};
+
+/// Authentication provider backed by a PostgreSQL connection pool
+#[derive(Clone)]
+pub struct PgAuthProvider {
+    pool: DbPool,
+    config: AuthConfig,
+    mailer: Arc<dyn Mailer>,
+    hasher: Arc<dyn PasswordHasher>,
+}
+
+impl PgAuthProvider {
+    /// Wraps an existing connection pool with default config, logging mail
+    pub fn new(pool: DbPool) -> Self {
+        Self {
+            pool,
+            config: AuthConfig::default(),
+            mailer: Arc::new(LogMailer),
+            hasher: Arc::new(Argon2Hasher::default()),
+        }
+    }
+
+    /// Replaces the configuration, rebuilding the Argon2id hasher from it
+    pub fn with_config(mut self, config: AuthConfig) -> Self {
+        self.hasher = Arc::new(Argon2Hasher::from_config(&config));
+        self.config = config;
+        self
+    }
+
+    /// Replaces the password hasher; call after `with_config`
+    pub fn with_hasher(mut self, hasher: Arc<dyn PasswordHasher>) -> Self {
+        self.hasher = hasher;
+        self
+    }
+
+    /// Replaces the mail transport
+    pub fn with_mailer(mut self, mailer: Arc<dyn Mailer>) -> Self {
+        self.mailer = mailer;
+        self
+    }
+
+    /// Creates the pool with `db::create_pool` and wraps it
+    ///
+    /// # Example
+    /// ```rust
+    /// let provider = Arc::new(PgAuthProvider::connect(&database_url).await?);
+    /// let auth_context = AuthContext::new(provider);
+    /// ```
+    pub async fn connect(database_url: &str) -> Result<Self, AuthError> {
+        let pool = db::create_pool(database_url)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        Ok(Self::new(pool))
+    }
+
+    /// Underlying pool, for callers that need raw queries
+    pub fn pool(&self) -> &DbPool {
+        &self.pool
+    }
+
+    /// Builds the application `User`, loading roles and permissions
+    async fn load_user(
+        &self,
+        id: Uuid,
+        email: String,
+        bearer_token: String,
+        session_expires_at: DateTime<Utc>,
+    ) -> Result<User, AuthError> {
+        let roles = queries::roles::get_user_roles(&self.pool, id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        let permissions = queries::roles::get_user_permissions(&self.pool, id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+
+        Ok(User {
+            id,
+            email,
+            bearer_token,
+            session_expires_at,
+            refresh_token: None,
+            roles,
+            permissions,
+            impersonator_id: None,
+        })
+    }
+
+    /// Starts a new refresh token family and returns the signed-in user
+    async fn issue_session(&self, user_id: Uuid, email: String) -> Result<User, AuthError> {
+        let family_id = Uuid::new_v4();
+        let token = generate_random_token();
+        let expires_at = Utc::now() + self.config.access_token_ttl;
+        self.create_session(user_id, family_id, &token, expires_at).await?;
+        self.issue_refresh_token(user_id, email, family_id, token, expires_at).await
+    }
+
+    /// Keyed hash an access token is stored under
+    fn session_hash(&self, token: &str) -> String {
+        hash_session_token(token, &self.config.session_token_key)
+    }
+
+    /// Stores a session for `family_id`, describing the device from the
+    /// current request
+    async fn create_session(
+        &self,
+        user_id: Uuid,
+        family_id: Uuid,
+        token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AuthError> {
+        let meta = RequestMeta::current();
+        let label = device_label(meta.user_agent.as_deref());
+        queries::session::create_session(
+            &self.pool,
+            user_id,
+            &self.session_hash(token),
+            expires_at,
+            Some(family_id),
+            Some(&label),
+            meta.user_agent.as_deref(),
+            meta.ip.as_deref(),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|_| AuthError::DatabaseError)
+    }
+
+    /// Creates the next refresh token in `family_id` to go with access `token`
+    async fn issue_refresh_token(
+        &self,
+        user_id: Uuid,
+        email: String,
+        family_id: Uuid,
+        token: String,
+        expires_at: DateTime<Utc>,
+    ) -> Result<User, AuthError> {
+        let now = Utc::now();
+        let refresh_token = generate_random_token();
+        queries::refresh::create_refresh_token(
+            &self.pool,
+            user_id,
+            family_id,
+            &hash_token(&refresh_token),
+            now + self.config.refresh_token_ttl,
+        )
+        .await
+        .map_err(|_| AuthError::DatabaseError)?;
+
+        let mut user = self.load_user(user_id, email, token, expires_at).await?;
+        user.refresh_token = Some(refresh_token);
+        Ok(user)
+    }
+
+    /// Ends every token of `user_id`'s family after a replayed refresh token
+    async fn revoke_family(&self, user_id: Uuid, family_id: Uuid) -> AuthError {
+        match queries::refresh::revoke_family(&self.pool, family_id).await {
+            Ok(session_ids) => AuthError::TokenReuseDetected { user_id, session_ids },
+            Err(_) => AuthError::DatabaseError,
+        }
+    }
+
+    /// Hashes off the async executor, password hashing is deliberately slow
+    async fn hash_password(&self, password: &str) -> Result<String, AuthError> {
+        let hasher = self.hasher.clone();
+        let password = password.to_owned();
+        tokio::task::spawn_blocking(move || hasher.hash(&password))
+            .await
+            .map_err(|_| AuthError::DatabaseError)?
+    }
+
+    /// Replaces an outdated hash after a successful login; a failure only
+    /// postpones the upgrade to the next login
+    async fn upgrade_password_hash(&self, user_id: Uuid, password: &str) {
+        let result = match self.hash_password(password).await {
+            Ok(hash) => queries::users::update_password_hash(&self.pool, user_id, &hash)
+                .await
+                .map_err(|_| AuthError::DatabaseError),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to upgrade password hash for user {user_id}: {e}");
+        }
+    }
+
+    /// Refuses the attempt while the email is backing off or locked
+    async fn check_lockout(&self, lockout_key: &str) -> Result<(), AuthError> {
+        match queries::lockout::get_login_attempts(&self.pool, lockout_key).await {
+            Ok(attempts) => lockout::check(
+                attempts.failed_count,
+                attempts.last_failed_at,
+                Utc::now(),
+                &self.config,
+            ),
+            Err(DbError::NotFound) => Ok(()),
+            Err(_) => Err(AuthError::DatabaseError),
+        }
+    }
+
+    /// Counts a wrong password or second-factor code and returns the error
+    /// to report
+    async fn record_login_failure(&self, lockout_key: &str) -> AuthError {
+        match queries::lockout::record_failed_login(
+            &self.pool,
+            lockout_key,
+            Utc::now(),
+            self.config.lockout_threshold,
+        )
+        .await
+        {
+            Ok(_) => AuthError::AuthenticationFailed,
+            Err(_) => AuthError::DatabaseError,
+        }
+    }
+
+    /// Ends the failure streak after a complete login
+    async fn clear_login_failures(&self, lockout_key: &str) -> Result<(), AuthError> {
+        queries::lockout::clear_login_attempts(&self.pool, lockout_key)
+            .await
+            .map(|_| ())
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    /// The user's TOTP record, if two-factor is switched on
+    async fn enabled_totp(&self, user_id: Uuid) -> Result<Option<UserTotp>, AuthError> {
+        match queries::mfa::get_totp(&self.pool, user_id).await {
+            Ok(totp) if totp.enabled_at.is_some() => Ok(Some(totp)),
+            Ok(_) | Err(DbError::NotFound) => Ok(None),
+            Err(_) => Err(AuthError::DatabaseError),
+        }
+    }
+
+    /// Accepts a current TOTP code or burns an unused recovery code
+    async fn check_second_factor(&self, totp: &UserTotp, code: &str) -> Result<bool, AuthError> {
+        if totp::is_totp_code(code) {
+            let step = totp::verify_code(&totp.secret, code, Utc::now(), totp.last_used_step)?;
+            let Some(step) = step else {
+                return Ok(false);
+            };
+            queries::mfa::set_last_used_step(&self.pool, totp.user_id, step)
+                .await
+                .map_err(|_| AuthError::DatabaseError)?;
+            return Ok(true);
+        }
+
+        queries::mfa::consume_recovery_code(&self.pool, totp.user_id, &totp::hash_recovery_code(code))
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    /// Asks for the second factor if the user has one, otherwise signs them in
+    async fn finish_login(&self, user_id: Uuid, email: String) -> Result<User, AuthError> {
+        if self.enabled_totp(user_id).await?.is_some() {
+            let challenge = generate_random_token();
+            let expires_at = Utc::now() + self.config.mfa_challenge_ttl;
+            queries::mfa::create_challenge(&self.pool, user_id, &hash_token(&challenge), expires_at)
+                .await
+                .map_err(|_| AuthError::DatabaseError)?;
+            return Err(AuthError::MfaRequired { challenge });
+        }
+
+        self.issue_session(user_id, email).await
+    }
+
+    /// Creates an account without a usable password, for sign-in methods
+    /// that prove the user some other way
+    async fn create_passwordless_user(&self, address: &EmailAddress) -> Result<DbUser, AuthError> {
+        if !self.config.allows_uninvited_signup() {
+            return Err(AuthError::RegistrationClosed);
+        }
+        self.config.email_policy.check_allowed(address)?;
+        let user = queries::users::create_user(
+            &self.pool,
+            &address.to_string(),
+            &self.config.email_policy.canonical(address),
+            UNUSABLE_PASSWORD_HASH,
+        )
+        .await
+        .map_err(|e| match e {
+            DbError::ConstraintViolation(_) => AuthError::UserExists,
+            _ => AuthError::DatabaseError,
+        })?;
+        queries::roles::assign_role(&self.pool, user.id, DEFAULT_ROLE)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        Ok(user)
+    }
+
+    /// Stores the link from an external account to `user_id`
+    async fn create_identity(&self, user_id: Uuid, identity: &ExternalIdentity) -> Result<(), AuthError> {
+        queries::identities::create_identity(
+            &self.pool,
+            user_id,
+            &identity.issuer,
+            &identity.subject,
+            identity.email.as_deref(),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| match e {
+            DbError::ConstraintViolation(_) => AuthError::UserExists,
+            _ => AuthError::DatabaseError,
+        })
+    }
+
+    /// Stores the challenge of a new passkey ceremony
+    async fn store_passkey_challenge(&self, ceremony: Ceremony, user_id: Option<Uuid>) -> Result<String, AuthError> {
+        let challenge = webauthn::new_challenge();
+        let now = Utc::now();
+        // Abandoned ceremonies pile up otherwise
+        let _ = queries::passkeys::delete_expired_challenges(&self.pool, now).await;
+        queries::passkeys::create_challenge(
+            &self.pool,
+            &hash_token(&challenge),
+            ceremony.as_str(),
+            user_id,
+            now + self.config.passkey_challenge_ttl,
+        )
+        .await
+        .map_err(|_| AuthError::DatabaseError)?;
+        Ok(challenge)
+    }
+
+    /// Spends the challenge a passkey response answers
+    ///
+    /// # Returns
+    /// The challenge and the user the ceremony was started for, if any
+    async fn take_passkey_challenge(
+        &self,
+        client_data_json: &str,
+        ceremony: Ceremony,
+    ) -> Result<(String, Option<Uuid>), AuthError> {
+        let challenge = webauthn::response_challenge(client_data_json)?;
+        let record = queries::passkeys::take_challenge(&self.pool, &hash_token(&challenge), ceremony.as_str())
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidToken,
+                _ => AuthError::DatabaseError,
+            })?;
+        if record.expires_at <= Utc::now() {
+            return Err(AuthError::InvalidToken);
+        }
+        Ok((challenge, record.user_id))
+    }
+
+    /// Issues a verification token and mails the link
+    async fn send_verification(&self, user_id: Uuid, email: &str) -> Result<(), AuthError> {
+        let token = generate_random_token();
+        let expires_at = Utc::now() + self.config.verification_ttl;
+        queries::verification::create_verification_token(&self.pool, user_id, &hash_token(&token), expires_at)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+
+        self.mailer
+            .send(verification_email(email, &self.config.app_url, &token))
+            .await
+    }
+}
+
+#[async_trait]
+impl AuthProvider for PgAuthProvider {
+    async fn register(&self, email: &str, password: &str, invite_code: Option<&str>) -> Result<(), AuthError> {
+        if !self.config.open_registration {
+            return Err(AuthError::RegistrationClosed);
+        }
+        let invite_code = invitations::code_to_redeem(&self.config, invite_code)?;
+        let address = self.config.email_policy.parse_new(email)?;
+        self.config.password_policy.validate(password, email)?;
+
+        let password_hash = self.hash_password(password).await?;
+
+        let email = address.to_string();
+        let canonical = self.config.email_policy.canonical(&address);
+        let created = match invite_code {
+            Some(code) => {
+                let code_hash = invitations::hash_invitation_code(code);
+                queries::invitations::redeem_invitation(&self.pool, &code_hash, &email, &canonical, &password_hash)
+                    .await
+            }
+            None => queries::users::create_user(&self.pool, &email, &canonical, &password_hash).await,
+        };
+        let user = created.map_err(|e| match e {
+            DbError::NotFound => AuthError::InvalidInvitation,
+            DbError::ConstraintViolation(_) => AuthError::UserExists,
+            _ => AuthError::DatabaseError,
+        })?;
+        queries::roles::assign_role(&self.pool, user.id, DEFAULT_ROLE)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+
+        self.send_verification(user.id, &user.email).await
+    }
+
+    async fn authenticate(&self, email: &str, password: &str) -> Result<User, AuthError> {
+        let email = self.config.email_policy.canonicalize(email);
+        let lockout_key = lockout::lockout_key(&email);
+        self.check_lockout(&lockout_key).await?;
+
+        let user = match queries::users::get_user_by_email(&self.pool, &email).await {
+            Ok(user) => user,
+            // Don't reveal whether the email exists
+            Err(DbError::NotFound) => return Err(self.record_login_failure(&lockout_key).await),
+            Err(_) => return Err(AuthError::DatabaseError),
+        };
+
+        let hasher = self.hasher.clone();
+        let candidate = password.to_owned();
+        let password_hash = user.password_hash.clone();
+        let valid = tokio::task::spawn_blocking(move || hasher.verify(&candidate, &password_hash))
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        if !valid {
+            return Err(self.record_login_failure(&lockout_key).await);
+        }
+        // Old algorithm or cost; the plaintext is only at hand right now
+        if self.hasher.needs_rehash(&user.password_hash) {
+            self.upgrade_password_hash(user.id, password).await;
+        }
+        // Only reported once the password checks out, so it leaks nothing
+        if self.config.require_email_verification && user.email_verified_at.is_none() {
+            return Err(AuthError::EmailNotVerified);
+        }
+
+        // The streak only ends once any second factor has been passed too
+        let session = self.finish_login(user.id, user.email).await?;
+        self.clear_login_failures(&lockout_key).await?;
+        Ok(session)
+    }
+
+    async fn request_magic_link(&self, email: &str) -> Result<String, AuthError> {
+        let address = EmailAddress::parse(email)?;
+        let browser_secret = generate_random_token();
+        let canonical = self.config.email_policy.canonical(&address);
+        let exists = match queries::users::get_user_by_email(&self.pool, &canonical).await {
+            Ok(_) => true,
+            Err(DbError::NotFound) => false,
+            Err(_) => return Err(AuthError::DatabaseError),
+        };
+        if !exists {
+            if !self.config.allows_uninvited_signup() {
+                return Ok(browser_secret);
+            }
+            self.config.email_policy.check_allowed(&address)?;
+        }
+        let email = address.to_string();
+
+        let token = generate_random_token();
+        let expires_at = Utc::now() + self.config.magic_link_ttl;
+        queries::magic_links::create_magic_link(
+            &self.pool,
+            &email,
+            &hash_token(&token),
+            &hash_token(&browser_secret),
+            expires_at,
+        )
+        .await
+        .map_err(|_| AuthError::DatabaseError)?;
+
+        self.mailer
+            .send(magic_link_email(&email, &self.config.app_url, &token))
+            .await?;
+        Ok(browser_secret)
+    }
+
+    async fn login_with_magic_link(&self, token: &str, browser_secret: &str) -> Result<User, AuthError> {
+        let link = queries::magic_links::consume_magic_link(
+            &self.pool,
+            &hash_token(token),
+            &hash_token(browser_secret),
+        )
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound => AuthError::InvalidToken,
+            _ => AuthError::DatabaseError,
+        })?;
+
+        let address = EmailAddress::parse(&link.email)?;
+        let canonical = self.config.email_policy.canonical(&address);
+        let user = match queries::users::get_user_by_email(&self.pool, &canonical).await {
+            Ok(user) => user,
+            Err(DbError::NotFound) => self.create_passwordless_user(&address).await?,
+            Err(_) => return Err(AuthError::DatabaseError),
+        };
+        if user.email_verified_at.is_none() {
+            queries::users::mark_email_verified(&self.pool, user.id)
+                .await
+                .map_err(|_| AuthError::DatabaseError)?;
+        }
+
+        self.finish_login(user.id, user.email).await
+    }
+
+    async fn login_with_identity(&self, identity: &ExternalIdentity) -> Result<User, AuthError> {
+        match queries::identities::get_identity(&self.pool, &identity.issuer, &identity.subject).await {
+            Ok(link) => {
+                let user = queries::users::get_user_by_id(&self.pool, link.user_id)
+                    .await
+                    .map_err(|_| AuthError::DatabaseError)?;
+                return self.finish_login(user.id, user.email).await;
+            }
+            Err(DbError::NotFound) => {}
+            Err(_) => return Err(AuthError::DatabaseError),
+        }
+
+        let address = EmailAddress::parse(identity.email.as_deref().unwrap_or_default())?;
+        let canonical = self.config.email_policy.canonical(&address);
+        match queries::users::get_user_by_email(&self.pool, &canonical).await {
+            // Only link when both sides vouch for the address, otherwise
+            // whoever registers the email at the provider takes the account
+            Ok(user) if identity.email_verified && user.email_verified_at.is_some() => {
+                self.create_identity(user.id, identity).await?;
+                return self.finish_login(user.id, user.email).await;
+            }
+            Ok(_) => return Err(AuthError::UserExists),
+            Err(DbError::NotFound) => {}
+            Err(_) => return Err(AuthError::DatabaseError),
+        }
+
+        let user = self.create_passwordless_user(&address).await?;
+        self.create_identity(user.id, identity).await?;
+
+        if identity.email_verified {
+            queries::users::mark_email_verified(&self.pool, user.id)
+                .await
+                .map_err(|_| AuthError::DatabaseError)?;
+        } else if self.config.require_email_verification {
+            self.send_verification(user.id, &user.email).await?;
+            return Err(AuthError::EmailNotVerified);
+        }
+
+        self.finish_login(user.id, user.email).await
+    }
+
+    async fn link_identity(&self, user_id: Uuid, identity: &ExternalIdentity) -> Result<(), AuthError> {
+        match self.create_identity(user_id, identity).await {
+            Err(AuthError::UserExists) => {
+                // Linking the same account twice is harmless
+                let link = queries::identities::get_identity(&self.pool, &identity.issuer, &identity.subject)
+                    .await
+                    .map_err(|_| AuthError::DatabaseError)?;
+                if link.user_id == user_id {
+                    Ok(())
+                } else {
+                    Err(AuthError::UserExists)
+                }
+            }
+            result => result,
+        }
+    }
+
+    async fn begin_passkey_registration(&self, user_id: Uuid) -> Result<PasskeyCreationOptions, AuthError> {
+        let user = queries::users::get_user_by_id(&self.pool, user_id)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::Unauthorized,
+                _ => AuthError::DatabaseError,
+            })?;
+        let existing: Vec<Vec<u8>> = queries::passkeys::list_passkeys(&self.pool, user_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?
+            .into_iter()
+            .map(|passkey| passkey.credential_id)
+            .collect();
+
+        let challenge = self.store_passkey_challenge(Ceremony::Register, Some(user_id)).await?;
+        Ok(webauthn::creation_options(&self.config, user_id, &user.email, challenge, &existing))
+    }
+
+    async fn finish_passkey_registration(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        credential: &PasskeyCredential,
+    ) -> Result<PasskeyInfo, AuthError> {
+        let (challenge, owner) = self
+            .take_passkey_challenge(&credential.client_data_json, Ceremony::Register)
+            .await?;
+        if owner != Some(user_id) {
+            return Err(AuthError::InvalidToken);
+        }
+        let verified = webauthn::verify_registration(&self.config, &challenge, credential)?;
+
+        let name = match name.trim() {
+            "" => "Passkey",
+            name => name,
+        };
+        let passkey = queries::passkeys::create_passkey(
+            &self.pool,
+            user_id,
+            &verified.credential_id,
+            &verified.public_key,
+            i64::from(verified.sign_count),
+            name,
+        )
+        .await
+        .map_err(|e| match e {
+            DbError::ConstraintViolation(_) => AuthError::InvalidPasskey,
+            _ => AuthError::DatabaseError,
+        })?;
+        Ok(passkey_info(passkey))
+    }
+
+    async fn begin_passkey_authentication(&self) -> Result<PasskeyRequestOptions, AuthError> {
+        let challenge = self.store_passkey_challenge(Ceremony::Authenticate, None).await?;
+        Ok(webauthn::request_options(&self.config, challenge))
+    }
+
+    async fn authenticate_passkey(&self, assertion: &PasskeyAssertion) -> Result<User, AuthError> {
+        let (challenge, _) = self
+            .take_passkey_challenge(&assertion.client_data_json, Ceremony::Authenticate)
+            .await?;
+        let credential_id = webauthn::decode(&assertion.credential_id)?;
+        let passkey = queries::passkeys::get_passkey_by_credential_id(&self.pool, &credential_id)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::AuthenticationFailed,
+                _ => AuthError::DatabaseError,
+            })?;
+        if let Some(handle) = &assertion.user_handle {
+            if webauthn::decode(handle)? != passkey.user_id.as_bytes() {
+                return Err(AuthError::AuthenticationFailed);
+            }
+        }
+
+        let sign_count = webauthn::verify_assertion(
+            &self.config,
+            &challenge,
+            assertion,
+            &passkey.public_key,
+            passkey.sign_count as u32,
+        )?;
+        let recorded = queries::passkeys::record_passkey_use(&self.pool, passkey.id, passkey.sign_count, i64::from(sign_count))
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        // Another sign-in advanced the counter first, or the passkey was just deleted
+        if !recorded {
+            return Err(AuthError::InvalidPasskey);
+        }
+
+        let user = queries::users::get_user_by_id(&self.pool, passkey.user_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        self.issue_session(user.id, user.email).await
+    }
+
+    async fn list_passkeys(&self, user_id: Uuid) -> Result<Vec<PasskeyInfo>, AuthError> {
+        queries::passkeys::list_passkeys(&self.pool, user_id)
+            .await
+            .map(|passkeys| passkeys.into_iter().map(passkey_info).collect())
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    async fn delete_passkey(&self, user_id: Uuid, passkey_id: Uuid) -> Result<bool, AuthError> {
+        queries::passkeys::delete_passkey(&self.pool, user_id, passkey_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    async fn create_api_key(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<NewApiKey, AuthError> {
+        let user = queries::users::get_user_by_id(&self.pool, user_id)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::Unauthorized,
+                _ => AuthError::DatabaseError,
+            })?;
+        let owner = self.load_user(user.id, user.email, String::new(), Utc::now()).await?;
+        let scopes = api_keys::check_scopes(scopes, &owner.permissions)?;
+
+        let key = api_keys::generate_api_key();
+        let name = match name.trim() {
+            "" => "API key",
+            name => name,
+        };
+        let stored = queries::api_keys::create_api_key(
+            &self.pool,
+            user_id,
+            name,
+            &hash_token(&key),
+            &api_keys::display_prefix(&key),
+            &scopes,
+            expires_at,
+        )
+        .await
+        .map_err(|_| AuthError::DatabaseError)?;
+        Ok(NewApiKey { key, api_key: api_key_info(stored) })
+    }
+
+    async fn list_api_keys(&self, user_id: Uuid) -> Result<Vec<ApiKeyInfo>, AuthError> {
+        let keys = queries::api_keys::list_api_keys(&self.pool, user_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        Ok(keys.into_iter().map(api_key_info).collect())
+    }
+
+    async fn revoke_api_key(&self, user_id: Uuid, key_id: Uuid) -> Result<bool, AuthError> {
+        queries::api_keys::delete_api_key(&self.pool, user_id, key_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    async fn create_invitation(
+        &self,
+        email: Option<&str>,
+        max_uses: Option<u32>,
+        expires_at: Option<DateTime<Utc>>,
+        invited_by: Option<Uuid>,
+    ) -> Result<NewInvitation, AuthError> {
+        let max_uses = invitations::check_max_uses(max_uses)?
+            .map(i32::try_from)
+            .transpose()
+            .map_err(|_| AuthError::InvalidInvitation)?;
+        let email = invitations::bound_email(&self.config.email_policy, email)?;
+
+        let code = invitations::generate_invitation_code();
+        let stored = queries::invitations::create_invitation(
+            &self.pool,
+            &invitations::hash_invitation_code(&code),
+            email.as_deref(),
+            max_uses,
+            invited_by,
+            expires_at,
+        )
+        .await
+        .map_err(|_| AuthError::DatabaseError)?;
+        Ok(NewInvitation { code, invitation: invitation_info(stored) })
+    }
+
+    async fn list_invitations(&self) -> Result<Vec<InvitationInfo>, AuthError> {
+        let invitations = queries::invitations::list_invitations(&self.pool)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        Ok(invitations.into_iter().map(invitation_info).collect())
+    }
+
+    async fn revoke_invitation(&self, invitation_id: Uuid) -> Result<bool, AuthError> {
+        queries::invitations::revoke_invitation(&self.pool, invitation_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    async fn authenticate_api_key(&self, key: &str) -> Result<User, AuthError> {
+        let stored = queries::api_keys::get_api_key_by_hash(&self.pool, &hash_token(key))
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidSession,
+                _ => AuthError::DatabaseError,
+            })?;
+        if stored.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            return Err(AuthError::InvalidSession);
+        }
+        let _ = queries::api_keys::touch_api_key(&self.pool, stored.id).await;
+
+        let user = queries::users::get_user_by_id(&self.pool, stored.user_id)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidSession,
+                _ => AuthError::DatabaseError,
+            })?;
+        let expires_at =
+            api_keys::session_expires_at(stored.expires_at, Utc::now(), self.config.access_token_ttl);
+        let user = self.load_user(user.id, user.email, key.to_owned(), expires_at).await?;
+        Ok(api_keys::scoped_user(user, &stored.scopes))
+    }
+
+    async fn validate_session(&self, token: &str) -> Result<User, AuthError> {
+        let token_hash = self.session_hash(token);
+        let session = queries::session::get_session(&self.pool, &token_hash)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidSession,
+                _ => AuthError::DatabaseError,
+            })?;
+
+        if session.expires_at <= Utc::now() {
+            // Sessions without a refresh family can never come back, drop
+            // them on sight; the others stay listed until their family ends
+            if session.family_id.is_none() {
+                let _ = queries::session::delete_session(&self.pool, &token_hash).await;
+            }
+            return Err(AuthError::InvalidSession);
+        }
+        let _ = queries::session::touch_session(&self.pool, session.id, RequestMeta::current().ip.as_deref()).await;
+
+        let user = queries::users::get_user_by_id(&self.pool, session.user_id)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidSession,
+                _ => AuthError::DatabaseError,
+            })?;
+
+        let mut user = self.load_user(user.id, user.email, token.to_owned(), session.expires_at).await?;
+        user.impersonator_id = session.impersonator_id;
+        Ok(user)
+    }
+
+    async fn logout(&self, token: &str) -> Result<(), AuthError> {
+        let token_hash = self.session_hash(token);
+        let session = queries::session::get_session(&self.pool, &token_hash)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidSession,
+                _ => AuthError::DatabaseError,
+            })?;
+
+        // Logging out also retires the refresh token that could renew the session
+        match session.family_id {
+            Some(family_id) => queries::refresh::revoke_family(&self.pool, family_id)
+                .await
+                .map(|_| ())
+                .map_err(|_| AuthError::DatabaseError),
+            None => queries::session::delete_session(&self.pool, &token_hash)
+                .await
+                .map(|_| ())
+                .map_err(|_| AuthError::DatabaseError),
+        }
+    }
+
+    async fn refresh(&self, refresh_token: &str) -> Result<User, AuthError> {
+        let record = queries::refresh::get_refresh_token(&self.pool, &hash_token(refresh_token))
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidToken,
+                _ => AuthError::DatabaseError,
+            })?;
+
+        if record.revoked_at.is_some() {
+            return Err(AuthError::InvalidToken);
+        }
+        if record.used_at.is_some() {
+            // Someone holds a copy of a spent token; nothing in the family can be trusted
+            return Err(self.revoke_family(record.user_id, record.family_id).await);
+        }
+        if record.expires_at <= Utc::now() {
+            return Err(AuthError::InvalidToken);
+        }
+        let spent = queries::refresh::mark_refresh_token_used(&self.pool, record.id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        if !spent {
+            // Lost a race against another refresh with the same token
+            return Err(self.revoke_family(record.user_id, record.family_id).await);
+        }
+
+        let user = queries::users::get_user_by_id(&self.pool, record.user_id)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidToken,
+                _ => AuthError::DatabaseError,
+            })?;
+
+        // The session moves onto the new access token, the old one stops working
+        let token = generate_random_token();
+        let expires_at = Utc::now() + self.config.access_token_ttl;
+        let rotated = queries::session::rotate_family_session(
+            &self.pool,
+            record.family_id,
+            &self.session_hash(&token),
+            expires_at,
+            RequestMeta::current().ip.as_deref(),
+        )
+        .await
+        .map_err(|_| AuthError::DatabaseError)?;
+        if rotated.is_none() {
+            self.create_session(user.id, record.family_id, &token, expires_at).await?;
+        }
+        self.issue_refresh_token(user.id, user.email, record.family_id, token, expires_at).await
+    }
+
+    async fn list_sessions(&self, user_id: Uuid, current_token: &str) -> Result<Vec<SessionInfo>, AuthError> {
+        let sessions = queries::session::list_active_sessions(&self.pool, user_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+
+        let current_hash = self.session_hash(current_token);
+        Ok(sessions
+            .into_iter()
+            .map(|session| session_info(session, &current_hash))
+            .collect())
+    }
+
+    async fn revoke_session(&self, user_id: Uuid, session_id: Uuid) -> Result<bool, AuthError> {
+        let session = match queries::session::get_user_session(&self.pool, user_id, session_id).await {
+            Ok(session) => session,
+            Err(DbError::NotFound) => return Ok(false),
+            Err(_) => return Err(AuthError::DatabaseError),
+        };
+
+        match session.family_id {
+            Some(family_id) => {
+                queries::refresh::revoke_family(&self.pool, family_id)
+                    .await
+                    .map_err(|_| AuthError::DatabaseError)?;
+            }
+            None => {
+                queries::session::delete_session(&self.pool, &session.token_hash)
+                    .await
+                    .map_err(|_| AuthError::DatabaseError)?;
+            }
+        }
+        Ok(true)
+    }
+
+    async fn revoke_other_sessions(&self, user_id: Uuid, keep_token: &str) -> Result<u64, AuthError> {
+        queries::session::revoke_other_sessions(&self.pool, user_id, &self.session_hash(keep_token))
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    async fn impersonate(&self, impersonator_id: Uuid, user_id: Uuid) -> Result<User, AuthError> {
+        if impersonator_id == user_id {
+            return Err(AuthError::Forbidden);
+        }
+        let target = queries::users::get_user_by_id(&self.pool, user_id)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::UserNotFound,
+                _ => AuthError::DatabaseError,
+            })?;
+
+        let token = generate_random_token();
+        let expires_at = Utc::now() + self.config.impersonation_ttl;
+        let mut user = self.load_user(target.id, target.email, token, expires_at).await?;
+        // Acting as another admin would hand out their powers under someone else's name
+        if user.has_role(ADMIN_ROLE) {
+            return Err(AuthError::Forbidden);
+        }
+
+        let meta = RequestMeta::current();
+        let label = device_label(meta.user_agent.as_deref());
+        queries::session::create_impersonation_session(
+            &self.pool,
+            user_id,
+            impersonator_id,
+            &self.session_hash(&user.bearer_token),
+            expires_at,
+            Some(&label),
+            meta.user_agent.as_deref(),
+            meta.ip.as_deref(),
+        )
+        .await
+        .map_err(|_| AuthError::DatabaseError)?;
+
+        user.impersonator_id = Some(impersonator_id);
+        Ok(user)
+    }
+
+    async fn verify_email(&self, token: &str) -> Result<(), AuthError> {
+        let record = queries::verification::take_verification_token(&self.pool, &hash_token(token))
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidToken,
+                _ => AuthError::DatabaseError,
+            })?;
+
+        if record.expires_at <= Utc::now() {
+            return Err(AuthError::InvalidToken);
+        }
+
+        queries::users::mark_email_verified(&self.pool, record.user_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        queries::verification::delete_verification_tokens_for_user(&self.pool, record.user_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+
+        Ok(())
+    }
+
+    async fn resend_verification(&self, email: &str) -> Result<(), AuthError> {
+        let email = self.config.email_policy.canonicalize(email);
+        let user = match queries::users::get_user_by_email(&self.pool, &email).await {
+            Ok(user) => user,
+            Err(DbError::NotFound) => return Ok(()),
+            Err(_) => return Err(AuthError::DatabaseError),
+        };
+        if user.email_verified_at.is_some() {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        let recent = queries::verification::recent_verification_tokens(
+            &self.pool,
+            user.id,
+            now - Duration::hours(24),
+        )
+        .await
+        .map_err(|_| AuthError::DatabaseError)?;
+
+        let too_soon = recent
+            .first()
+            .is_some_and(|t| now - t.created_at < self.config.verification_resend_interval);
+        if too_soon || recent.len() as i64 >= self.config.verification_max_per_day {
+            return Err(AuthError::RateLimited);
+        }
+
+        self.send_verification(user.id, &user.email).await
+    }
+
+    async fn request_password_reset(&self, email: &str) -> Result<(), AuthError> {
+        let email = self.config.email_policy.canonicalize(email);
+        let user = match queries::users::get_user_by_email(&self.pool, &email).await {
+            Ok(user) => user,
+            Err(DbError::NotFound) => return Ok(()),
+            Err(_) => return Err(AuthError::DatabaseError),
+        };
+
+        let token = generate_random_token();
+        let expires_at = Utc::now() + self.config.password_reset_ttl;
+        queries::password_reset::create_reset_token(&self.pool, user.id, &hash_token(&token), expires_at)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+
+        self.mailer
+            .send(password_reset_email(&user.email, &self.config.app_url, &token))
+            .await
+    }
+
+    async fn reset_password(&self, token: &str, new_password: &str) -> Result<(Uuid, Vec<Uuid>), AuthError> {
+        // Validate against the account first so a weak password doesn't burn the token
+        let token_hash = hash_token(token);
+        let pending = queries::password_reset::get_valid_reset_token(&self.pool, &token_hash)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidToken,
+                _ => AuthError::DatabaseError,
+            })?;
+        let user = queries::users::get_user_by_id(&self.pool, pending.user_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        self.config.password_policy.validate(new_password, &user.email)?;
+
+        let record = queries::password_reset::consume_reset_token(&self.pool, &token_hash)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidToken,
+                _ => AuthError::DatabaseError,
+            })?;
+
+        let password_hash = self.hash_password(new_password).await?;
+
+        queries::users::update_password_hash(&self.pool, record.user_id, &password_hash)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        let session_ids = queries::session::delete_sessions_for_user(&self.pool, record.user_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        queries::refresh::revoke_refresh_tokens_for_user(&self.pool, record.user_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+
+        Ok((record.user_id, session_ids))
+    }
+
+    async fn change_password(
+        &self,
+        user_id: Uuid,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<(), AuthError> {
+        let user = queries::users::get_user_by_id(&self.pool, user_id)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::UserNotFound,
+                _ => AuthError::DatabaseError,
+            })?;
+        let lockout_key = lockout::lockout_key(&user.email_canonical);
+        self.check_lockout(&lockout_key).await?;
+
+        let hasher = self.hasher.clone();
+        let candidate = current_password.to_owned();
+        let password_hash = user.password_hash.clone();
+        let valid = tokio::task::spawn_blocking(move || hasher.verify(&candidate, &password_hash))
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        if !valid {
+            return Err(self.record_login_failure(&lockout_key).await);
+        }
+        self.config.password_policy.validate(new_password, &user.email)?;
+
+        let password_hash = self.hash_password(new_password).await?;
+        queries::users::update_password_hash(&self.pool, user_id, &password_hash)
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    async fn assign_role(&self, user_id: Uuid, role: &str) -> Result<(), AuthError> {
+        queries::roles::assign_role(&self.pool, user_id, role)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::UnknownRole,
+                DbError::ConstraintViolation(_) => AuthError::UserNotFound,
+                _ => AuthError::DatabaseError,
+            })
+    }
+
+    async fn revoke_role(&self, user_id: Uuid, role: &str) -> Result<(), AuthError> {
+        queries::roles::revoke_role(&self.pool, user_id, role)
+            .await
+            .map(|_| ())
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    async fn verify_mfa(&self, challenge: &str, code: &str) -> Result<User, AuthError> {
+        let challenge_hash = hash_token(challenge);
+        let record = queries::mfa::get_challenge(&self.pool, &challenge_hash)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidToken,
+                _ => AuthError::DatabaseError,
+            })?;
+        if record.expires_at <= Utc::now() {
+            let _ = queries::mfa::delete_challenge(&self.pool, &challenge_hash).await;
+            return Err(AuthError::InvalidToken);
+        }
+
+        let user = queries::users::get_user_by_id(&self.pool, record.user_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        let lockout_key = lockout::lockout_key(&user.email_canonical);
+        self.check_lockout(&lockout_key).await?;
+
+        let totp = self
+            .enabled_totp(record.user_id)
+            .await?
+            .ok_or(AuthError::InvalidToken)?;
+
+        if !self.check_second_factor(&totp, code).await? {
+            let failure = self.record_login_failure(&lockout_key).await;
+            if matches!(failure, AuthError::DatabaseError) {
+                return Err(failure);
+            }
+            let record = queries::mfa::record_failed_attempt(&self.pool, &challenge_hash)
+                .await
+                .map_err(|_| AuthError::DatabaseError)?;
+            if record.attempts >= self.config.mfa_max_attempts {
+                // Too many guesses, make them start over with the password
+                let _ = queries::mfa::delete_challenge(&self.pool, &challenge_hash).await;
+                return Err(AuthError::InvalidToken);
+            }
+            return Err(AuthError::InvalidMfaCode);
+        }
+
+        queries::mfa::delete_challenge(&self.pool, &challenge_hash)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+        self.clear_login_failures(&lockout_key).await?;
+
+        self.issue_session(user.id, user.email).await
+    }
+
+    async fn mfa_challenge_email(&self, challenge: &str) -> Result<String, AuthError> {
+        let record = queries::mfa::get_challenge(&self.pool, &hash_token(challenge))
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidToken,
+                _ => AuthError::DatabaseError,
+            })?;
+        if record.expires_at <= Utc::now() {
+            return Err(AuthError::InvalidToken);
+        }
+
+        let user = queries::users::get_user_by_id(&self.pool, record.user_id)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::InvalidToken,
+                _ => AuthError::DatabaseError,
+            })?;
+        Ok(user.email)
+    }
+
+    async fn begin_totp_enrollment(&self, user_id: Uuid) -> Result<TotpEnrollment, AuthError> {
+        let user = queries::users::get_user_by_id(&self.pool, user_id)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::Unauthorized,
+                _ => AuthError::DatabaseError,
+            })?;
+
+        let secret = totp::generate_secret();
+        queries::mfa::upsert_pending_totp(&self.pool, user_id, &secret)
+            .await
+            .map_err(|e| match e {
+                DbError::ConstraintViolation(_) => AuthError::MfaAlreadyEnabled,
+                _ => AuthError::DatabaseError,
+            })?;
+
+        totp::enrollment(&secret, &self.config.totp_issuer, &user.email)
+    }
+
+    async fn confirm_totp_enrollment(&self, user_id: Uuid, code: &str) -> Result<Vec<String>, AuthError> {
+        let record = queries::mfa::get_totp(&self.pool, user_id)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => AuthError::MfaNotEnrolled,
+                _ => AuthError::DatabaseError,
+            })?;
+        if record.enabled_at.is_some() {
+            return Err(AuthError::MfaAlreadyEnabled);
+        }
+
+        let step = totp::verify_code(&record.secret, code, Utc::now(), None)?
+            .ok_or(AuthError::InvalidMfaCode)?;
+
+        let codes = totp::generate_recovery_codes();
+        let hashes: Vec<String> = codes.iter().map(|c| totp::hash_recovery_code(c)).collect();
+        queries::mfa::enable_totp(&self.pool, user_id, step, &hashes)
+            .await
+            .map_err(|_| AuthError::DatabaseError)?;
+
+        Ok(codes)
+    }
+
+    async fn disable_totp(&self, user_id: Uuid, code: &str) -> Result<(), AuthError> {
+        let totp = self
+            .enabled_totp(user_id)
+            .await?
+            .ok_or(AuthError::MfaNotEnrolled)?;
+
+        if !self.check_second_factor(&totp, code).await? {
+            return Err(AuthError::InvalidMfaCode);
+        }
+
+        queries::mfa::delete_totp(&self.pool, user_id)
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    async fn unlock_account(&self, email: &str) -> Result<bool, AuthError> {
+        let email = self.config.email_policy.canonicalize(email);
+        queries::lockout::clear_login_attempts(&self.pool, &lockout::lockout_key(&email))
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+}
+
+/// Describes a stored session for the active sessions page
+fn session_info(session: UserSession, current_hash: &str) -> SessionInfo {
+    SessionInfo {
+        id: session.id,
+        device_label: session
+            .device_label
+            .unwrap_or_else(|| device_label(session.user_agent.as_deref())),
+        current: session.token_hash == current_hash,
+        user_agent: session.user_agent,
+        ip: session.ip,
+        created_at: session.created_at,
+        last_seen_at: session.last_seen_at,
+    }
+}
+
+/// Describes a stored passkey for the settings page
+fn passkey_info(passkey: Passkey) -> PasskeyInfo {
+    PasskeyInfo {
+        id: passkey.id,
+        name: passkey.name,
+        created_at: passkey.created_at,
+        last_used_at: passkey.last_used_at,
+    }
+}
+
+/// Describes a stored API key for the settings page
+fn api_key_info(key: ApiKey) -> ApiKeyInfo {
+    ApiKeyInfo {
+        id: key.id,
+        name: key.name,
+        prefix: key.prefix,
+        scopes: key.scopes,
+        expires_at: key.expires_at,
+        last_used_at: key.last_used_at,
+        created_at: key.created_at,
+    }
+}
+
+/// Describes a stored invitation for the admin listing
+fn invitation_info(invitation: Invitation) -> InvitationInfo {
+    InvitationInfo {
+        id: invitation.id,
+        email: invitation.email,
+        max_uses: invitation.max_uses.map(|n| n as u32),
+        use_count: invitation.use_count as u32,
+        invited_by: invitation.invited_by,
+        expires_at: invitation.expires_at,
+        revoked_at: invitation.revoked_at,
+        created_at: invitation.created_at,
+    }
+}
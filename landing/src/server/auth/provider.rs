@@ -2,23 +2,35 @@
 //!
 //! This module defines the core authentication trait that all providers must implement.
 
+use crate::server::auth::oidc::ExternalIdentity;
 use crate::server::error::AuthError;
-use crate::server::models::User;
+use crate::server::models::{
+    ApiKeyInfo, InvitationInfo, NewApiKey, NewInvitation, PasskeyAssertion, PasskeyCreationOptions, PasskeyCredential,
+    PasskeyInfo, PasskeyRequestOptions, SessionInfo, TotpEnrollment, User,
+};
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
 
 /// Core authentication trait defining required operations
 #[async_trait]
 pub trait AuthProvider: Send + Sync {
     /// Register a new user with email and password
+    ///
+    /// A given invitation code is spent even when `invite_only` is off, so
+    /// the account is still attributed to the invitation.
     /// 
     /// # Arguments
     /// * `email` - User's email address
     /// * `password` - User's password
+    /// * `invite_code` - Code from `create_invitation`, required when
+    ///   `invite_only` is on
     /// 
     /// # Returns
-    /// Result indicating success or specific authentication error
-    async fn register(&self, email: &str, password: &str) -> Result<(), AuthError>;
+    /// Result indicating success or specific authentication error, including
+    /// `AuthError::InvitationRequired` and `AuthError::InvalidInvitation`
+    async fn register(&self, email: &str, password: &str, invite_code: Option<&str>) -> Result<(), AuthError>;
     
     /// Authenticate a user with email and password
     /// 
@@ -28,8 +40,184 @@ pub trait AuthProvider: Send + Sync {
     /// 
     /// # Returns
     /// On success, returns a User with session token. On failure, returns AuthError.
+    /// Accounts with two-factor enabled get `AuthError::MfaRequired` instead,
+    /// carrying the challenge to pass to `verify_mfa`. Repeated failures for
+    /// the same email, existing or not, slow down and then lock with
+    /// `AuthError::AccountLocked`.
     async fn authenticate(&self, email: &str, password: &str) -> Result<User, AuthError>;
+
+    /// Email a single-use sign-in link
+    ///
+    /// The link only works together with the returned browser secret, which
+    /// the caller keeps in the requesting browser (an HttpOnly cookie), so a
+    /// link read in transit or forwarded is useless elsewhere. Unknown emails
+    /// get a link only when `AuthConfig::allows_uninvited_signup`; either way
+    /// a secret is returned so the response doesn't reveal which addresses
+    /// have accounts.
+    ///
+    /// # Arguments
+    /// * `email` - Address to send the link to
+    ///
+    /// # Returns
+    /// The browser secret, or `AuthError::InvalidEmail`
+    async fn request_magic_link(&self, email: &str) -> Result<String, AuthError>;
+
+    /// Log in with a token from a magic link
+    ///
+    /// Opening the link proves the address, so the account is marked
+    /// verified, and created on first use if it doesn't exist yet.
+    ///
+    /// # Arguments
+    /// * `token` - Token from the emailed link
+    /// * `browser_secret` - Secret `request_magic_link` returned to the browser
+    ///
+    /// # Returns
+    /// The User with a session token, `AuthError::MfaRequired` as for
+    /// `authenticate`, or `AuthError::InvalidToken` for an unknown, spent or
+    /// expired link or one opened in another browser
+    async fn login_with_magic_link(&self, token: &str, browser_secret: &str) -> Result<User, AuthError>;
     
+    /// Log in with an account at an external identity provider
+    ///
+    /// A linked identity logs its user in. An unlinked one is linked to the
+    /// local account with the same email when both sides have verified the
+    /// address, and otherwise gets a new account without a usable password.
+    ///
+    /// # Arguments
+    /// * `identity` - Identity from a verified ID token
+    ///
+    /// # Returns
+    /// The User with a session token, `AuthError::MfaRequired` as for
+    /// `authenticate`, or `AuthError::UserExists` if a local account holds
+    /// the email but can't be linked automatically
+    async fn login_with_identity(&self, identity: &ExternalIdentity) -> Result<User, AuthError>;
+
+    /// Link an external account to a signed-in user
+    ///
+    /// # Arguments
+    /// * `user_id` - User the identity should log in as from now on
+    /// * `identity` - Identity from a verified ID token
+    ///
+    /// # Returns
+    /// `AuthError::UserExists` if the identity already belongs to someone else
+    async fn link_identity(&self, user_id: Uuid, identity: &ExternalIdentity) -> Result<(), AuthError>;
+
+    /// Start adding a passkey to a signed-in user's account
+    ///
+    /// # Arguments
+    /// * `user_id` - User the passkey will sign in as
+    ///
+    /// # Returns
+    /// Options for `navigator.credentials.create()`, valid for
+    /// `AuthConfig::passkey_challenge_ttl`
+    async fn begin_passkey_registration(&self, user_id: Uuid) -> Result<PasskeyCreationOptions, AuthError>;
+
+    /// Store the passkey the browser created from `begin_passkey_registration`
+    ///
+    /// # Arguments
+    /// * `user_id` - Same user the ceremony was started for
+    /// * `name` - Label to tell the user's passkeys apart
+    /// * `credential` - Response from `navigator.credentials.create()`
+    ///
+    /// # Returns
+    /// `AuthError::InvalidToken` for an unknown, spent or expired challenge,
+    /// `AuthError::InvalidPasskey` if the response doesn't verify
+    async fn finish_passkey_registration(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        credential: &PasskeyCredential,
+    ) -> Result<PasskeyInfo, AuthError>;
+
+    /// Start a passkey sign-in; no email is needed, the authenticator
+    /// offers the passkeys it holds for this site
+    async fn begin_passkey_authentication(&self) -> Result<PasskeyRequestOptions, AuthError>;
+
+    /// Sign in with a response from `navigator.credentials.get()`
+    ///
+    /// A passkey already proves possession and user verification, so no
+    /// TOTP code is asked for.
+    ///
+    /// # Returns
+    /// The User with a session token, `AuthError::AuthenticationFailed` for
+    /// an unknown credential, `AuthError::InvalidPasskey` if the signature
+    /// or signature counter doesn't check out
+    async fn authenticate_passkey(&self, assertion: &PasskeyAssertion) -> Result<User, AuthError>;
+
+    /// List a user's passkeys
+    async fn list_passkeys(&self, user_id: Uuid) -> Result<Vec<PasskeyInfo>, AuthError>;
+
+    /// Remove one of a user's passkeys
+    ///
+    /// # Returns
+    /// Whether the user had such a passkey
+    async fn delete_passkey(&self, user_id: Uuid, passkey_id: Uuid) -> Result<bool, AuthError>;
+
+    /// Create a personal API key
+    ///
+    /// # Arguments
+    /// * `user_id` - Owner the key acts as
+    /// * `name` - Label to tell the user's keys apart
+    /// * `scopes` - Permissions the key may use, all held by the owner
+    /// * `expires_at` - When the key stops working, `None` for never
+    ///
+    /// # Returns
+    /// The key, which is only stored hashed and can't be shown again, or
+    /// `AuthError::InvalidScope`
+    async fn create_api_key(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<NewApiKey, AuthError>;
+
+    /// List a user's API keys, without the keys themselves
+    async fn list_api_keys(&self, user_id: Uuid) -> Result<Vec<ApiKeyInfo>, AuthError>;
+
+    /// Revoke one of a user's API keys
+    ///
+    /// # Returns
+    /// Whether the user had such a key
+    async fn revoke_api_key(&self, user_id: Uuid, key_id: Uuid) -> Result<bool, AuthError>;
+
+    /// Create a registration invitation
+    ///
+    /// # Arguments
+    /// * `email` - Address the code is bound to, `None` for anyone
+    /// * `max_uses` - Accounts the code can create, `None` for no limit
+    /// * `expires_at` - When the code stops working, `None` for never
+    /// * `invited_by` - Account the invitation is attributed to
+    ///
+    /// # Returns
+    /// The code, which is only stored hashed and can't be shown again,
+    /// `AuthError::InvalidEmail`, or `AuthError::InvalidInvitation` for a
+    /// `max_uses` of 0
+    async fn create_invitation(
+        &self,
+        email: Option<&str>,
+        max_uses: Option<u32>,
+        expires_at: Option<DateTime<Utc>>,
+        invited_by: Option<Uuid>,
+    ) -> Result<NewInvitation, AuthError>;
+
+    /// List every invitation, newest first, without the codes
+    async fn list_invitations(&self) -> Result<Vec<InvitationInfo>, AuthError>;
+
+    /// Stop an invitation code from being used
+    ///
+    /// # Returns
+    /// Whether there was such an invitation that wasn't revoked yet
+    async fn revoke_invitation(&self, invitation_id: Uuid) -> Result<bool, AuthError>;
+
+    /// Resolve an API key presented as a bearer credential
+    ///
+    /// # Returns
+    /// The owner narrowed with `api_keys::scoped_user`, with the key as
+    /// `bearer_token`, or `AuthError::InvalidSession` for an unknown or
+    /// expired key
+    async fn authenticate_api_key(&self, key: &str) -> Result<User, AuthError>;
+
     /// Validate an existing session token
     /// 
     /// # Arguments
@@ -39,9 +227,184 @@ pub trait AuthProvider: Send + Sync {
     /// User information if token is valid, AuthError otherwise
     async fn validate_session(&self, token: &str) -> Result<User, AuthError>;
     
-    /// Invalidate a session token (logout)
+    /// Invalidate a session token (logout), together with the refresh
+    /// token family it belongs to
     /// 
     /// # Arguments
     /// * `token` - Session token to invalidate
     async fn logout(&self, token: &str) -> Result<(), AuthError>;
-}
\ No newline at end of file
+
+    /// Exchange a refresh token for a new access token and refresh token
+    ///
+    /// Refresh tokens are single-use. Presenting one a second time revokes
+    /// every token issued from the same login.
+    ///
+    /// # Arguments
+    /// * `refresh_token` - Token from the last login or refresh
+    ///
+    /// # Returns
+    /// The User with fresh `bearer_token` and `refresh_token`, or
+    /// `AuthError::TokenReuseDetected` if the token was already spent
+    async fn refresh(&self, refresh_token: &str) -> Result<User, AuthError>;
+
+    /// List the devices a user is signed in on
+    ///
+    /// A session counts while its access token or its refresh token is
+    /// still usable.
+    ///
+    /// # Arguments
+    /// * `user_id` - Owner of the sessions
+    /// * `current_token` - Access token of the caller, flagged as `current`
+    async fn list_sessions(&self, user_id: Uuid, current_token: &str) -> Result<Vec<SessionInfo>, AuthError>;
+
+    /// Sign a user out on one device, together with its refresh token family
+    ///
+    /// # Arguments
+    /// * `user_id` - Owner of the session; other users' sessions are never touched
+    /// * `session_id` - `SessionInfo::id` from `list_sessions`
+    ///
+    /// # Returns
+    /// Whether the user had such a session
+    async fn revoke_session(&self, user_id: Uuid, session_id: Uuid) -> Result<bool, AuthError>;
+
+    /// Sign a user out everywhere except the session holding `keep_token`
+    ///
+    /// # Returns
+    /// Number of sessions ended
+    async fn revoke_other_sessions(&self, user_id: Uuid, keep_token: &str) -> Result<u64, AuthError>;
+
+    /// Open a session for an admin to act as another user
+    ///
+    /// The session is marked with the impersonator, has no refresh token and
+    /// lasts `AuthConfig::impersonation_ttl`. Callers check that the
+    /// impersonator is an admin.
+    ///
+    /// # Arguments
+    /// * `impersonator_id` - Admin opening the session
+    /// * `user_id` - User to act as
+    ///
+    /// # Returns
+    /// The User with `impersonator_id` set, `AuthError::UserNotFound`, or
+    /// `AuthError::Forbidden` when the target is the impersonator or an admin
+    async fn impersonate(&self, impersonator_id: Uuid, user_id: Uuid) -> Result<User, AuthError>;
+
+    /// Confirm an email address using the token from the verification link
+    ///
+    /// # Arguments
+    /// * `token` - Single-use verification token
+    async fn verify_email(&self, token: &str) -> Result<(), AuthError>;
+
+    /// Send a fresh verification link, subject to rate limiting
+    ///
+    /// Succeeds silently for unknown or already verified addresses so the
+    /// endpoint can't be used to probe for accounts.
+    ///
+    /// # Arguments
+    /// * `email` - Address the account was registered with
+    async fn resend_verification(&self, email: &str) -> Result<(), AuthError>;
+
+    /// Email a single-use password reset link
+    ///
+    /// Succeeds silently for unknown addresses.
+    ///
+    /// # Arguments
+    /// * `email` - Address the account was registered with
+    async fn request_password_reset(&self, email: &str) -> Result<(), AuthError>;
+
+    /// Set a new password using a reset token, revoking every existing session
+    ///
+    /// # Arguments
+    /// * `token` - Token from the reset link
+    /// * `new_password` - Replacement password, checked against the password policy
+    ///
+    /// # Returns
+    /// * `Ok((Uuid, Vec<Uuid>))` - ID of the user whose password changed and
+    ///   the ids of the sessions revoked
+    async fn reset_password(&self, token: &str, new_password: &str) -> Result<(Uuid, Vec<Uuid>), AuthError>;
+
+    /// Replace a signed-in user's password after checking the current one
+    ///
+    /// Wrong current passwords count toward the login lockout. Sessions are
+    /// left alone; the caller decides which of them to end.
+    ///
+    /// # Arguments
+    /// * `user_id` - User changing their password
+    /// * `current_password` - Password the account has now
+    /// * `new_password` - Replacement password, checked against the password policy
+    ///
+    /// # Returns
+    /// * `Err(AuthError::AuthenticationFailed)` - `current_password` is wrong
+    async fn change_password(
+        &self,
+        user_id: Uuid,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<(), AuthError>;
+
+    /// Grant a role to a user
+    ///
+    /// # Arguments
+    /// * `user_id` - User receiving the role
+    /// * `role` - Role name, e.g. `admin`
+    ///
+    /// # Returns
+    /// `AuthError::UnknownRole` or `AuthError::UserNotFound` when either
+    /// doesn't exist
+    async fn assign_role(&self, user_id: Uuid, role: &str) -> Result<(), AuthError>;
+
+    /// Take a role away from a user
+    ///
+    /// # Arguments
+    /// * `user_id` - User losing the role
+    /// * `role` - Role name
+    async fn revoke_role(&self, user_id: Uuid, role: &str) -> Result<(), AuthError>;
+
+    /// Finish a login that returned `AuthError::MfaRequired`
+    ///
+    /// # Arguments
+    /// * `challenge` - Challenge from the `MfaRequired` error
+    /// * `code` - Current TOTP code or an unused recovery code
+    ///
+    /// # Returns
+    /// The User with a new session token on success
+    async fn verify_mfa(&self, challenge: &str, code: &str) -> Result<User, AuthError>;
+
+    /// Email of the account a pending `MfaRequired` challenge belongs to,
+    /// so the second step can be rate limited per account like the first
+    ///
+    /// # Arguments
+    /// * `challenge` - Challenge from the `MfaRequired` error
+    ///
+    /// # Returns
+    /// `AuthError::InvalidToken` for an unknown or expired challenge
+    async fn mfa_challenge_email(&self, challenge: &str) -> Result<String, AuthError>;
+
+    /// Start TOTP enrollment, generating a secret that isn't active yet
+    ///
+    /// # Arguments
+    /// * `user_id` - User enrolling
+    async fn begin_totp_enrollment(&self, user_id: Uuid) -> Result<TotpEnrollment, AuthError>;
+
+    /// Turn on two-factor after checking a first code from the new secret
+    ///
+    /// # Arguments
+    /// * `user_id` - User enrolling
+    /// * `code` - Code currently shown by the authenticator app
+    ///
+    /// # Returns
+    /// One-time recovery codes; only their digests are kept
+    async fn confirm_totp_enrollment(&self, user_id: Uuid, code: &str) -> Result<Vec<String>, AuthError>;
+
+    /// Turn off two-factor, requiring a valid TOTP or recovery code
+    ///
+    /// # Arguments
+    /// * `user_id` - User disabling 2FA
+    /// * `code` - Current TOTP code or an unused recovery code
+    async fn disable_totp(&self, user_id: Uuid, code: &str) -> Result<(), AuthError>;
+
+    /// Clear the failed login streak for an email, lifting any lockout
+    ///
+    /// # Returns
+    /// Whether there was anything to clear
+    async fn unlock_account(&self, email: &str) -> Result<bool, AuthError>;
+}
@@ -0,0 +1,217 @@
+//! Token-bucket rate limiting
+//!
+//! The `rate_limit` middleware keys buckets by route and client IP; the
+//! client IP is the peer address unless the peer is a trusted proxy, in
+//! which case the forwarding headers are believed. `AuthContext::login`
+//! additionally keys one bucket per account so password guessing can't be
+//! spread across many addresses.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use chrono::{DateTime, Utc};
+
+use crate::server::auth::email::{EmailAddress, EmailPolicy};
+use crate::server::auth::memory::{Clock, SystemClock};
+use crate::server::config::{RateLimit, RateLimitConfig};
+
+/// Most buckets a `RateLimiter` keeps at once
+pub const MAX_BUCKETS: usize = 10_000;
+
+/// Outcome of a single rate limit check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    /// Bucket size
+    pub limit: u32,
+    /// Requests left right now
+    pub remaining: u32,
+    /// Seconds until the bucket is full again
+    pub reset_secs: u64,
+    /// Seconds until the next request would be allowed, when refused
+    pub retry_after_secs: Option<u64>,
+}
+
+impl RateLimitDecision {
+    /// Writes the `RateLimit-*` headers, plus `Retry-After` when refused
+    pub fn apply_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(HeaderName::from_static("ratelimit-limit"), HeaderValue::from(self.limit));
+        headers.insert(
+            HeaderName::from_static("ratelimit-remaining"),
+            HeaderValue::from(self.remaining),
+        );
+        headers.insert(HeaderName::from_static("ratelimit-reset"), HeaderValue::from(self.reset_secs));
+        if let Some(retry_after) = self.retry_after_secs {
+            headers.insert(axum::http::header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: DateTime<Utc>,
+    limit: RateLimit,
+}
+
+impl Bucket {
+    fn burst(&self) -> f64 {
+        f64::from(self.limit.burst.max(1))
+    }
+
+    fn per_sec(&self) -> f64 {
+        f64::from(self.limit.per_minute.max(1)) / 60.0
+    }
+
+    /// Tokens available at `now`
+    fn refilled(&self, now: DateTime<Utc>) -> f64 {
+        let elapsed = (now - self.updated_at).num_milliseconds().max(0) as f64 / 1000.0;
+        (self.tokens + elapsed * self.per_sec()).min(self.burst())
+    }
+}
+
+/// In-process token-bucket limiter
+///
+/// # Example
+/// ```rust
+/// let limiter = Arc::new(RateLimiter::new(RateLimitConfig::from_env()));
+/// let app = Router::new()
+///     .merge(api::auth::router())
+///     .layer(middleware::from_fn(rate_limit))
+///     .layer(Extension(limiter));
+/// ```
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<String, Bucket>>,
+    clock: Arc<dyn Clock>,
+    email_policy: EmailPolicy,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+            clock: Arc::new(SystemClock),
+            email_policy: EmailPolicy::default(),
+        }
+    }
+
+    /// Uses `clock` for refills, so tests can move time forward
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Keys per-account buckets by the canonical form under `email_policy`,
+    /// the same key lockout uses
+    pub fn with_email_policy(mut self, email_policy: EmailPolicy) -> Self {
+        self.email_policy = email_policy;
+        self
+    }
+
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    /// Takes one token from the bucket named `key`
+    pub fn check(&self, key: &str, limit: RateLimit) -> RateLimitDecision {
+        let now = self.clock.now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(key) {
+            make_room(&mut buckets, now);
+        }
+
+        let bucket = buckets.entry(key.to_owned()).or_insert(Bucket {
+            tokens: f64::from(limit.burst.max(1)),
+            updated_at: now,
+            limit,
+        });
+        bucket.limit = limit;
+        bucket.tokens = bucket.refilled(now);
+        bucket.updated_at = now;
+        let (burst, per_sec) = (bucket.burst(), bucket.per_sec());
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        RateLimitDecision {
+            allowed,
+            limit: limit.burst,
+            remaining: bucket.tokens.floor() as u32,
+            reset_secs: ((burst - bucket.tokens) / per_sec).ceil() as u64,
+            retry_after_secs: (!allowed)
+                .then(|| ((1.0 - bucket.tokens) / per_sec).ceil().max(1.0) as u64),
+        }
+    }
+
+    /// Buckets currently held
+    pub fn bucket_count(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+
+    /// Per-IP check for a request to `path`
+    pub fn check_route(&self, path: &str, ip: IpAddr) -> RateLimitDecision {
+        self.check(&format!("route:{path}:{ip}"), self.config.limit_for(path))
+    }
+
+    /// Per-account check for a password attempt
+    pub fn check_login(&self, address: &EmailAddress) -> RateLimitDecision {
+        self.check(
+            &format!("login:{}", self.email_policy.canonical(address)),
+            self.config.login_per_account,
+        )
+    }
+
+    /// Address of the client behind a request
+    ///
+    /// Forwarding headers are only honoured when `peer` is a trusted proxy;
+    /// otherwise any client could pick its own bucket.
+    pub fn client_ip(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        let trusted = |ip: &IpAddr| self.config.trusted_proxies.contains(ip);
+        match peer {
+            Some(peer) if trusted(&peer) => {
+                let forwarded = headers
+                    .get("x-forwarded-for")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| {
+                        // Walk back from the proxy nearest to us, skipping our own proxies
+                        v.rsplit(',')
+                            .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+                            .find(|ip| !trusted(ip))
+                    });
+                let real_ip = || {
+                    headers
+                        .get("x-real-ip")
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.trim().parse().ok())
+                };
+                Some(forwarded.or_else(real_ip).unwrap_or(peer))
+            }
+            peer => peer,
+        }
+    }
+}
+
+/// Shrinks a full map to 90% of `MAX_BUCKETS`, so the sweep runs once per
+/// `MAX_BUCKETS / 10` new keys rather than on every request
+fn make_room(buckets: &mut HashMap<String, Bucket>, now: DateTime<Utc>) {
+    // Full buckets would be recreated identical, no need to keep them
+    buckets.retain(|_, bucket| bucket.refilled(now) < bucket.burst());
+
+    let target = MAX_BUCKETS - MAX_BUCKETS / 10;
+    if buckets.len() > target {
+        // Then the least recently used
+        let mut by_age: Vec<_> =
+            buckets.iter().map(|(key, bucket)| (bucket.updated_at, key.clone())).collect();
+        by_age.sort_unstable();
+        let excess = buckets.len() - target;
+        for (_, key) in by_age.into_iter().take(excess) {
+            buckets.remove(&key);
+        }
+    }
+}
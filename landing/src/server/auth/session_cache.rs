@@ -0,0 +1,219 @@
+//! Cache of validated sessions in front of the `AuthProvider`
+//!
+//! `auth_middleware` runs on every protected request; without a cache each
+//! one costs a session lookup plus the role and permission queries. Entries
+//! live for a short TTL, never past the session's own expiry, and are
+//! dropped on logout, password reset and role changes so revocations made
+//! through `AuthContext` take effect immediately.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::server::auth::memory::{Clock, SystemClock};
+use crate::server::models::User;
+
+/// Sessions kept by `LruSessionCache::default()`
+pub const DEFAULT_SESSION_CACHE_CAPACITY: usize = 10_000;
+/// How long `LruSessionCache::default()` trusts an entry, in seconds
+pub const DEFAULT_SESSION_CACHE_TTL_SECS: i64 = 60;
+
+/// Hit/miss counters of a `SessionCache`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped to stay within capacity
+    pub evictions: u64,
+    /// Entries currently held
+    pub entries: usize,
+}
+
+/// Storage for recently validated sessions, keyed by bearer token
+#[async_trait]
+pub trait SessionCache: Send + Sync {
+    /// The user behind `token`, if cached and still fresh
+    async fn get(&self, token: &str) -> Option<User>;
+
+    /// Caches a freshly validated session
+    async fn set(&self, token: &str, user: &User);
+
+    /// Drops a single session, e.g. on logout
+    async fn invalidate(&self, token: &str);
+
+    /// Drops every session belonging to a user
+    async fn invalidate_user(&self, user_id: Uuid);
+
+    /// Current counters
+    async fn stats(&self) -> CacheStats;
+}
+
+struct Entry {
+    user: User,
+    expires_at: DateTime<Utc>,
+    /// Position in the recency order
+    tick: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    /// Tick → token, oldest first
+    recency: BTreeMap<u64, String>,
+    next_tick: u64,
+}
+
+impl Inner {
+    fn remove(&mut self, token: &str) {
+        if let Some(entry) = self.entries.remove(token) {
+            self.recency.remove(&entry.tick);
+        }
+    }
+
+    fn touch(&mut self, token: &str) {
+        let tick = self.next_tick;
+        if let Some(entry) = self.entries.get_mut(token) {
+            self.recency.remove(&entry.tick);
+            entry.tick = tick;
+            self.recency.insert(tick, token.to_owned());
+            self.next_tick += 1;
+        }
+    }
+}
+
+/// In-process cache with a per-entry TTL and least-recently-used eviction
+///
+/// # Example
+/// ```rust
+/// let cache = LruSessionCache::new(50_000, Duration::seconds(30));
+/// let auth = AuthContext::new(provider).with_session_cache(Arc::new(cache));
+/// ```
+pub struct LruSessionCache {
+    inner: Mutex<Inner>,
+    capacity: usize,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Default for LruSessionCache {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_SESSION_CACHE_CAPACITY,
+            Duration::seconds(DEFAULT_SESSION_CACHE_TTL_SECS),
+        )
+    }
+}
+
+impl LruSessionCache {
+    /// Cache holding at most `capacity` sessions, each trusted for `ttl`
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            inner: Mutex::new(Inner::default()),
+            capacity: capacity.max(1),
+            ttl,
+            clock: Arc::new(SystemClock),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Uses `clock` for expiry, so tests can move time forward
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+}
+
+#[async_trait]
+impl SessionCache for LruSessionCache {
+    async fn get(&self, token: &str) -> Option<User> {
+        let mut inner = self.inner.lock().await;
+        let now = self.clock.now();
+
+        let user = match inner.entries.get(token) {
+            Some(entry) if entry.expires_at > now => Some(entry.user.clone()),
+            Some(_) => {
+                inner.remove(token);
+                None
+            }
+            None => None,
+        };
+
+        match user {
+            Some(user) => {
+                inner.touch(token);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(user)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    async fn set(&self, token: &str, user: &User) {
+        let expires_at = (self.clock.now() + self.ttl).min(user.session_expires_at);
+        if expires_at <= self.clock.now() {
+            return;
+        }
+
+        let mut inner = self.inner.lock().await;
+        inner.remove(token);
+        while inner.entries.len() >= self.capacity {
+            let Some((_, oldest)) = inner.recency.pop_first() else {
+                break;
+            };
+            inner.entries.remove(&oldest);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let tick = inner.next_tick;
+        inner.next_tick += 1;
+        inner.recency.insert(tick, token.to_owned());
+        inner.entries.insert(
+            token.to_owned(),
+            Entry {
+                user: user.clone(),
+                expires_at,
+                tick,
+            },
+        );
+    }
+
+    async fn invalidate(&self, token: &str) {
+        self.inner.lock().await.remove(token);
+    }
+
+    async fn invalidate_user(&self, user_id: Uuid) {
+        let mut inner = self.inner.lock().await;
+        let tokens: Vec<String> = inner
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.user.id == user_id)
+            .map(|(token, _)| token.clone())
+            .collect();
+        for token in tokens {
+            inner.remove(&token);
+        }
+    }
+
+    async fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: self.inner.lock().await.entries.len(),
+        }
+    }
+}
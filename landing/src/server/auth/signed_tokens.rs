@@ -0,0 +1,428 @@
+//! Stateless signed access tokens
+//!
+//! With `AuthContext::with_signed_tokens`, the access token issued at login
+//! and refresh is an HS256 JWT carrying the user id, email, roles,
+//! permissions, expiry and the id of the session it belongs to, with the
+//! signing key's id in the `kid` header. `auth_middleware` checks such
+//! tokens without a database lookup. Refresh tokens, logout and the
+//! sessions page keep working on the provider's session rows.
+//!
+//! Keys live in a `SigningKeyStore`. The newest key without `retire_at`
+//! signs and every key verifies until its `retire_at`, so `rotate` can
+//! bring in a new key while tokens signed with the old one are still out.
+//! Signing out a session puts it on a revocation list checked on every
+//! request. Each `TokenSigner` holds keys and revocations in memory and
+//! reloads them every `reload_interval`, and early when a token names a
+//! key it hasn't seen, so other servers pick up a rotation or a logout
+//! within that interval.
+//!
+//! Password resets, signing out other devices and a replayed refresh token
+//! revoke every session they end the same way. Role changes reach a token
+//! that is already out when it is next refreshed, at most `token_ttl` later.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::{Mutex, RwLock};
+use uuid::Uuid;
+
+use crate::db::{queries, DbPool, SigningKey};
+use crate::server::auth::memory::{Clock, SystemClock};
+use crate::server::auth::utils::generate_random_token;
+use crate::server::config::TokenKey;
+use crate::server::error::AuthError;
+use crate::server::models::User;
+
+/// How often `TokenSigner::new` reloads keys and revocations, in seconds
+pub const DEFAULT_RELOAD_INTERVAL_SECS: i64 = 30;
+
+/// Minimum time between two reloads triggered by unknown key ids
+const UNKNOWN_KID_RELOAD_INTERVAL_SECS: i64 = 5;
+
+/// Claims of a signed access token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessClaims {
+    /// User id
+    pub sub: Uuid,
+    pub email: String,
+    pub roles: Vec<String>,
+    pub permissions: Vec<String>,
+    /// `SessionInfo::id` of the login the token was issued for
+    pub sid: Uuid,
+    /// Admin acting as the user, for impersonation sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imp: Option<Uuid>,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+impl AccessClaims {
+    /// The user the token vouches for, with `token` as bearer token
+    pub fn into_user(self, token: &str) -> User {
+        User {
+            id: self.sub,
+            email: self.email,
+            bearer_token: token.to_owned(),
+            session_expires_at: DateTime::from_timestamp(self.exp, 0).unwrap_or_default(),
+            refresh_token: None,
+            roles: self.roles,
+            permissions: self.permissions,
+            impersonator_id: self.imp,
+        }
+    }
+}
+
+/// Whether `token` has the shape of a signed access token rather than an
+/// opaque session token
+pub fn is_signed_token(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+/// Storage for signing keys and revoked sessions, shared by every server
+#[async_trait]
+pub trait SigningKeyStore: Send + Sync {
+    /// Keys that may still verify tokens, newest first
+    async fn keys(&self) -> Result<Vec<SigningKey>, AuthError>;
+
+    /// Adds a key, which signs from now on
+    async fn add_key(&self) -> Result<SigningKey, AuthError>;
+
+    /// Schedules every key except `keep_kid` to stop verifying at `retire_at`
+    async fn retire_keys(&self, keep_kid: &str, retire_at: DateTime<Utc>) -> Result<u64, AuthError>;
+
+    /// Refuses tokens naming `session_id` until `expires_at`
+    async fn revoke_session(&self, session_id: Uuid, expires_at: DateTime<Utc>) -> Result<(), AuthError>;
+
+    /// Sessions whose tokens are currently refused
+    async fn revoked_sessions(&self) -> Result<Vec<Uuid>, AuthError>;
+
+    /// Adds a key that signs from now on; tokens signed with the others
+    /// keep verifying for `grace`, which should cover the access token
+    /// lifetime plus the reload interval
+    async fn rotate(&self, grace: Duration) -> Result<SigningKey, AuthError> {
+        let key = self.add_key().await?;
+        self.retire_keys(&key.kid, Utc::now() + grace).await?;
+        Ok(key)
+    }
+}
+
+/// Fresh key id and seed
+fn new_key_material() -> (String, String) {
+    (Uuid::new_v4().simple().to_string()[..12].to_owned(), generate_random_token())
+}
+
+/// Keeps keys in `signing_keys` and revocations in `revoked_token_sessions`
+#[derive(Clone)]
+pub struct PgSigningKeyStore {
+    pool: DbPool,
+}
+
+impl PgSigningKeyStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    /// Deletes retired keys and lapsed revocations
+    pub async fn prune(&self) -> Result<u64, AuthError> {
+        queries::signing_keys::delete_expired_signing_state(&self.pool)
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+}
+
+#[async_trait]
+impl SigningKeyStore for PgSigningKeyStore {
+    async fn keys(&self) -> Result<Vec<SigningKey>, AuthError> {
+        queries::signing_keys::list_signing_keys(&self.pool)
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    async fn add_key(&self) -> Result<SigningKey, AuthError> {
+        let (kid, seed) = new_key_material();
+        queries::signing_keys::create_signing_key(&self.pool, &kid, &seed)
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    async fn retire_keys(&self, keep_kid: &str, retire_at: DateTime<Utc>) -> Result<u64, AuthError> {
+        queries::signing_keys::retire_signing_keys(&self.pool, keep_kid, retire_at)
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    async fn revoke_session(&self, session_id: Uuid, expires_at: DateTime<Utc>) -> Result<(), AuthError> {
+        queries::signing_keys::revoke_token_session(&self.pool, session_id, expires_at)
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+
+    async fn revoked_sessions(&self) -> Result<Vec<Uuid>, AuthError> {
+        queries::signing_keys::list_revoked_token_sessions(&self.pool)
+            .await
+            .map_err(|_| AuthError::DatabaseError)
+    }
+}
+
+/// Keeps keys and revocations in memory, for tests and single-process setups
+#[derive(Default)]
+pub struct MemorySigningKeyStore {
+    keys: Mutex<Vec<SigningKey>>,
+    revoked: Mutex<Vec<(Uuid, DateTime<Utc>)>>,
+}
+
+impl MemorySigningKeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SigningKeyStore for MemorySigningKeyStore {
+    async fn keys(&self) -> Result<Vec<SigningKey>, AuthError> {
+        Ok(self.keys.lock().await.iter().rev().cloned().collect())
+    }
+
+    async fn add_key(&self) -> Result<SigningKey, AuthError> {
+        let (kid, key_seed) = new_key_material();
+        let key = SigningKey { kid, key_seed, created_at: Utc::now(), retire_at: None };
+        self.keys.lock().await.push(key.clone());
+        Ok(key)
+    }
+
+    async fn retire_keys(&self, keep_kid: &str, retire_at: DateTime<Utc>) -> Result<u64, AuthError> {
+        let mut retired = 0;
+        for key in self.keys.lock().await.iter_mut() {
+            if key.retire_at.is_none() && key.kid != keep_kid {
+                key.retire_at = Some(retire_at);
+                retired += 1;
+            }
+        }
+        Ok(retired)
+    }
+
+    async fn revoke_session(&self, session_id: Uuid, expires_at: DateTime<Utc>) -> Result<(), AuthError> {
+        self.revoked.lock().await.push((session_id, expires_at));
+        Ok(())
+    }
+
+    async fn revoked_sessions(&self) -> Result<Vec<Uuid>, AuthError> {
+        let now = Utc::now();
+        Ok(self
+            .revoked
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(session_id, _)| *session_id)
+            .collect())
+    }
+}
+
+/// A verifying key, with its secret derived
+struct LoadedKey {
+    kid: String,
+    secret: Vec<u8>,
+    retire_at: Option<DateTime<Utc>>,
+}
+
+/// What a `TokenSigner` last read from its store
+struct KeyRing {
+    /// Newest first
+    keys: Vec<LoadedKey>,
+    revoked: HashSet<Uuid>,
+    loaded_at: Option<DateTime<Utc>>,
+}
+
+/// Issues and checks signed access tokens
+///
+/// # Example
+/// ```rust
+/// let store = Arc::new(PgSigningKeyStore::new(pool));
+/// let signer = TokenSigner::new(store, config.session_token_key.clone())
+///     .with_token_ttl(config.access_token_ttl);
+/// let auth = AuthContext::new(provider).with_signed_tokens(Arc::new(signer));
+/// ```
+pub struct TokenSigner {
+    store: Arc<dyn SigningKeyStore>,
+    /// Secrets are HMAC(`master_key`, key seed), so a leaked store can't sign
+    master_key: TokenKey,
+    ring: RwLock<KeyRing>,
+    token_ttl: Duration,
+    reload_interval: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl TokenSigner {
+    /// Signer using the keys in `store`; the first token signed creates a
+    /// key if there is none
+    pub fn new(store: Arc<dyn SigningKeyStore>, master_key: TokenKey) -> Self {
+        Self {
+            store,
+            master_key,
+            ring: RwLock::new(KeyRing { keys: Vec::new(), revoked: HashSet::new(), loaded_at: None }),
+            token_ttl: Duration::minutes(15),
+            reload_interval: Duration::seconds(DEFAULT_RELOAD_INTERVAL_SECS),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Caps token lifetimes, normally `AuthConfig::access_token_ttl`
+    pub fn with_token_ttl(mut self, token_ttl: Duration) -> Self {
+        self.token_ttl = token_ttl;
+        self
+    }
+
+    /// How stale keys and revocations may get before they are read again
+    pub fn with_reload_interval(mut self, reload_interval: Duration) -> Self {
+        self.reload_interval = reload_interval;
+        self
+    }
+
+    /// Uses `clock` for expiry, so tests can move time forward
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn derive_secret(&self, key_seed: &str) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.master_key.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(key_seed.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// Reads keys and revocations from the store
+    async fn reload(&self) -> Result<(), AuthError> {
+        let keys = self.store.keys().await?;
+        let revoked = self.store.revoked_sessions().await?;
+        let keys = keys
+            .into_iter()
+            .map(|key| LoadedKey {
+                secret: self.derive_secret(&key.key_seed),
+                kid: key.kid,
+                retire_at: key.retire_at,
+            })
+            .collect();
+        *self.ring.write().await = KeyRing {
+            keys,
+            revoked: revoked.into_iter().collect(),
+            loaded_at: Some(self.clock.now()),
+        };
+        Ok(())
+    }
+
+    /// Reloads if the last load is older than `max_age`
+    async fn reload_if_older(&self, max_age: Duration) -> Result<(), AuthError> {
+        let loaded_at = self.ring.read().await.loaded_at;
+        match loaded_at {
+            Some(at) if self.clock.now() - at < max_age => Ok(()),
+            _ => self.reload().await,
+        }
+    }
+
+    /// Signs an access token for `user`'s session `session_id`
+    ///
+    /// The token expires with the session, and no later than `token_ttl`
+    /// from now.
+    pub async fn sign(&self, user: &User, session_id: Uuid) -> Result<String, AuthError> {
+        self.reload_if_older(self.reload_interval).await?;
+        if !self.ring.read().await.keys.iter().any(|k| k.retire_at.is_none()) {
+            self.store.add_key().await?;
+            self.reload().await?;
+        }
+
+        let now = self.clock.now();
+        let claims = AccessClaims {
+            sub: user.id,
+            email: user.email.clone(),
+            roles: user.roles.clone(),
+            permissions: user.permissions.clone(),
+            sid: session_id,
+            imp: user.impersonator_id,
+            iat: now.timestamp(),
+            exp: user.session_expires_at.min(now + self.token_ttl).timestamp(),
+        };
+
+        let ring = self.ring.read().await;
+        let key = ring
+            .keys
+            .iter()
+            .find(|k| k.retire_at.is_none())
+            .ok_or(AuthError::TokenSigningFailed)?;
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(key.kid.clone());
+        encode(&header, &claims, &EncodingKey::from_secret(&key.secret)).map_err(|e| {
+            log::error!("Failed to sign access token: {e}");
+            AuthError::TokenSigningFailed
+        })
+    }
+
+    /// Checks a token's key, signature, expiry and revocation
+    ///
+    /// # Returns
+    /// - `Err(AuthError::InvalidSession)` for any token that doesn't pass
+    pub async fn verify(&self, token: &str) -> Result<AccessClaims, AuthError> {
+        let (claims, retire_at) = self.decode(token).await?;
+        let now = self.clock.now();
+        if retire_at.is_some_and(|at| at <= now)
+            || claims.exp <= now.timestamp()
+            || self.ring.read().await.revoked.contains(&claims.sid)
+        {
+            return Err(AuthError::InvalidSession);
+        }
+        Ok(claims)
+    }
+
+    /// Checks only a token's key and signature, so logout also works with
+    /// an access token that has already expired
+    pub async fn claims(&self, token: &str) -> Result<AccessClaims, AuthError> {
+        Ok(self.decode(token).await?.0)
+    }
+
+    /// Claims of a token signed by a known key, and when that key retires
+    async fn decode(&self, token: &str) -> Result<(AccessClaims, Option<DateTime<Utc>>), AuthError> {
+        let kid = decode_header(token)
+            .ok()
+            .and_then(|header| header.kid)
+            .ok_or(AuthError::InvalidSession)?;
+
+        self.reload_if_older(self.reload_interval).await?;
+        if !self.ring.read().await.keys.iter().any(|k| k.kid == kid) {
+            // Possibly a key another server rotated in since the last load
+            self.reload_if_older(Duration::seconds(UNKNOWN_KID_RELOAD_INTERVAL_SECS)).await?;
+        }
+
+        let ring = self.ring.read().await;
+        let key = ring
+            .keys
+            .iter()
+            .find(|k| k.kid == kid)
+            .ok_or(AuthError::InvalidSession)?;
+
+        // Expiry is checked against `clock` by `verify`
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.validate_exp = false;
+        validation.set_required_spec_claims(&["exp", "sub"]);
+        let claims = decode::<AccessClaims>(token, &DecodingKey::from_secret(&key.secret), &validation)
+            .map_err(|_| AuthError::InvalidSession)?
+            .claims;
+        Ok((claims, key.retire_at))
+    }
+
+    /// Refuses every token issued for `session_id` from now on
+    pub async fn revoke_session(&self, session_id: Uuid) -> Result<(), AuthError> {
+        self.store
+            .revoke_session(session_id, self.clock.now() + self.token_ttl)
+            .await?;
+        self.ring.write().await.revoked.insert(session_id);
+        Ok(())
+    }
+}
+
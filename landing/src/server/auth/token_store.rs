@@ -0,0 +1,192 @@
+//! Client-side persistence of the signed-in session
+//!
+//! `AuthClient` saves the current user's tokens to a `TokenStore` whenever
+//! they change and reads them back at startup, so a reload doesn't sign the
+//! user out. Browsers keep them in web storage, native apps in the system
+//! keychain; `default_token_store` picks the backend for the build target.
+
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::server::error::AuthError;
+use crate::server::models::User;
+
+/// Key the session is stored under, in web storage and the keychain
+pub const TOKEN_STORE_KEY: &str = "landing.session";
+
+/// Tokens needed to pick a session back up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredTokens {
+    pub bearer_token: String,
+    /// Absent for sessions that can't be renewed, such as impersonations
+    pub refresh_token: Option<String>,
+}
+
+impl From<&User> for StoredTokens {
+    fn from(user: &User) -> Self {
+        Self {
+            bearer_token: user.bearer_token.clone(),
+            refresh_token: user.refresh_token.clone(),
+        }
+    }
+}
+
+/// Where the client keeps its tokens between runs
+pub trait TokenStore: Send + Sync {
+    /// The saved tokens, `None` if nothing was saved
+    fn load(&self) -> Result<Option<StoredTokens>, AuthError>;
+
+    /// Replaces whatever was saved before
+    fn save(&self, tokens: &StoredTokens) -> Result<(), AuthError>;
+
+    /// Forgets the saved tokens; succeeds if there were none
+    fn clear(&self) -> Result<(), AuthError>;
+}
+
+/// Keeps tokens for the life of the process only, for tests and for
+/// clients that shouldn't remember sessions
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+    tokens: Mutex<Option<StoredTokens>>,
+}
+
+impl MemoryTokenStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn load(&self) -> Result<Option<StoredTokens>, AuthError> {
+        Ok(self.tokens.lock().map_err(|_| AuthError::TokenStorageFailed)?.clone())
+    }
+
+    fn save(&self, tokens: &StoredTokens) -> Result<(), AuthError> {
+        *self.tokens.lock().map_err(|_| AuthError::TokenStorageFailed)? = Some(tokens.clone());
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<(), AuthError> {
+        *self.tokens.lock().map_err(|_| AuthError::TokenStorageFailed)? = None;
+        Ok(())
+    }
+}
+
+/// Which of the browser's storage areas a `WebStorageTokenStore` uses
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WebStorageArea {
+    /// Survives closing the browser
+    Local,
+    /// Cleared when the tab is closed
+    Session,
+}
+
+/// Keeps tokens in the browser's `localStorage` or `sessionStorage`
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, Copy)]
+pub struct WebStorageTokenStore {
+    area: WebStorageArea,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl WebStorageTokenStore {
+    /// Stays signed in across browser restarts
+    pub fn local() -> Self {
+        Self { area: WebStorageArea::Local }
+    }
+
+    /// Stays signed in across reloads of the same tab only
+    pub fn session() -> Self {
+        Self { area: WebStorageArea::Session }
+    }
+
+    fn storage(&self) -> Result<web_sys::Storage, AuthError> {
+        let window = web_sys::window().ok_or(AuthError::TokenStorageFailed)?;
+        let storage = match self.area {
+            WebStorageArea::Local => window.local_storage(),
+            WebStorageArea::Session => window.session_storage(),
+        };
+        storage.ok().flatten().ok_or(AuthError::TokenStorageFailed)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl TokenStore for WebStorageTokenStore {
+    fn load(&self) -> Result<Option<StoredTokens>, AuthError> {
+        let stored = self
+            .storage()?
+            .get_item(TOKEN_STORE_KEY)
+            .map_err(|_| AuthError::TokenStorageFailed)?;
+        // Unreadable data is as good as none; the next save overwrites it
+        Ok(stored.and_then(|json| serde_json::from_str(&json).ok()))
+    }
+
+    fn save(&self, tokens: &StoredTokens) -> Result<(), AuthError> {
+        let json = serde_json::to_string(tokens).map_err(|_| AuthError::TokenStorageFailed)?;
+        self.storage()?
+            .set_item(TOKEN_STORE_KEY, &json)
+            .map_err(|_| AuthError::TokenStorageFailed)
+    }
+
+    fn clear(&self) -> Result<(), AuthError> {
+        self.storage()?
+            .remove_item(TOKEN_STORE_KEY)
+            .map_err(|_| AuthError::TokenStorageFailed)
+    }
+}
+
+/// Keeps tokens in the operating system's credential store through `keyring`
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct KeyringTokenStore {
+    service: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl KeyringTokenStore {
+    /// Stores under `service`, typically the application's name
+    pub fn new(service: impl Into<String>) -> Self {
+        Self { service: service.into() }
+    }
+
+    fn entry(&self) -> Result<keyring::Entry, AuthError> {
+        keyring::Entry::new(&self.service, TOKEN_STORE_KEY).map_err(|_| AuthError::TokenStorageFailed)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TokenStore for KeyringTokenStore {
+    fn load(&self) -> Result<Option<StoredTokens>, AuthError> {
+        match self.entry()?.get_password() {
+            Ok(json) => Ok(serde_json::from_str(&json).ok()),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(_) => Err(AuthError::TokenStorageFailed),
+        }
+    }
+
+    fn save(&self, tokens: &StoredTokens) -> Result<(), AuthError> {
+        let json = serde_json::to_string(tokens).map_err(|_| AuthError::TokenStorageFailed)?;
+        self.entry()?.set_password(&json).map_err(|_| AuthError::TokenStorageFailed)
+    }
+
+    fn clear(&self) -> Result<(), AuthError> {
+        match self.entry()?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(_) => Err(AuthError::TokenStorageFailed),
+        }
+    }
+}
+
+/// `localStorage` in the browser
+#[cfg(target_arch = "wasm32")]
+pub fn default_token_store() -> Arc<dyn TokenStore> {
+    Arc::new(WebStorageTokenStore::local())
+}
+
+/// The system keychain on native targets
+#[cfg(not(target_arch = "wasm32"))]
+pub fn default_token_store() -> Arc<dyn TokenStore> {
+    Arc::new(KeyringTokenStore::new(env!("CARGO_PKG_NAME")))
+}
@@ -0,0 +1,111 @@
+//! TOTP (RFC 6238) second factor and recovery codes
+//!
+//! Shared by every `AuthProvider`: secrets are base32 strings, codes are
+//! 6 digits on a 30 second step with one step of clock skew either side.
+
+use chrono::{DateTime, Utc};
+use rand::{distr::Alphanumeric, Rng};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::server::auth::utils::hash_token;
+use crate::server::error::AuthError;
+use crate::server::models::TotpEnrollment;
+
+const DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+const SKEW_STEPS: i64 = 1;
+/// Recovery codes handed out when 2FA is turned on
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Fresh random secret, base32 encoded
+pub fn generate_secret() -> String {
+    match Secret::generate_secret().to_encoded() {
+        Secret::Encoded(secret) => secret,
+        Secret::Raw(_) => unreachable!("to_encoded always returns Secret::Encoded"),
+    }
+}
+
+fn build(secret: &str, issuer: Option<&str>, account: &str) -> Result<TOTP, AuthError> {
+    let bytes = Secret::Encoded(secret.to_owned())
+        .to_bytes()
+        .map_err(|_| AuthError::DatabaseError)?;
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        SKEW_STEPS as u8,
+        STEP_SECONDS,
+        bytes,
+        issuer.map(str::to_owned),
+        account.to_owned(),
+    )
+    .map_err(|_| AuthError::DatabaseError)
+}
+
+/// Everything an authenticator app needs to add the account
+pub fn enrollment(secret: &str, issuer: &str, account: &str) -> Result<TotpEnrollment, AuthError> {
+    let totp = build(secret, Some(issuer), account)?;
+    Ok(TotpEnrollment {
+        secret: secret.to_owned(),
+        otpauth_uri: totp.get_url(),
+        qr_png_base64: totp.get_qr_base64().map_err(|_| AuthError::DatabaseError)?,
+    })
+}
+
+/// Checks a code against the secret at `now`
+///
+/// Returns the time step the code belongs to, so callers can refuse to
+/// accept the same step twice.
+pub fn verify_code(
+    secret: &str,
+    code: &str,
+    now: DateTime<Utc>,
+    last_used_step: Option<i64>,
+) -> Result<Option<i64>, AuthError> {
+    if !is_totp_code(code) {
+        return Ok(None);
+    }
+    let code = code.trim();
+
+    // Issuer and account only label the URI, they don't affect codes
+    let totp = build(secret, None, "account")?;
+    let current_step = now.timestamp() / STEP_SECONDS as i64;
+    for step in (current_step - SKEW_STEPS)..=(current_step + SKEW_STEPS) {
+        if last_used_step.is_some_and(|last| step <= last) {
+            continue;
+        }
+        if totp.generate(step as u64 * STEP_SECONDS) == code {
+            return Ok(Some(step));
+        }
+    }
+    Ok(None)
+}
+
+/// The code an authenticator app would show at `at`
+pub fn generate_code(secret: &str, at: DateTime<Utc>) -> Result<String, AuthError> {
+    Ok(build(secret, None, "account")?.generate(at.timestamp() as u64))
+}
+
+/// Whether the input looks like a TOTP code rather than a recovery code
+pub fn is_totp_code(code: &str) -> bool {
+    let code = code.trim();
+    code.len() == DIGITS && code.chars().all(|c| c.is_ascii_digit())
+}
+
+/// A new batch of recovery codes, formatted `xxxxx-xxxxx`
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let raw: String = rand::rng()
+                .sample_iter(&Alphanumeric)
+                .take(10)
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .collect();
+            format!("{}-{}", &raw[..5], &raw[5..])
+        })
+        .collect()
+}
+
+/// Digest stored for a recovery code, tolerant of case and spacing
+pub fn hash_recovery_code(code: &str) -> String {
+    hash_token(&code.trim().to_ascii_lowercase())
+}
@@ -1,53 +1,15 @@
 use base64::engine::general_purpose;
 use base64::Engine;
-use bcrypt::verify;
 
 use rand::{distr::Alphanumeric, Rng};
-use web_sys::window;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use crate::server::auth::email::EmailAddress;
+use crate::server::auth::password::{self, Argon2Hasher, PasswordHasher};
+use crate::server::auth::password_policy::PasswordPolicy;
+use crate::server::config::TokenKey;
 use crate::server::error::AuthError;
 
-/// Generates and stores session tokens securely
-/// 
-/// # Arguments
-/// * `access_token` - The OAuth access token to store
-/// * `refresh_token` - The OAuth refresh token to store
-/// 
-/// # Returns
-/// `Result<String, AuthError>` - The generated session token or error
-#[cfg(target_arch = "wasm32")]
-pub async fn generate_session_token(access_token: &str, refresh_token: &str) -> Result<String, AuthError> {
-    use web_sys::window;
-
-    let session_token = generate_random_token(); // Your token generation logic
-    
-    if let Some(window) = window() {
-        if let Ok(Some(storage)) = window.local_storage() {
-            storage.set_item("access_token", access_token)
-                .map_err(|_| AuthError::TokenStorageFailed)?;
-            storage.set_item("refresh_token", refresh_token)
-                .map_err(|_| AuthError::TokenStorageFailed)?;
-            storage.set_item("session_token", &session_token)
-                .map_err(|_| AuthError::TokenStorageFailed)?;
-        }
-    }
-    Ok(session_token)
-}
-
-#[cfg(not(target_arch = "wasm32"))]
-pub async fn generate_session_token(access_token: &str, refresh_token: &str) -> Result<String, AuthError> {
-    // For desktop/native targets, use a different storage method
-    // Example: File system, SQLite, or system keychain
-    let session_token = generate_random_token();
-    
-    // Implement native storage here (example using `keyring` crate)
-    let entry = keyring::Entry::new("your_app", "access_token")
-        .map_err(|_| AuthError::TokenStorageFailed)?;
-    entry.set_password(access_token)
-        .map_err(|_| AuthError::TokenStorageFailed)?;
-    
-    Ok(session_token)
-}
-
 pub fn generate_random_token() -> String {
     rand::rng()
         .sample_iter(&Alphanumeric)
@@ -56,22 +18,77 @@ pub fn generate_random_token() -> String {
         .collect()
 }
 
+/// Hashes a password with Argon2id at the default parameters
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    Argon2Hasher::default().hash(password)
+}
+
+/// One-way digest for single-use tokens that must not be stored in plaintext
+pub fn hash_token(token: &str) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Keyed digest session tokens are stored and looked up under
+///
+/// Unlike `hash_token`, a leaked table can't be checked against guessed
+/// tokens without the key as well.
+pub fn hash_session_token(token: &str, key: &TokenKey) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(token.as_bytes());
+    general_purpose::URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+}
+
+/// Checks a password against an Argon2 or legacy bcrypt hash
 pub fn verify_password(password: &str, hash: &str) -> bool {
-    match verify(password, hash) {
-        Ok(result) => result,
-        Err(_) => false,
-    }
+    password::verify_any(password, hash)
 }
 
+/// Whether a password passes the default `PasswordPolicy`; providers
+/// check `AuthConfig::password_policy` instead, which reports why not
 pub fn meets_password_requirements(password: &str) -> bool {
-    let has_uppercase = password.chars().any(|c| c.is_uppercase());
-    let has_lowercase = password.chars().any(|c| c.is_lowercase());
-    let has_digit = password.chars().any(|c| c.is_digit(10));
-    
-    password.len() >= 8 && has_uppercase && has_lowercase && has_digit
+    PasswordPolicy::default().check(password, "").is_empty()
 }
 
+/// Whether `email` parses as an `EmailAddress`
 pub fn is_valid_email(email: &str) -> bool {
-    // Basic regex or more comprehensive validation
-    !email.is_empty() && email.contains('@') && email.contains('.')
-}
\ No newline at end of file
+    EmailAddress::parse(email).is_ok()
+}
+
+/// Short "Browser on OS" description of a `User-Agent` header
+pub fn device_label(user_agent: Option<&str>) -> String {
+    let Some(ua) = user_agent.filter(|ua| !ua.trim().is_empty()) else {
+        return "Unknown device".to_string();
+    };
+
+    // Order matters: Edge and Opera also claim Chrome, Chrome claims Safari
+    let browser = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+        ("curl/", "curl"),
+    ]
+    .iter()
+    .find(|(marker, _)| ua.contains(marker))
+    .map_or("Unknown browser", |(_, name)| name);
+
+    let os = [
+        ("Android", "Android"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("CrOS", "ChromeOS"),
+        ("Linux", "Linux"),
+    ]
+    .iter()
+    .find(|(marker, _)| ua.contains(marker))
+    .map(|(_, name)| name);
+
+    match os {
+        Some(os) => format!("{browser} on {os}"),
+        None => browser.to_string(),
+    }
+}
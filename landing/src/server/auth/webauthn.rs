@@ -0,0 +1,352 @@
+//! WebAuthn ceremonies for passkeys
+//!
+//! Covers what passwordless sign-in needs and no more. Attestation is
+//! requested as `none`: a new credential is trusted because a signed-in
+//! session registers it, not because of a certificate chain, so statements
+//! some authenticators send anyway are ignored. Public keys are stored as
+//! COSE_Key bytes; ES256 and RS256 are supported.
+//!
+//! Providers keep the challenges and credentials. The functions here only
+//! build the browser options and check the responses against them.
+
+use base64::engine::general_purpose;
+use base64::Engine;
+use ciborium::value::Value;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::server::config::AuthConfig;
+use crate::server::error::AuthError;
+use crate::server::models::{
+    PasskeyAssertion, PasskeyCreationOptions, PasskeyCredential, PasskeyRequestOptions,
+};
+
+/// COSE algorithm id of ECDSA P-256 with SHA-256
+pub const COSE_ES256: i64 = -7;
+/// COSE algorithm id of RSASSA-PKCS1-v1_5 with SHA-256
+pub const COSE_RS256: i64 = -257;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// The two WebAuthn ceremonies; a challenge is only good for the one it was issued for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ceremony {
+    Register,
+    Authenticate,
+}
+
+impl Ceremony {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Ceremony::Register => "register",
+            Ceremony::Authenticate => "authenticate",
+        }
+    }
+
+    /// `type` the browser puts in the client data
+    fn client_data_type(&self) -> &'static str {
+        match self {
+            Ceremony::Register => "webauthn.create",
+            Ceremony::Authenticate => "webauthn.get",
+        }
+    }
+}
+
+/// A credential that passed registration, ready to be stored
+#[derive(Debug, Clone)]
+pub struct VerifiedCredential {
+    pub credential_id: Vec<u8>,
+    /// COSE_Key encoded public key
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+    #[serde(rename = "crossOrigin", default)]
+    cross_origin: bool,
+}
+
+struct AuthenticatorData<'a> {
+    rp_id_hash: &'a [u8],
+    flags: u8,
+    sign_count: u32,
+    /// Attested credential data and extensions
+    rest: &'a [u8],
+}
+
+enum CoseKey {
+    Es256(p256::ecdsa::VerifyingKey),
+    Rs256(rsa::pkcs1v15::VerifyingKey<Sha256>),
+}
+
+/// Base64url without padding, as WebAuthn encodes binary values
+pub fn encode(bytes: &[u8]) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Reverses `encode`, tolerating padding
+pub fn decode(value: &str) -> Result<Vec<u8>, AuthError> {
+    general_purpose::URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| AuthError::InvalidPasskey)
+}
+
+/// WebAuthn user handle of a user: the id's bytes, base64url
+pub fn user_handle(user_id: Uuid) -> String {
+    encode(user_id.as_bytes())
+}
+
+/// A fresh random challenge
+pub fn new_challenge() -> String {
+    encode(&rand::random::<[u8; 32]>())
+}
+
+fn timeout_ms(config: &AuthConfig) -> u32 {
+    config.passkey_challenge_ttl.num_milliseconds().clamp(0, u32::MAX as i64) as u32
+}
+
+/// Options for creating a discoverable passkey for a user
+///
+/// # Arguments
+/// * `existing` - Credential ids the user already registered
+pub fn creation_options(
+    config: &AuthConfig,
+    user_id: Uuid,
+    email: &str,
+    challenge: String,
+    existing: &[Vec<u8>],
+) -> PasskeyCreationOptions {
+    PasskeyCreationOptions {
+        challenge,
+        rp_id: config.passkey_rp_id.clone(),
+        rp_name: config.passkey_rp_name.clone(),
+        user_id: user_handle(user_id),
+        user_name: email.to_owned(),
+        algorithms: vec![COSE_ES256, COSE_RS256],
+        exclude_credentials: existing.iter().map(|id| encode(id)).collect(),
+        timeout_ms: timeout_ms(config),
+    }
+}
+
+/// Options for signing in with any passkey registered for this site
+pub fn request_options(config: &AuthConfig, challenge: String) -> PasskeyRequestOptions {
+    PasskeyRequestOptions {
+        challenge,
+        rp_id: config.passkey_rp_id.clone(),
+        timeout_ms: timeout_ms(config),
+    }
+}
+
+/// The challenge a browser response answers, to look up its ceremony
+pub fn response_challenge(client_data_json: &str) -> Result<String, AuthError> {
+    Ok(parse_client_data(client_data_json)?.1.challenge)
+}
+
+/// Checks a new credential against the registration challenge
+pub fn verify_registration(
+    config: &AuthConfig,
+    challenge: &str,
+    credential: &PasskeyCredential,
+) -> Result<VerifiedCredential, AuthError> {
+    let (_, client_data) = parse_client_data(&credential.client_data_json)?;
+    check_client_data(config, Ceremony::Register, challenge, &client_data)?;
+
+    let attestation: Value = ciborium::from_reader(decode(&credential.attestation_object)?.as_slice())
+        .map_err(|_| AuthError::InvalidPasskey)?;
+    let auth_data = attestation
+        .as_map()
+        .and_then(|fields| map_get(fields, "authData"))
+        .and_then(Value::as_bytes)
+        .ok_or(AuthError::InvalidPasskey)?;
+    let data = parse_authenticator_data(auth_data)?;
+    check_authenticator_data(config, &data)?;
+    if data.flags & FLAG_ATTESTED_CREDENTIAL == 0 || data.rest.len() < 18 {
+        return Err(AuthError::InvalidPasskey);
+    }
+
+    // AAGUID (16 bytes), credential id length (2), credential id, COSE key
+    let id_len = u16::from_be_bytes([data.rest[16], data.rest[17]]) as usize;
+    let attested = &data.rest[18..];
+    if attested.len() < id_len {
+        return Err(AuthError::InvalidPasskey);
+    }
+    let (credential_id, mut remaining) = attested.split_at(id_len);
+    let key: Value = ciborium::from_reader(&mut remaining).map_err(|_| AuthError::InvalidPasskey)?;
+    CoseKey::parse(&key)?;
+    // Extensions may follow the key; store only the key's own bytes
+    let public_key = &attested[id_len..attested.len() - remaining.len()];
+
+    if credential_id != decode(&credential.credential_id)? {
+        return Err(AuthError::InvalidPasskey);
+    }
+    Ok(VerifiedCredential {
+        credential_id: credential_id.to_vec(),
+        public_key: public_key.to_vec(),
+        sign_count: data.sign_count,
+    })
+}
+
+/// Checks a sign-in response against its challenge and the stored credential
+///
+/// # Arguments
+/// * `public_key` - COSE_Key bytes from registration
+/// * `stored_count` - Signature counter seen last time
+///
+/// # Returns
+/// The new signature counter to store. Authenticators that keep a counter
+/// must report a higher one each time; a lower or equal one means the key
+/// may have been cloned and the sign-in is refused. Synced passkeys always
+/// report 0 and skip the check.
+pub fn verify_assertion(
+    config: &AuthConfig,
+    challenge: &str,
+    assertion: &PasskeyAssertion,
+    public_key: &[u8],
+    stored_count: u32,
+) -> Result<u32, AuthError> {
+    let (client_data_json, client_data) = parse_client_data(&assertion.client_data_json)?;
+    check_client_data(config, Ceremony::Authenticate, challenge, &client_data)?;
+
+    let auth_data = decode(&assertion.authenticator_data)?;
+    let data = parse_authenticator_data(&auth_data)?;
+    check_authenticator_data(config, &data)?;
+
+    let key: Value = ciborium::from_reader(public_key).map_err(|_| AuthError::InvalidPasskey)?;
+    let mut signed = auth_data.clone();
+    signed.extend_from_slice(&Sha256::digest(&client_data_json));
+    if !CoseKey::parse(&key)?.verify(&signed, &decode(&assertion.signature)?) {
+        return Err(AuthError::InvalidPasskey);
+    }
+
+    if (data.sign_count != 0 || stored_count != 0) && data.sign_count <= stored_count {
+        log::warn!(
+            "Passkey signature counter went from {stored_count} to {}, possible cloned authenticator",
+            data.sign_count
+        );
+        return Err(AuthError::InvalidPasskey);
+    }
+    Ok(data.sign_count)
+}
+
+/// Raw client data JSON, which assertion signatures cover, and its parsed form
+fn parse_client_data(encoded: &str) -> Result<(Vec<u8>, ClientData), AuthError> {
+    let raw = decode(encoded)?;
+    let parsed = serde_json::from_slice(&raw).map_err(|_| AuthError::InvalidPasskey)?;
+    Ok((raw, parsed))
+}
+
+fn check_client_data(
+    config: &AuthConfig,
+    ceremony: Ceremony,
+    challenge: &str,
+    client_data: &ClientData,
+) -> Result<(), AuthError> {
+    let valid = client_data.kind == ceremony.client_data_type()
+        && client_data.challenge == challenge
+        && client_data.origin == config.passkey_origin
+        && !client_data.cross_origin;
+    if valid {
+        Ok(())
+    } else {
+        Err(AuthError::InvalidPasskey)
+    }
+}
+
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData<'_>, AuthError> {
+    if data.len() < 37 {
+        return Err(AuthError::InvalidPasskey);
+    }
+    Ok(AuthenticatorData {
+        rp_id_hash: &data[..32],
+        flags: data[32],
+        sign_count: u32::from_be_bytes([data[33], data[34], data[35], data[36]]),
+        rest: &data[37..],
+    })
+}
+
+/// The response must be for our RP ID, with the user present and verified,
+/// since a passkey replaces both password and second factor
+fn check_authenticator_data(config: &AuthConfig, data: &AuthenticatorData<'_>) -> Result<(), AuthError> {
+    let required = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+    if data.rp_id_hash != Sha256::digest(config.passkey_rp_id.as_bytes()).as_slice()
+        || data.flags & required != required
+    {
+        return Err(AuthError::InvalidPasskey);
+    }
+    Ok(())
+}
+
+fn map_get<'a>(fields: &'a [(Value, Value)], key: &str) -> Option<&'a Value> {
+    fields
+        .iter()
+        .find(|(k, _)| k.as_text() == Some(key))
+        .map(|(_, v)| v)
+}
+
+fn cose_param(fields: &[(Value, Value)], label: i64) -> Option<&Value> {
+    fields
+        .iter()
+        .find(|(k, _)| k.as_integer().is_some_and(|k| i128::from(k) == i128::from(label)))
+        .map(|(_, v)| v)
+}
+
+fn cose_int(fields: &[(Value, Value)], label: i64) -> Option<i128> {
+    cose_param(fields, label)?.as_integer().map(i128::from)
+}
+
+fn cose_bytes(fields: &[(Value, Value)], label: i64) -> Option<&[u8]> {
+    cose_param(fields, label)?.as_bytes().map(Vec::as_slice)
+}
+
+impl CoseKey {
+    fn parse(key: &Value) -> Result<Self, AuthError> {
+        let fields = key.as_map().ok_or(AuthError::InvalidPasskey)?;
+        // 1: kty, 3: alg; the rest depends on the key type
+        match (cose_int(fields, 1), cose_int(fields, 3)) {
+            (Some(2), Some(alg)) if alg == COSE_ES256 as i128 => {
+                let (Some(1), Some(x), Some(y)) = (cose_int(fields, -1), cose_bytes(fields, -2), cose_bytes(fields, -3))
+                else {
+                    return Err(AuthError::InvalidPasskey);
+                };
+                if x.len() != 32 || y.len() != 32 {
+                    return Err(AuthError::InvalidPasskey);
+                }
+                let point = p256::EncodedPoint::from_affine_coordinates(
+                    p256::FieldBytes::from_slice(x),
+                    p256::FieldBytes::from_slice(y),
+                    false,
+                );
+                p256::ecdsa::VerifyingKey::from_encoded_point(&point)
+                    .map(CoseKey::Es256)
+                    .map_err(|_| AuthError::InvalidPasskey)
+            }
+            (Some(3), Some(alg)) if alg == COSE_RS256 as i128 => {
+                let (Some(n), Some(e)) = (cose_bytes(fields, -1), cose_bytes(fields, -2)) else {
+                    return Err(AuthError::InvalidPasskey);
+                };
+                rsa::RsaPublicKey::new(rsa::BigUint::from_bytes_be(n), rsa::BigUint::from_bytes_be(e))
+                    .map(|key| CoseKey::Rs256(rsa::pkcs1v15::VerifyingKey::new(key)))
+                    .map_err(|_| AuthError::InvalidPasskey)
+            }
+            _ => Err(AuthError::InvalidPasskey),
+        }
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match self {
+            // WebAuthn ECDSA signatures are DER encoded
+            CoseKey::Es256(key) => p256::ecdsa::Signature::from_der(signature)
+                .is_ok_and(|sig| p256::ecdsa::signature::Verifier::verify(key, message, &sig).is_ok()),
+            CoseKey::Rs256(key) => rsa::pkcs1v15::Signature::try_from(signature)
+                .is_ok_and(|sig| rsa::signature::Verifier::verify(key, message, &sig).is_ok()),
+        }
+    }
+}
@@ -0,0 +1,492 @@
+//! Runtime configuration for the auth subsystem
+//!
+//! Values come from environment variables (see `AuthConfig::from_env`) and
+//! fall back to defaults suitable for local development.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use chrono::Duration;
+
+use crate::server::auth::email::EmailPolicy;
+use crate::server::auth::password_policy::PasswordPolicy;
+
+/// Tunables shared by the authentication providers
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    /// Public base URL used to build links in outgoing email
+    pub app_url: String,
+    /// Refuse to log in accounts whose email hasn't been confirmed
+    pub require_email_verification: bool,
+    /// Let anyone create an account, by registering or by their first magic link
+    pub open_registration: bool,
+    /// Only let people register with an invitation code; accounts can't be
+    /// created by a first magic link or external login either
+    pub invite_only: bool,
+    /// How long an access token (session) stays valid
+    pub access_token_ttl: Duration,
+    /// How long a refresh token can be exchanged for a new access token
+    pub refresh_token_ttl: Duration,
+    /// How long an email verification link stays valid
+    pub verification_ttl: Duration,
+    /// Minimum time between two verification emails for the same account
+    pub verification_resend_interval: Duration,
+    /// Maximum verification emails per account in a rolling 24 hours
+    pub verification_max_per_day: i64,
+    /// How long a password reset link stays valid
+    pub password_reset_ttl: Duration,
+    /// How long a magic sign-in link stays valid
+    pub magic_link_ttl: Duration,
+    /// Issuer name shown in authenticator apps
+    pub totp_issuer: String,
+    /// How long a user has to enter their second factor after the password
+    pub mfa_challenge_ttl: Duration,
+    /// Wrong codes allowed per login attempt before the password is asked again
+    pub mfa_max_attempts: i32,
+    /// Consecutive wrong passwords allowed before attempts are slowed down
+    pub login_backoff_after: i32,
+    /// Wait imposed after the first slowed-down failure, doubled for each one after
+    pub login_backoff_base: Duration,
+    /// Consecutive wrong passwords that lock the account
+    pub lockout_threshold: i32,
+    /// How long a locked account stays locked
+    pub lockout_duration: Duration,
+    /// Argon2id memory cost in KiB
+    pub argon2_memory_kib: u32,
+    /// Argon2id passes over memory
+    pub argon2_iterations: u32,
+    /// Argon2id lanes
+    pub argon2_parallelism: u32,
+    /// Key for the HMAC session tokens are stored under
+    pub session_token_key: TokenKey,
+    /// Domain passkeys are scoped to, the app's host or a parent of it
+    pub passkey_rp_id: String,
+    /// Site name authenticators show when creating a passkey
+    pub passkey_rp_name: String,
+    /// Origin the browser reports during passkey ceremonies
+    pub passkey_origin: String,
+    /// How long a passkey ceremony may take between begin and finish
+    pub passkey_challenge_ttl: Duration,
+    /// How long a support session acting as another user lasts; it can't be refreshed
+    pub impersonation_ttl: Duration,
+    /// Rules new passwords are checked against at registration and reset
+    pub password_policy: PasswordPolicy,
+    /// Which addresses new accounts may use and when two addresses are the same
+    pub email_policy: EmailPolicy,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            app_url: "http://localhost:8080".into(),
+            require_email_verification: true,
+            open_registration: true,
+            invite_only: false,
+            access_token_ttl: Duration::minutes(15),
+            refresh_token_ttl: Duration::days(30),
+            verification_ttl: Duration::hours(24),
+            verification_resend_interval: Duration::seconds(60),
+            verification_max_per_day: 5,
+            password_reset_ttl: Duration::minutes(30),
+            magic_link_ttl: Duration::minutes(15),
+            totp_issuer: "landing".into(),
+            mfa_challenge_ttl: Duration::minutes(5),
+            mfa_max_attempts: 5,
+            login_backoff_after: 3,
+            login_backoff_base: Duration::seconds(1),
+            lockout_threshold: 10,
+            lockout_duration: Duration::minutes(15),
+            argon2_memory_kib: 19_456,
+            argon2_iterations: 2,
+            argon2_parallelism: 1,
+            session_token_key: TokenKey::random(),
+            passkey_rp_id: "localhost".into(),
+            passkey_rp_name: "landing".into(),
+            passkey_origin: "http://localhost:8080".into(),
+            passkey_challenge_ttl: Duration::minutes(5),
+            impersonation_ttl: Duration::minutes(30),
+            password_policy: PasswordPolicy::default(),
+            email_policy: EmailPolicy::default(),
+        }
+    }
+}
+
+impl AuthConfig {
+    /// Builds a config from the environment, keeping defaults for unset keys
+    ///
+    /// | Variable                     | Field                        |
+    /// |------------------------------|------------------------------|
+    /// | `APP_URL`                    | `app_url`                    |
+    /// | `REQUIRE_EMAIL_VERIFICATION` | `require_email_verification` |
+    /// | `OPEN_REGISTRATION`          | `open_registration`          |
+    /// | `INVITE_ONLY`                | `invite_only`                |
+    /// | `MAGIC_LINK_MINUTES`         | `magic_link_ttl`             |
+    /// | `TOTP_ISSUER`                | `totp_issuer`                |
+    /// | `ACCESS_TOKEN_MINUTES`       | `access_token_ttl`           |
+    /// | `REFRESH_TOKEN_DAYS`         | `refresh_token_ttl`          |
+    /// | `LOCKOUT_THRESHOLD`          | `lockout_threshold`          |
+    /// | `LOCKOUT_MINUTES`            | `lockout_duration`           |
+    /// | `ARGON2_MEMORY_KIB`          | `argon2_memory_kib`          |
+    /// | `ARGON2_ITERATIONS`          | `argon2_iterations`          |
+    /// | `ARGON2_PARALLELISM`         | `argon2_parallelism`         |
+    /// | `SESSION_TOKEN_KEY`          | `session_token_key`          |
+    /// | `PASSKEY_RP_ID`              | `passkey_rp_id`              |
+    /// | `PASSKEY_RP_NAME`            | `passkey_rp_name`            |
+    /// | `PASSKEY_ORIGIN`             | `passkey_origin`             |
+    /// | `IMPERSONATION_MINUTES`      | `impersonation_ttl`          |
+    ///
+    /// `password_policy` and `email_policy` are read by their own `from_env`.
+    ///
+    /// Without `PASSKEY_RP_ID` and `PASSKEY_ORIGIN` passkeys are scoped to
+    /// the host and origin of `app_url`.
+    ///
+    /// # Panics
+    /// In release builds if `SESSION_TOKEN_KEY` is unset or empty; debug and
+    /// test builds fall back to a random key.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let session_token_key = match std::env::var("SESSION_TOKEN_KEY") {
+            Ok(key) if !key.is_empty() => TokenKey::new(key),
+            // A random key would quietly sign everyone out on each restart
+            _ if !cfg!(debug_assertions) => panic!("SESSION_TOKEN_KEY must be set"),
+            _ => {
+                log::warn!("SESSION_TOKEN_KEY is not set, sessions won't survive a restart");
+                defaults.session_token_key.clone()
+            }
+        };
+        let app_url = std::env::var("APP_URL").unwrap_or(defaults.app_url.clone());
+        let app_origin = origin_of(&app_url);
+        let app_host = app_origin
+            .split_once("://")
+            .map_or(app_origin.as_str(), |(_, rest)| rest)
+            .split(':')
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+        Self {
+            passkey_rp_id: std::env::var("PASSKEY_RP_ID").unwrap_or(app_host),
+            passkey_rp_name: std::env::var("PASSKEY_RP_NAME").unwrap_or(defaults.passkey_rp_name.clone()),
+            passkey_origin: std::env::var("PASSKEY_ORIGIN").unwrap_or(app_origin),
+            app_url,
+            require_email_verification: env_parse(
+                "REQUIRE_EMAIL_VERIFICATION",
+                defaults.require_email_verification,
+            ),
+            open_registration: env_parse("OPEN_REGISTRATION", defaults.open_registration),
+            invite_only: env_parse("INVITE_ONLY", defaults.invite_only),
+            magic_link_ttl: Duration::minutes(env_parse(
+                "MAGIC_LINK_MINUTES",
+                defaults.magic_link_ttl.num_minutes(),
+            )),
+            totp_issuer: std::env::var("TOTP_ISSUER").unwrap_or(defaults.totp_issuer),
+            access_token_ttl: Duration::minutes(env_parse(
+                "ACCESS_TOKEN_MINUTES",
+                defaults.access_token_ttl.num_minutes(),
+            )),
+            refresh_token_ttl: Duration::days(env_parse(
+                "REFRESH_TOKEN_DAYS",
+                defaults.refresh_token_ttl.num_days(),
+            )),
+            lockout_threshold: env_parse("LOCKOUT_THRESHOLD", defaults.lockout_threshold),
+            lockout_duration: Duration::minutes(env_parse(
+                "LOCKOUT_MINUTES",
+                defaults.lockout_duration.num_minutes(),
+            )),
+            argon2_memory_kib: env_parse("ARGON2_MEMORY_KIB", defaults.argon2_memory_kib),
+            argon2_iterations: env_parse("ARGON2_ITERATIONS", defaults.argon2_iterations),
+            argon2_parallelism: env_parse("ARGON2_PARALLELISM", defaults.argon2_parallelism),
+            impersonation_ttl: Duration::minutes(env_parse(
+                "IMPERSONATION_MINUTES",
+                defaults.impersonation_ttl.num_minutes(),
+            )),
+            password_policy: PasswordPolicy::from_env(),
+            email_policy: EmailPolicy::from_env(),
+            session_token_key,
+            ..defaults
+        }
+    }
+
+    /// Whether accounts may be created without an invitation code: by
+    /// registering, or by a first magic link or external login
+    pub fn allows_uninvited_signup(&self) -> bool {
+        self.open_registration && !self.invite_only
+    }
+}
+
+/// Scheme, host and port of a URL, e.g. `https://example.com` for `https://example.com/app/`
+fn origin_of(url: &str) -> String {
+    match url.split_once("://") {
+        Some((scheme, rest)) => format!("{scheme}://{}", rest.split('/').next().unwrap_or_default()),
+        None => url.trim_end_matches('/').to_owned(),
+    }
+}
+
+/// An OpenID Connect identity provider the app accepts logins from
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    /// Issuer URL; discovery reads `{issuer}/.well-known/openid-configuration`
+    pub issuer: String,
+    pub client_id: String,
+    /// Absent for public clients, which rely on PKCE alone
+    pub client_secret: Option<String>,
+    /// Where the provider sends the browser back, normally `{app_url}/api/auth/oidc/callback`
+    pub redirect_uri: String,
+    /// Requested scopes; `openid` is always sent
+    pub scopes: Vec<String>,
+    /// How long a started login may take to come back
+    pub login_ttl: Duration,
+    /// Allowed clock difference when checking ID token times
+    pub leeway: Duration,
+}
+
+impl OidcConfig {
+    pub fn new(issuer: impl Into<String>, client_id: impl Into<String>, redirect_uri: impl Into<String>) -> Self {
+        Self {
+            issuer: issuer.into(),
+            client_id: client_id.into(),
+            client_secret: None,
+            redirect_uri: redirect_uri.into(),
+            scopes: vec!["email".into(), "profile".into()],
+            login_ttl: Duration::minutes(10),
+            leeway: Duration::seconds(60),
+        }
+    }
+
+    pub fn with_client_secret(mut self, client_secret: impl Into<String>) -> Self {
+        self.client_secret = Some(client_secret.into());
+        self
+    }
+
+    /// Builds a config from the environment, or `None` without an issuer
+    ///
+    /// | Variable             | Field           |
+    /// |----------------------|-----------------|
+    /// | `OIDC_ISSUER`        | `issuer`        |
+    /// | `OIDC_CLIENT_ID`     | `client_id`     |
+    /// | `OIDC_CLIENT_SECRET` | `client_secret` |
+    /// | `OIDC_REDIRECT_URI`  | `redirect_uri`  |
+    pub fn from_env() -> Option<Self> {
+        let issuer = std::env::var("OIDC_ISSUER").ok().filter(|v| !v.is_empty())?;
+        let client_id = std::env::var("OIDC_CLIENT_ID").ok()?;
+        let redirect_uri = std::env::var("OIDC_REDIRECT_URI").unwrap_or_else(|_| {
+            let app_url = std::env::var("APP_URL").unwrap_or_else(|_| AuthConfig::default().app_url);
+            format!("{}/api/auth/oidc/callback", app_url.trim_end_matches('/'))
+        });
+        let config = Self::new(issuer, client_id, redirect_uri);
+        Some(match std::env::var("OIDC_CLIENT_SECRET") {
+            Ok(secret) if !secret.is_empty() => config.with_client_secret(secret),
+            _ => config,
+        })
+    }
+}
+
+/// Secret key for keyed token hashes, kept out of `Debug` output
+#[derive(Clone)]
+pub struct TokenKey(Vec<u8>);
+
+impl TokenKey {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self(key.into())
+    }
+
+    /// 32 random bytes, valid for the life of the process
+    pub fn random() -> Self {
+        Self(rand::random::<[u8; 32]>().to_vec())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for TokenKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TokenKey(<redacted>)")
+    }
+}
+
+/// Where the server looks for session tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SessionMode {
+    /// `Authorization: Bearer` header only, for API clients
+    #[default]
+    Bearer,
+    /// HttpOnly cookies only, for browsers; state-changing requests need a CSRF token
+    Cookie,
+    /// Either; a bearer header wins when both are present
+    Both,
+}
+
+impl SessionMode {
+    pub fn accepts_bearer(self) -> bool {
+        matches!(self, SessionMode::Bearer | SessionMode::Both)
+    }
+
+    pub fn accepts_cookie(self) -> bool {
+        matches!(self, SessionMode::Cookie | SessionMode::Both)
+    }
+}
+
+impl std::str::FromStr for SessionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "bearer" => Ok(SessionMode::Bearer),
+            "cookie" => Ok(SessionMode::Cookie),
+            "both" => Ok(SessionMode::Both),
+            other => Err(format!("unknown session mode: {other}")),
+        }
+    }
+}
+
+/// How sessions travel between browser and server
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    pub mode: SessionMode,
+    /// Mark cookies `Secure`; only turn off for plain-HTTP local development
+    pub secure_cookies: bool,
+    /// Lifetime of the refresh and CSRF cookies, normally `AuthConfig::refresh_token_ttl`
+    pub refresh_cookie_max_age: Duration,
+    /// Lifetime of the magic link cookie, normally `AuthConfig::magic_link_ttl`
+    pub magic_link_cookie_max_age: Duration,
+    /// Issue signed access tokens, checked without a database lookup;
+    /// the server then wires a `TokenSigner` into `AuthContext`
+    pub signed_tokens: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            mode: SessionMode::default(),
+            secure_cookies: true,
+            refresh_cookie_max_age: Duration::days(30),
+            magic_link_cookie_max_age: Duration::minutes(15),
+            signed_tokens: false,
+        }
+    }
+}
+
+impl SessionConfig {
+    /// Builds a config from the environment, keeping defaults for unset keys
+    ///
+    /// | Variable             | Field                       |
+    /// |----------------------|-----------------------------|
+    /// | `SESSION_MODE`       | `mode`                      |
+    /// | `SECURE_COOKIES`     | `secure_cookies`            |
+    /// | `REFRESH_TOKEN_DAYS` | `refresh_cookie_max_age`    |
+    /// | `MAGIC_LINK_MINUTES` | `magic_link_cookie_max_age` |
+    /// | `SIGNED_TOKENS`      | `signed_tokens`             |
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            mode: env_parse("SESSION_MODE", defaults.mode),
+            secure_cookies: env_parse("SECURE_COOKIES", defaults.secure_cookies),
+            refresh_cookie_max_age: Duration::days(env_parse(
+                "REFRESH_TOKEN_DAYS",
+                defaults.refresh_cookie_max_age.num_days(),
+            )),
+            magic_link_cookie_max_age: Duration::minutes(env_parse(
+                "MAGIC_LINK_MINUTES",
+                defaults.magic_link_cookie_max_age.num_minutes(),
+            )),
+            signed_tokens: env_parse("SIGNED_TOKENS", defaults.signed_tokens),
+        }
+    }
+}
+
+/// Token bucket parameters: up to `burst` requests at once, refilled at
+/// `per_minute` requests per minute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl RateLimit {
+    pub const fn new(burst: u32, per_minute: u32) -> Self {
+        Self { burst, per_minute }
+    }
+}
+
+/// Limits enforced by the `rate_limit` middleware and `AuthContext::login`
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    /// Per-IP limit for routes without an override
+    pub default: RateLimit,
+    /// Per-IP overrides keyed by route path, e.g. `/api/auth/forgot-password`
+    pub routes: HashMap<String, RateLimit>,
+    /// Password attempts per account, whatever IP they come from
+    pub login_per_account: RateLimit,
+    /// Reverse proxies whose `X-Forwarded-For` and `X-Real-IP` headers are believed
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            default: RateLimit::new(60, 60),
+            routes: HashMap::from([
+                ("/api/auth/forgot-password".to_string(), RateLimit::new(5, 5)),
+                ("/api/auth/resend-verification".to_string(), RateLimit::new(5, 5)),
+                ("/api/auth/reset-password".to_string(), RateLimit::new(10, 10)),
+                ("/api/auth/verify-email".to_string(), RateLimit::new(10, 10)),
+                ("/api/auth/refresh".to_string(), RateLimit::new(10, 10)),
+                ("/api/auth/register".to_string(), RateLimit::new(5, 5)),
+                ("/api/auth/login".to_string(), RateLimit::new(10, 10)),
+                ("/api/auth/mfa".to_string(), RateLimit::new(10, 10)),
+                ("/api/auth/magic-link/login".to_string(), RateLimit::new(10, 10)),
+                ("/api/auth/password".to_string(), RateLimit::new(10, 10)),
+            ]),
+            login_per_account: RateLimit::new(10, 5),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Builds a config from the environment, keeping defaults for unset keys
+    ///
+    /// | Variable                 | Field                              |
+    /// |--------------------------|------------------------------------|
+    /// | `RATE_LIMIT_BURST`       | `default.burst`                    |
+    /// | `RATE_LIMIT_PER_MINUTE`  | `default.per_minute`               |
+    /// | `LOGIN_LIMIT_PER_MINUTE` | `login_per_account.per_minute`     |
+    /// | `TRUSTED_PROXIES`        | `trusted_proxies`, comma separated |
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            default: RateLimit::new(
+                env_parse("RATE_LIMIT_BURST", defaults.default.burst),
+                env_parse("RATE_LIMIT_PER_MINUTE", defaults.default.per_minute),
+            ),
+            login_per_account: RateLimit {
+                per_minute: env_parse("LOGIN_LIMIT_PER_MINUTE", defaults.login_per_account.per_minute),
+                ..defaults.login_per_account
+            },
+            trusted_proxies: std::env::var("TRUSTED_PROXIES")
+                .map(|v| v.split(',').filter_map(|ip| ip.trim().parse().ok()).collect())
+                .unwrap_or(defaults.trusted_proxies),
+            ..defaults
+        }
+    }
+
+    /// Overrides the per-IP limit for one route
+    pub fn with_route(mut self, path: impl Into<String>, limit: RateLimit) -> Self {
+        self.routes.insert(path.into(), limit);
+        self
+    }
+
+    /// Limit that applies to `path`
+    pub fn limit_for(&self, path: &str) -> RateLimit {
+        self.routes.get(path).copied().unwrap_or(self.default)
+    }
+}
+
+/// Reads and parses an environment variable, falling back on absence or parse failure
+pub(crate) fn env_parse<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
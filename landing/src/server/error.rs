@@ -1,4 +1,12 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
 use thiserror::Error;
+use uuid::Uuid;
+
+use crate::server::models::PasswordViolation;
 
 #[derive(Debug, Error)]
 pub enum AuthError {
@@ -10,10 +18,132 @@ pub enum AuthError {
     DatabaseError,
     #[error("Invalid session")]
     InvalidSession,
-    #[error("Password requirements not met")]
-    PasswordRequirements,
+    /// Every policy rule the password failed, in the order they were checked
+    #[error("Password requirements not met: {}", describe_violations(.violations))]
+    PasswordRequirements { violations: Vec<PasswordViolation> },
     #[error("Invalid email")]
     InvalidEmail,
-    #[error("Token Sorage Error")]
+    /// The address is at a domain on `EmailPolicy::blocked_domains`
+    #[error("Email addresses from this domain are not accepted")]
+    DisposableEmail,
+    /// A client-side `TokenStore` couldn't be read or written
+    #[error("Token storage error")]
     TokenStorageFailed,
-}
\ No newline at end of file
+    #[error("Email address not verified")]
+    EmailNotVerified,
+    #[error("Invalid or expired token")]
+    InvalidToken,
+    #[error("Too many requests, try again later")]
+    RateLimited,
+    #[error("Not authenticated")]
+    Unauthorized,
+    #[error("Insufficient permissions")]
+    Forbidden,
+    #[error("Unknown role")]
+    UnknownRole,
+    /// Password accepted, a second factor is needed to finish logging in
+    #[error("Two-factor authentication required")]
+    MfaRequired { challenge: String },
+    #[error("Invalid authentication code")]
+    InvalidMfaCode,
+    #[error("Two-factor authentication is not set up")]
+    MfaNotEnrolled,
+    #[error("Two-factor authentication is already enabled")]
+    MfaAlreadyEnabled,
+    /// Too many failed passwords; also returned for unknown emails
+    #[error("Too many failed login attempts, try again later")]
+    AccountLocked,
+    /// A refresh token was presented twice; its whole family is revoked.
+    /// `user_id` is the token's owner, `session_ids` the sessions ended.
+    #[error("Refresh token reuse detected, please log in again")]
+    TokenReuseDetected { user_id: Uuid, session_ids: Vec<Uuid> },
+    /// Cookie-authenticated request without a matching CSRF token
+    #[error("Missing or invalid CSRF token")]
+    InvalidCsrfToken,
+    /// The OpenID provider failed or returned something that didn't verify
+    #[error("Identity provider error: {0}")]
+    IdentityProvider(String),
+    /// A passkey response that doesn't verify against its challenge or key
+    #[error("Passkey could not be verified")]
+    InvalidPasskey,
+    /// New accounts can't be created while `open_registration` is off, nor
+    /// by magic link or external login while `invite_only` is on
+    #[error("Registration is closed")]
+    RegistrationClosed,
+    /// `invite_only` is on and no invitation code was given
+    #[error("An invitation is required to register")]
+    InvitationRequired,
+    /// Unknown, revoked, expired or used-up code, or one bound to another address
+    #[error("Invalid or expired invitation")]
+    InvalidInvitation,
+    /// API key scopes that are empty or not held by the key's owner
+    #[error("Invalid API key scope")]
+    InvalidScope,
+    /// No usable signing key, or the access token couldn't be encoded
+    #[error("Could not sign access token")]
+    TokenSigningFailed,
+    #[error("User not found")]
+    UserNotFound,
+}
+
+impl AuthError {
+    /// Whether the same request may succeed if retried later: rate limits
+    /// and failures on the server's side rather than a rejected credential
+    pub fn is_transient(&self) -> bool {
+        let status = self.status_code();
+        status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+    }
+
+    /// HTTP status used when the error is returned from an API route
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::AuthenticationFailed
+            | AuthError::InvalidSession
+            | AuthError::Unauthorized
+            | AuthError::MfaRequired { .. }
+            | AuthError::InvalidMfaCode
+            | AuthError::TokenReuseDetected { .. } => StatusCode::UNAUTHORIZED,
+            AuthError::EmailNotVerified
+            | AuthError::Forbidden
+            | AuthError::InvalidCsrfToken
+            | AuthError::RegistrationClosed
+            | AuthError::InvitationRequired => StatusCode::FORBIDDEN,
+            AuthError::UserExists | AuthError::MfaAlreadyEnabled => StatusCode::CONFLICT,
+            AuthError::PasswordRequirements { .. }
+            | AuthError::InvalidEmail
+            | AuthError::DisposableEmail
+            | AuthError::InvalidToken
+            | AuthError::UnknownRole
+            | AuthError::MfaNotEnrolled
+            | AuthError::InvalidPasskey
+            | AuthError::InvalidInvitation
+            | AuthError::InvalidScope => StatusCode::BAD_REQUEST,
+            AuthError::RateLimited | AuthError::AccountLocked => StatusCode::TOO_MANY_REQUESTS,
+            AuthError::DatabaseError
+            | AuthError::TokenStorageFailed
+            | AuthError::TokenSigningFailed => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            AuthError::UserNotFound => StatusCode::NOT_FOUND,
+            AuthError::IdentityProvider(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        match &self {
+            // Structured, so forms can show each failed rule
+            AuthError::PasswordRequirements { violations } => (
+                self.status_code(),
+                Json(serde_json::json!({ "error": self.to_string(), "violations": violations })),
+            )
+                .into_response(),
+            _ => (self.status_code(), self.to_string()).into_response(),
+        }
+    }
+}
+
+fn describe_violations(violations: &[PasswordViolation]) -> String {
+    violations.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
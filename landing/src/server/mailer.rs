@@ -0,0 +1,111 @@
+//! Outgoing email
+//!
+//! Auth flows (verification, password reset, ...) send mail through the
+//! `Mailer` trait so the transport can be swapped without touching them.
+
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::server::error::AuthError;
+
+/// A single outgoing message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Transport for outgoing email
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), AuthError>;
+}
+
+/// Writes messages to the log instead of sending them (development default)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: Email) -> Result<(), AuthError> {
+        log::info!("Email to {}: {}\n{}", email.to, email.subject, email.body);
+        Ok(())
+    }
+}
+
+/// Keeps every message in memory so tests can inspect them
+#[derive(Debug, Default)]
+pub struct MemoryMailer {
+    outbox: Mutex<Vec<Email>>,
+}
+
+impl MemoryMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All messages sent so far, oldest first
+    pub fn outbox(&self) -> Vec<Email> {
+        self.outbox.lock().unwrap().clone()
+    }
+
+    /// Most recent message sent to `to`
+    pub fn last_to(&self, to: &str) -> Option<Email> {
+        self.outbox
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|e| e.to == to)
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, email: Email) -> Result<(), AuthError> {
+        self.outbox.lock().unwrap().push(email);
+        Ok(())
+    }
+}
+
+/// Message carrying an email verification link
+pub fn verification_email(to: &str, app_url: &str, token: &str) -> Email {
+    Email {
+        to: to.to_owned(),
+        subject: "Confirm your email address".into(),
+        body: format!(
+            "Welcome! Confirm your email address by opening this link:\n\n{}/verify-email/{}",
+            app_url.trim_end_matches('/'),
+            token
+        ),
+    }
+}
+
+/// Message carrying a single-use sign-in link
+pub fn magic_link_email(to: &str, app_url: &str, token: &str) -> Email {
+    Email {
+        to: to.to_owned(),
+        subject: "Your sign-in link".into(),
+        body: format!(
+            "Open this link in the same browser you asked from to sign in. It works once and expires shortly:\n\n{}/magic-link/{}",
+            app_url.trim_end_matches('/'),
+            token
+        ),
+    }
+}
+
+/// Message carrying a password reset link
+pub fn password_reset_email(to: &str, app_url: &str, token: &str) -> Email {
+    Email {
+        to: to.to_owned(),
+        subject: "Reset your password".into(),
+        body: format!(
+            "Someone asked to reset the password for this account. If it was you, open this link:\n\n{}/reset-password/{}",
+            app_url.trim_end_matches('/'),
+            token
+        ),
+    }
+}
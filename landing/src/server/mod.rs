@@ -1,9 +1,29 @@
+pub mod audit;
 pub mod auth;
 pub mod error;
 pub mod api;
+pub mod models;
+pub mod config;
+pub mod mailer;
 
 pub use error::AuthError;
+pub use models::{
+    ApiKeyInfo, InvitationInfo, NewApiKey, NewInvitation, PasskeyAssertion, PasskeyCreationOptions,
+    PasskeyCredential, PasskeyInfo, PasskeyRequestOptions, PasswordViolation, SessionInfo, TotpEnrollment,
+    User, ADMIN_ROLE, DEFAULT_ROLE,
+};
+pub use config::{AuthConfig, RateLimit, RateLimitConfig, OidcConfig, SessionConfig, SessionMode, TokenKey};
+pub use mailer::{Mailer, LogMailer, MemoryMailer};
+pub use audit::{
+    AuditEvent, AuditEventType, AuditFilter, AuditRecorder, MemoryAuditRecorder,
+    NoopAuditRecorder, PgAuditRecorder,
+};
 
 pub use auth::AuthProvider;
 pub use auth::AuthContext;
+pub use auth::use_auth;
+pub use auth::use_session_refresh;
+pub use auth::LoginStatus;
 pub use auth::auth_middleware;
+pub use auth::PgAuthProvider;
+pub use auth::InMemoryAuthProvider;
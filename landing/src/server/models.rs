@@ -0,0 +1,225 @@
+//! Shared authentication models
+//!
+//! These types cross the server/client boundary, so they only carry
+//! what the UI needs to know about the signed-in user.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Role granted to every new account
+pub const DEFAULT_ROLE: &str = "user";
+/// Role with full administrative access
+pub const ADMIN_ROLE: &str = "admin";
+
+/// An authenticated user together with the session token that proves it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub id: Uuid,
+    pub email: String,
+    /// Session token sent back as `Authorization: Bearer <token>`
+    pub bearer_token: String,
+    /// When the session behind `bearer_token` stops being valid
+    pub session_expires_at: DateTime<Utc>,
+    /// Single-use token for `AuthProvider::refresh`; only present right
+    /// after a login or refresh, never on a validated session
+    pub refresh_token: Option<String>,
+    /// Names of the roles granted to this user
+    pub roles: Vec<String>,
+    /// Permissions held through those roles, e.g. `posts:write`
+    pub permissions: Vec<String>,
+    /// Admin acting as this user, when the session is an impersonation
+    #[serde(default)]
+    pub impersonator_id: Option<Uuid>,
+}
+
+impl User {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|p| p == permission)
+    }
+
+    pub fn is_impersonated(&self) -> bool {
+        self.impersonator_id.is_some()
+    }
+}
+
+/// A signed-in device, as listed on the active sessions page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// Stays the same across refreshes; pass to `revoke_session`
+    pub id: Uuid,
+    /// Browser and OS, e.g. "Firefox on Linux"
+    pub device_label: String,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// When the user signed in on this device
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    /// Whether this is the session making the request
+    pub current: bool,
+}
+
+/// Data needed to add an account to an authenticator app
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TotpEnrollment {
+    /// Base32 secret for manual entry
+    pub secret: String,
+    /// `otpauth://totp/...` URI
+    pub otpauth_uri: String,
+    /// PNG QR code of the URI, base64 encoded
+    pub qr_png_base64: String,
+}
+
+/// An API key of the signed-in user; the key itself is only shown once
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiKeyInfo {
+    /// Pass to `revoke_api_key`
+    pub id: Uuid,
+    /// Label the user gave it, e.g. "Deploy script"
+    pub name: String,
+    /// First characters of the key, to tell keys apart
+    pub prefix: String,
+    /// Permissions the key may use, e.g. `posts:write`
+    pub scopes: Vec<String>,
+    /// `None` for keys that don't expire
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A newly created API key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewApiKey {
+    /// Sent as `Authorization: Bearer <key>`; not stored, so not shown again
+    pub key: String,
+    pub api_key: ApiKeyInfo,
+}
+
+/// A registration invitation; the code itself is only shown once
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InvitationInfo {
+    /// Pass to `revoke_invitation`
+    pub id: Uuid,
+    /// Canonical address the code is bound to, `None` if anyone may use it
+    pub email: Option<String>,
+    /// Accounts the code can create, `None` for no limit
+    pub max_uses: Option<u32>,
+    pub use_count: u32,
+    /// Account the invitation is attributed to
+    pub invited_by: Option<Uuid>,
+    /// `None` for codes that don't expire
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl InvitationInfo {
+    /// Whether the code can still create an account at `now`
+    pub fn is_usable(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none()
+            && self.expires_at.is_none_or(|at| at > now)
+            && self.max_uses.is_none_or(|max| self.use_count < max)
+    }
+}
+
+/// A newly created invitation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewInvitation {
+    /// Passed to `register`; not stored, so not shown again
+    pub code: String,
+    pub invitation: InvitationInfo,
+}
+
+/// A passkey registered to the signed-in user
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PasskeyInfo {
+    /// Pass to `delete_passkey`
+    pub id: Uuid,
+    /// Label the user gave it, e.g. "MacBook"
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Arguments for `navigator.credentials.create()`; binary values are base64url
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PasskeyCreationOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub rp_name: String,
+    /// WebAuthn user handle
+    pub user_id: String,
+    pub user_name: String,
+    /// COSE algorithm ids the server can verify, most preferred first
+    pub algorithms: Vec<i64>,
+    /// Credentials the user already has, so the authenticator doesn't add a second one
+    pub exclude_credentials: Vec<String>,
+    pub timeout_ms: u32,
+}
+
+/// Arguments for `navigator.credentials.get()`; binary values are base64url
+///
+/// No credentials are listed: the authenticator offers its discoverable
+/// passkeys for `rp_id`, so no email is needed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PasskeyRequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub timeout_ms: u32,
+}
+
+/// A new credential from `navigator.credentials.create()`, base64url encoded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PasskeyCredential {
+    pub credential_id: String,
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+/// A signed challenge from `navigator.credentials.get()`, base64url encoded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PasskeyAssertion {
+    pub credential_id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: Option<String>,
+}
+
+/// One password policy rule a candidate password failed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum PasswordViolation {
+    TooShort { min: usize },
+    TooLong { max: usize },
+    MissingUppercase,
+    MissingLowercase,
+    MissingDigit,
+    MissingSymbol,
+    /// Estimated strength `score` on a 0-4 scale, below the required `min`
+    TooWeak { score: u8, min: u8 },
+    /// Contains the email address or a part of it
+    ContainsPersonalInfo,
+    /// On the configured list of passwords known from breaches
+    Breached,
+}
+
+impl std::fmt::Display for PasswordViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PasswordViolation::TooShort { min } => write!(f, "must be at least {min} characters"),
+            PasswordViolation::TooLong { max } => write!(f, "must be at most {max} characters"),
+            PasswordViolation::MissingUppercase => f.write_str("must contain an uppercase letter"),
+            PasswordViolation::MissingLowercase => f.write_str("must contain a lowercase letter"),
+            PasswordViolation::MissingDigit => f.write_str("must contain a digit"),
+            PasswordViolation::MissingSymbol => f.write_str("must contain a symbol"),
+            PasswordViolation::TooWeak { .. } => f.write_str("is too easy to guess"),
+            PasswordViolation::ContainsPersonalInfo => f.write_str("must not contain your email address"),
+            PasswordViolation::Breached => f.write_str("has appeared in a data breach"),
+        }
+    }
+}
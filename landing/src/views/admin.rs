@@ -0,0 +1,52 @@
+use dioxus::prelude::*;
+use uuid::Uuid;
+use crate::{views::routes::Routes, server::use_auth};
+
+/// Landing page for administrators, only reachable with the `admin` role
+///
+/// Support staff can act as a user from here to see what they see; the
+/// banner on every page ends it.
+#[component]
+pub fn Admin() -> Element {
+    let auth = use_auth();
+    let nav = use_navigator();
+    let mut user_id = use_signal(|| String::new());
+    let mut error = use_signal::<Option<String>>(|| None);
+
+    let on_impersonate = move |_| {
+        let auth = auth.clone();
+        let Ok(id) = user_id.read().trim().parse::<Uuid>() else {
+            error.set(Some("Enter a user ID".into()));
+            return;
+        };
+        spawn(async move {
+            match auth.impersonate(id).await {
+                Ok(_) => {
+                    error.set(None);
+                    nav.push(Routes::Home {});
+                },
+                Err(e) => error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    rsx! {
+        div { class: "max-w-lg mx-auto py-2",
+            h1 { class: "text-3xl", "Administration" }
+            h2 { class: "text-xl", "Impersonate user" }
+            p { "See the site as a user to debug their issue. Admin accounts can't be impersonated." }
+            div {
+                label { "User ID" }
+                input {
+                    r#type: "text",
+                    value: "{user_id}",
+                    oninput: move |e| user_id.set(e.value().clone()),
+                }
+                button { r#type: "button", onclick: on_impersonate, "Impersonate" }
+            }
+            if let Some(e) = error.read().as_ref() {
+                div { style: "color: red;", "{e}" }
+            }
+        }
+    }
+}
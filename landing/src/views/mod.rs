@@ -7,6 +7,9 @@ pub use blog::Blog;
 mod not_found;
 pub use not_found::NotFound;
 
+mod admin;
+pub use admin::Admin;
+
 pub mod routes;
 pub use routes::{AppRouter, AppLayout};
 
@@ -2,11 +2,23 @@ use dioxus::prelude::*;
 use dioxus_router::prelude::*;
 use crate::{
     components::Navbar,
-    views::{home::Home, blog::Blog, not_found::NotFound},
+    views::{home::Home, blog::Blog, not_found::NotFound, admin::Admin},
 };
 use crate::components::auth::login::Login;
+use crate::components::auth::register::Register;
+use crate::components::auth::verify_email::VerifyEmail;
+use crate::components::auth::forgot_password::ForgotPassword;
+use crate::components::auth::reset_password::ResetPassword;
+use crate::components::auth::mfa_challenge::MfaChallenge;
+use crate::components::auth::two_factor_setup::TwoFactorSetup;
+use crate::components::auth::active_sessions::ActiveSessions;
+use crate::components::auth::passkeys::Passkeys;
+use crate::components::auth::magic_link::MagicLink;
+use crate::components::auth::api_keys::ApiKeys;
+use crate::components::auth::impersonation_banner::ImpersonationBanner;
 use crate::components::protected::Protected;
 use crate::server::auth::context::AuthClient;
+use crate::server::{use_session_refresh, ADMIN_ROLE};
 
 /// Core application routing configuration
 #[derive(Clone, Routable, Debug, serde::Serialize)]
@@ -17,10 +29,43 @@ pub enum Routes {
     
     #[route("/login")]
     Login {},
+
+    #[route("/register")]
+    Register {},
+
+    #[route("/login/two-factor")]
+    MfaChallenge {},
+
+    #[route("/verify-email/:token")]
+    VerifyEmail { token: String },
+
+    #[route("/magic-link/:token")]
+    MagicLink { token: String },
+
+    #[route("/forgot-password")]
+    ForgotPassword {},
+
+    #[route("/reset-password/:token")]
+    ResetPassword { token: String },
     
     #[route("/protected")]
     Protected {},
 
+    #[route("/settings/two-factor")]
+    TwoFactorSetup {},
+
+    #[route("/settings/sessions")]
+    ActiveSessions {},
+
+    #[route("/settings/passkeys")]
+    Passkeys {},
+
+    #[route("/settings/api-keys")]
+    ApiKeys {},
+
+    #[route("/admin")]
+    Admin {},
+
     #[route("/blog/:id")]
     Blog { id: i32 },
     
@@ -31,10 +76,13 @@ pub enum Routes {
 /// Main application layout with navigation
 #[component]
 pub fn AppLayout() -> Element {
+    use_session_refresh();
+
     rsx! {
         Navbar {
             items: nav_items()
         }
+        ImpersonationBanner {}
         main { class: "relative isolate pt-16",
             Outlet::<Routes> {}
         }
@@ -89,8 +137,17 @@ fn handle_route_guard(
 ) -> Option<NavigationTarget> {
     let current = state.current();
     
-    if is_protected_route(¤t) && !auth.is_authenticated() {
+    if is_protected_route(&current) && !auth.is_authenticated() {
         Some(NavigationTarget::from(Routes::Login {}))
+    } else if let Some(role) = required_role(&current) {
+        if !auth.is_authenticated() {
+            Some(NavigationTarget::from(Routes::Login {}))
+        } else if !auth.has_role(role) {
+            // Don't advertise that the page exists
+            Some(NavigationTarget::from(Routes::NotFound {}))
+        } else {
+            None
+        }
     } else {
         None
     }
@@ -98,7 +155,22 @@ fn handle_route_guard(
 
 /// Route protection rules
 fn is_protected_route(route: &Routes) -> bool {
-    matches!(route, Routes::Protected {})
+    matches!(
+        route,
+        Routes::Protected {}
+            | Routes::TwoFactorSetup {}
+            | Routes::ActiveSessions {}
+            | Routes::Passkeys {}
+            | Routes::ApiKeys {}
+    )
+}
+
+/// Role required to view a route, if any
+fn required_role(route: &Routes) -> Option<&'static str> {
+    match route {
+        Routes::Admin {} => Some(ADMIN_ROLE),
+        _ => None,
+    }
 }
 
 /// Navigation item definition
@@ -106,5 +178,5 @@ fn is_protected_route(route: &Routes) -> bool {
 pub struct NavItem {
     pub to: Routes,
     pub text: String,
-\    pub protected: bool,
+    pub protected: bool,
 }
\ No newline at end of file
@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use landing::server::auth::{AuthProvider, InMemoryAuthProvider, ManualClock};
+use landing::server::{AuthError, ADMIN_ROLE};
+use uuid::Uuid;
+
+use crate::common::signed_in;
+
+fn scopes(names: &[&str]) -> Vec<String> {
+    names.iter().map(|s| s.to_string()).collect()
+}
+
+#[tokio::test]
+async fn test_api_key_is_narrowed_to_its_scopes() -> anyhow::Result<()> {
+    let (_, auth) = signed_in(InMemoryAuthProvider::new(), "abby@example.com").await?;
+    let owner = auth.current_user().await.unwrap();
+    auth.assign_role(owner.id, ADMIN_ROLE).await?;
+
+    let created = auth.create_api_key("Deploy script", &scopes(&["posts:write"]), None).await?;
+    assert!(created.key.starts_with(&created.api_key.prefix));
+
+    let user = auth.resolve_api_key(&created.key).await?;
+    assert_eq!(user.id, owner.id);
+    assert_eq!(user.permissions, vec!["posts:write".to_string()]);
+    assert!(user.roles.is_empty());
+
+    let keys = auth.list_api_keys().await?;
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].name, "Deploy script");
+    assert!(keys[0].last_used_at.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_api_key_scopes_must_be_held() -> anyhow::Result<()> {
+    let (_, auth) = signed_in(InMemoryAuthProvider::new(), "ben@example.com").await?;
+
+    assert!(matches!(
+        auth.create_api_key("Reader", &scopes(&["users:read"]), None).await,
+        Err(AuthError::InvalidScope)
+    ));
+    assert!(matches!(
+        auth.create_api_key("Nothing", &[], None).await,
+        Err(AuthError::InvalidScope)
+    ));
+
+    let user = auth.current_user().await.unwrap();
+    auth.assign_role(user.id, ADMIN_ROLE).await?;
+    auth.create_api_key("Reader", &scopes(&["users:read"]), None).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_api_key_expires() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let (_, auth) = signed_in(InMemoryAuthProvider::with_clock(clock.clone()), "cleo@example.com").await?;
+
+    let expires_at = Some(Utc::now() + Duration::days(30));
+    let created = auth.create_api_key("CI", &scopes(&["posts:write"]), expires_at).await?;
+    auth.resolve_api_key(&created.key).await?;
+
+    clock.advance(Duration::days(31));
+    assert!(matches!(
+        auth.resolve_api_key(&created.key).await,
+        Err(AuthError::InvalidSession)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_api_key_user_expiry_is_bounded() -> anyhow::Result<()> {
+    let now = Utc::now();
+    let clock = Arc::new(ManualClock::new(now));
+    let provider = InMemoryAuthProvider::with_clock(clock).with_session_ttl(Duration::minutes(15));
+    let (_, auth) = signed_in(provider, "cora@example.com").await?;
+
+    // A key that never expires still only vouches for one access token lifetime
+    let forever = auth.create_api_key("Cron", &scopes(&["posts:write"]), None).await?;
+    let user = auth.resolve_api_key(&forever.key).await?;
+    assert_eq!(user.session_expires_at, now + Duration::minutes(15));
+
+    let soon = Some(now + Duration::minutes(5));
+    let short = auth.create_api_key("Demo", &scopes(&["posts:write"]), soon).await?;
+    let user = auth.resolve_api_key(&short.key).await?;
+    assert_eq!(Some(user.session_expires_at), soon);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_revoked_api_key_stops_working() -> anyhow::Result<()> {
+    let (_, auth) = signed_in(InMemoryAuthProvider::new(), "dana@example.com").await?;
+    let created = auth.create_api_key("Laptop", &scopes(&["posts:write"]), None).await?;
+
+    assert!(auth.revoke_api_key(created.api_key.id).await?);
+    assert!(!auth.revoke_api_key(created.api_key.id).await?);
+    assert!(auth.list_api_keys().await?.is_empty());
+    assert!(matches!(
+        auth.resolve_api_key(&created.key).await,
+        Err(AuthError::InvalidSession)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_api_keys_belong_to_their_owner() -> anyhow::Result<()> {
+    let (provider, auth) = signed_in(InMemoryAuthProvider::new(), "eli@example.com").await?;
+    let created = auth.create_api_key("Mine", &scopes(&["posts:write"]), None).await?;
+
+    let stranger = Uuid::new_v4();
+    assert!(!provider.revoke_api_key(stranger, created.api_key.id).await?);
+    assert!(provider.list_api_keys(stranger).await?.is_empty());
+    assert_eq!(auth.list_api_keys().await?.len(), 1);
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+//! Fixtures shared by the auth test modules
+
+use std::sync::Arc;
+
+use landing::server::auth::{AuthContext, AuthProvider, InMemoryAuthProvider};
+use landing::server::MemoryMailer;
+
+pub const PASSWORD: &str = "Sup3rSecret";
+
+/// Pulls the token off the end of the last link mailed to `email`
+pub fn mailed_token(mailer: &MemoryMailer, email: &str) -> String {
+    let mail = mailer.last_to(email).expect("an email should have been sent");
+    mail.body.rsplit('/').next().unwrap().trim().to_string()
+}
+
+/// Attaches a fresh in-memory mailer to `provider`
+pub fn attach_mailer(provider: InMemoryAuthProvider) -> (InMemoryAuthProvider, Arc<MemoryMailer>) {
+    let mailer = Arc::new(MemoryMailer::new());
+    (provider.with_mailer(mailer.clone()), mailer)
+}
+
+pub fn provider_with_mailer() -> (InMemoryAuthProvider, Arc<MemoryMailer>) {
+    attach_mailer(InMemoryAuthProvider::new())
+}
+
+/// Registers an account and follows its verification link
+pub async fn register_verified(
+    provider: &InMemoryAuthProvider,
+    mailer: &MemoryMailer,
+    email: &str,
+) -> anyhow::Result<()> {
+    provider.register(email, PASSWORD, None).await?;
+    provider.verify_email(&mailed_token(mailer, email)).await?;
+    Ok(())
+}
+
+/// A signed-in context for a verified account on `provider`
+pub async fn signed_in(
+    provider: InMemoryAuthProvider,
+    email: &str,
+) -> anyhow::Result<(Arc<InMemoryAuthProvider>, AuthContext)> {
+    let (provider, mailer) = attach_mailer(provider);
+    register_verified(&provider, &mailer, email).await?;
+
+    let provider = Arc::new(provider);
+    let auth = AuthContext::new(provider.clone());
+    auth.login(email, PASSWORD).await?;
+    Ok((provider, auth))
+}
@@ -0,0 +1,89 @@
+use axum::http::{header, HeaderMap, HeaderValue, Method};
+use landing::server::auth::{cookies, AuthProvider};
+use landing::server::{AuthError, SessionConfig, SessionMode};
+
+use crate::common::{provider_with_mailer, register_verified, PASSWORD};
+
+fn request_headers(cookie: &str, csrf: Option<&str>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::COOKIE, HeaderValue::from_str(cookie).unwrap());
+    if let Some(csrf) = csrf {
+        headers.insert(cookies::CSRF_HEADER, HeaderValue::from_str(csrf).unwrap());
+    }
+    headers
+}
+
+#[tokio::test]
+async fn test_session_cookies_attributes() -> anyhow::Result<()> {
+    let (provider, mailer) = provider_with_mailer();
+    register_verified(&provider, &mailer, "carol@example.com").await?;
+    let user = provider.authenticate("carol@example.com", PASSWORD).await?;
+
+    let set = cookies::session_cookies(&user, &SessionConfig::default());
+    let set: Vec<&str> = set.iter().map(|v| v.to_str().unwrap()).collect();
+    assert_eq!(set.len(), 3);
+
+    let session = set.iter().find(|c| c.starts_with("session=")).unwrap();
+    assert!(session.contains(&user.bearer_token));
+    assert!(session.contains("HttpOnly") && session.contains("Secure"));
+    assert!(session.contains("SameSite=Lax"));
+
+    let refresh = set.iter().find(|c| c.starts_with("refresh_token=")).unwrap();
+    assert!(refresh.contains("Path=/api/auth") && refresh.contains("SameSite=Strict"));
+
+    // Scripts must be able to read the CSRF token to echo it back
+    let csrf = set.iter().find(|c| c.starts_with("csrf_token=")).unwrap();
+    assert!(!csrf.contains("HttpOnly"));
+
+    let insecure = SessionConfig { secure_cookies: false, ..SessionConfig::default() };
+    let cleared = cookies::clear_session_cookies(&insecure);
+    assert!(cleared.iter().all(|c| {
+        let c = c.to_str().unwrap();
+        c.contains("Max-Age=0") && !c.contains("Secure")
+    }));
+
+    Ok(())
+}
+
+#[test]
+fn test_read_cookie() {
+    let headers = request_headers("theme=dark; session=abc123; csrf_token=xyz", None);
+
+    assert_eq!(cookies::read_cookie(&headers, cookies::SESSION_COOKIE), Some("abc123"));
+    assert_eq!(cookies::read_cookie(&headers, cookies::CSRF_COOKIE), Some("xyz"));
+    assert_eq!(cookies::read_cookie(&headers, cookies::REFRESH_COOKIE), None);
+    assert!(cookies::has_session_cookie(&headers));
+    assert!(!cookies::has_session_cookie(&request_headers("theme=dark", None)));
+}
+
+#[test]
+fn test_csrf_double_submit() {
+    let matching = request_headers("session=abc; csrf_token=t0k3n", Some("t0k3n"));
+    assert!(cookies::check_csrf(&Method::POST, &matching).is_ok());
+
+    let missing = request_headers("session=abc; csrf_token=t0k3n", None);
+    assert!(matches!(
+        cookies::check_csrf(&Method::POST, &missing),
+        Err(AuthError::InvalidCsrfToken)
+    ));
+
+    let wrong = request_headers("session=abc; csrf_token=t0k3n", Some("forged"));
+    assert!(matches!(
+        cookies::check_csrf(&Method::DELETE, &wrong),
+        Err(AuthError::InvalidCsrfToken)
+    ));
+
+    // Safe methods don't change state and pass without a token
+    assert!(cookies::check_csrf(&Method::GET, &missing).is_ok());
+}
+
+#[test]
+fn test_session_mode_parsing() {
+    assert_eq!("cookie".parse::<SessionMode>(), Ok(SessionMode::Cookie));
+    assert_eq!(" Both ".parse::<SessionMode>(), Ok(SessionMode::Both));
+    assert!("jwt".parse::<SessionMode>().is_err());
+
+    assert!(SessionMode::Both.accepts_bearer() && SessionMode::Both.accepts_cookie());
+    assert!(!SessionMode::Cookie.accepts_bearer());
+    assert!(!SessionMode::Bearer.accepts_cookie());
+}
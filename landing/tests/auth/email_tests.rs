@@ -0,0 +1,152 @@
+use landing::server::auth::{
+    is_valid_email, AuthProvider, DomainBlocklist, EmailAddress, EmailPolicy, InMemoryAuthProvider,
+};
+use landing::server::{AuthConfig, AuthError};
+
+use crate::common::{attach_mailer, PASSWORD};
+
+#[test]
+fn test_address_syntax() {
+    for valid in [
+        "bob@example.com",
+        "Bob.Smith@Example.COM",
+        "bob+news@example.com",
+        "o'brien@example.co.uk",
+        "x@a-b.io",
+        "  padded@example.com ",
+    ] {
+        assert!(is_valid_email(valid), "{valid} should parse");
+    }
+
+    for invalid in [
+        "",
+        "bob",
+        "@example.com",
+        "bob@",
+        "bob@localhost",
+        ".bob@example.com",
+        "bob.@example.com",
+        "bo..b@example.com",
+        "bob smith@example.com",
+        "\"bob\"@example.com",
+        "bob@exa_mple.com",
+        "bob@-example.com",
+        "bob@example..com",
+        "bob@example.com.",
+        "bob@192.168.0.1",
+        "bob@[192.168.0.1]",
+        "bob@example@example.com",
+    ] {
+        assert!(!is_valid_email(invalid), "{invalid} should be rejected");
+    }
+}
+
+#[test]
+fn test_length_limits() {
+    let domain = "example.com";
+    assert!(is_valid_email(&format!("{}@{domain}", "a".repeat(64))));
+    assert!(!is_valid_email(&format!("{}@{domain}", "a".repeat(65))));
+
+    assert!(is_valid_email(&format!("bob@{}.com", "a".repeat(63))));
+    assert!(!is_valid_email(&format!("bob@{}.com", "a".repeat(64))));
+
+    // Each part is within its own limit, the whole address isn't
+    let long_domain = ["a".repeat(50), "b".repeat(50), "c".repeat(50), "d".repeat(50)].join(".");
+    assert!(is_valid_email(&format!("bob@{long_domain}")));
+    assert!(!is_valid_email(&format!("{}@{long_domain}", "a".repeat(64))));
+}
+
+#[test]
+fn test_idn_domains() -> anyhow::Result<()> {
+    let address = EmailAddress::parse("Juergen@Bücher.Example")?;
+    assert_eq!(address.domain(), "xn--bcher-kva.example");
+    assert_eq!(address.to_string(), "Juergen@xn--bcher-kva.example");
+    assert_eq!(EmailAddress::parse("juergen@xn--bcher-kva.example")?.domain(), address.domain());
+
+    // Local parts stay ASCII
+    assert!(EmailAddress::parse("jürgen@example.com").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_canonical_form() {
+    let policy = EmailPolicy::default();
+    assert_eq!(policy.canonicalize("Bob.Smith@Example.COM"), "bob.smith@example.com");
+    assert_eq!(policy.canonicalize("bob+news@example.com"), "bob+news@example.com");
+    assert_eq!(policy.canonicalize("bob@bücher.example"), "bob@xn--bcher-kva.example");
+
+    let folding = EmailPolicy { fold_plus_tags: true, ..Default::default() };
+    assert_eq!(folding.canonicalize("Bob+News@example.com"), "bob@example.com");
+    // A tag with no name before it is kept
+    assert_eq!(folding.canonicalize("+news@example.com"), "+news@example.com");
+
+    // Unparseable input is still normalised for use as a lockout key
+    assert_eq!(policy.canonicalize(" Not An Email "), "not an email");
+}
+
+#[test]
+fn test_blocked_domains() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("disposable-{}.txt", uuid::Uuid::new_v4()));
+    std::fs::write(&path, "# throwaway inboxes\nmailinator.com\n\nTempMail.dev\n")?;
+    let list = DomainBlocklist::load(&path)?;
+    std::fs::remove_file(&path)?;
+    assert_eq!(list.len(), 2);
+
+    let policy = EmailPolicy::default().with_blocked_domains(list);
+    assert!(matches!(policy.parse_new("bob@mailinator.com"), Err(AuthError::DisposableEmail)));
+    assert!(matches!(policy.parse_new("bob@eu.tempmail.dev"), Err(AuthError::DisposableEmail)));
+    assert!(policy.parse_new("bob@notmailinator.com").is_ok());
+    assert!(matches!(policy.parse_new("bob"), Err(AuthError::InvalidEmail)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_accounts_unique_by_canonical_email() -> anyhow::Result<()> {
+    let config = AuthConfig {
+        require_email_verification: false,
+        email_policy: EmailPolicy { fold_plus_tags: true, ..Default::default() },
+        ..Default::default()
+    };
+    let provider = InMemoryAuthProvider::new().with_config(config);
+
+    provider.register("Wendy+signup@Example.com", PASSWORD, None).await?;
+    for duplicate in ["wendy+signup@example.com", "WENDY@example.com", "wendy+other@EXAMPLE.COM"] {
+        assert!(matches!(
+            provider.register(duplicate, PASSWORD, None).await,
+            Err(AuthError::UserExists)
+        ));
+    }
+
+    // Stored as given apart from the domain, found under any variant
+    let user = provider.authenticate("wendy@example.com", PASSWORD).await?;
+    assert_eq!(user.email, "Wendy+signup@example.com");
+    assert_eq!(provider.authenticate(" WENDY+x@example.com", PASSWORD).await?.id, user.id);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_disposable_domains_cannot_sign_up() -> anyhow::Result<()> {
+    let config = AuthConfig {
+        email_policy: EmailPolicy::default()
+            .with_blocked_domains(["mailinator.com"].into_iter().collect()),
+        ..Default::default()
+    };
+    let (provider, mailer) = attach_mailer(InMemoryAuthProvider::new().with_config(config));
+
+    assert!(matches!(
+        provider.register("xavier@mailinator.com", PASSWORD, None).await,
+        Err(AuthError::DisposableEmail)
+    ));
+    assert!(matches!(
+        provider.request_magic_link("xavier@mailinator.com").await,
+        Err(AuthError::DisposableEmail)
+    ));
+    assert!(mailer.last_to("xavier@mailinator.com").is_none());
+
+    provider.register("xavier@example.com", PASSWORD, None).await?;
+
+    Ok(())
+}
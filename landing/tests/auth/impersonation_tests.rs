@@ -0,0 +1,162 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use landing::server::audit::RequestMeta;
+use landing::server::auth::{
+    AuthContext, AuthProvider, InMemoryAuthProvider, ManualClock, MemorySigningKeyStore, TokenSigner,
+};
+use landing::server::{
+    AuditEventType, AuditFilter, AuditRecorder, AuthError, MemoryAuditRecorder, TokenKey, User,
+    ADMIN_ROLE,
+};
+use uuid::Uuid;
+
+use crate::common::{attach_mailer, register_verified, PASSWORD};
+
+struct Setup {
+    clock: Arc<ManualClock>,
+    provider: Arc<InMemoryAuthProvider>,
+    audit: Arc<MemoryAuditRecorder>,
+    admin: User,
+    customer: User,
+}
+
+/// A verified admin and a verified customer, both signed in
+async fn setup() -> anyhow::Result<Setup> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let (provider, mailer) = attach_mailer(InMemoryAuthProvider::with_clock(clock.clone()));
+    let provider = Arc::new(provider);
+    for email in ["support@example.com", "carol@example.com"] {
+        register_verified(&provider, &mailer, email).await?;
+    }
+
+    let support = provider.authenticate("support@example.com", PASSWORD).await?;
+    provider.assign_role(support.id, ADMIN_ROLE).await?;
+    let admin = provider.authenticate("support@example.com", PASSWORD).await?;
+    let customer = provider.authenticate("carol@example.com", PASSWORD).await?;
+    let audit = Arc::new(MemoryAuditRecorder::new());
+    Ok(Setup { clock, provider, audit, admin, customer })
+}
+
+#[tokio::test]
+async fn test_only_admins_impersonate_non_admins() -> anyhow::Result<()> {
+    let Setup { provider, admin, customer, .. } = setup().await?;
+    let auth = AuthContext::new(provider.clone());
+
+    assert!(matches!(
+        auth.impersonate_for(&customer, admin.id).await,
+        Err(AuthError::Forbidden)
+    ));
+    assert!(matches!(
+        auth.impersonate_for(&admin, admin.id).await,
+        Err(AuthError::Forbidden)
+    ));
+    assert!(matches!(
+        auth.impersonate_for(&admin, Uuid::new_v4()).await,
+        Err(AuthError::UserNotFound)
+    ));
+
+    // No chaining from an impersonation session either
+    let session = auth.impersonate_for(&admin, customer.id).await?;
+    assert!(matches!(
+        auth.impersonate_for(&session, admin.id).await,
+        Err(AuthError::Forbidden)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_impersonation_session_is_marked() -> anyhow::Result<()> {
+    let Setup { clock, provider, admin, customer, .. } = setup().await?;
+    let auth = AuthContext::new(provider.clone());
+
+    let session = auth.impersonate_for(&admin, customer.id).await?;
+    assert_eq!(session.id, customer.id);
+    assert_eq!(session.impersonator_id, Some(admin.id));
+    assert!(session.refresh_token.is_none());
+
+    let resolved = auth.resolve_session(&session.bearer_token).await?;
+    assert_eq!(resolved.email, "carol@example.com");
+    assert_eq!(resolved.impersonator_id, Some(admin.id));
+    assert!(!resolved.has_role(ADMIN_ROLE));
+
+    // The customer's own session isn't affected
+    assert!(auth.resolve_session(&customer.bearer_token).await?.impersonator_id.is_none());
+
+    clock.advance(Duration::minutes(31));
+    assert!(auth.resolve_session(&session.bearer_token).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stop_impersonating_restores_admin() -> anyhow::Result<()> {
+    let Setup { provider, customer, .. } = setup().await?;
+    let auth = AuthContext::new(provider.clone());
+    auth.login("support@example.com", PASSWORD).await?;
+    let admin = auth.current_user().await.unwrap();
+
+    auth.impersonate(customer.id).await?;
+    let acting = auth.current_user().await.unwrap();
+    assert_eq!(acting.id, customer.id);
+    assert_eq!(acting.impersonator_id, Some(admin.id));
+
+    auth.stop_impersonating().await?;
+    assert_eq!(auth.current_user().await, Some(admin));
+    assert!(auth.resolve_session(&acting.bearer_token).await.is_err());
+    assert!(matches!(auth.stop_impersonating().await, Err(AuthError::Forbidden)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_impersonated_actions_are_tagged() -> anyhow::Result<()> {
+    let Setup { provider, audit, admin, customer, .. } = setup().await?;
+    let auth = AuthContext::new(provider.clone()).with_audit(audit.clone());
+
+    let session = auth.impersonate_for(&admin, customer.id).await?;
+    // As `auth_middleware` does for requests on the session
+    let meta = RequestMeta { impersonator_id: session.impersonator_id, ..Default::default() };
+    meta.scope(auth.revoke_other_sessions_for(&session)).await?;
+    auth.stop_impersonating_for(&session).await?;
+
+    let events = audit
+        .query(&AuditFilter { user_id: Some(customer.id), ..Default::default() })
+        .await?;
+    let kinds: Vec<_> = events.iter().map(|e| e.event_type.as_str()).collect();
+    assert_eq!(kinds, ["impersonation_ended", "session_revoked", "impersonation_started"]);
+    for event in &events {
+        assert_eq!(event.actor_id, Some(admin.id));
+        assert_eq!(event.impersonator_id, Some(admin.id));
+    }
+
+    // The customer's own actions stay theirs
+    let own = provider.authenticate("carol@example.com", PASSWORD).await?;
+    auth.sign_out(&own.bearer_token).await?;
+    let logout = audit
+        .query(&AuditFilter { event_type: Some(AuditEventType::Logout), ..Default::default() })
+        .await?;
+    assert_eq!(logout[0].actor_id, Some(customer.id));
+    assert!(logout[0].impersonator_id.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_signed_token_carries_impersonator() -> anyhow::Result<()> {
+    let Setup { clock, provider, admin, customer, .. } = setup().await?;
+    let signer = TokenSigner::new(Arc::new(MemorySigningKeyStore::new()), TokenKey::new("test-master-key"))
+        .with_reload_interval(Duration::zero())
+        .with_clock(clock.clone());
+    let auth = AuthContext::new(provider.clone()).with_signed_tokens(Arc::new(signer));
+
+    let session = auth.impersonate_for(&admin, customer.id).await?;
+    assert_eq!(session.bearer_token.split('.').count(), 3);
+    assert_eq!(auth.resolve_session(&session.bearer_token).await?.impersonator_id, Some(admin.id));
+
+    auth.stop_impersonating_for(&session).await?;
+    assert!(auth.resolve_session(&session.bearer_token).await.is_err());
+
+    Ok(())
+}
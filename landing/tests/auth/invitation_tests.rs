@@ -0,0 +1,232 @@
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::Extension;
+use chrono::{Duration, Utc};
+use landing::server::api::auth::router;
+use landing::server::auth::{AuthProvider, Clock, InMemoryAuthProvider, ManualClock};
+use landing::server::{AuthConfig, AuthContext, AuthError};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::common::{attach_mailer, PASSWORD};
+
+fn invite_only() -> AuthConfig {
+    AuthConfig { invite_only: true, ..Default::default() }
+}
+
+#[tokio::test]
+async fn test_invite_only_requires_a_valid_code() -> anyhow::Result<()> {
+    let provider = InMemoryAuthProvider::new().with_config(invite_only());
+    let invite = provider.create_invitation(None, Some(1), None, None).await?;
+
+    for missing in [None, Some(""), Some("  ")] {
+        assert!(matches!(
+            provider.register("quinn@example.com", PASSWORD, missing).await,
+            Err(AuthError::InvitationRequired)
+        ));
+    }
+    assert!(matches!(
+        provider.register("quinn@example.com", PASSWORD, Some("not-a-code")).await,
+        Err(AuthError::InvalidInvitation)
+    ));
+
+    // Whitespace picked up when copying the code is ignored
+    provider.register("quinn@example.com", PASSWORD, Some(&format!(" {} ", invite.code))).await?;
+    assert!(matches!(
+        provider.register("rory@example.com", PASSWORD, Some(&invite.code)).await,
+        Err(AuthError::InvalidInvitation)
+    ));
+
+    let invitations = provider.list_invitations().await?;
+    let [listed] = invitations.as_slice() else {
+        panic!("expected one invitation");
+    };
+    assert_eq!(listed.use_count, 1);
+    assert!(!listed.is_usable(Utc::now()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_multi_use_and_email_bound_codes() -> anyhow::Result<()> {
+    let provider = InMemoryAuthProvider::new().with_config(invite_only());
+    let inviter = Uuid::new_v4();
+
+    let team = provider.create_invitation(None, Some(2), None, Some(inviter)).await?;
+    assert_eq!(team.invitation.invited_by, Some(inviter));
+    provider.register("sam@example.com", PASSWORD, Some(&team.code)).await?;
+    provider.register("sky@example.com", PASSWORD, Some(&team.code)).await?;
+    assert!(matches!(
+        provider.register("sol@example.com", PASSWORD, Some(&team.code)).await,
+        Err(AuthError::InvalidInvitation)
+    ));
+
+    let unlimited = provider.create_invitation(None, None, None, None).await?;
+    for email in ["u1@example.com", "u2@example.com", "u3@example.com"] {
+        provider.register(email, PASSWORD, Some(&unlimited.code)).await?;
+    }
+
+    // Bound to the canonical address, so case doesn't matter
+    let personal = provider.create_invitation(Some("Pat@Example.com"), Some(1), None, None).await?;
+    assert_eq!(personal.invitation.email.as_deref(), Some("pat@example.com"));
+    assert!(matches!(
+        provider.register("kim@example.com", PASSWORD, Some(&personal.code)).await,
+        Err(AuthError::InvalidInvitation)
+    ));
+    provider.register("PAT@example.com", PASSWORD, Some(&personal.code)).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_expired_and_revoked_codes() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let provider = InMemoryAuthProvider::with_clock(clock.clone()).with_config(invite_only());
+
+    let expiring = provider
+        .create_invitation(None, Some(5), Some(clock.now() + Duration::days(1)), None)
+        .await?;
+    let revoked = provider.create_invitation(None, Some(5), None, None).await?;
+
+    assert!(provider.revoke_invitation(revoked.invitation.id).await?);
+    assert!(!provider.revoke_invitation(revoked.invitation.id).await?);
+    assert!(!provider.revoke_invitation(Uuid::new_v4()).await?);
+    assert!(matches!(
+        provider.register("tess@example.com", PASSWORD, Some(&revoked.code)).await,
+        Err(AuthError::InvalidInvitation)
+    ));
+
+    provider.register("tess@example.com", PASSWORD, Some(&expiring.code)).await?;
+    clock.advance(Duration::days(2));
+    assert!(matches!(
+        provider.register("theo@example.com", PASSWORD, Some(&expiring.code)).await,
+        Err(AuthError::InvalidInvitation)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_signup_keeps_the_code() -> anyhow::Result<()> {
+    let provider = InMemoryAuthProvider::new().with_config(invite_only());
+    let existing = provider.create_invitation(None, None, None, None).await?;
+    provider.register("uma@example.com", PASSWORD, Some(&existing.code)).await?;
+
+    let invite = provider.create_invitation(None, Some(1), None, None).await?;
+    assert!(matches!(
+        provider.register("vic@example.com", "short", Some(&invite.code)).await,
+        Err(AuthError::PasswordRequirements { .. })
+    ));
+    assert!(matches!(
+        provider.register("uma@example.com", PASSWORD, Some(&invite.code)).await,
+        Err(AuthError::UserExists)
+    ));
+    provider.register("vic@example.com", PASSWORD, Some(&invite.code)).await?;
+
+    // Codes are still honoured, and count, when invite_only is off
+    let open = InMemoryAuthProvider::new();
+    let invite = open.create_invitation(None, Some(1), None, None).await?;
+    open.register("wren@example.com", PASSWORD, None).await?;
+    open.register("xiu@example.com", PASSWORD, Some(&invite.code)).await?;
+    assert!(matches!(
+        open.register("yara@example.com", PASSWORD, Some(&invite.code)).await,
+        Err(AuthError::InvalidInvitation)
+    ));
+
+    assert!(matches!(
+        open.create_invitation(None, Some(0), None, None).await,
+        Err(AuthError::InvalidInvitation)
+    ));
+    assert!(matches!(
+        open.create_invitation(Some("not-an-email"), Some(1), None, None).await,
+        Err(AuthError::InvalidEmail)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invite_only_blocks_passwordless_signup() -> anyhow::Result<()> {
+    let (provider, mailer) = attach_mailer(InMemoryAuthProvider::new().with_config(invite_only()));
+    let invite = provider.create_invitation(None, Some(1), None, None).await?;
+
+    // No link for an unknown address, without saying so
+    provider.request_magic_link("zed@example.com").await?;
+    assert!(mailer.last_to("zed@example.com").is_none());
+
+    // Registration closed altogether wins over a valid code
+    let closed = InMemoryAuthProvider::new().with_config(AuthConfig {
+        open_registration: false,
+        ..invite_only()
+    });
+    let closed_invite = closed.create_invitation(None, Some(1), None, None).await?;
+    assert!(matches!(
+        closed.register("zed@example.com", PASSWORD, Some(&closed_invite.code)).await,
+        Err(AuthError::RegistrationClosed)
+    ));
+
+    provider.register("zed@example.com", PASSWORD, Some(&invite.code)).await?;
+    provider.request_magic_link("zed@example.com").await?;
+    let mail = mailer.last_to("zed@example.com").expect("sign-in link");
+    assert!(mail.body.contains("/magic-link/"));
+
+    Ok(())
+}
+
+/// Serves the auth routes on a local port, returning the register URL
+async fn serve(auth: AuthContext) -> anyhow::Result<String> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/auth/register", listener.local_addr()?);
+    let app = router().layer(Extension(Arc::new(auth)));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    Ok(url)
+}
+
+#[tokio::test]
+async fn test_register_endpoint() -> anyhow::Result<()> {
+    let provider = Arc::new(InMemoryAuthProvider::new().with_config(invite_only()));
+    let invite = provider.create_invitation(None, Some(1), None, None).await?;
+    let url = serve(AuthContext::new(provider)).await?;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(&url)
+        .json(&json!({ "email": "uri@example.com", "password": PASSWORD }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = client
+        .post(&url)
+        .json(&json!({ "email": "uri@example.com", "password": PASSWORD, "invite_code": "not-a-code" }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = client
+        .post(&url)
+        .json(&json!({ "email": "uri@example.com", "password": "short", "invite_code": invite.code }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json().await?;
+    assert!(body["violations"].as_array().is_some_and(|v| !v.is_empty()));
+
+    let response = client
+        .post(&url)
+        .json(&json!({ "email": "uri@example.com", "password": PASSWORD, "invite_code": invite.code }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // Used up
+    let response = client
+        .post(&url)
+        .json(&json!({ "email": "vera@example.com", "password": PASSWORD, "invite_code": invite.code }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    Ok(())
+}
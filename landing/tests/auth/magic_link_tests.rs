@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use landing::server::auth::{AuthContext, AuthProvider, InMemoryAuthProvider, LoginStatus, ManualClock};
+use landing::server::{AuthConfig, AuthError};
+
+use crate::common::{attach_mailer, mailed_token, provider_with_mailer, PASSWORD};
+
+#[tokio::test]
+async fn test_magic_link_creates_account_once() -> anyhow::Result<()> {
+    let (provider, mailer) = provider_with_mailer();
+
+    let secret = provider.request_magic_link("uma@example.com").await?;
+    let token = mailed_token(&mailer, "uma@example.com");
+    let user = provider.login_with_magic_link(&token, &secret).await?;
+    assert_eq!(user.email, "uma@example.com");
+    assert!(provider.validate_session(&user.bearer_token).await.is_ok());
+
+    // Single use
+    assert!(matches!(
+        provider.login_with_magic_link(&token, &secret).await,
+        Err(AuthError::InvalidToken)
+    ));
+
+    // The next link signs in to the same account
+    let secret = provider.request_magic_link("uma@example.com").await?;
+    let again = provider
+        .login_with_magic_link(&mailed_token(&mailer, "uma@example.com"), &secret)
+        .await?;
+    assert_eq!(again.id, user.id);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_magic_link_bound_to_requesting_browser() -> anyhow::Result<()> {
+    let (provider, mailer) = provider_with_mailer();
+    let secret = provider.request_magic_link("vic@example.com").await?;
+    let token = mailed_token(&mailer, "vic@example.com");
+
+    // Someone who only has the link gets nowhere
+    let intruder = provider.request_magic_link("mallory@example.com").await?;
+    assert!(matches!(
+        provider.login_with_magic_link(&token, &intruder).await,
+        Err(AuthError::InvalidToken)
+    ));
+
+    // ...and doesn't burn it for its owner
+    provider.login_with_magic_link(&token, &secret).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_magic_link_expires() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let (provider, mailer) = attach_mailer(InMemoryAuthProvider::with_clock(clock.clone()));
+
+    let secret = provider.request_magic_link("wes@example.com").await?;
+    clock.advance(Duration::minutes(16));
+    assert!(matches!(
+        provider
+            .login_with_magic_link(&mailed_token(&mailer, "wes@example.com"), &secret)
+            .await,
+        Err(AuthError::InvalidToken)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_closed_registration_only_signs_in_existing_accounts() -> anyhow::Result<()> {
+    let (open, mailer) = provider_with_mailer();
+    open.register("xena@example.com", PASSWORD, None).await?;
+
+    let config = AuthConfig { open_registration: false, ..AuthConfig::default() };
+    let (closed, closed_mailer) = attach_mailer(InMemoryAuthProvider::new().with_config(config));
+    assert!(matches!(
+        closed.register("xena@example.com", PASSWORD, None).await,
+        Err(AuthError::RegistrationClosed)
+    ));
+
+    // Unknown addresses get the same answer but no email
+    closed.request_magic_link("yuri@example.com").await?;
+    assert!(closed_mailer.last_to("yuri@example.com").is_none());
+
+    // An existing, unverified account is verified by the link
+    let secret = open.request_magic_link("xena@example.com").await?;
+    open.login_with_magic_link(&mailed_token(&mailer, "xena@example.com"), &secret).await?;
+    let user = open.authenticate("xena@example.com", PASSWORD).await?;
+    assert_eq!(user.email, "xena@example.com");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_context_magic_link_login() -> anyhow::Result<()> {
+    let (provider, mailer) = provider_with_mailer();
+    let auth = AuthContext::new(Arc::new(provider));
+
+    // Nothing requested from this context yet
+    assert!(matches!(
+        auth.login_with_magic_link("anything").await,
+        Err(AuthError::InvalidToken)
+    ));
+
+    auth.request_magic_link("zoe@example.com").await?;
+    let status = auth.login_with_magic_link(&mailed_token(&mailer, "zoe@example.com")).await?;
+    assert_eq!(status, LoginStatus::Complete);
+    assert_eq!(auth.current_user().await.unwrap().email, "zoe@example.com");
+
+    Ok(())
+}
@@ -0,0 +1,19 @@
+//! Integration tests for the auth subsystem, built as one test binary
+
+mod common;
+mod user_tests;
+mod provider_tests;
+mod session_cache_tests;
+mod rate_limit_tests;
+mod cookie_tests;
+mod password_tests;
+mod password_policy_tests;
+mod email_tests;
+mod oidc_tests;
+mod passkey_tests;
+mod magic_link_tests;
+mod api_key_tests;
+mod signed_token_tests;
+mod impersonation_tests;
+mod token_store_tests;
+mod invitation_tests;
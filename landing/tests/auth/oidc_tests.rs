@@ -0,0 +1,307 @@
+use axum::extract::{Form, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use base64::engine::general_purpose;
+use base64::Engine;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use landing::server::auth::oidc::MAX_PENDING_LOGINS;
+use landing::server::auth::{cookies, AuthProvider, InMemoryAuthProvider, OidcClient, OidcOutcome};
+use landing::server::{AuthContext, AuthError, MemoryMailer, OidcConfig, SessionConfig};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::common::{provider_with_mailer, register_verified, PASSWORD};
+
+const CLIENT_ID: &str = "landing-test";
+const KEY_ID: &str = "test-key";
+/// Modulus of `fixtures/oidc_rsa_key.pem`, exponent 65537
+const KEY_MODULUS: &str = "xhDulYxzgG1Um76CJMXADyHbg62qbdH-dCB5pTuES2Po5m0Qx-H3vA_u1MxUSoOOXH1UEtQgLatZ-jOANsg_vsV4WIjC2xZupXbFFn0bjMtOenCssgVeysHQCR8b5vBiMerjOAPXkeNjZ20vBEM9uKjkUBTZV9R27ub3iHMnOjhXqKkwduNVK5ux8yaev9nINYUeQRS8USz_8tw-7df8Xh4SEwxw8al6lOc7yJ7D3qzP7mhQUOl88d3VzufdnspJQCbwsObpE1lxbW3VAZ2OucpKh3PzDKI94qwTj347nS0BNP1BTiR5XUzx0G48O_rdtMWktftMEL7CKdDlk4vbgw";
+
+/// What the mock issuer hands out for one authorization code
+#[derive(Clone)]
+struct Grant {
+    code_challenge: String,
+    nonce: String,
+    subject: String,
+    email: String,
+    email_verified: bool,
+    audience: String,
+}
+
+#[derive(Clone)]
+struct MockIssuer {
+    issuer: String,
+    grants: Arc<Mutex<HashMap<String, Grant>>>,
+}
+
+impl MockIssuer {
+    /// Serves discovery, JWKS and a token endpoint on a local port
+    async fn start() -> anyhow::Result<Self> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let mock = Self {
+            issuer: format!("http://{}", listener.local_addr()?),
+            grants: Arc::new(Mutex::new(HashMap::new())),
+        };
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .with_state(mock.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        Ok(mock)
+    }
+
+    fn config(&self) -> OidcConfig {
+        OidcConfig::new(&self.issuer, CLIENT_ID, "http://localhost:8080/api/auth/oidc/callback")
+    }
+
+    fn grant(&self, code: &str, grant: Grant) {
+        self.grants.lock().unwrap().insert(code.to_owned(), grant);
+    }
+}
+
+async fn discovery(State(mock): State<MockIssuer>) -> Json<serde_json::Value> {
+    Json(json!({
+        "issuer": mock.issuer,
+        "authorization_endpoint": format!("{}/authorize", mock.issuer),
+        "token_endpoint": format!("{}/token", mock.issuer),
+        "jwks_uri": format!("{}/jwks", mock.issuer),
+    }))
+}
+
+async fn jwks() -> Json<serde_json::Value> {
+    Json(json!({
+        "keys": [{ "kty": "RSA", "use": "sig", "alg": "RS256", "kid": KEY_ID, "n": KEY_MODULUS, "e": "AQAB" }]
+    }))
+}
+
+/// Redeems a code, refusing it unless the PKCE verifier matches
+async fn token(
+    State(mock): State<MockIssuer>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let grant = mock
+        .grants
+        .lock()
+        .unwrap()
+        .remove(&form["code"])
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let challenge = general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(form["code_verifier"].as_bytes()));
+    if challenge != grant.code_challenge || form["client_id"] != CLIENT_ID {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let claims = json!({
+        "iss": mock.issuer,
+        "aud": grant.audience,
+        "sub": grant.subject,
+        "iat": now,
+        "exp": now + 300,
+        "nonce": grant.nonce,
+        "email": grant.email,
+        "email_verified": grant.email_verified,
+    });
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some(KEY_ID.to_owned());
+    let key = EncodingKey::from_rsa_pem(include_bytes!("fixtures/oidc_rsa_key.pem")).unwrap();
+    let id_token = encode(&header, &claims, &key).unwrap();
+
+    Ok(Json(json!({ "access_token": "unused", "token_type": "Bearer", "id_token": id_token })))
+}
+
+async fn setup() -> anyhow::Result<(MockIssuer, Arc<InMemoryAuthProvider>, Arc<MemoryMailer>, AuthContext)> {
+    let mock = MockIssuer::start().await?;
+    let (provider, mailer) = provider_with_mailer();
+    let provider = Arc::new(provider);
+    let oidc = OidcClient::discover(mock.config()).await?;
+    let auth = AuthContext::new(provider.clone()).with_oidc(Arc::new(oidc));
+    Ok((mock, provider, mailer, auth))
+}
+
+/// Plays the browser's trip to the provider: reads state, nonce and PKCE
+/// challenge off the authorization URL and has the mock issue a code
+fn authorize(mock: &MockIssuer, url: &str, subject: &str, email: &str, email_verified: bool) -> (String, Grant) {
+    let url = reqwest::Url::parse(url).unwrap();
+    let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+    assert_eq!(params["code_challenge_method"], "S256");
+    assert_eq!(params["client_id"], CLIENT_ID);
+    assert!(params["scope"].split(' ').any(|s| s == "openid"));
+
+    let grant = Grant {
+        code_challenge: params["code_challenge"].clone(),
+        nonce: params["nonce"].clone(),
+        subject: subject.to_owned(),
+        email: email.to_owned(),
+        email_verified,
+        audience: CLIENT_ID.to_owned(),
+    };
+    (params["state"].clone(), grant)
+}
+
+async fn oidc_login(
+    mock: &MockIssuer,
+    auth: &AuthContext,
+    subject: &str,
+    email: &str,
+    email_verified: bool,
+) -> Result<OidcOutcome, AuthError> {
+    let request = auth.begin_oidc_login(None)?;
+    let (state, grant) = authorize(mock, &request.url, subject, email, email_verified);
+    assert_eq!(state, request.state);
+    let code = format!("code-{subject}-{state}");
+    mock.grant(&code, grant);
+    auth.complete_oidc_login(&code, &state).await
+}
+
+#[tokio::test]
+async fn test_oidc_login_creates_and_reuses_account() -> anyhow::Result<()> {
+    let (mock, provider, _, auth) = setup().await?;
+
+    let OidcOutcome::SignedIn(first) = oidc_login(&mock, &auth, "idp-user-1", "dana@example.com", true).await? else {
+        panic!("expected a login");
+    };
+    assert_eq!(first.email, "dana@example.com");
+    assert!(auth.validate_session(&first.bearer_token).await.is_ok());
+
+    // Same subject, new email at the provider: still the same account
+    let OidcOutcome::SignedIn(second) = oidc_login(&mock, &auth, "idp-user-1", "dana@new.example.com", true).await? else {
+        panic!("expected a login");
+    };
+    assert_eq!(second.id, first.id);
+
+    // The account has no password that could be guessed
+    assert!(matches!(
+        provider.authenticate("dana@example.com", "!").await,
+        Err(AuthError::AuthenticationFailed)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_oidc_state_is_single_use() -> anyhow::Result<()> {
+    let (mock, _, _, auth) = setup().await?;
+
+    let request = auth.begin_oidc_login(None)?;
+    let (state, grant) = authorize(&mock, &request.url, "idp-user-2", "erin@example.com", true);
+    mock.grant("code-1", grant.clone());
+    auth.complete_oidc_login("code-1", &state).await?;
+
+    mock.grant("code-2", grant);
+    assert!(matches!(
+        auth.complete_oidc_login("code-2", &state).await,
+        Err(AuthError::InvalidToken)
+    ));
+    assert!(matches!(
+        auth.complete_oidc_login("code-2", "never-issued").await,
+        Err(AuthError::InvalidToken)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_oidc_pending_logins_are_capped() -> anyhow::Result<()> {
+    let (mock, _, _, auth) = setup().await?;
+
+    let oldest = auth.begin_oidc_login(None)?;
+    let mut latest = None;
+    for _ in 0..MAX_PENDING_LOGINS {
+        latest = Some(auth.begin_oidc_login(None)?);
+    }
+
+    // The oldest login gave way, the newest ones still work
+    assert!(matches!(
+        auth.complete_oidc_login("code-1", &oldest.state).await,
+        Err(AuthError::InvalidToken)
+    ));
+    let latest = latest.unwrap();
+    let (state, grant) = authorize(&mock, &latest.url, "idp-user-9", "ivy@example.com", true);
+    mock.grant("code-2", grant);
+    auth.complete_oidc_login("code-2", &state).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_oidc_rejects_mismatched_nonce_audience_and_pkce() -> anyhow::Result<()> {
+    let (mock, _, _, auth) = setup().await?;
+
+    let tampered: [fn(&mut Grant); 3] = [
+        |grant| grant.nonce = "replayed-nonce".into(),
+        |grant| grant.audience = "some-other-client".into(),
+        |grant| grant.code_challenge = "not-the-challenge".into(),
+    ];
+    for tamper in tampered {
+        let request = auth.begin_oidc_login(None)?;
+        let (state, mut grant) = authorize(&mock, &request.url, "idp-user-3", "finn@example.com", true);
+        tamper(&mut grant);
+        mock.grant("code", grant);
+        assert!(matches!(
+            auth.complete_oidc_login("code", &state).await,
+            Err(AuthError::IdentityProvider(_))
+        ));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_oidc_links_signed_in_user() -> anyhow::Result<()> {
+    let (mock, provider, mailer, auth) = setup().await?;
+    register_verified(&provider, &mailer, "gwen@example.com").await?;
+    let user = provider.authenticate("gwen@example.com", PASSWORD).await?;
+
+    // The provider knows her under another address
+    let request = auth.begin_oidc_login(Some(&user))?;
+    let (state, grant) = authorize(&mock, &request.url, "idp-user-4", "gwen@elsewhere.example", false);
+    mock.grant("link-code", grant);
+    let outcome = auth.complete_oidc_login("link-code", &state).await?;
+    assert!(matches!(outcome, OidcOutcome::Linked(id) if id == user.id));
+
+    let OidcOutcome::SignedIn(signed_in) = oidc_login(&mock, &auth, "idp-user-4", "gwen@elsewhere.example", false).await? else {
+        panic!("expected a login");
+    };
+    assert_eq!(signed_in.id, user.id);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_oidc_only_auto_links_verified_email() -> anyhow::Result<()> {
+    let (mock, provider, mailer, auth) = setup().await?;
+    register_verified(&provider, &mailer, "hana@example.com").await?;
+    let user = provider.authenticate("hana@example.com", PASSWORD).await?;
+
+    // Anyone can claim an address the provider hasn't checked
+    assert!(matches!(
+        oidc_login(&mock, &auth, "idp-user-5", "hana@example.com", false).await,
+        Err(AuthError::UserExists)
+    ));
+
+    let OidcOutcome::SignedIn(signed_in) = oidc_login(&mock, &auth, "idp-user-5", "hana@example.com", true).await? else {
+        panic!("expected a login");
+    };
+    assert_eq!(signed_in.id, user.id);
+
+    Ok(())
+}
+
+#[test]
+fn test_oidc_state_cookie() {
+    let config = SessionConfig::default();
+    let set = cookies::oidc_state_cookie("abc", chrono::Duration::minutes(10), &config).unwrap();
+    let set = set.to_str().unwrap();
+    assert!(set.starts_with("oidc_state=abc;"));
+    assert!(set.contains("Path=/api/auth/oidc") && set.contains("HttpOnly") && set.contains("SameSite=Lax"));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::COOKIE, HeaderValue::from_static("oidc_state=abc"));
+    assert!(cookies::check_oidc_state(&headers, "abc").is_ok());
+    assert!(matches!(cookies::check_oidc_state(&headers, "abd"), Err(AuthError::InvalidToken)));
+    assert!(cookies::check_oidc_state(&HeaderMap::new(), "abc").is_err());
+}
@@ -0,0 +1,210 @@
+use ciborium::value::Value;
+use landing::server::auth::webauthn::{self, COSE_ES256};
+use landing::server::auth::{AuthContext, AuthProvider, InMemoryAuthProvider};
+use landing::server::{
+    AuthError, PasskeyAssertion, PasskeyCreationOptions, PasskeyCredential, PasskeyRequestOptions,
+};
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::common::signed_in;
+
+const ORIGIN: &str = "http://localhost:8080";
+const CREDENTIAL_ID: &[u8] = b"software-credential";
+
+/// Plays the browser and a platform authenticator holding one ES256 key
+struct SoftwareAuthenticator {
+    key: SigningKey,
+    origin: String,
+    sign_count: u32,
+}
+
+impl SoftwareAuthenticator {
+    fn new() -> Self {
+        Self {
+            key: SigningKey::from_slice(&[7u8; 32]).unwrap(),
+            origin: ORIGIN.to_owned(),
+            sign_count: 0,
+        }
+    }
+
+    fn client_data(&self, kind: &str, challenge: &str) -> Vec<u8> {
+        json!({ "type": kind, "challenge": challenge, "origin": self.origin, "crossOrigin": false })
+            .to_string()
+            .into_bytes()
+    }
+
+    /// RP ID hash, user present + verified flags and the counter
+    fn authenticator_data(&self, rp_id: &str, flags: u8) -> Vec<u8> {
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&self.sign_count.to_be_bytes());
+        data
+    }
+
+    fn cose_key(&self) -> Vec<u8> {
+        let point = self.key.verifying_key().to_encoded_point(false);
+        let key = Value::Map(vec![
+            (Value::Integer(1.into()), Value::Integer(2.into())),
+            (Value::Integer(3.into()), Value::Integer(COSE_ES256.into())),
+            (Value::Integer((-1).into()), Value::Integer(1.into())),
+            (Value::Integer((-2).into()), Value::Bytes(point.x().unwrap().to_vec())),
+            (Value::Integer((-3).into()), Value::Bytes(point.y().unwrap().to_vec())),
+        ]);
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&key, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn create(&mut self, options: &PasskeyCreationOptions) -> PasskeyCredential {
+        self.sign_count += 1;
+        let mut auth_data = self.authenticator_data(&options.rp_id, 0x45);
+        auth_data.extend_from_slice(&[0u8; 16]);
+        auth_data.extend_from_slice(&(CREDENTIAL_ID.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(CREDENTIAL_ID);
+        auth_data.extend_from_slice(&self.cose_key());
+
+        let attestation = Value::Map(vec![
+            (Value::Text("fmt".into()), Value::Text("none".into())),
+            (Value::Text("attStmt".into()), Value::Map(Vec::new())),
+            (Value::Text("authData".into()), Value::Bytes(auth_data)),
+        ]);
+        let mut attestation_object = Vec::new();
+        ciborium::into_writer(&attestation, &mut attestation_object).unwrap();
+
+        PasskeyCredential {
+            credential_id: webauthn::encode(CREDENTIAL_ID),
+            client_data_json: webauthn::encode(&self.client_data("webauthn.create", &options.challenge)),
+            attestation_object: webauthn::encode(&attestation_object),
+        }
+    }
+
+    fn get(&mut self, options: &PasskeyRequestOptions, user_handle: &str) -> PasskeyAssertion {
+        self.sign_count += 1;
+        let auth_data = self.authenticator_data(&options.rp_id, 0x05);
+        let client_data = self.client_data("webauthn.get", &options.challenge);
+
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(&Sha256::digest(&client_data));
+        let signature: Signature = self.key.sign(&signed);
+
+        PasskeyAssertion {
+            credential_id: webauthn::encode(CREDENTIAL_ID),
+            client_data_json: webauthn::encode(&client_data),
+            authenticator_data: webauthn::encode(&auth_data),
+            signature: webauthn::encode(signature.to_der().as_bytes()),
+            user_handle: Some(user_handle.to_owned()),
+        }
+    }
+}
+
+/// Registers the authenticator's key and returns the user handle to sign in with
+async fn register(auth: &AuthContext, authenticator: &mut SoftwareAuthenticator) -> anyhow::Result<String> {
+    let options = auth.begin_passkey_registration().await?;
+    assert_eq!(options.algorithms[0], COSE_ES256);
+    let credential = authenticator.create(&options);
+    auth.finish_passkey_registration("Laptop", &credential).await?;
+    Ok(options.user_id)
+}
+
+#[tokio::test]
+async fn test_register_and_sign_in_with_passkey() -> anyhow::Result<()> {
+    let (_, auth) = signed_in(InMemoryAuthProvider::new(), "paula@example.com").await?;
+    let mut authenticator = SoftwareAuthenticator::new();
+    let handle = register(&auth, &mut authenticator).await?;
+    let user = auth.current_user().await.unwrap();
+    auth.logout().await?;
+
+    let options = auth.begin_passkey_login().await?;
+    auth.login_with_passkey(&authenticator.get(&options, &handle)).await?;
+
+    let signed_in = auth.current_user().await.unwrap();
+    assert_eq!(signed_in.id, user.id);
+    assert!(auth.validate_session(&signed_in.bearer_token).await.is_ok());
+    assert!(auth.list_passkeys().await?[0].last_used_at.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_passkey_challenge_is_single_use() -> anyhow::Result<()> {
+    let (provider, auth) = signed_in(InMemoryAuthProvider::new(), "quinn@example.com").await?;
+    let mut authenticator = SoftwareAuthenticator::new();
+    let handle = register(&auth, &mut authenticator).await?;
+
+    let options = provider.begin_passkey_authentication().await?;
+    let assertion = authenticator.get(&options, &handle);
+    provider.authenticate_passkey(&assertion).await?;
+
+    assert!(matches!(
+        provider.authenticate_passkey(&assertion).await,
+        Err(AuthError::InvalidToken)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_passkey_rejects_wrong_origin() -> anyhow::Result<()> {
+    let (provider, auth) = signed_in(InMemoryAuthProvider::new(), "rosa@example.com").await?;
+    let mut authenticator = SoftwareAuthenticator::new();
+    let handle = register(&auth, &mut authenticator).await?;
+
+    authenticator.origin = "https://evil.example.com".to_owned();
+    let options = provider.begin_passkey_authentication().await?;
+    assert!(matches!(
+        provider.authenticate_passkey(&authenticator.get(&options, &handle)).await,
+        Err(AuthError::InvalidPasskey)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_passkey_rejects_counter_regression() -> anyhow::Result<()> {
+    let (provider, auth) = signed_in(InMemoryAuthProvider::new(), "sam@example.com").await?;
+    let mut authenticator = SoftwareAuthenticator::new();
+    let handle = register(&auth, &mut authenticator).await?;
+
+    let options = provider.begin_passkey_authentication().await?;
+    provider.authenticate_passkey(&authenticator.get(&options, &handle)).await?;
+
+    // A cloned authenticator reports a counter the server has already seen
+    authenticator.sign_count -= 1;
+    let options = provider.begin_passkey_authentication().await?;
+    assert!(matches!(
+        provider.authenticate_passkey(&authenticator.get(&options, &handle)).await,
+        Err(AuthError::InvalidPasskey)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_list_and_delete_passkeys() -> anyhow::Result<()> {
+    let (_, auth) = signed_in(InMemoryAuthProvider::new(), "tara@example.com").await?;
+    let mut authenticator = SoftwareAuthenticator::new();
+    let handle = register(&auth, &mut authenticator).await?;
+
+    let passkeys = auth.list_passkeys().await?;
+    assert_eq!(passkeys.len(), 1);
+    assert_eq!(passkeys[0].name, "Laptop");
+
+    // The next registration excludes the credential already there
+    let options = auth.begin_passkey_registration().await?;
+    assert_eq!(options.exclude_credentials, vec![webauthn::encode(CREDENTIAL_ID)]);
+
+    assert!(auth.delete_passkey(passkeys[0].id).await?);
+    assert!(!auth.delete_passkey(passkeys[0].id).await?);
+    assert!(auth.list_passkeys().await?.is_empty());
+
+    let options = auth.begin_passkey_login().await?;
+    assert!(matches!(
+        auth.login_with_passkey(&authenticator.get(&options, &handle)).await,
+        Err(AuthError::AuthenticationFailed)
+    ));
+
+    Ok(())
+}
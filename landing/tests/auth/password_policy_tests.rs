@@ -0,0 +1,126 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use landing::server::auth::{
+    estimate_strength, AuthProvider, BreachedPasswords, InMemoryAuthProvider, PasswordPolicy,
+};
+use landing::server::{AuthConfig, AuthError, PasswordViolation};
+
+use crate::common::PASSWORD;
+
+#[test]
+fn test_policy_reports_every_violation() {
+    let policy = PasswordPolicy::default();
+    assert!(policy.check(PASSWORD, "bob@example.com").is_empty());
+
+    assert_eq!(
+        policy.check("short", "bob@example.com"),
+        [
+            PasswordViolation::TooShort { min: 8 },
+            PasswordViolation::MissingUppercase,
+            PasswordViolation::MissingDigit,
+            PasswordViolation::TooWeak { score: 0, min: 2 },
+        ]
+    );
+
+    let strict = PasswordPolicy { max_length: 16, require_symbol: true, ..Default::default() };
+    assert_eq!(
+        strict.check("Sup3rSecretSup3rSecret", "bob@example.com"),
+        [PasswordViolation::TooLong { max: 16 }, PasswordViolation::MissingSymbol]
+    );
+    assert!(strict.check("Sup3r#Secret", "bob@example.com").is_empty());
+}
+
+#[test]
+fn test_policy_rejects_personal_info() {
+    let policy = PasswordPolicy::default();
+
+    for password in ["Dana.Smith99", "xSMITH2024x", "Dana1990Rules"] {
+        assert!(
+            policy.check(password, "dana.smith@example.com").contains(&PasswordViolation::ContainsPersonalInfo),
+            "{password} should be rejected"
+        );
+    }
+    // Words shorter than 3 characters and the domain don't count
+    assert!(policy.check("Jo7Example", "jo@example.com").is_empty());
+
+    let relaxed = PasswordPolicy { reject_personal_info: false, ..Default::default() };
+    assert!(relaxed.check("Dana.Smith99", "dana.smith@example.com").is_empty());
+}
+
+#[test]
+fn test_breached_passwords_file() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("breached-{}.txt", uuid::Uuid::new_v4()));
+    std::fs::write(&path, "# top passwords\nPassword1\n\n  Welcome123  \n")?;
+    let list = BreachedPasswords::load(&path)?;
+    std::fs::remove_file(&path)?;
+    assert_eq!(list.len(), 2);
+
+    let policy = PasswordPolicy::default().with_breached_passwords(list);
+    assert!(policy.check("Welcome123", "").contains(&PasswordViolation::Breached));
+    // Case variations are caught too
+    assert!(policy.check("pASSWORD1", "").contains(&PasswordViolation::Breached));
+    assert!(policy.check(PASSWORD, "").is_empty());
+
+    assert!(BreachedPasswords::load("/nonexistent/breached.txt").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_strength_estimate() {
+    assert_eq!(estimate_strength(""), 0);
+    assert_eq!(estimate_strength("aaaaaaaaaaaa"), 0);
+    // Runs count for little however long they are
+    assert_eq!(estimate_strength("Abcdefghijklmnop1"), 1);
+    assert!(estimate_strength(PASSWORD) >= 3);
+    assert_eq!(estimate_strength("Xq7#mVr2!pLz9&Tw"), 4);
+}
+
+#[tokio::test]
+async fn test_provider_enforces_configured_policy() -> anyhow::Result<()> {
+    let config = AuthConfig {
+        password_policy: PasswordPolicy { min_length: 12, ..Default::default() }
+            .with_breached_passwords(["Correct7Horse"].into_iter().collect()),
+        ..Default::default()
+    };
+    let provider = InMemoryAuthProvider::new().with_config(config);
+
+    let Err(AuthError::PasswordRequirements { violations }) =
+        provider.register("olga@example.com", "Correct7Horse", None).await
+    else {
+        panic!("breached password accepted");
+    };
+    assert_eq!(violations, [PasswordViolation::Breached]);
+
+    let Err(AuthError::PasswordRequirements { violations }) =
+        provider.register("olga@example.com", PASSWORD, None).await
+    else {
+        panic!("short password accepted");
+    };
+    assert_eq!(violations, [PasswordViolation::TooShort { min: 12 }]);
+
+    provider.register("olga@example.com", "Sup3rSecretPlus", None).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_violations_in_error_response() -> anyhow::Result<()> {
+    let error = AuthError::PasswordRequirements {
+        violations: vec![PasswordViolation::TooShort { min: 8 }, PasswordViolation::MissingDigit],
+    };
+    assert_eq!(
+        error.to_string(),
+        "Password requirements not met: must be at least 8 characters, must contain a digit"
+    );
+
+    let response = error.into_response();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    let json: serde_json::Value = serde_json::from_slice(&body)?;
+    assert_eq!(
+        json["violations"],
+        serde_json::json!([{ "code": "too_short", "min": 8 }, { "code": "missing_digit" }])
+    );
+
+    Ok(())
+}
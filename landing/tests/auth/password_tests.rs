@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use landing::server::auth::{
+    Argon2Hasher, AuthProvider, BcryptHasher, InMemoryAuthProvider, PasswordHasher,
+};
+
+use crate::common::{attach_mailer, register_verified, PASSWORD};
+
+fn cheap_argon2() -> Argon2Hasher {
+    Argon2Hasher::new(8, 1, 1).unwrap()
+}
+
+#[test]
+fn test_argon2_hashes_and_verifies() -> anyhow::Result<()> {
+    let hasher = cheap_argon2();
+    let hash = hasher.hash(PASSWORD)?;
+
+    assert!(hash.starts_with("$argon2id$v=19$m=8,t=1,p=1$"));
+    assert!(hasher.verify(PASSWORD, &hash));
+    assert!(!hasher.verify("Wr0ngPassword", &hash));
+    // Salted, so the same password never hashes the same way twice
+    assert_ne!(hash, hasher.hash(PASSWORD)?);
+    assert!(!hasher.needs_rehash(&hash));
+
+    Ok(())
+}
+
+#[test]
+fn test_outdated_hashes_need_rehash() -> anyhow::Result<()> {
+    let hasher = cheap_argon2();
+
+    let bcrypt_hash = BcryptHasher::new(4).hash(PASSWORD)?;
+    assert!(hasher.verify(PASSWORD, &bcrypt_hash));
+    assert!(hasher.needs_rehash(&bcrypt_hash));
+
+    let weaker = Argon2Hasher::new(8, 1, 1)?.hash(PASSWORD)?;
+    assert!(Argon2Hasher::new(16, 2, 1)?.needs_rehash(&weaker));
+
+    assert!(hasher.needs_rehash("not a hash"));
+    assert!(!hasher.verify(PASSWORD, "not a hash"));
+
+    let bcrypt = BcryptHasher::new(5);
+    assert!(bcrypt.needs_rehash(&bcrypt_hash));
+    assert!(!BcryptHasher::new(4).needs_rehash(&bcrypt_hash));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_login_upgrades_bcrypt_hash() -> anyhow::Result<()> {
+    let provider = InMemoryAuthProvider::new().with_hasher(Arc::new(BcryptHasher::new(4)));
+    let (provider, mailer) = attach_mailer(provider);
+    register_verified(&provider, &mailer, "legacy@example.com").await?;
+    assert!(provider.password_hash("legacy@example.com").await.unwrap().starts_with("$2"));
+
+    // Deploying Argon2id: the old hash still works and is replaced at login
+    let provider = provider.with_hasher(Arc::new(cheap_argon2()));
+    assert!(provider.authenticate("legacy@example.com", "Wr0ngPassword").await.is_err());
+    assert!(provider.password_hash("legacy@example.com").await.unwrap().starts_with("$2"));
+
+    provider.authenticate("legacy@example.com", PASSWORD).await?;
+    let upgraded = provider.password_hash("legacy@example.com").await.unwrap();
+    assert!(upgraded.starts_with("$argon2id$"));
+    provider.authenticate("legacy@example.com", PASSWORD).await?;
+    assert_eq!(provider.password_hash("legacy@example.com").await.unwrap(), upgraded);
+
+    Ok(())
+}
@@ -0,0 +1,629 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use landing::server::auth::{
+    hash_session_token, hash_token, totp, AuthContext, AuthProvider, InMemoryAuthProvider,
+    LoginStatus, ManualClock,
+};
+use landing::server::audit::RequestMeta;
+use landing::server::{
+    AuditEventType, AuditFilter, AuditRecorder, AuthConfig, AuthError, MemoryAuditRecorder,
+    TokenKey, ADMIN_ROLE, DEFAULT_ROLE,
+};
+use uuid::Uuid;
+
+use crate::common::{
+    attach_mailer, mailed_token, provider_with_mailer, register_verified, signed_in, PASSWORD,
+};
+
+#[tokio::test]
+async fn test_register_and_login() -> anyhow::Result<()> {
+    let (provider, mailer) = provider_with_mailer();
+
+    register_verified(&provider, &mailer, "alice@example.com").await?;
+    let user = provider.authenticate("alice@example.com", PASSWORD).await?;
+
+    assert_eq!(user.email, "alice@example.com");
+    let validated = provider.validate_session(&user.bearer_token).await?;
+    assert_eq!(validated.id, user.id);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_rules() {
+    let provider = InMemoryAuthProvider::new();
+
+    assert!(matches!(
+        provider.register("not-an-email", PASSWORD, None).await,
+        Err(AuthError::InvalidEmail)
+    ));
+    assert!(matches!(
+        provider.register("bob@example.com", "short", None).await,
+        Err(AuthError::PasswordRequirements { .. })
+    ));
+
+    provider.register("bob@example.com", PASSWORD, None).await.unwrap();
+    assert!(matches!(
+        provider.register("bob@example.com", PASSWORD, None).await,
+        Err(AuthError::UserExists)
+    ));
+}
+
+#[tokio::test]
+async fn test_wrong_password() {
+    let (provider, mailer) = provider_with_mailer();
+    register_verified(&provider, &mailer, "carol@example.com").await.unwrap();
+
+    assert!(matches!(
+        provider.authenticate("carol@example.com", "Wr0ngPassword").await,
+        Err(AuthError::AuthenticationFailed)
+    ));
+    assert!(matches!(
+        provider.authenticate("nobody@example.com", PASSWORD).await,
+        Err(AuthError::AuthenticationFailed)
+    ));
+}
+
+#[tokio::test]
+async fn test_session_expiry() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let provider =
+        InMemoryAuthProvider::with_clock(clock.clone()).with_session_ttl(Duration::hours(1));
+    let (provider, mailer) = attach_mailer(provider);
+    register_verified(&provider, &mailer, "dave@example.com").await?;
+    let user = provider.authenticate("dave@example.com", PASSWORD).await?;
+
+    clock.advance(Duration::minutes(59));
+    assert!(provider.validate_session(&user.bearer_token).await.is_ok());
+
+    clock.advance(Duration::minutes(2));
+    assert!(matches!(
+        provider.validate_session(&user.bearer_token).await,
+        Err(AuthError::InvalidSession)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_context_login_logout() -> anyhow::Result<()> {
+    let (provider, mailer) = provider_with_mailer();
+    register_verified(&provider, &mailer, "erin@example.com").await?;
+    let provider = Arc::new(provider);
+    let auth = AuthContext::new(provider.clone());
+
+    auth.login("erin@example.com", PASSWORD).await?;
+    let user = auth.current_user().await.expect("user should be logged in");
+
+    auth.logout().await?;
+    assert!(auth.current_user().await.is_none());
+    assert!(provider.validate_session(&user.bearer_token).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unverified_login_refused() -> anyhow::Result<()> {
+    let (provider, mailer) = provider_with_mailer();
+    provider.register("frank@example.com", PASSWORD, None).await?;
+
+    assert!(matches!(
+        provider.authenticate("frank@example.com", PASSWORD).await,
+        Err(AuthError::EmailNotVerified)
+    ));
+
+    let token = mailed_token(&mailer, "frank@example.com");
+    provider.verify_email(&token).await?;
+    assert!(provider.authenticate("frank@example.com", PASSWORD).await.is_ok());
+
+    // Links are single-use
+    assert!(matches!(
+        provider.verify_email(&token).await,
+        Err(AuthError::InvalidToken)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_resend_verification_rate_limited() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let (provider, mailer) = attach_mailer(InMemoryAuthProvider::with_clock(clock.clone()));
+    provider.register("grace@example.com", PASSWORD, None).await?;
+
+    assert!(matches!(
+        provider.resend_verification("grace@example.com").await,
+        Err(AuthError::RateLimited)
+    ));
+
+    clock.advance(Duration::minutes(2));
+    provider.resend_verification("grace@example.com").await?;
+    assert_eq!(mailer.outbox().len(), 2);
+
+    // Unknown addresses look exactly like success
+    provider.resend_verification("nobody@example.com").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_password_reset() -> anyhow::Result<()> {
+    let (provider, mailer) = provider_with_mailer();
+    register_verified(&provider, &mailer, "heidi@example.com").await?;
+    let session = provider.authenticate("heidi@example.com", PASSWORD).await?;
+
+    provider.request_password_reset("heidi@example.com").await?;
+    let token = mailed_token(&mailer, "heidi@example.com");
+
+    // Policy is enforced and doesn't consume the token
+    assert!(matches!(
+        provider.reset_password(&token, "weak").await,
+        Err(AuthError::PasswordRequirements { .. })
+    ));
+
+    provider.reset_password(&token, "N3wPassword").await?;
+    assert!(provider.validate_session(&session.bearer_token).await.is_err());
+    assert!(provider.authenticate("heidi@example.com", PASSWORD).await.is_err());
+    assert!(provider.authenticate("heidi@example.com", "N3wPassword").await.is_ok());
+
+    assert!(matches!(
+        provider.reset_password(&token, "An0therPassword").await,
+        Err(AuthError::InvalidToken)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_password_reset_expiry() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let (provider, mailer) = attach_mailer(InMemoryAuthProvider::with_clock(clock.clone()));
+    register_verified(&provider, &mailer, "ivan@example.com").await?;
+
+    provider.request_password_reset("ivan@example.com").await?;
+    let token = mailed_token(&mailer, "ivan@example.com");
+
+    clock.advance(Duration::hours(1));
+    assert!(matches!(
+        provider.reset_password(&token, "N3wPassword").await,
+        Err(AuthError::InvalidToken)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_change_password() -> anyhow::Result<()> {
+    let (provider, auth) = signed_in(InMemoryAuthProvider::new(), "oscar@example.com").await?;
+    let phone = provider.authenticate("oscar@example.com", PASSWORD).await?;
+
+    assert!(matches!(
+        auth.change_password("Wr0ngPassword", "N3wPassword").await,
+        Err(AuthError::AuthenticationFailed)
+    ));
+    assert!(matches!(
+        auth.change_password(PASSWORD, "weak").await,
+        Err(AuthError::PasswordRequirements { .. })
+    ));
+    assert!(provider.validate_session(&phone.bearer_token).await.is_ok());
+
+    auth.change_password(PASSWORD, "N3wPassword").await?;
+
+    // Every other device is signed out, refresh token included
+    assert!(provider.validate_session(&phone.bearer_token).await.is_err());
+    assert!(provider.refresh(phone.refresh_token.as_deref().unwrap()).await.is_err());
+    let current = auth.current_user().await.expect("still signed in");
+    assert!(provider.validate_session(&current.bearer_token).await.is_ok());
+
+    assert!(provider.authenticate("oscar@example.com", PASSWORD).await.is_err());
+    assert!(provider.authenticate("oscar@example.com", "N3wPassword").await.is_ok());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_roles() -> anyhow::Result<()> {
+    let (provider, mailer) = provider_with_mailer();
+    register_verified(&provider, &mailer, "judy@example.com").await?;
+    let user = provider.authenticate("judy@example.com", PASSWORD).await?;
+
+    assert_eq!(user.roles, vec![DEFAULT_ROLE.to_string()]);
+    assert!(user.has_permission("posts:write"));
+    assert!(!user.has_permission("users:manage"));
+
+    provider.assign_role(user.id, ADMIN_ROLE).await?;
+    let user = provider.validate_session(&user.bearer_token).await?;
+    assert!(user.has_role(ADMIN_ROLE));
+    assert!(user.has_permission("users:manage"));
+
+    provider.revoke_role(user.id, ADMIN_ROLE).await?;
+    let user = provider.validate_session(&user.bearer_token).await?;
+    assert!(!user.has_role(ADMIN_ROLE));
+
+    assert!(matches!(
+        provider.assign_role(user.id, "superuser").await,
+        Err(AuthError::UnknownRole)
+    ));
+    assert!(matches!(
+        provider.assign_role(Uuid::new_v4(), ADMIN_ROLE).await,
+        Err(AuthError::UserNotFound)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_totp_two_step_login() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let (provider, mailer) = attach_mailer(InMemoryAuthProvider::with_clock(clock.clone()));
+    register_verified(&provider, &mailer, "ken@example.com").await?;
+    let user = provider.authenticate("ken@example.com", PASSWORD).await?;
+
+    let enrollment = provider.begin_totp_enrollment(user.id).await?;
+    assert!(enrollment.otpauth_uri.starts_with("otpauth://totp/"));
+    assert!(matches!(
+        provider.confirm_totp_enrollment(user.id, "000000").await,
+        Err(AuthError::InvalidMfaCode)
+    ));
+    let code = totp::generate_code(&enrollment.secret, clock.now())?;
+    let recovery_codes = provider.confirm_totp_enrollment(user.id, &code).await?;
+    assert_eq!(recovery_codes.len(), totp::RECOVERY_CODE_COUNT);
+
+    let Err(AuthError::MfaRequired { challenge }) =
+        provider.authenticate("ken@example.com", PASSWORD).await
+    else {
+        panic!("password alone should not be enough");
+    };
+
+    // The code used to enroll can't be replayed
+    assert!(matches!(
+        provider.verify_mfa(&challenge, &code).await,
+        Err(AuthError::InvalidMfaCode)
+    ));
+
+    clock.advance(Duration::seconds(30));
+    let code = totp::generate_code(&enrollment.secret, clock.now())?;
+    let session = provider.verify_mfa(&challenge, &code).await?;
+    assert!(provider.validate_session(&session.bearer_token).await.is_ok());
+
+    // Challenges are single-use
+    assert!(matches!(
+        provider.verify_mfa(&challenge, &code).await,
+        Err(AuthError::InvalidToken)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_codes_count_toward_lockout() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let config = AuthConfig { lockout_threshold: 3, login_backoff_after: 3, ..Default::default() };
+    let provider = InMemoryAuthProvider::with_clock(clock.clone()).with_config(config);
+    let (provider, mailer) = attach_mailer(provider);
+    register_verified(&provider, &mailer, "lola@example.com").await?;
+    let user = provider.authenticate("lola@example.com", PASSWORD).await?;
+    let enrollment = provider.begin_totp_enrollment(user.id).await?;
+    let code = totp::generate_code(&enrollment.secret, clock.now())?;
+    provider.confirm_totp_enrollment(user.id, &code).await?;
+
+    let Err(AuthError::MfaRequired { challenge }) =
+        provider.authenticate("lola@example.com", PASSWORD).await
+    else {
+        panic!("password alone should not be enough");
+    };
+    for _ in 0..3 {
+        assert!(matches!(
+            provider.verify_mfa(&challenge, "000000").await,
+            Err(AuthError::InvalidMfaCode)
+        ));
+    }
+
+    // Locked even with the right code, and for the password step too
+    clock.advance(Duration::seconds(30));
+    let code = totp::generate_code(&enrollment.secret, clock.now())?;
+    assert!(matches!(
+        provider.verify_mfa(&challenge, &code).await,
+        Err(AuthError::AccountLocked)
+    ));
+    assert!(matches!(
+        provider.authenticate("lola@example.com", PASSWORD).await,
+        Err(AuthError::AccountLocked)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_password_alone_keeps_the_streak() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let config = AuthConfig { lockout_threshold: 3, login_backoff_after: 3, ..Default::default() };
+    let provider = InMemoryAuthProvider::with_clock(clock.clone()).with_config(config);
+    let (provider, mailer) = attach_mailer(provider);
+    register_verified(&provider, &mailer, "mona@example.com").await?;
+    let user = provider.authenticate("mona@example.com", PASSWORD).await?;
+    let enrollment = provider.begin_totp_enrollment(user.id).await?;
+    let code = totp::generate_code(&enrollment.secret, clock.now())?;
+    provider.confirm_totp_enrollment(user.id, &code).await?;
+
+    let challenge = || {
+        let provider = &provider;
+        async move {
+            match provider.authenticate("mona@example.com", PASSWORD).await {
+                Err(AuthError::MfaRequired { challenge }) => challenge,
+                other => panic!("expected a second-factor challenge, got {other:?}"),
+            }
+        }
+    };
+    let miss = |challenge: String| {
+        let provider = &provider;
+        async move {
+            assert!(matches!(
+                provider.verify_mfa(&challenge, "000000").await,
+                Err(AuthError::InvalidMfaCode)
+            ));
+        }
+    };
+
+    // Passing the second factor ends the streak
+    let first = challenge().await;
+    miss(first.clone()).await;
+    miss(first).await;
+    let second = challenge().await;
+    clock.advance(Duration::seconds(30));
+    let code = totp::generate_code(&enrollment.secret, clock.now())?;
+    provider.verify_mfa(&second, &code).await?;
+
+    // The password alone doesn't
+    let third = challenge().await;
+    miss(third.clone()).await;
+    miss(third).await;
+    miss(challenge().await).await;
+    assert!(matches!(
+        provider.authenticate("mona@example.com", PASSWORD).await,
+        Err(AuthError::AccountLocked)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_recovery_codes_single_use() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let (provider, mailer) = attach_mailer(InMemoryAuthProvider::with_clock(clock.clone()));
+    let provider = Arc::new(provider);
+    register_verified(&provider, &mailer, "leo@example.com").await?;
+    let user = provider.authenticate("leo@example.com", PASSWORD).await?;
+    let enrollment = provider.begin_totp_enrollment(user.id).await?;
+    let code = totp::generate_code(&enrollment.secret, clock.now())?;
+    let recovery_codes = provider.confirm_totp_enrollment(user.id, &code).await?;
+
+    let auth = AuthContext::new(provider.clone());
+    assert_eq!(auth.login("leo@example.com", PASSWORD).await?, LoginStatus::MfaPending);
+    assert!(auth.current_user().await.is_none());
+    auth.complete_mfa(&recovery_codes[0].to_uppercase()).await?;
+    assert!(auth.current_user().await.is_some());
+
+    assert_eq!(auth.login("leo@example.com", PASSWORD).await?, LoginStatus::MfaPending);
+    assert!(matches!(
+        auth.complete_mfa(&recovery_codes[0]).await,
+        Err(AuthError::InvalidMfaCode)
+    ));
+    auth.complete_mfa(&recovery_codes[1]).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_audit_events() -> anyhow::Result<()> {
+    let (provider, mailer) = provider_with_mailer();
+    let audit = Arc::new(MemoryAuditRecorder::new());
+    let auth = AuthContext::new(Arc::new(provider)).with_audit(audit.clone());
+
+    auth.register("mallory@example.com", PASSWORD, None).await?;
+    auth.verify_email(&mailed_token(&mailer, "mallory@example.com")).await?;
+    assert!(auth.login("mallory@example.com", "Wr0ngPassword").await.is_err());
+
+    let meta = RequestMeta {
+        ip: Some("203.0.113.7".into()),
+        user_agent: Some("test-agent".into()),
+        ..Default::default()
+    };
+    meta.scope(auth.login("mallory@example.com", PASSWORD)).await?;
+    let user = auth.current_user().await.unwrap();
+    auth.assign_role(user.id, ADMIN_ROLE).await?;
+    auth.logout().await?;
+
+    assert_eq!(
+        audit.event_types(),
+        ["register", "login_failure", "login_success", "role_change", "logout"]
+    );
+
+    let failures = audit
+        .query(&AuditFilter {
+            event_type: Some(AuditEventType::LoginFailure),
+            ..Default::default()
+        })
+        .await?;
+    assert_eq!(failures[0].metadata["email"], "mallory@example.com");
+
+    let events = audit
+        .query(&AuditFilter { user_id: Some(user.id), ..Default::default() })
+        .await?;
+    let kinds: Vec<_> = events.iter().map(|e| e.event_type.as_str()).collect();
+    assert_eq!(kinds, ["logout", "role_change", "login_success"]);
+    assert_eq!(events[2].ip.as_deref(), Some("203.0.113.7"));
+    assert_eq!(events[2].user_agent.as_deref(), Some("test-agent"));
+    assert_eq!(events[1].actor_id, Some(user.id));
+    assert_eq!(events[1].metadata["role"], ADMIN_ROLE);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lockout_and_backoff() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let (provider, mailer) = attach_mailer(InMemoryAuthProvider::with_clock(clock.clone()));
+    register_verified(&provider, &mailer, "quentin@example.com").await?;
+
+    let attempt = |email: &'static str, password: &'static str| {
+        let provider = &provider;
+        async move { provider.authenticate(email, password).await }
+    };
+
+    // Three free failures, then each one doubles the wait: 1s, 2s, 4s...
+    for _ in 0..3 {
+        assert!(matches!(
+            attempt("quentin@example.com", "Wr0ngPassword").await,
+            Err(AuthError::AuthenticationFailed)
+        ));
+    }
+    assert!(matches!(
+        attempt("quentin@example.com", PASSWORD).await,
+        Err(AuthError::AccountLocked)
+    ));
+    clock.advance(Duration::seconds(1));
+    assert!(matches!(
+        attempt("quentin@example.com", "Wr0ngPassword").await,
+        Err(AuthError::AuthenticationFailed)
+    ));
+    clock.advance(Duration::seconds(1));
+    assert!(matches!(
+        attempt("quentin@example.com", PASSWORD).await,
+        Err(AuthError::AccountLocked)
+    ));
+    clock.advance(Duration::seconds(1));
+    assert!(attempt("quentin@example.com", PASSWORD).await.is_ok());
+
+    // Unknown emails lock exactly the same way
+    for _ in 0..10 {
+        assert!(attempt("nobody@example.com", PASSWORD).await.is_err());
+        clock.advance(Duration::minutes(10));
+    }
+    assert!(matches!(
+        attempt("nobody@example.com", PASSWORD).await,
+        Err(AuthError::AccountLocked)
+    ));
+    assert!(provider.unlock_account("Nobody@example.com").await?);
+    assert!(matches!(
+        attempt("nobody@example.com", PASSWORD).await,
+        Err(AuthError::AuthenticationFailed)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_refresh_rotation_and_reuse() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let provider =
+        InMemoryAuthProvider::with_clock(clock.clone()).with_session_ttl(Duration::minutes(15));
+    let (provider, mailer) = attach_mailer(provider);
+    register_verified(&provider, &mailer, "rupert@example.com").await?;
+    let first = provider.authenticate("rupert@example.com", PASSWORD).await?;
+    let first_refresh = first.refresh_token.clone().expect("login should issue a refresh token");
+
+    clock.advance(Duration::minutes(14));
+    let second = provider.refresh(&first_refresh).await?;
+    let second_refresh = second.refresh_token.clone().unwrap();
+    assert_ne!(second_refresh, first_refresh);
+    assert!(provider.validate_session(&first.bearer_token).await.is_err());
+
+    // The new access token outlives the one it replaced
+    clock.advance(Duration::minutes(10));
+    assert!(provider.validate_session(&second.bearer_token).await.is_ok());
+
+    // Replaying the spent token kills the whole family
+    assert!(matches!(
+        provider.refresh(&first_refresh).await,
+        Err(AuthError::TokenReuseDetected { user_id, .. }) if user_id == first.id
+    ));
+    assert!(provider.validate_session(&second.bearer_token).await.is_err());
+    assert!(matches!(
+        provider.refresh(&second_refresh).await,
+        Err(AuthError::InvalidToken)
+    ));
+
+    // Other logins are unaffected, and logging out retires their refresh token
+    let other = provider.authenticate("rupert@example.com", PASSWORD).await?;
+    provider.logout(&other.bearer_token).await?;
+    assert!(provider.refresh(other.refresh_token.as_deref().unwrap()).await.is_err());
+
+    Ok(())
+}
+
+
+#[tokio::test]
+async fn test_list_and_revoke_sessions() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let provider =
+        InMemoryAuthProvider::with_clock(clock.clone()).with_session_ttl(Duration::minutes(15));
+    let (provider, mailer) = attach_mailer(provider);
+    let provider = Arc::new(provider);
+    register_verified(&provider, &mailer, "sofia@example.com").await?;
+    let auth = AuthContext::new(provider.clone());
+
+    let laptop_meta = RequestMeta {
+        ip: Some("203.0.113.7".into()),
+        user_agent: Some("Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0".into()),
+        ..Default::default()
+    };
+    let laptop = laptop_meta.scope(provider.authenticate("sofia@example.com", PASSWORD)).await?;
+    let phone = provider.authenticate("sofia@example.com", PASSWORD).await?;
+    let tablet = provider.authenticate("sofia@example.com", PASSWORD).await?;
+
+    let sessions = auth.sessions_for(&laptop).await?;
+    assert_eq!(sessions.len(), 3);
+    let this = sessions.iter().find(|s| s.current).expect("caller's session is flagged");
+    assert_eq!(this.device_label, "Firefox on Linux");
+    assert_eq!(this.ip.as_deref(), Some("203.0.113.7"));
+
+    // An expired access token still lists while its refresh token can renew it,
+    // and refreshing keeps the session's id
+    clock.advance(Duration::minutes(20));
+    let laptop = provider.refresh(laptop.refresh_token.as_deref().unwrap()).await?;
+    let after_refresh = auth.sessions_for(&laptop).await?;
+    assert_eq!(after_refresh.len(), 3);
+    assert_eq!(after_refresh.iter().find(|s| s.current).unwrap().id, this.id);
+
+    // Sessions can only be revoked by their owner
+    let phone_id = auth
+        .sessions_for(&phone)
+        .await?
+        .into_iter()
+        .find(|s| s.current)
+        .unwrap()
+        .id;
+    register_verified(&provider, &mailer, "mallory@example.com").await?;
+    let mallory = provider.authenticate("mallory@example.com", PASSWORD).await?;
+    assert!(!auth.revoke_session_for(&mallory, phone_id).await?);
+
+    assert!(auth.revoke_session_for(&laptop, phone_id).await?);
+    assert!(provider.refresh(phone.refresh_token.as_deref().unwrap()).await.is_err());
+    assert_eq!(auth.sessions_for(&laptop).await?.len(), 2);
+
+    assert_eq!(auth.revoke_other_sessions_for(&laptop).await?, 1);
+    assert!(provider.refresh(tablet.refresh_token.as_deref().unwrap()).await.is_err());
+    assert!(provider.validate_session(&laptop.bearer_token).await.is_ok());
+
+    // A password change signs out every device
+    provider.request_password_reset("sofia@example.com").await?;
+    provider
+        .reset_password(&mailed_token(&mailer, "sofia@example.com"), "N3wPassword")
+        .await?;
+    assert!(auth.sessions_for(&laptop).await?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_session_token_hash_is_keyed() {
+    let key = TokenKey::new("first key");
+    let digest = hash_session_token("token", &key);
+
+    assert_eq!(digest, hash_session_token("token", &TokenKey::new("first key")));
+    assert_ne!(digest, hash_session_token("token", &TokenKey::new("second key")));
+    assert_ne!(digest, hash_token("token"));
+    assert!(!format!("{key:?}").contains("first key"));
+}
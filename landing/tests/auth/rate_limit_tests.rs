@@ -0,0 +1,164 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use axum::http::HeaderMap;
+use chrono::{Duration, Utc};
+use landing::server::auth::rate_limit::MAX_BUCKETS;
+use landing::server::auth::{
+    totp, AuthContext, AuthProvider, EmailPolicy, InMemoryAuthProvider, ManualClock, RateLimiter,
+};
+use landing::server::{AuthError, RateLimit, RateLimitConfig};
+
+use crate::common::{attach_mailer, register_verified, PASSWORD};
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[tokio::test]
+async fn test_token_bucket_per_route_and_ip() {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let config = RateLimitConfig::default().with_route("/api/auth/forgot-password", RateLimit::new(2, 6));
+    let limiter = RateLimiter::new(config).with_clock(clock.clone());
+    let client = ip("198.51.100.1");
+
+    assert!(limiter.check_route("/api/auth/forgot-password", client).allowed);
+    let second = limiter.check_route("/api/auth/forgot-password", client);
+    assert!(second.allowed);
+    assert_eq!((second.limit, second.remaining), (2, 0));
+
+    let refused = limiter.check_route("/api/auth/forgot-password", client);
+    assert!(!refused.allowed);
+    assert_eq!(refused.retry_after_secs, Some(10));
+
+    // Other clients and other routes have their own buckets
+    assert!(limiter.check_route("/api/auth/forgot-password", ip("198.51.100.2")).allowed);
+    assert!(limiter.check_route("/api/posts", client).allowed);
+
+    clock.advance(Duration::seconds(10));
+    assert!(limiter.check_route("/api/auth/forgot-password", client).allowed);
+}
+
+#[tokio::test]
+async fn test_sign_in_routes_have_default_limits() {
+    let limiter = RateLimiter::new(RateLimitConfig::default());
+    let client = ip("198.51.100.3");
+
+    for route in ["/api/auth/login", "/api/auth/mfa", "/api/auth/magic-link/login"] {
+        for _ in 0..10 {
+            assert!(limiter.check_route(route, client).allowed, "{route}");
+        }
+        assert!(!limiter.check_route(route, client).allowed, "{route}");
+    }
+}
+
+#[tokio::test]
+async fn test_client_ip_only_trusts_known_proxies() {
+    let config = RateLimitConfig {
+        trusted_proxies: vec![ip("10.0.0.1")],
+        ..Default::default()
+    };
+    let limiter = RateLimiter::new(config);
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", "203.0.113.9, 10.0.0.1".parse().unwrap());
+
+    assert_eq!(limiter.client_ip(Some(ip("10.0.0.1")), &headers), Some(ip("203.0.113.9")));
+    assert_eq!(limiter.client_ip(Some(ip("192.0.2.4")), &headers), Some(ip("192.0.2.4")));
+    assert_eq!(limiter.client_ip(None, &headers), None);
+}
+
+#[tokio::test]
+async fn test_login_limited_per_account() -> anyhow::Result<()> {
+    let config = RateLimitConfig {
+        login_per_account: RateLimit::new(3, 1),
+        ..Default::default()
+    };
+    let policy = EmailPolicy { fold_plus_tags: true, ..Default::default() };
+    let limiter = Arc::new(RateLimiter::new(config).with_email_policy(policy));
+    let auth = AuthContext::new(Arc::new(InMemoryAuthProvider::new())).with_rate_limiter(limiter);
+
+    for _ in 0..3 {
+        assert!(matches!(
+            auth.login("olivia@example.com", PASSWORD).await,
+            Err(AuthError::AuthenticationFailed)
+        ));
+    }
+    assert!(matches!(
+        auth.login("Olivia@Example.com", PASSWORD).await,
+        Err(AuthError::RateLimited)
+    ));
+    assert!(matches!(
+        auth.login("olivia+again@example.com", PASSWORD).await,
+        Err(AuthError::RateLimited)
+    ));
+    // Garbage never gets a bucket of its own
+    assert!(matches!(
+        auth.login("olivia", PASSWORD).await,
+        Err(AuthError::InvalidEmail)
+    ));
+    assert!(matches!(
+        auth.login("peggy@example.com", PASSWORD).await,
+        Err(AuthError::AuthenticationFailed)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mfa_codes_share_the_account_limit() -> anyhow::Result<()> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let (provider, mailer) = attach_mailer(InMemoryAuthProvider::with_clock(clock.clone()));
+    register_verified(&provider, &mailer, "rosa@example.com").await?;
+    let user = provider.authenticate("rosa@example.com", PASSWORD).await?;
+    let enrollment = provider.begin_totp_enrollment(user.id).await?;
+    let code = totp::generate_code(&enrollment.secret, clock.now())?;
+    provider.confirm_totp_enrollment(user.id, &code).await?;
+
+    let config = RateLimitConfig {
+        login_per_account: RateLimit::new(3, 1),
+        ..Default::default()
+    };
+    let limiter = Arc::new(RateLimiter::new(config).with_clock(clock.clone()));
+    let auth = AuthContext::new(Arc::new(provider)).with_rate_limiter(limiter);
+
+    let Err(AuthError::MfaRequired { challenge }) = auth.sign_in("rosa@example.com", PASSWORD).await
+    else {
+        panic!("password alone should not be enough");
+    };
+    for _ in 0..2 {
+        assert!(matches!(
+            auth.verify_mfa(&challenge, "000000").await,
+            Err(AuthError::InvalidMfaCode)
+        ));
+    }
+
+    // The password attempt and both codes used up the account's bucket
+    clock.advance(Duration::seconds(30));
+    let code = totp::generate_code(&enrollment.secret, clock.now())?;
+    assert!(matches!(
+        auth.verify_mfa(&challenge, &code).await,
+        Err(AuthError::RateLimited)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bucket_map_stays_bounded() {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let limiter = RateLimiter::new(RateLimitConfig::default()).with_clock(clock.clone());
+    let strict = RateLimit::new(1, 1);
+    assert!(limiter.check("busy", strict).allowed);
+
+    for i in 0..MAX_BUCKETS * 2 {
+        clock.advance(Duration::milliseconds(1));
+        limiter.check(&format!("client-{i}"), RateLimit::new(5, 5));
+        if i % 100 == 0 {
+            assert!(!limiter.check("busy", strict).allowed);
+        }
+        assert!(limiter.bucket_count() <= MAX_BUCKETS);
+    }
+
+    // Recently used buckets survive the sweeps with their tokens spent
+    assert!(!limiter.check("busy", strict).allowed);
+}
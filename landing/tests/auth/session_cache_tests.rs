@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use landing::server::auth::{AuthContext, LruSessionCache, ManualClock, SessionCache};
+use landing::server::{AuthError, User, ADMIN_ROLE};
+use uuid::Uuid;
+
+use crate::common::{provider_with_mailer, register_verified, PASSWORD};
+
+fn user(token: &str, expires_in: Duration) -> User {
+    User {
+        id: Uuid::new_v4(),
+        email: format!("{token}@example.com"),
+        bearer_token: token.to_string(),
+        session_expires_at: Utc::now() + expires_in,
+        refresh_token: None,
+        roles: Vec::new(),
+        permissions: Vec::new(),
+        impersonator_id: None,
+    }
+}
+
+#[tokio::test]
+async fn test_lru_eviction_and_stats() {
+    let cache = LruSessionCache::new(2, Duration::minutes(1));
+    cache.set("a", &user("a", Duration::hours(1))).await;
+    cache.set("b", &user("b", Duration::hours(1))).await;
+
+    // Touching "a" makes "b" the least recently used
+    assert!(cache.get("a").await.is_some());
+    cache.set("c", &user("c", Duration::hours(1))).await;
+
+    assert!(cache.get("b").await.is_none());
+    assert!(cache.get("a").await.is_some());
+    assert!(cache.get("c").await.is_some());
+
+    let stats = cache.stats().await;
+    assert_eq!((stats.hits, stats.misses, stats.evictions, stats.entries), (3, 1, 1, 2));
+}
+
+#[tokio::test]
+async fn test_entries_bounded_by_ttl_and_session_expiry() {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let cache = LruSessionCache::new(10, Duration::minutes(5)).with_clock(clock.clone());
+    cache.set("long", &user("long", Duration::hours(1))).await;
+    cache.set("short", &user("short", Duration::minutes(1))).await;
+
+    clock.advance(Duration::minutes(2));
+    assert!(cache.get("long").await.is_some());
+    assert!(cache.get("short").await.is_none());
+
+    clock.advance(Duration::minutes(4));
+    assert!(cache.get("long").await.is_none());
+}
+
+#[tokio::test]
+async fn test_context_invalidates_cache() -> anyhow::Result<()> {
+    let (provider, mailer) = provider_with_mailer();
+    register_verified(&provider, &mailer, "nina@example.com").await?;
+
+    let cache = Arc::new(LruSessionCache::default());
+    let auth = AuthContext::new(Arc::new(provider)).with_session_cache(cache.clone());
+    auth.login("nina@example.com", PASSWORD).await?;
+    let user = auth.current_user().await.unwrap();
+
+    auth.resolve_session(&user.bearer_token).await?;
+    auth.resolve_session(&user.bearer_token).await?;
+    let stats = auth.session_cache_stats().await;
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+
+    // Role changes are visible right away
+    auth.assign_role(user.id, ADMIN_ROLE).await?;
+    assert!(auth.resolve_session(&user.bearer_token).await?.has_role(ADMIN_ROLE));
+
+    auth.logout().await?;
+    assert!(cache.get(&user.bearer_token).await.is_none());
+    assert!(matches!(
+        auth.resolve_session(&user.bearer_token).await,
+        Err(AuthError::InvalidSession)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_refresh_exchange_invalidates_cache() -> anyhow::Result<()> {
+    let (provider, mailer) = provider_with_mailer();
+    register_verified(&provider, &mailer, "otto@example.com").await?;
+
+    let cache = Arc::new(LruSessionCache::default());
+    let auth = AuthContext::new(Arc::new(provider)).with_session_cache(cache.clone());
+    auth.login("otto@example.com", PASSWORD).await?;
+    let first = auth.current_user().await.unwrap();
+    auth.resolve_session(&first.bearer_token).await?;
+
+    // Rotation retires the old access token, cached or not
+    let second = auth
+        .exchange_refresh_token(first.refresh_token.as_deref().unwrap(), None)
+        .await?;
+    assert!(cache.get(&first.bearer_token).await.is_none());
+    assert!(auth.resolve_session(&first.bearer_token).await.is_err());
+
+    // Replaying the spent refresh token ends the family, cached copies too
+    auth.resolve_session(&second.bearer_token).await?;
+    assert!(matches!(
+        auth.exchange_refresh_token(first.refresh_token.as_deref().unwrap(), None).await,
+        Err(AuthError::TokenReuseDetected { .. })
+    ));
+    assert!(cache.get(&second.bearer_token).await.is_none());
+    assert!(auth.resolve_session(&second.bearer_token).await.is_err());
+
+    Ok(())
+}
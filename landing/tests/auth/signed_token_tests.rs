@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use jsonwebtoken::decode_header;
+use landing::server::auth::{
+    AuthContext, AuthProvider, InMemoryAuthProvider, ManualClock, MemorySigningKeyStore,
+    SigningKeyStore, TokenSigner,
+};
+use landing::server::{AuthError, MemoryMailer, TokenKey, DEFAULT_ROLE};
+use uuid::Uuid;
+
+use crate::common::{attach_mailer, mailed_token, register_verified, PASSWORD};
+
+struct Setup {
+    clock: Arc<ManualClock>,
+    store: Arc<MemorySigningKeyStore>,
+    provider: Arc<InMemoryAuthProvider>,
+    mailer: Arc<MemoryMailer>,
+    auth: AuthContext,
+}
+
+/// A context issuing signed tokens, with a verified account for `email`
+async fn setup(email: &str) -> anyhow::Result<Setup> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let (provider, mailer) = attach_mailer(InMemoryAuthProvider::with_clock(clock.clone()));
+    register_verified(&provider, &mailer, email).await?;
+    let provider = Arc::new(provider);
+
+    let store = Arc::new(MemorySigningKeyStore::new());
+    let signer = TokenSigner::new(store.clone(), TokenKey::new("test-master-key"))
+        .with_reload_interval(Duration::zero())
+        .with_clock(clock.clone());
+    let auth = AuthContext::new(provider.clone()).with_signed_tokens(Arc::new(signer));
+    Ok(Setup { clock, store, provider, mailer, auth })
+}
+
+fn kid(token: &str) -> String {
+    decode_header(token).unwrap().kid.unwrap()
+}
+
+#[tokio::test]
+async fn test_signed_token_carries_the_user() -> anyhow::Result<()> {
+    let Setup { provider, auth, .. } = setup("gale@example.com").await?;
+    let user = auth.sign_in("gale@example.com", PASSWORD).await?;
+    assert_eq!(user.bearer_token.split('.').count(), 3);
+
+    // The provider has never seen the signed token
+    assert!(provider.validate_session(&user.bearer_token).await.is_err());
+
+    let resolved = auth.resolve_session(&user.bearer_token).await?;
+    assert_eq!(resolved.id, user.id);
+    assert_eq!(resolved.email, "gale@example.com");
+    assert_eq!(resolved.roles, vec![DEFAULT_ROLE.to_string()]);
+    assert!(resolved.has_permission("posts:write"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_signed_token_expires() -> anyhow::Result<()> {
+    let Setup { clock, auth, .. } = setup("hank@example.com").await?;
+    let user = auth.sign_in("hank@example.com", PASSWORD).await?;
+
+    clock.advance(Duration::minutes(16));
+    assert!(matches!(
+        auth.resolve_session(&user.bearer_token).await,
+        Err(AuthError::InvalidSession)
+    ));
+
+    // The refresh token still renews it
+    let renewed = auth.exchange_refresh_token(&user.refresh_token.unwrap(), None).await?;
+    auth.resolve_session(&renewed.bearer_token).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_logout_revokes_signed_token() -> anyhow::Result<()> {
+    let Setup { auth, .. } = setup("ida@example.com").await?;
+    let user = auth.sign_in("ida@example.com", PASSWORD).await?;
+
+    auth.sign_out(&user.bearer_token).await?;
+    assert!(matches!(
+        auth.resolve_session(&user.bearer_token).await,
+        Err(AuthError::InvalidSession)
+    ));
+    assert!(auth.exchange_refresh_token(&user.refresh_token.unwrap(), None).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tampered_or_foreign_tokens_are_refused() -> anyhow::Result<()> {
+    let Setup { store, auth, .. } = setup("jude@example.com").await?;
+    let user = auth.sign_in("jude@example.com", PASSWORD).await?;
+
+    let mut parts: Vec<&str> = user.bearer_token.split('.').collect();
+    let forged_signature = "A".repeat(parts[2].len());
+    parts[2] = &forged_signature;
+    assert!(auth.resolve_session(&parts.join(".")).await.is_err());
+
+    // Same keys, different master key: the derived secrets differ
+    let other = TokenSigner::new(store, TokenKey::new("another-master-key"));
+    let token = other.sign(&user, Uuid::new_v4()).await?;
+    assert_eq!(kid(&token), kid(&user.bearer_token));
+    assert!(auth.resolve_session(&token).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rotation_keeps_old_tokens_until_retired() -> anyhow::Result<()> {
+    let Setup { clock, store, auth, .. } = setup("lena@example.com").await?;
+    let before = auth.sign_in("lena@example.com", PASSWORD).await?;
+
+    store.rotate(Duration::minutes(5)).await?;
+    let after = auth.exchange_refresh_token(before.refresh_token.as_deref().unwrap(), None).await?;
+    assert_ne!(kid(&before.bearer_token), kid(&after.bearer_token));
+
+    // Both keys verify during the grace period...
+    auth.resolve_session(&before.bearer_token).await?;
+    auth.resolve_session(&after.bearer_token).await?;
+
+    // ...and only the new one after it
+    clock.advance(Duration::minutes(6));
+    assert!(auth.resolve_session(&before.bearer_token).await.is_err());
+    auth.resolve_session(&after.bearer_token).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_revoke_other_sessions_with_signed_tokens() -> anyhow::Result<()> {
+    let Setup { auth, .. } = setup("milo@example.com").await?;
+    let laptop = auth.sign_in("milo@example.com", PASSWORD).await?;
+    let phone = auth.sign_in("milo@example.com", PASSWORD).await?;
+
+    let sessions = auth.sessions_for(&laptop).await?;
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions.iter().filter(|s| s.current).count(), 1);
+
+    assert_eq!(auth.revoke_other_sessions_for(&laptop).await?, 1);
+    auth.resolve_session(&laptop.bearer_token).await?;
+    assert!(auth.resolve_session(&phone.bearer_token).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_password_reset_revokes_signed_tokens() -> anyhow::Result<()> {
+    let Setup { provider, mailer, auth, .. } = setup("nell@example.com").await?;
+    let laptop = auth.sign_in("nell@example.com", PASSWORD).await?;
+    let phone = auth.sign_in("nell@example.com", PASSWORD).await?;
+
+    provider.request_password_reset("nell@example.com").await?;
+    auth.reset_password(&mailed_token(&mailer, "nell@example.com"), "N3wPassword")
+        .await?;
+
+    assert!(auth.resolve_session(&laptop.bearer_token).await.is_err());
+    assert!(auth.resolve_session(&phone.bearer_token).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_refresh_token_reuse_revokes_signed_tokens() -> anyhow::Result<()> {
+    let Setup { auth, .. } = setup("otto@example.com").await?;
+    let user = auth.sign_in("otto@example.com", PASSWORD).await?;
+    let stolen = user.refresh_token.clone().unwrap();
+
+    let renewed = auth.exchange_refresh_token(&stolen, None).await?;
+    auth.resolve_session(&renewed.bearer_token).await?;
+
+    assert!(matches!(
+        auth.exchange_refresh_token(&stolen, None).await,
+        Err(AuthError::TokenReuseDetected { .. })
+    ));
+    assert!(auth.resolve_session(&renewed.bearer_token).await.is_err());
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use landing::server::auth::{
+    AuthClient, AuthProvider, InMemoryAuthProvider, LoginStatus, ManualClock, MemoryTokenStore, StoredTokens,
+    TokenStore,
+};
+use landing::server::{AuthConfig, AuthError};
+
+use crate::common::PASSWORD;
+
+struct Setup {
+    clock: Arc<ManualClock>,
+    provider: Arc<InMemoryAuthProvider>,
+    store: Arc<MemoryTokenStore>,
+}
+
+/// A registered account and an empty token store
+async fn setup() -> anyhow::Result<Setup> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let config = AuthConfig { require_email_verification: false, ..Default::default() };
+    let provider = Arc::new(InMemoryAuthProvider::with_clock(clock.clone()).with_config(config));
+    provider.register("nora@example.com", PASSWORD, None).await?;
+    Ok(Setup { clock, provider, store: Arc::new(MemoryTokenStore::new()) })
+}
+
+/// A client as it comes up after a reload, sharing only the store
+fn client(setup: &Setup) -> AuthClient {
+    AuthClient::new(setup.provider.clone()).with_token_store(setup.store.clone())
+}
+
+#[tokio::test]
+async fn test_login_is_restored_after_reload() -> anyhow::Result<()> {
+    let setup = setup().await?;
+    let first = client(&setup);
+    assert!(first.restore_session().await?.is_none());
+
+    assert_eq!(first.login("nora@example.com", PASSWORD).await?, LoginStatus::Complete);
+    let user = first.current_user().await.expect("signed in");
+    assert_eq!(setup.store.load()?, Some(StoredTokens::from(&user)));
+
+    let second = client(&setup);
+    let restored = second.restore_session().await?.expect("session restored");
+    assert_eq!(restored.id, user.id);
+    // The refresh token is kept for the next renewal
+    assert_eq!(restored.refresh_token, user.refresh_token);
+    assert!(second.is_authenticated());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_expired_access_token_is_refreshed_on_restore() -> anyhow::Result<()> {
+    let setup = setup().await?;
+    client(&setup).login("nora@example.com", PASSWORD).await?;
+    let saved = setup.store.load()?.expect("tokens saved");
+
+    setup.clock.advance(Duration::hours(2));
+    let restored = client(&setup).restore_session().await?.expect("session restored");
+    assert_ne!(restored.bearer_token, saved.bearer_token);
+    // The rotated pair replaces the spent one
+    assert_eq!(setup.store.load()?, Some(StoredTokens::from(&restored)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rejected_tokens_are_forgotten() -> anyhow::Result<()> {
+    let setup = setup().await?;
+    let user = setup.provider.authenticate("nora@example.com", PASSWORD).await?;
+    setup.provider.logout(&user.bearer_token).await?;
+    setup.store.save(&StoredTokens { bearer_token: user.bearer_token, refresh_token: None })?;
+
+    let auth = client(&setup);
+    assert!(matches!(auth.restore_session().await, Err(AuthError::InvalidSession)));
+    assert!(!auth.is_authenticated());
+    assert_eq!(setup.store.load()?, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_logout_clears_saved_session() -> anyhow::Result<()> {
+    let setup = setup().await?;
+    let auth = client(&setup);
+    auth.login("nora@example.com", PASSWORD).await?;
+    auth.logout().await?;
+    assert_eq!(setup.store.load()?, None);
+
+    assert!(client(&setup).restore_session().await?.is_none());
+
+    Ok(())
+}
@@ -1,38 +1,32 @@
-use your_crate::db::{self, queries::UserQueries};
-use sqlx::PgPool;
-use fake::{Fake, faker::internet::en::SafeEmail};
+use landing::db::queries::users::{create_user, get_user_by_email};
+use landing::db::{DbError, PgPool};
+
+const PASSWORD_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA";
 
 #[sqlx::test]
-async fn test_user_creation(pool: PgPool) -> anyhow::Result<()> {
-    // Arrange
-    let email: String = SafeEmail().fake();
-    let password_hash = "$2b$12$..."; // Fake bcrypt hash
-    
-    // Act
-    let user = UserQueries::create(&pool, &email, password_hash).await?;
-    
-    // Assert
-    assert_eq!(user.email, email);
-    assert!(!user.password_hash.is_empty());
-    
-    // Verify in database
-    let fetched = UserQueries::get_by_email(&pool, &email).await?;
-    assert!(fetched.is_some());
-    
-    Ok(())
+async fn test_user_creation(pool: PgPool) {
+    let user = create_user(&pool, "Bob@Example.com", "bob@example.com", PASSWORD_HASH)
+        .await
+        .unwrap();
+    assert_eq!(user.email, "Bob@Example.com");
+    assert_eq!(user.password_hash, PASSWORD_HASH);
+
+    let fetched = get_user_by_email(&pool, "bob@example.com").await.unwrap();
+    assert_eq!(fetched.id, user.id);
 }
 
 #[sqlx::test]
-async fn test_duplicate_email(pool: PgPool) -> anyhow::Result<()> {
-    let email = "test@example.com";
-    let password = "hash";
-    
-    // First creation should succeed
-    UserQueries::create(&pool, email, password).await?;
-    
-    // Second attempt should fail
-    let result = UserQueries::create(&pool, email, password).await;
-    assert!(result.is_err());
-    
-    Ok(())
-}
\ No newline at end of file
+async fn test_duplicate_email(pool: PgPool) {
+    create_user(&pool, "bob@example.com", "bob@example.com", PASSWORD_HASH)
+        .await
+        .unwrap();
+
+    let result = create_user(&pool, "BOB@example.com", "bob@example.com", PASSWORD_HASH).await;
+    assert!(matches!(result, Err(DbError::ConstraintViolation(_))));
+}
+
+#[sqlx::test]
+async fn test_unknown_email(pool: PgPool) {
+    let result = get_user_by_email(&pool, "nobody@example.com").await;
+    assert!(matches!(result, Err(DbError::NotFound)));
+}